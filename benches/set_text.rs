@@ -0,0 +1,62 @@
+//! Compares how long placing large text takes depending on how it's passed, run with
+//! `cargo bench --bench set_text`.
+//!
+//! Owned and shared strings are handed over without copying them on Linux, so they should be
+//! faster to place than borrowed ones there, while borrowed ones shouldn't be any slower than
+//! before. Needs a clipboard to place the text onto.
+
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use arboard::Clipboard;
+
+const TEXT_BYTES: usize = 64 * 1024 * 1024;
+const ROUNDS: u32 = 10;
+
+fn main() {
+	let mut clipboard = match Clipboard::new() {
+		Ok(clipboard) => clipboard,
+		Err(error) => {
+			eprintln!("Skipping the benchmark, no clipboard is available: {error}");
+			return;
+		}
+	};
+	let text = "clipboard ".repeat(TEXT_BYTES / 10);
+
+	let borrowed = measure(|| {
+		let start = Instant::now();
+		clipboard.set_text(text.as_str()).unwrap();
+		start.elapsed()
+	});
+	report("&str", borrowed);
+
+	let owned = measure(|| {
+		// Copying the text beforehand is left out of the time, as the caller owns it already.
+		let text = text.clone();
+		let start = Instant::now();
+		clipboard.set_text(text).unwrap();
+		start.elapsed()
+	});
+	report("String", owned);
+
+	let shared: Arc<str> = text.as_str().into();
+	let shared = measure(|| {
+		let start = Instant::now();
+		clipboard.set_text(Arc::clone(&shared)).unwrap();
+		start.elapsed()
+	});
+	report("Arc<str>", shared);
+
+	clipboard.clear().unwrap();
+}
+
+/// Runs `round` a few times and returns the fastest of the times it reported.
+fn measure(mut round: impl FnMut() -> Duration) -> Duration {
+	(0..ROUNDS).map(|_| round()).min().unwrap()
+}
+
+fn report(name: &str, time: Duration) {
+	println!("{name:>10}: {time:?} to place {} MiB", TEXT_BYTES / 1024 / 1024);
+}
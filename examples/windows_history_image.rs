@@ -0,0 +1,33 @@
+//! Manual test for images showing up in the Windows clipboard history.
+//!
+//! Run it, then press Win+V: the gradient should be the newest entry. With `--exclude`, it
+//! should be pasteable but missing from the history.
+
+#[cfg(windows)]
+use arboard::SetExtWindows;
+use arboard::{Clipboard, ImageData};
+use std::{env, error::Error};
+
+fn main() -> Result<(), Box<dyn Error>> {
+	env_logger::init();
+
+	let (width, height) = (64, 64);
+	let bytes: Vec<u8> = (0..width * height)
+		.flat_map(|i| [(i % width * 4) as u8, (i / width * 4) as u8, 128, 255])
+		.collect();
+	let image = ImageData { width, height, bytes: bytes.into() };
+
+	let exclude = env::args().any(|arg| arg == "--exclude");
+	let mut clipboard = Clipboard::new()?;
+	let set = clipboard.set();
+	#[cfg(windows)]
+	let set = if exclude { set.exclude_from_history() } else { set };
+	#[cfg(not(windows))]
+	if exclude {
+		println!("--exclude only has an effect on Windows");
+	}
+	set.image(image)?;
+
+	println!("The image was placed on the clipboard, open the clipboard history with Win+V.");
+	Ok(())
+}
@@ -8,8 +8,14 @@ the Apache 2.0 or the MIT license at the licensee's choice. The terms
 and conditions of the chosen license apply to this file.
 */
 
-#[cfg(feature = "image-data")]
-use std::borrow::Cow;
+use std::{
+	borrow::Cow,
+	io::{self, Write},
+	path::PathBuf,
+	rc::Rc,
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
 /// An error that might happen during a clipboard operation.
 ///
@@ -21,7 +27,10 @@ pub enum Error {
 	/// The clipboard contents were not available in the requested format.
 	/// This could either be due to the clipboard being empty or the clipboard contents having
 	/// an incompatible format to the requested one (eg when calling `get_image` on text)
-	ContentNotAvailable,
+	///
+	/// `tried` lists the formats that were asked for, as X11 target names or MIME types. It's only
+	/// filled in on Linux, and may be empty there too, for example when the clipboard is empty.
+	ContentNotAvailable { tried: Vec<String> },
 
 	/// The selected clipboard is not supported by the current configuration (system and/or environment).
 	///
@@ -43,7 +52,38 @@ pub enum Error {
 
 	/// The image or the text that was about the be transferred to/from the clipboard could not be
 	/// converted to the appropriate format.
-	ConversionFailure,
+	///
+	/// The `detail` tells which format failed to be converted, and at which point.
+	ConversionFailure { detail: ConversionDetail },
+
+	/// The data on the clipboard exceeds the configured size limit, so it wasn't read.
+	///
+	/// This is returned by [`Get::image`](crate::Get::image) for images with more pixels than
+	/// allowed by [`Get::max_image_pixels`](crate::Get::max_image_pixels). On Windows, it's also
+	/// returned when setting data larger than allowed by `SetExtWindows::max_format_bytes`.
+	TooLarge,
+
+	/// The connection to the display server was lost, for example because the Wayland compositor
+	/// restarted.
+	///
+	/// This is currently only returned on Wayland, where a reconnect is attempted once per
+	/// operation before giving up. Later operations may succeed again once the compositor is back,
+	/// which [`Clipboard::reconnect`](crate::Clipboard::reconnect) can be used to check for.
+	Disconnected,
+
+	/// The application which owned the clipboard went away while its contents were being read,
+	/// for example because it was quitting.
+	///
+	/// This is a race with the owner rather than a lasting failure, so the read may succeed when
+	/// it's retried, with the contents of a new owner like a clipboard manager, or with nothing.
+	/// It's currently only returned on X11, where the server reports the owner's window or the
+	/// atoms of the request as invalid.
+	OwnerLost,
+
+	/// The clipboard didn't change before the deadline given to
+	/// [`Get::wait_until`](crate::Get::wait_until) passed, or the contents weren't read before
+	/// the deadline given to [`Get::deadline`](crate::Get::deadline).
+	Timeout,
 
 	/// Any error that doesn't fit the other error types.
 	///
@@ -55,10 +95,15 @@ pub enum Error {
 impl std::fmt::Display for Error {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
-			Error::ContentNotAvailable => f.write_str("The clipboard contents were not available in the requested format or the clipboard is empty."),
+			Error::ContentNotAvailable { tried } if tried.is_empty() => f.write_str("The clipboard contents were not available in the requested format or the clipboard is empty."),
+			Error::ContentNotAvailable { tried } => f.write_fmt(format_args!("The clipboard contents were not available in the requested format or the clipboard is empty. Tried: {}", tried.join(", "))),
 			Error::ClipboardNotSupported => f.write_str("The selected clipboard is not supported with the current system configuration."),
 			Error::ClipboardOccupied => f.write_str("The native clipboard is not accessible due to being held by another party."),
-			Error::ConversionFailure => f.write_str("The image or the text that was about the be transferred to/from the clipboard could not be converted to the appropriate format."),
+			Error::ConversionFailure { detail } => f.write_fmt(format_args!("The image or the text that was about the be transferred to/from the clipboard could not be converted to the appropriate format: {detail}")),
+			Error::TooLarge => f.write_str("The clipboard contents exceed the configured size limit."),
+			Error::Disconnected => f.write_str("The connection to the display server was lost."),
+			Error::OwnerLost => f.write_str("The owner of the clipboard went away while its contents were being read."),
+			Error::Timeout => f.write_str("The clipboard didn't change or couldn't be read before the deadline."),
 			Error::Unknown { description } => f.write_fmt(format_args!("Unknown error while interacting with the clipboard: {description}")),
 		}
 	}
@@ -79,10 +124,14 @@ impl std::fmt::Debug for Error {
 			}
 		}
 		let name = kind_to_str!(
-			ContentNotAvailable,
+			ContentNotAvailable { .. },
 			ClipboardNotSupported,
 			ClipboardOccupied,
-			ConversionFailure,
+			ConversionFailure { .. },
+			TooLarge,
+			Disconnected,
+			OwnerLost,
+			Timeout,
 			Unknown { .. }
 		);
 		f.write_fmt(format_args!("{name} - \"{self}\""))
@@ -93,6 +142,505 @@ impl Error {
 	pub(crate) fn unknown<M: Into<String>>(message: M) -> Self {
 		Error::Unknown { description: message.into() }
 	}
+
+	/// A [`ContentNotAvailable`](Error::ContentNotAvailable) which doesn't tell what was tried.
+	pub(crate) fn content_not_available() -> Self {
+		Error::ContentNotAvailable { tried: Vec::new() }
+	}
+
+	pub(crate) fn conversion<M: Into<String>>(
+		format: Format,
+		stage: ConversionStage,
+		message: M,
+	) -> Self {
+		Error::ConversionFailure {
+			detail: ConversionDetail { format, stage, message: message.into() },
+		}
+	}
+
+	/// The details of a [`ConversionFailure`](Error::ConversionFailure), if it is one.
+	pub fn conversion_detail(&self) -> Option<&ConversionDetail> {
+		match self {
+			Error::ConversionFailure { detail } => Some(detail),
+			_ => None,
+		}
+	}
+
+	/// Creates an identical error, for reporting a single failure to several callers.
+	#[cfg(all(
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	pub(crate) fn duplicate(&self) -> Self {
+		match self {
+			Error::ContentNotAvailable { tried } => {
+				Error::ContentNotAvailable { tried: tried.clone() }
+			}
+			Error::ClipboardNotSupported => Error::ClipboardNotSupported,
+			Error::ClipboardOccupied => Error::ClipboardOccupied,
+			Error::ConversionFailure { detail } => {
+				Error::ConversionFailure { detail: detail.clone() }
+			}
+			Error::TooLarge => Error::TooLarge,
+			Error::Disconnected => Error::Disconnected,
+			Error::OwnerLost => Error::OwnerLost,
+			Error::Timeout => Error::Timeout,
+			Error::Unknown { description } => Error::unknown(description.clone()),
+		}
+	}
+}
+
+/// Details about an [`Error::ConversionFailure`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConversionDetail {
+	/// The format of the data that couldn't be converted.
+	pub format: Format,
+	/// At which point the conversion failed.
+	pub stage: ConversionStage,
+	/// What went wrong. Like the description of [`Error::Unknown`], this is only meant to help the
+	/// developer.
+	pub message: String,
+}
+
+impl std::fmt::Display for ConversionDetail {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let stage = match self.stage {
+			ConversionStage::Decode => "decoding",
+			ConversionStage::Encode => "encoding",
+			ConversionStage::Validate => "validating",
+		};
+		write!(f, "{stage} {} failed: {}", self.format, self.message)
+	}
+}
+
+/// A kind of data which is converted to or from what's on the clipboard, as named by a
+/// [`ConversionDetail`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Format {
+	/// Plain text.
+	Text,
+	/// HTML, including the `CF_HTML` wrapper it's placed in on Windows.
+	Html,
+	/// Rich text (RTF).
+	Rtf,
+	/// A list of files.
+	FileList,
+	/// An image as RGBA pixels, like in `ImageData`.
+	Pixels,
+	/// A PNG image.
+	Png,
+	/// A JPEG image.
+	Jpeg,
+	/// A BMP file, or a bitmap in one of the Windows clipboard formats.
+	Bitmap,
+	/// A TIFF image.
+	Tiff,
+	/// An image file in some other encoding.
+	Image,
+}
+
+impl std::fmt::Display for Format {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Format::Text => "text",
+			Format::Html => "HTML",
+			Format::Rtf => "RTF",
+			Format::FileList => "file list",
+			Format::Pixels => "pixels",
+			Format::Png => "PNG",
+			Format::Jpeg => "JPEG",
+			Format::Bitmap => "bitmap",
+			Format::Tiff => "TIFF",
+			Format::Image => "image",
+		})
+	}
+}
+
+/// At which point a conversion failed, as told by a [`ConversionDetail`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConversionStage {
+	/// Reading what's on the clipboard.
+	Decode,
+	/// Turning data into what's placed onto the clipboard.
+	Encode,
+	/// Checking the data passed in, before anything was converted.
+	Validate,
+}
+
+/// Whether files on the clipboard were copied or cut, as returned by
+/// [`Get::file_list_with_op`](crate::Get::file_list_with_op).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileOp {
+	/// The files should stay where they are when pasted.
+	Copy,
+	/// The files should be moved when pasted.
+	Cut,
+}
+
+/// A file or directory on the clipboard, as returned by
+/// [`Get::file_list_detailed`](crate::Get::file_list_detailed).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileEntry {
+	/// The path as it was listed on the clipboard.
+	pub path: PathBuf,
+	/// Whether something exists at `path`. Files which were deleted since they were copied, or
+	/// which can't be accessed, are still listed.
+	pub exists: bool,
+	/// Whether `path` is a directory, following symbolic links. This is `false` if nothing
+	/// exists at `path`.
+	pub is_dir: bool,
+}
+
+impl FileEntry {
+	/// Looks up what is at `path` on the file system.
+	pub(crate) fn stat(path: PathBuf) -> Self {
+		let (exists, is_dir) = match std::fs::metadata(&path) {
+			Ok(metadata) => (true, metadata.is_dir()),
+			Err(_) => (false, false),
+		};
+		Self { path, exists, is_dir }
+	}
+}
+
+/// What a "set" operation placed onto the clipboard, as returned by the methods of
+/// [`SetWithReport`](crate::SetWithReport).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SetReport {
+	/// The formats the data was placed in, in the order they were placed. The formats which only
+	/// mark the data to be kept out of clipboard histories aren't listed.
+	pub formats: Vec<PlacedFormat>,
+	/// Whether the data was marked to be kept out of clipboard histories, like with
+	/// `exclude_from_history`.
+	pub excluded_from_history: bool,
+}
+
+/// A format that a "set" operation placed data in, as listed in a [`SetReport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlacedFormat {
+	/// The platform's name of the format: the X11 target or Wayland MIME type on Linux, the
+	/// standard (like `CF_UNICODETEXT`) or registered format on Windows and the pasteboard type on
+	/// macOS.
+	pub name: String,
+	/// The size of the data in this format, in bytes, after it was converted, like the size of
+	/// the encoded PNG or of the UTF-16 text on Windows. This is `None` if the data is only
+	/// produced once it's pasted or if the platform converts it itself, like the `NSImage`
+	/// placed on macOS.
+	pub bytes: Option<usize>,
+}
+
+/// Whether a "set" operation started with [`Set::if_changed`](crate::Set::if_changed) placed
+/// its data, as returned by the methods of [`SetIfChanged`](crate::SetIfChanged).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SetOutcome {
+	/// The data was placed onto the clipboard.
+	Placed,
+	/// The clipboard already held the very same data, so it was left as it was.
+	Unchanged,
+}
+
+/// Where the platform's `Set` records the formats it places, if a [`SetReport`] was asked for.
+#[derive(Default)]
+pub(crate) struct Recorder<'a>(Option<&'a mut SetReport>);
+
+impl<'a> Recorder<'a> {
+	/// Records into `report`, starting with whether the data is excluded from clipboard
+	/// histories, which is already known from the `Set`'s configuration.
+	pub(crate) fn new(report: &'a mut SetReport, excluded_from_history: bool) -> Self {
+		report.excluded_from_history = excluded_from_history;
+		Self(Some(report))
+	}
+
+	/// Whether a report was asked for, for formats whose names are costly to find out.
+	#[cfg_attr(
+		not(all(
+			feature = "x11-interop",
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+		)),
+		allow(dead_code)
+	)]
+	pub(crate) fn is_recording(&self) -> bool {
+		self.0.is_some()
+	}
+
+	pub(crate) fn format(&mut self, name: &str, bytes: Option<usize>) {
+		if let Some(report) = &mut self.0 {
+			report.formats.push(PlacedFormat { name: name.to_owned(), bytes });
+		}
+	}
+}
+
+/// Which features arboard was built with and how it talks to the system's clipboard, as
+/// returned by [`Clipboard::diagnostics`](crate::Clipboard::diagnostics).
+///
+/// This is meant for bug reports: its `Display` implementation lists everything on separate
+/// lines, ready to be pasted into an issue.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Diagnostics {
+	/// The version of arboard.
+	pub version: String,
+	/// The Cargo features arboard was built with.
+	pub features: Vec<String>,
+	/// The backend in use, like `X11` or `Wayland data control`.
+	pub backend: String,
+	/// Why the backend was chosen, which on Linux includes why Wayland wasn't used.
+	pub backend_reason: String,
+	/// The display server, like the X server's vendor and release. This is `None` if it can't be
+	/// found out, as on Wayland, where the compositor doesn't tell its name.
+	pub server: Option<String>,
+	/// The version of the protocol spoken with the display server, if there is one.
+	pub protocol: Option<String>,
+	/// Whether the primary selection can be used on Linux, and `None` on other platforms.
+	pub primary_selection: Option<bool>,
+	/// The environment variables which influence the choice of the backend, with their values.
+	pub environment: Vec<(String, String)>,
+}
+
+impl Diagnostics {
+	/// What is known without asking the platform.
+	pub(crate) fn new() -> Self {
+		let features = [
+			("image-data", cfg!(feature = "image-data")),
+			("image-data-png", cfg!(feature = "image-data-png")),
+			("wayland-data-control", cfg!(feature = "wayland-data-control")),
+			("serde", cfg!(feature = "serde")),
+			("text-preview", cfg!(feature = "text-preview")),
+			("x11-interop", cfg!(feature = "x11-interop")),
+		];
+		Self {
+			version: env!("CARGO_PKG_VERSION").to_owned(),
+			features: features
+				.into_iter()
+				.filter(|&(_, enabled)| enabled)
+				.map(|(feature, _)| feature.to_owned())
+				.collect(),
+			..Self::default()
+		}
+	}
+}
+
+impl std::fmt::Display for Diagnostics {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "arboard version: {}", self.version)?;
+		let features =
+			if self.features.is_empty() { "none".into() } else { self.features.join(", ") };
+		writeln!(f, "features: {features}")?;
+		writeln!(f, "backend: {} ({})", self.backend, self.backend_reason)?;
+		writeln!(f, "server: {}", self.server.as_deref().unwrap_or("unknown"))?;
+		writeln!(f, "protocol: {}", self.protocol.as_deref().unwrap_or("unknown"))?;
+		if let Some(supported) = self.primary_selection {
+			writeln!(
+				f,
+				"primary selection: {}",
+				if supported { "supported" } else { "unsupported" }
+			)?;
+		}
+		for (name, value) in &self.environment {
+			writeln!(f, "{name}={value}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Where the text returned by [`Get::text_or_html_stripped`](crate::Get::text_or_html_stripped)
+/// came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextSource {
+	/// The clipboard contained the text as it is.
+	Text,
+	/// The clipboard only contained HTML, which was converted to text.
+	Html,
+}
+
+/// Text which can be placed onto the clipboard.
+///
+/// The clipboard needs the text for as long as it holds it. On Linux, owned strings (`String`,
+/// `Box<str>` and owned `Cow`s) are moved into it and `Arc<str>`s are shared with it, both
+/// without copying, while borrowed strings and `Rc<str>`s are copied once. Windows and macOS
+/// convert the text into an encoding of their own, which copies it in any case.
+pub trait ClipboardText<'a> {
+	/// Converts the text into a `Cow`, without copying it if possible.
+	fn into_cow(self) -> Cow<'a, str>;
+
+	/// Returns the text as an `Arc<str>` if it's shared already, so that it can be shared
+	/// further instead of copied, or converts it into a `Cow` otherwise.
+	fn into_shared(self) -> Result<Arc<str>, Cow<'a, str>>
+	where
+		Self: Sized,
+	{
+		Err(self.into_cow())
+	}
+}
+
+impl<'a> ClipboardText<'a> for &'a str {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Borrowed(self)
+	}
+}
+
+impl<'a> ClipboardText<'a> for &'a String {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Borrowed(self)
+	}
+}
+
+impl<'a> ClipboardText<'a> for String {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Owned(self)
+	}
+}
+
+impl<'a> ClipboardText<'a> for Cow<'a, str> {
+	fn into_cow(self) -> Cow<'a, str> {
+		self
+	}
+}
+
+impl<'a> ClipboardText<'a> for Box<str> {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Owned(self.into_string())
+	}
+}
+
+impl<'a> ClipboardText<'a> for Arc<str> {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Owned(String::from(&*self))
+	}
+
+	fn into_shared(self) -> Result<Arc<str>, Cow<'a, str>> {
+		Ok(self)
+	}
+}
+
+impl<'a> ClipboardText<'a> for &'a Arc<str> {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Borrowed(self)
+	}
+
+	fn into_shared(self) -> Result<Arc<str>, Cow<'a, str>> {
+		Ok(Arc::clone(self))
+	}
+}
+
+impl<'a> ClipboardText<'a> for Rc<str> {
+	fn into_cow(self) -> Cow<'a, str> {
+		Cow::Owned(String::from(&*self))
+	}
+}
+
+/// The pieces of data passed to `Set::stream`, which are produced as they are needed.
+pub(crate) type Chunks = Box<dyn Iterator<Item = Vec<u8>> + Send>;
+
+/// Turns the result of reading a format which is allowed to be missing into an `Option`.
+pub(crate) fn if_available<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+	match result {
+		Ok(value) => Ok(Some(value)),
+		Err(Error::ContentNotAvailable { .. }) => Ok(None),
+		Err(error) => Err(error),
+	}
+}
+
+/// Writes contents that were read as a whole to the writer of `Get::write_to`.
+pub(crate) fn write_contents(writer: &mut dyn Write, bytes: &[u8]) -> Result<u64, Error> {
+	writer.write_all(bytes).map_err(write_error)?;
+	Ok(bytes.len() as u64)
+}
+
+pub(crate) fn write_error(error: io::Error) -> Error {
+	Error::unknown(format!(
+		"Failed to write the clipboard's contents ({:?}): {error}",
+		error.kind()
+	))
+}
+
+/// Falls back to the text content of the HTML read by `html` when no `text` was available.
+pub(crate) fn text_or_html_stripped(
+	text: Result<String, Error>,
+	html: impl FnOnce() -> Result<String, Error>,
+) -> Result<(String, TextSource), Error> {
+	match text {
+		Ok(text) => Ok((text, TextSource::Text)),
+		Err(Error::ContentNotAvailable { .. }) => {
+			Ok((crate::formats::html_to_text(&html()?), TextSource::Html))
+		}
+		Err(error) => Err(error),
+	}
+}
+
+/// Shortens `text` to at most `max_bytes` of UTF-8 without splitting a character.
+///
+/// Returns the text and whether anything was cut off.
+pub(crate) fn truncate_text(mut text: String, max_bytes: usize) -> (String, bool) {
+	if text.len() <= max_bytes {
+		return (text, false);
+	}
+
+	let mut end = max_bytes;
+	while !text.is_char_boundary(end) {
+		end -= 1;
+	}
+	text.truncate(end);
+	(text, true)
+}
+
+/// Shortens `text` to at most `max_graphemes` grapheme clusters, the last of which is an ellipsis
+/// if anything was cut off, so that emoji and combining sequences stay whole.
+#[cfg(feature = "text-preview")]
+pub(crate) fn preview_text(text: &str, max_graphemes: usize) -> String {
+	use unicode_segmentation::UnicodeSegmentation;
+
+	if text.graphemes(true).nth(max_graphemes).is_none() {
+		return text.to_owned();
+	}
+	// The ellipsis takes the place of the last grapheme that would fit.
+	let Some(kept) = max_graphemes.checked_sub(1) else {
+		return String::new();
+	};
+	let end = text.grapheme_indices(true).nth(kept).map_or(0, |(end, _)| end);
+	format!("{}\u{2026}", &text[..end])
+}
+
+/// Decodes UTF-8 text read from the clipboard.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+pub(crate) fn text_from_utf8(bytes: Vec<u8>) -> Result<String, Error> {
+	String::from_utf8(bytes).map_err(|error| text_error(error.to_string()))
+}
+
+fn text_error(message: String) -> Error {
+	Error::conversion(Format::Text, ConversionStage::Decode, message)
+}
+
+/// Decodes the UTF-8 `bytes` as text of at most `max_bytes`, see [`truncate_text`].
+///
+/// `bytes` may be the start of a longer text, so a character which is incomplete at their end
+/// is dropped rather than treated as invalid when they go beyond `max_bytes`.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),))]
+pub(crate) fn decode_text_prefix(
+	mut bytes: Vec<u8>,
+	max_bytes: usize,
+) -> Result<(String, bool), Error> {
+	let truncated = bytes.len() > max_bytes;
+	bytes.truncate(max_bytes);
+
+	match String::from_utf8(bytes) {
+		Ok(text) => Ok((text, truncated)),
+		// The error has no length when the bytes end in the middle of a character.
+		Err(error) if truncated && error.utf8_error().error_len().is_none() => {
+			let valid_up_to = error.utf8_error().valid_up_to();
+			let mut bytes = error.into_bytes();
+			bytes.truncate(valid_up_to);
+			text_from_utf8(bytes).map(|text| (text, true))
+		}
+		Err(error) => Err(text_error(error.to_string())),
+	}
 }
 
 /// Stores pixel data of an image.
@@ -105,6 +653,9 @@ impl Error {
 /// in `bytes` (starting at the fifth byte) corresponds to the pixel that's
 /// sitting to the right side of the top-left pixel (x=1, y=0)
 ///
+/// Rows follow each other without any padding. Pixels whose rows are padded, like in many
+/// framebuffers, can be copied with [`ImageData::from_strided`].
+///
 /// Assigning a `2*1` image would for example look like this
 /// ```
 /// use arboard::ImageData;
@@ -122,7 +673,7 @@ impl Error {
 ///     bytes: Cow::from(bytes.as_ref())
 /// };
 /// ```
-#[cfg(feature = "image-data")]
+#[cfg(feature = "image-data-png")]
 #[derive(Debug, Clone)]
 pub struct ImageData<'a> {
 	pub width: usize,
@@ -130,7 +681,7 @@ pub struct ImageData<'a> {
 	pub bytes: Cow<'a, [u8]>,
 }
 
-#[cfg(feature = "image-data")]
+#[cfg(feature = "image-data-png")]
 impl ImageData<'_> {
 	/// Returns a the bytes field in a way that it's guaranteed to be owned.
 	/// It moves the bytes if they are already owned and clones them if they are borrowed.
@@ -147,6 +698,502 @@ impl ImageData<'_> {
 			bytes: self.bytes.clone().into_owned().into(),
 		}
 	}
+
+	/// Copies an image out of RGBA pixels whose rows are `stride` bytes apart, like in the
+	/// framebuffers of GPUs and screen capture APIs, which pad each row for alignment.
+	///
+	/// The padding at the end of each row is left out of the image in the same pass that copies
+	/// the pixels. The last row doesn't need to be padded.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if `stride` is shorter than a row of `width` pixels, or
+	/// if `bytes` don't hold `height` rows.
+	pub fn from_strided(
+		bytes: &[u8],
+		width: usize,
+		height: usize,
+		stride: usize,
+	) -> Result<ImageData<'static>, Error> {
+		if width.checked_mul(4).map_or(true, |row_len| row_len > stride) {
+			return Err(Error::conversion(
+				Format::Pixels,
+				ConversionStage::Validate,
+				format!("a stride of {stride} bytes is too short for rows of {width} pixels"),
+			));
+		}
+		copy_region(bytes, stride, (0, 0), (width, height))
+	}
+
+	/// Copies the `width` by `height` pixels whose top-left corner is at `x`, `y` into a new
+	/// image.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if the region doesn't lie within the image, or if
+	/// `bytes` don't hold exactly the RGBA pixels of the image.
+	pub fn crop(
+		&self,
+		x: usize,
+		y: usize,
+		width: usize,
+		height: usize,
+	) -> Result<ImageData<'static>, Error> {
+		self.check_pixel_count()?;
+		let fits = |start: usize, len: usize, size: usize| {
+			start.checked_add(len).is_some_and(|end| end <= size)
+		};
+		if !fits(x, width, self.width) || !fits(y, height, self.height) {
+			return Err(Error::conversion(
+				Format::Pixels,
+				ConversionStage::Validate,
+				format!(
+					"the {width}x{height} region at {x}, {y} exceeds the {}x{} image",
+					self.width, self.height
+				),
+			));
+		}
+		copy_region(&self.bytes, self.width * 4, (x, y), (width, height))
+	}
+
+	/// Checks that the image isn't empty and that its bytes hold exactly its RGBA pixels.
+	pub(crate) fn validate(&self) -> Result<(), Error> {
+		if self.bytes.is_empty() || self.width == 0 || self.height == 0 {
+			return Err(Error::conversion(
+				Format::Pixels,
+				ConversionStage::Validate,
+				"the image is empty",
+			));
+		}
+		self.check_pixel_count()
+	}
+
+	/// Checks that the bytes of the image hold exactly its RGBA pixels.
+	fn check_pixel_count(&self) -> Result<(), Error> {
+		let expected_len =
+			self.width.checked_mul(self.height).and_then(|pixels| pixels.checked_mul(4));
+		if expected_len != Some(self.bytes.len()) {
+			return Err(pixel_count_mismatch(self));
+		}
+		Ok(())
+	}
+
+	/// Returns a copy of the image as an [`image::RgbaImage`], for use with the `image` crate.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if `bytes` don't hold exactly the RGBA pixels of a
+	/// `width` by `height` image.
+	#[cfg(feature = "image-data")]
+	pub fn to_dynamic_image(&self) -> Result<image::RgbaImage, Error> {
+		self.check_pixel_count()?;
+		let width = u32::try_from(self.width).map_err(|_| pixel_count_mismatch(self))?;
+		let height = u32::try_from(self.height).map_err(|_| pixel_count_mismatch(self))?;
+		image::RgbaImage::from_raw(width, height, self.bytes.to_vec())
+			.ok_or_else(|| pixel_count_mismatch(self))
+	}
+
+	/// Converts an image of the `image` crate, in any of its color types, to RGBA pixels.
+	#[cfg(feature = "image-data")]
+	pub fn from_dynamic_image(img: &image::DynamicImage) -> ImageData<'static> {
+		let rgba = img.to_rgba8();
+		ImageData {
+			width: rgba.width() as usize,
+			height: rgba.height() as usize,
+			bytes: rgba.into_raw().into(),
+		}
+	}
+
+	/// Returns a copy of the image that is scaled down so that neither side exceeds `max_dim`,
+	/// preserving the aspect ratio. Images that already fit are returned unchanged.
+	#[cfg(feature = "image-data")]
+	pub(crate) fn thumbnail(&self, max_dim: u32) -> Result<ImageData<'static>, Error> {
+		let too_large = |_| {
+			Error::conversion(Format::Pixels, ConversionStage::Validate, "the image is too large")
+		};
+		let width = u32::try_from(self.width).map_err(too_large)?;
+		let height = u32::try_from(self.height).map_err(too_large)?;
+		if max_dim == 0 || width == 0 || height == 0 {
+			return Err(Error::conversion(
+				Format::Pixels,
+				ConversionStage::Validate,
+				"the image or the thumbnail size is empty",
+			));
+		}
+		if width <= max_dim && height <= max_dim {
+			return Ok(self.to_owned_img());
+		}
+
+		let image = image::RgbaImage::from_raw(width, height, self.bytes.to_vec())
+			.ok_or_else(|| pixel_count_mismatch(self))?;
+
+		let scale = f64::from(max_dim) / f64::from(width.max(height));
+		let new_width = ((f64::from(width) * scale).round() as u32).max(1);
+		let new_height = ((f64::from(height) * scale).round() as u32).max(1);
+		let thumbnail = image::imageops::thumbnail(&image, new_width, new_height);
+
+		Ok(ImageData {
+			width: thumbnail.width() as usize,
+			height: thumbnail.height() as usize,
+			bytes: thumbnail.into_raw().into(),
+		})
+	}
+}
+
+/// The image on the clipboard, or the bytes it was encoded in if it couldn't be decoded, as
+/// returned by [`Get::image_or_bytes`](crate::Get::image_or_bytes).
+#[cfg(feature = "image-data-png")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ImageOrBytes {
+	/// The image, decoded into RGBA pixels.
+	Image(ImageData<'static>),
+	/// The image as it was read from the clipboard, since it couldn't be decoded.
+	Encoded {
+		/// The format the image was read in: a MIME type or X11 target on Linux, the name of a
+		/// clipboard format on Windows and a UTI on macOS.
+		format: String,
+		/// The encoded image.
+		bytes: Vec<u8>,
+		/// Why the image couldn't be decoded, usually an [`Error::ConversionFailure`] or
+		/// [`Error::TooLarge`].
+		error: Error,
+	},
+}
+
+#[cfg(feature = "image-data-png")]
+impl ImageOrBytes {
+	/// Decodes the `bytes` with `decode`, keeping them along with the name of their `format` if
+	/// that fails. Borrowed bytes are only copied then.
+	pub(crate) fn decode<'a>(
+		bytes: impl Into<Cow<'a, [u8]>>,
+		decode: impl FnOnce(&[u8]) -> Result<ImageData<'static>, Error>,
+		format: impl FnOnce() -> String,
+	) -> Self {
+		let bytes = bytes.into();
+		match decode(&bytes) {
+			Ok(image) => Self::Image(image),
+			Err(error) => Self::Encoded { format: format(), bytes: bytes.into_owned(), error },
+		}
+	}
+
+	/// Returns the decoded image, or the error that kept it from being decoded.
+	pub fn into_image(self) -> Result<ImageData<'static>, Error> {
+		match self {
+			Self::Image(image) => Ok(image),
+			Self::Encoded { error, .. } => Err(error),
+		}
+	}
+}
+
+/// An encoding in which an image can be placed onto the clipboard, see
+/// [`Set::image_multi`](crate::Set::image_multi).
+#[cfg(feature = "image-data")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ImageEncoding {
+	/// PNG, which keeps the image as it is.
+	Png,
+	/// An uncompressed bitmap, which is what older Windows applications expect.
+	Bmp,
+	/// JPEG, which drops the alpha channel and loses some detail.
+	Jpeg,
+}
+
+#[cfg(feature = "image-data")]
+impl ImageEncoding {
+	/// The MIME type of images in this encoding.
+	#[cfg_attr(any(windows, target_os = "macos"), allow(dead_code))]
+	pub(crate) fn mime_type(self) -> &'static str {
+		match self {
+			ImageEncoding::Png => "image/png",
+			ImageEncoding::Bmp => "image/bmp",
+			ImageEncoding::Jpeg => "image/jpeg",
+		}
+	}
+
+	/// The [`Format`] that conversion errors name for this encoding.
+	pub(crate) fn format(self) -> Format {
+		match self {
+			ImageEncoding::Png => Format::Png,
+			ImageEncoding::Bmp => Format::Bitmap,
+			ImageEncoding::Jpeg => Format::Jpeg,
+		}
+	}
+
+	/// Encodes `image` as a file in this encoding.
+	pub(crate) fn encode(self, image: &ImageData) -> Result<Vec<u8>, Error> {
+		image.validate()?;
+		let rgba = image::RgbaImage::from_raw(
+			image.width as u32,
+			image.height as u32,
+			image.bytes.to_vec(),
+		)
+		.ok_or_else(|| pixel_count_mismatch(image))?;
+
+		let mut bytes = std::io::Cursor::new(Vec::new());
+		let result = match self {
+			ImageEncoding::Png => rgba.write_to(&mut bytes, image::ImageFormat::Png),
+			ImageEncoding::Bmp => rgba.write_to(&mut bytes, image::ImageFormat::Bmp),
+			ImageEncoding::Jpeg => image::DynamicImage::ImageRgba8(rgba)
+				.to_rgb8()
+				.write_to(&mut bytes, image::ImageFormat::Jpeg),
+		};
+		result.map_err(|error| {
+			Error::conversion(self.format(), ConversionStage::Encode, error.to_string())
+		})?;
+		Ok(bytes.into_inner())
+	}
+}
+
+/// Copies the `width` by `height` pixels at `x`, `y` out of RGBA pixels whose rows are `stride`
+/// bytes apart, checking that `bytes` hold all of them.
+#[cfg(feature = "image-data-png")]
+fn copy_region(
+	bytes: &[u8],
+	stride: usize,
+	(x, y): (usize, usize),
+	(width, height): (usize, usize),
+) -> Result<ImageData<'static>, Error> {
+	let row_len = width.saturating_mul(4);
+	// Where the last row of the region ends, which is out of reach if it overflows.
+	let end = height.checked_sub(1).map_or(Some(0), |last_row| {
+		let row_start =
+			y.checked_add(last_row)?.checked_mul(stride)?.checked_add(x.checked_mul(4)?)?;
+		row_start.checked_add(row_len)
+	});
+	if end.map_or(true, |end| end > bytes.len()) {
+		return Err(Error::conversion(
+			Format::Pixels,
+			ConversionStage::Validate,
+			format!("{} bytes don't hold {height} rows of {stride} bytes", bytes.len()),
+		));
+	}
+
+	let mut pixels = Vec::with_capacity(row_len.saturating_mul(height));
+	for row in y..y + height {
+		let start = row * stride + x * 4;
+		pixels.extend_from_slice(&bytes[start..start + row_len]);
+	}
+	Ok(ImageData { width, height, bytes: pixels.into() })
+}
+
+#[cfg(feature = "image-data-png")]
+fn pixel_count_mismatch(image: &ImageData) -> Error {
+	Error::conversion(
+		Format::Pixels,
+		ConversionStage::Validate,
+		format!(
+			"{} bytes don't hold the RGBA pixels of a {}x{} image",
+			image.bytes.len(),
+			image.width,
+			image.height
+		),
+	)
+}
+
+/// Encodes `image` in each of `encodings`, skipping duplicates.
+///
+/// Encodings which fail are logged and left out, so this only fails if none of them succeeded.
+#[cfg(feature = "image-data")]
+#[cfg_attr(windows, allow(dead_code))]
+pub(crate) fn encode_images(
+	image: &ImageData,
+	encodings: &[ImageEncoding],
+) -> Result<Vec<(ImageEncoding, Vec<u8>)>, Error> {
+	let mut encoded: Vec<(ImageEncoding, Vec<u8>)> = Vec::with_capacity(encodings.len());
+	let mut last_error =
+		Error::conversion(Format::Image, ConversionStage::Validate, "no encodings were given");
+	for &encoding in encodings {
+		if encoded.iter().any(|(done, _)| *done == encoding) {
+			continue;
+		}
+		match encoding.encode(image) {
+			Ok(bytes) => encoded.push((encoding, bytes)),
+			Err(error) => {
+				log::warn!("failed to encode the image as {encoding:?}: {error}");
+				last_error = error;
+			}
+		}
+	}
+
+	if encoded.is_empty() {
+		Err(last_error)
+	} else {
+		Ok(encoded)
+	}
+}
+
+/// The largest image, in pixels, which is decoded when reading from the clipboard unless
+/// configured otherwise.
+#[cfg(feature = "image-data-png")]
+pub(crate) const DEFAULT_MAX_IMAGE_PIXELS: u64 = 512_000_000;
+
+/// Decodes an image read from the clipboard into RGBA pixels.
+///
+/// The clipboard contents come from other applications, so the dimensions the image claims to
+/// have are checked against `max_pixels` before allocating anything for its pixels. `format` is
+/// what errors name as the format which couldn't be decoded.
+#[cfg(feature = "image-data")]
+pub(crate) fn decode_image(
+	mut decoder: impl image::ImageDecoder,
+	format: Format,
+	max_pixels: u64,
+) -> Result<ImageData<'static>, Error> {
+	let (width, height) = decoder.dimensions();
+	if u64::from(width) * u64::from(height) > max_pixels {
+		return Err(Error::TooLarge);
+	}
+
+	let mut limits = image::io::Limits::default();
+	// Enough for the widest pixel type (32-bit float RGBA) plus the converted RGBA copy.
+	limits.max_alloc = Some(max_pixels.saturating_mul(16 + 4));
+	decoder.set_limits(limits).map_err(|error| from_image_error(error, format))?;
+
+	let image = image::DynamicImage::from_decoder(decoder)
+		.map_err(|error| from_image_error(error, format))?
+		.into_rgba8();
+	Ok(ImageData {
+		width: image.width() as usize,
+		height: image.height() as usize,
+		bytes: image.into_raw().into(),
+	})
+}
+
+/// Encodes `image` as a PNG file.
+#[cfg(feature = "image-data-png")]
+pub(crate) fn encode_png(image: &ImageData) -> Result<Vec<u8>, Error> {
+	image.validate()?;
+	let encode_error = |error: png::EncodingError| {
+		Error::conversion(Format::Png, ConversionStage::Encode, error.to_string())
+	};
+
+	let mut bytes = Vec::new();
+	let mut encoder = png::Encoder::new(&mut bytes, image.width as u32, image.height as u32);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	let mut writer = encoder.write_header().map_err(encode_error)?;
+	writer.write_image_data(&image.bytes).map_err(encode_error)?;
+	writer.finish().map_err(encode_error)?;
+	Ok(bytes)
+}
+
+/// Decodes a PNG file read from the clipboard into RGBA pixels, with the same checks against
+/// `max_pixels` as [`decode_image`].
+///
+/// This uses the `png` crate directly, so PNG images can be read without the `image` crate.
+#[cfg(feature = "image-data-png")]
+#[cfg_attr(all(target_os = "macos", feature = "image-data"), allow(dead_code))]
+pub(crate) fn decode_png(bytes: &[u8], max_pixels: u64) -> Result<ImageData<'static>, Error> {
+	let decode_error = |error: png::DecodingError| match error {
+		png::DecodingError::LimitsExceeded => Error::TooLarge,
+		error => Error::conversion(Format::Png, ConversionStage::Decode, error.to_string()),
+	};
+
+	// Enough for 16-bit RGBA pixels, before they are stripped to 8 bits.
+	let limit = usize::try_from(max_pixels.saturating_mul(8)).unwrap_or(usize::MAX);
+	let mut decoder = png::Decoder::new_with_limits(bytes, png::Limits { bytes: limit });
+	decoder.set_transformations(png::Transformations::normalize_to_color8());
+	let mut reader = decoder.read_info().map_err(decode_error)?;
+	let (width, height) = reader.info().size();
+	if u64::from(width) * u64::from(height) > max_pixels {
+		return Err(Error::TooLarge);
+	}
+
+	let mut pixels = vec![0; reader.output_buffer_size()];
+	let frame = reader.next_frame(&mut pixels).map_err(decode_error)?;
+	pixels.truncate(frame.buffer_size());
+	let rgba = match frame.color_type {
+		png::ColorType::Rgba => pixels,
+		png::ColorType::Rgb => {
+			pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect()
+		}
+		png::ColorType::GrayscaleAlpha => {
+			pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect()
+		}
+		png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+		// Palettes are expanded by the transformations.
+		png::ColorType::Indexed => {
+			return Err(Error::conversion(
+				Format::Png,
+				ConversionStage::Decode,
+				"the palette wasn't expanded",
+			))
+		}
+	};
+	Ok(ImageData { width: frame.width as usize, height: frame.height as usize, bytes: rgba.into() })
+}
+
+/// Maps an error from decoding an image in `format` to ours.
+#[cfg(feature = "image-data")]
+pub(crate) fn from_image_error(error: image::ImageError, format: Format) -> Error {
+	match error {
+		image::ImageError::Limits(_) => Error::TooLarge,
+		error => Error::conversion(format, ConversionStage::Decode, error.to_string()),
+	}
+}
+
+/// The format name under which `Set::image_with_thumbnail` publishes the scaled down copy
+/// of the image.
+#[cfg_attr(not(feature = "image-data"), allow(dead_code))]
+pub(crate) const THUMBNAIL_MIME: &str = "image/png;thumbnail";
+
+/// How often the clipboard is checked for changes by [`Get::wait`](crate::Get::wait) where
+/// the platform doesn't tell about them.
+pub(crate) const CHANGE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Calls `changed` every [`CHANGE_POLL_INTERVAL`] until it returns `true`, or returns
+/// [`Error::Timeout`] once `deadline` passed.
+pub(crate) fn poll_for_change(
+	deadline: Option<Instant>,
+	mut changed: impl FnMut() -> Result<bool, Error>,
+) -> Result<(), Error> {
+	loop {
+		if changed()? {
+			return Ok(());
+		}
+		let pause = match deadline {
+			Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+				Some(left) if !left.is_zero() => left.min(CHANGE_POLL_INTERVAL),
+				_ => return Err(Error::Timeout),
+			},
+			None => CHANGE_POLL_INTERVAL,
+		};
+		std::thread::sleep(pause);
+	}
+}
+
+/// Remembers when the contents of a clipboard were first noticed, for
+/// [`Clipboard::content_age`](crate::Clipboard::content_age) on platforms which only have a
+/// counter that changes along with the contents.
+#[cfg(any(windows, target_os = "macos"))]
+pub(crate) struct Arrivals<T> {
+	/// The counter of the contents last noticed, and when they arrived if that's known.
+	seen: (T, Option<Instant>),
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+impl<T: PartialEq> Arrivals<T> {
+	/// Starts with the contents identified by `current`, whose age is unknown.
+	pub(crate) fn new(current: T) -> Self {
+		Self { seen: (current, None) }
+	}
+
+	/// Records that `current` identifies contents which were just placed.
+	pub(crate) fn placed(&mut self, current: T) {
+		self.seen = (current, Some(Instant::now()));
+	}
+
+	/// The age of the contents identified by `current`.
+	///
+	/// Contents that are new since the last call are counted from now, since it isn't known
+	/// when in between they arrived.
+	pub(crate) fn age(&mut self, current: T) -> Option<Duration> {
+		if self.seen.0 != current {
+			self.seen = (current, Some(Instant::now()));
+		}
+		self.seen.1.map(|arrived| arrived.elapsed())
+	}
 }
 
 #[cfg(any(windows, all(unix, not(target_os = "macos"))))]
@@ -156,7 +1203,6 @@ pub(crate) struct ScopeGuard<F: FnOnce()> {
 
 #[cfg(any(windows, all(unix, not(target_os = "macos"))))]
 impl<F: FnOnce()> ScopeGuard<F> {
-	#[cfg_attr(all(windows, not(feature = "image-data")), allow(dead_code))]
 	pub(crate) fn new(callback: F) -> Self {
 		ScopeGuard { callback: Some(callback) }
 	}
@@ -175,7 +1221,385 @@ impl<F: FnOnce()> Drop for ScopeGuard<F> {
 pub(crate) mod private {
 	pub trait Sealed {}
 
+	impl Sealed for crate::Clipboard {}
 	impl Sealed for crate::Get<'_> {}
 	impl Sealed for crate::Set<'_> {}
 	impl Sealed for crate::Clear<'_> {}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(any(windows, target_os = "macos"))]
+	fn arrivals_are_counted_from_when_they_are_noticed() {
+		let mut arrivals = Arrivals::new(1);
+		// The contents were already there, so their age is unknown.
+		assert_eq!(arrivals.age(1), None);
+
+		assert_eq!(arrivals.age(2).map(|age| age < Duration::from_secs(1)), Some(true));
+		std::thread::sleep(Duration::from_millis(20));
+		assert!(arrivals.age(2).unwrap() >= Duration::from_millis(20));
+
+		arrivals.placed(3);
+		assert!(arrivals.age(3).unwrap() < Duration::from_millis(20));
+	}
+
+	#[test]
+	fn clipboard_text_moves_owned_strings() {
+		let text = String::from("owned");
+		let ptr = text.as_ptr();
+		assert!(matches!(text.into_cow(), Cow::Owned(s) if s.as_ptr() == ptr));
+
+		let boxed: Box<str> = "boxed".into();
+		let ptr = boxed.as_ptr();
+		assert!(matches!(boxed.into_cow(), Cow::Owned(s) if s.as_ptr() == ptr));
+
+		let borrowed = "borrowed";
+		assert!(matches!(borrowed.into_cow(), Cow::Borrowed(s) if s.as_ptr() == borrowed.as_ptr()));
+
+		let shared: Arc<str> = "shared".into();
+		assert_eq!((&shared).into_cow(), "shared");
+		assert!(matches!((&shared).into_shared(), Ok(s) if Arc::ptr_eq(&s, &shared)));
+		assert!(matches!(String::from("owned").into_shared(), Err(Cow::Owned(_))));
+		assert_eq!(shared.into_cow(), "shared");
+	}
+
+	#[test]
+	fn file_entry_stat() {
+		let this_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+		let entry = FileEntry::stat(this_dir.join("Cargo.toml"));
+		assert!(entry.exists && !entry.is_dir);
+
+		let entry = FileEntry::stat(this_dir.join("src"));
+		assert!(entry.exists && entry.is_dir);
+
+		let missing = this_dir.join("does-not-exist");
+		let entry = FileEntry::stat(missing.clone());
+		assert_eq!(entry, FileEntry { path: missing, exists: false, is_dir: false });
+	}
+
+	#[test]
+	fn truncate_text_keeps_characters_whole() {
+		assert_eq!(truncate_text("short".into(), 5), ("short".into(), false));
+		assert_eq!(truncate_text("shorter".into(), 5), ("short".into(), true));
+		// "é" takes up two bytes and "🐔" four.
+		assert_eq!(truncate_text("aé🐔".into(), 2), ("a".into(), true));
+		assert_eq!(truncate_text("aé🐔".into(), 6), ("aé".into(), true));
+		assert_eq!(truncate_text("aé🐔".into(), 7), ("aé🐔".into(), false));
+		assert_eq!(truncate_text("🐔".into(), 0), (String::new(), true));
+	}
+
+	#[test]
+	#[cfg(all(
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	fn decode_text_prefix_drops_split_characters() {
+		let text = "aé🐔".as_bytes();
+		assert_eq!(decode_text_prefix(text.to_vec(), 7).unwrap(), ("aé🐔".into(), false));
+		assert_eq!(decode_text_prefix(text.to_vec(), 5).unwrap(), ("aé".into(), true));
+		// Only the first bytes of a longer text might have been read.
+		assert_eq!(decode_text_prefix(text[..5].to_vec(), 4).unwrap(), ("aé".into(), true));
+
+		// An incomplete character is only fine where the text was cut off.
+		assert!(matches!(
+			decode_text_prefix(text[..5].to_vec(), 5),
+			Err(Error::ConversionFailure { .. })
+		));
+		assert!(matches!(
+			decode_text_prefix(vec![b'a', 0xFF, b'b'], 2),
+			Err(Error::ConversionFailure { .. })
+		));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn thumbnail_keeps_aspect_ratio() {
+		let image = ImageData { width: 40, height: 10, bytes: vec![255; 40 * 10 * 4].into() };
+
+		let thumbnail = image.thumbnail(8).unwrap();
+		assert_eq!((thumbnail.width, thumbnail.height), (8, 2));
+		assert_eq!(thumbnail.bytes.len(), 8 * 2 * 4);
+
+		let unchanged = image.thumbnail(64).unwrap();
+		assert_eq!((unchanged.width, unchanged.height), (40, 10));
+		assert_eq!(unchanged.bytes, image.bytes);
+
+		assert!(matches!(image.thumbnail(0), Err(Error::ConversionFailure { .. })));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn dynamic_image_conversions() {
+		let image = ImageData { width: 2, height: 1, bytes: vec![1, 2, 3, 4, 5, 6, 7, 8].into() };
+		let rgba = image.to_dynamic_image().unwrap();
+		assert_eq!(rgba.dimensions(), (2, 1));
+		assert_eq!(rgba.get_pixel(1, 0).0, [5, 6, 7, 8]);
+
+		let converted = ImageData::from_dynamic_image(&image::DynamicImage::ImageRgba8(rgba));
+		assert_eq!((converted.width, converted.height), (2, 1));
+		assert_eq!(converted.bytes, image.bytes);
+
+		let rgb = image::RgbImage::from_raw(1, 1, vec![9, 10, 11]).unwrap();
+		let converted = ImageData::from_dynamic_image(&image::DynamicImage::ImageRgb8(rgb));
+		assert_eq!(converted.bytes, [9, 10, 11, 255].as_slice());
+
+		let short = ImageData { width: 2, height: 2, bytes: vec![0; 4].into() };
+		assert!(matches!(short.to_dynamic_image(), Err(Error::ConversionFailure { .. })));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn encode_images_in_each_format() {
+		let image = ImageData { width: 2, height: 2, bytes: vec![128; 2 * 2 * 4].into() };
+		let encodings =
+			[ImageEncoding::Png, ImageEncoding::Jpeg, ImageEncoding::Bmp, ImageEncoding::Png];
+
+		let encoded = encode_images(&image, &encodings).unwrap();
+		let formats: Vec<_> = encoded
+			.iter()
+			.map(|(encoding, bytes)| (*encoding, image::guess_format(bytes).unwrap()))
+			.collect();
+		assert_eq!(
+			formats,
+			[
+				(ImageEncoding::Png, image::ImageFormat::Png),
+				(ImageEncoding::Jpeg, image::ImageFormat::Jpeg),
+				(ImageEncoding::Bmp, image::ImageFormat::Bmp),
+			]
+		);
+
+		let empty = ImageData { width: 0, height: 0, bytes: Vec::new().into() };
+		assert!(matches!(encode_images(&empty, &encodings), Err(Error::ConversionFailure { .. })));
+		assert!(matches!(encode_images(&image, &[]), Err(Error::ConversionFailure { .. })));
+	}
+
+	#[test]
+	fn conversion_failure_names_format_and_stage() {
+		let error = Error::conversion(Format::Png, ConversionStage::Decode, "bad signature");
+		let detail = error.conversion_detail().unwrap();
+		assert_eq!((detail.format, detail.stage), (Format::Png, ConversionStage::Decode));
+		assert_eq!(detail.to_string(), "decoding PNG failed: bad signature");
+		assert!(error.to_string().ends_with(": decoding PNG failed: bad signature"));
+		assert!(Error::content_not_available().conversion_detail().is_none());
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn invalid_png_fails_to_decode_as_png() {
+		let error = image::codecs::png::PngDecoder::new(std::io::Cursor::new(b"not a png"))
+			.map_err(|error| from_image_error(error, Format::Png))
+			.and_then(|decoder| decode_image(decoder, Format::Png, DEFAULT_MAX_IMAGE_PIXELS))
+			.unwrap_err();
+		let detail = error.conversion_detail().unwrap();
+		assert_eq!((detail.format, detail.stage), (Format::Png, ConversionStage::Decode));
+		assert!(!detail.message.is_empty());
+	}
+
+	#[test]
+	#[cfg(feature = "image-data-png")]
+	fn strided_pixels_are_repacked() {
+		const PADDING: u8 = 0xAA;
+		// A 3x2 image whose rows are padded to 16 bytes, except for the last one.
+		let pixel = |x: u8, y: u8| [x, y, x + y, 255];
+		let mut framebuffer = Vec::new();
+		for y in 0..2 {
+			(0..3).for_each(|x| framebuffer.extend(pixel(x, y)));
+			if y == 0 {
+				framebuffer.extend([PADDING; 4]);
+			}
+		}
+		let tight: Vec<u8> = (0..2).flat_map(|y| (0..3).flat_map(move |x| pixel(x, y))).collect();
+
+		let image = ImageData::from_strided(&framebuffer, 3, 2, 16).unwrap();
+		assert_eq!((image.width, image.height), (3, 2));
+		assert_eq!(image.bytes, tight);
+		assert!(!image.bytes.contains(&PADDING));
+
+		let cropped = image.crop(1, 1, 2, 1).unwrap();
+		assert_eq!((cropped.width, cropped.height), (2, 1));
+		assert_eq!(cropped.bytes, [pixel(1, 1), pixel(2, 1)].concat());
+		let whole = image.crop(0, 0, 3, 2).unwrap();
+		assert_eq!(whole.bytes, tight);
+		let empty = image.crop(3, 2, 0, 0).unwrap();
+		assert!(empty.bytes.is_empty());
+
+		// Rows that don't fit into the stride, or don't all fit into the bytes.
+		let invalid = |result: Result<ImageData, Error>| {
+			let error = result.unwrap_err();
+			let detail = error.conversion_detail().unwrap();
+			assert_eq!((detail.format, detail.stage), (Format::Pixels, ConversionStage::Validate));
+		};
+		invalid(ImageData::from_strided(&framebuffer, 3, 2, 8));
+		invalid(ImageData::from_strided(&framebuffer, 3, 3, 16));
+		invalid(ImageData::from_strided(&framebuffer, usize::MAX, 1, usize::MAX));
+		// Regions reaching beyond the image.
+		invalid(image.crop(2, 0, 2, 1));
+		invalid(image.crop(0, 1, 1, 2));
+		invalid(image.crop(usize::MAX, 0, 2, 1));
+		let truncated = ImageData { width: 3, height: 2, bytes: tight[..20].into() };
+		invalid(truncated.crop(0, 0, 1, 1));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data-png")]
+	fn png_round_trip() {
+		let image =
+			ImageData { width: 2, height: 1, bytes: vec![255, 0, 0, 255, 0, 255, 0, 128].into() };
+		let png = encode_png(&image).unwrap();
+		let decoded = decode_png(&png, DEFAULT_MAX_IMAGE_PIXELS).unwrap();
+		assert_eq!((decoded.width, decoded.height), (2, 1));
+		assert_eq!(decoded.bytes, image.bytes);
+
+		assert!(matches!(decode_png(&png, 1), Err(Error::TooLarge)));
+		let error = decode_png(b"not a png", DEFAULT_MAX_IMAGE_PIXELS).unwrap_err();
+		let detail = error.conversion_detail().unwrap();
+		assert_eq!((detail.format, detail.stage), (Format::Png, ConversionStage::Decode));
+
+		let empty = ImageData { width: 0, height: 0, bytes: Vec::new().into() };
+		assert!(matches!(encode_png(&empty), Err(Error::ConversionFailure { .. })));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data-png")]
+	fn decode_png_converts_to_rgba() {
+		let encode = |color, depth, data: &[u8]| {
+			let mut png = Vec::new();
+			let mut encoder = png::Encoder::new(&mut png, 2, 1);
+			encoder.set_color(color);
+			encoder.set_depth(depth);
+			let mut writer = encoder.write_header().unwrap();
+			writer.write_image_data(data).unwrap();
+			writer.finish().unwrap();
+			png
+		};
+		let decode = |png: Vec<u8>| decode_png(&png, DEFAULT_MAX_IMAGE_PIXELS).unwrap().bytes;
+
+		let rgb = encode(png::ColorType::Rgb, png::BitDepth::Eight, &[1, 2, 3, 4, 5, 6]);
+		assert_eq!(decode(rgb), [1, 2, 3, 255, 4, 5, 6, 255].as_slice());
+		let gray = encode(png::ColorType::Grayscale, png::BitDepth::Sixteen, &[7, 0, 9, 0]);
+		assert_eq!(decode(gray), [7, 7, 7, 255, 9, 9, 9, 255].as_slice());
+		let gray_alpha =
+			encode(png::ColorType::GrayscaleAlpha, png::BitDepth::Eight, &[7, 8, 9, 10]);
+		assert_eq!(decode(gray_alpha), [7, 7, 7, 8, 9, 9, 9, 10].as_slice());
+	}
+
+	#[test]
+	#[cfg(feature = "text-preview")]
+	fn previews_keep_graphemes_whole() {
+		assert_eq!(preview_text("short", 5), "short");
+		assert_eq!(preview_text("longer", 5), "long\u{2026}");
+		assert_eq!(preview_text("longer", 0), "");
+		// A family emoji joined by ZWJs and an `e` with a combining acute accent are one
+		// grapheme each.
+		let text = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}e\u{301}\u{1F1E9}\u{1F1EA}!";
+		assert_eq!(preview_text(text, 4), text);
+		assert_eq!(
+			preview_text(text, 3),
+			"\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}e\u{301}\u{2026}"
+		);
+		assert_eq!(preview_text(text, 1), "\u{2026}");
+	}
+
+	#[test]
+	fn diagnostics_list_the_build_configuration() {
+		let mut diagnostics = Diagnostics::new();
+		assert_eq!(diagnostics.version, env!("CARGO_PKG_VERSION"));
+		assert_eq!(
+			diagnostics.features.iter().any(|feature| feature == "image-data-png"),
+			cfg!(feature = "image-data-png")
+		);
+
+		diagnostics.backend = "Test".into();
+		diagnostics.backend_reason = "it was asked for".into();
+		diagnostics.environment.push(("DISPLAY".into(), ":0".into()));
+		let report = diagnostics.to_string();
+		assert!(report.starts_with(&format!("arboard version: {}\n", diagnostics.version)));
+		assert!(report.contains("backend: Test (it was asked for)\n"));
+		assert!(report.contains("server: unknown\n"));
+		assert!(!report.contains("primary selection"));
+		assert!(report.ends_with("DISPLAY=:0\n"));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn decode_image_enforces_pixel_limit() {
+		use image::{codecs::png::PngDecoder, ImageEncoder};
+
+		let mut png = Vec::new();
+		image::codecs::png::PngEncoder::new(&mut png)
+			.write_image(&[0; 4 * 4 * 4], 4, 4, image::ExtendedColorType::Rgba8)
+			.unwrap();
+		let decode = |max_pixels| {
+			decode_image(
+				PngDecoder::new(std::io::Cursor::new(&png)).unwrap(),
+				Format::Png,
+				max_pixels,
+			)
+		};
+
+		assert!(matches!(decode(15), Err(Error::TooLarge)));
+		let image = decode(16).unwrap();
+		assert_eq!((image.width, image.height), (4, 4));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data-png")]
+	fn exotic_pngs_are_decoded() {
+		#[rustfmt::skip]
+		let fixtures: [(&[u8], &[u8]); 5] = [
+			(
+				include_bytes!("../fixtures/png/rgba16.png"),
+				&[255, 128, 0, 255,   1, 32, 254, 128],
+			),
+			(
+				include_bytes!("../fixtures/png/palette_trns.png"),
+				&[255, 0, 0, 255,   0, 255, 0, 0,   0, 0, 255, 128],
+			),
+			(
+				include_bytes!("../fixtures/png/gray16_trns.png"),
+				&[18, 18, 18, 0,   192, 192, 192, 255],
+			),
+			// Only the first frame of an animation is read, red here.
+			(
+				include_bytes!("../fixtures/png/apng.png"),
+				&[255, 0, 0, 255,   255, 0, 0, 255],
+			),
+			// The default image isn't part of this animation, and is still what's read.
+			(
+				include_bytes!("../fixtures/png/apng_separate_default.png"),
+				&[0, 255, 0, 255,   0, 255, 0, 255],
+			),
+		];
+		for (png, rgba) in fixtures {
+			let image = decode_png(png, DEFAULT_MAX_IMAGE_PIXELS).unwrap();
+			assert_eq!((image.width, image.height), (rgba.len() / 4, 1));
+			assert_eq!(image.bytes, rgba);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "image-data-png")]
+	fn undecodable_images_keep_their_bytes() {
+		let decode = |bytes: &[u8]| decode_png(bytes, DEFAULT_MAX_IMAGE_PIXELS);
+		let png = include_bytes!("../fixtures/png/rgba16.png").to_vec();
+		assert!(matches!(
+			ImageOrBytes::decode(png, decode, || unreachable!()),
+			ImageOrBytes::Image(image) if image.width == 2
+		));
+
+		let garbage = b"\x89PNG but not really".to_vec();
+		match ImageOrBytes::decode(garbage.clone(), decode, || "image/png".to_owned()) {
+			ImageOrBytes::Encoded { format, bytes, error } => {
+				assert_eq!((format.as_str(), bytes), ("image/png", garbage));
+				assert!(matches!(error, Error::ConversionFailure { .. }));
+			}
+			ImageOrBytes::Image(_) => panic!("garbage was decoded"),
+		}
+		let error = ImageOrBytes::decode(b"".to_vec(), decode, String::new).into_image();
+		assert!(matches!(error, Err(Error::ConversionFailure { .. })));
+	}
+}
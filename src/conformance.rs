@@ -0,0 +1,96 @@
+/*
+SPDX-License-Identifier: Apache-2.0 OR MIT
+
+Copyright 2022 The Arboard contributors
+
+The project to which this file belongs is licensed under either of
+the Apache 2.0 or the MIT license at the licensee's choice. The terms
+and conditions of the chosen license apply to this file.
+*/
+
+//! Text that has round-tripped on one platform but not on another, and what every platform is
+//! expected to do with it.
+//!
+//! Each case is placed and read back through every text path. Unless noted below, the text must
+//! come back exactly as it was placed, without any normalization, line ending conversion or
+//! trimming:
+//!
+//! | Content                   | X11  | Wayland | Windows              | macOS |
+//! |---------------------------|------|---------|----------------------|-------|
+//! | NUL                       | kept | kept    | cut off at the first | kept  |
+//! | Byte order mark at start  | kept | kept    | kept                 | kept  |
+//! | Everything else           | kept | kept    | kept                 | kept  |
+//!
+//! `CF_UNICODETEXT` ends at its first NUL, so every application reading the text on Windows
+//! sees it cut off there.
+
+use crate::{Clipboard, SetOutcome, TextSource};
+
+/// A named piece of text from the corpus.
+pub(crate) struct Case {
+	pub(crate) name: &'static str,
+	pub(crate) text: String,
+}
+
+pub(crate) fn corpus() -> Vec<Case> {
+	let cases = [
+		("combining marks without a base", "\u{301}a\u{301}\u{301}\u{20dd}".to_owned()),
+		("decomposed characters", "e\u{301}le\u{300}ve A\u{30a}".to_owned()),
+		("byte order mark at the start", "\u{feff}starts with a BOM".to_owned()),
+		("byte order mark inside", "a\u{feff}b\u{feff}".to_owned()),
+		("zero width joiners", "👩\u{200d}💻 👨\u{200d}👩\u{200d}👧 a\u{200d}\u{200c}b".to_owned()),
+		("bidi controls", "\u{202e}abc\u{202c} \u{2067}שלום\u{2069} \u{200f}x\u{200e}".to_owned()),
+		("line endings", "lf\ncrlf\r\ncr\rend\r\n\n".to_owned()),
+		("NUL inside", "before\0after".to_owned()),
+		("NUL at the end", "ends with a NUL\0".to_owned()),
+		("noncharacters", "\u{fffe}\u{ffff}\u{10ffff}".to_owned()),
+		// Longer than a single X11 request can carry, without a line break anywhere.
+		("line of more than 1 MiB", "0123456789abcdef€🐔".repeat(60_000)),
+	];
+	cases.into_iter().map(|(name, text)| Case { name, text }).collect()
+}
+
+/// What reading `text` back gives on this platform, after placing it.
+pub(crate) fn expected(text: &str) -> &str {
+	if cfg!(windows) {
+		text.split('\0').next().unwrap_or_default()
+	} else {
+		text
+	}
+}
+
+/// Places and reads every case of the corpus through every text path.
+pub(crate) fn check_text_round_trips(ctx: &mut Clipboard) {
+	for Case { name, text } in corpus() {
+		let expected = expected(&text);
+		ctx.set_text(text.as_str()).unwrap();
+		assert!(read(ctx, name) == expected, "{name}: `set_text` didn't round-trip");
+
+		ctx.set().text_bytes(text.clone().into_bytes()).unwrap();
+		assert!(read(ctx, name) == expected, "{name}: `text_bytes` didn't round-trip");
+
+		ctx.set().html("<p>exotic</p>", Some(text.as_str())).unwrap();
+		assert!(read(ctx, name) == expected, "{name}: the alt text didn't round-trip");
+
+		ctx.set_text(text.as_str()).unwrap();
+		let (stripped, source) = ctx.get().text_or_html_stripped().unwrap();
+		assert!(stripped == expected && source == TextSource::Text, "{name}: stripped text");
+		let (_, alt_text) = ctx.get().html_and_text().unwrap();
+		assert!(alt_text.as_deref() == Some(expected), "{name}: `html_and_text`");
+
+		for max_bytes in [0, 1, 3, expected.len() / 2, expected.len(), expected.len() + 1] {
+			let truncated = ctx.get().text_truncated(max_bytes).unwrap();
+			let wanted = crate::common::truncate_text(expected.to_owned(), max_bytes);
+			assert!(truncated == wanted, "{name}: `text_truncated({max_bytes})`");
+		}
+
+		// Text is compared with what is read back, so text that doesn't survive is placed again.
+		let outcome = ctx.set().if_changed().text(text.as_str()).unwrap();
+		let unchanged = if expected == text { SetOutcome::Unchanged } else { SetOutcome::Placed };
+		assert_eq!(outcome, unchanged, "{name}: `if_changed`");
+	}
+}
+
+fn read(ctx: &mut Clipboard, name: &str) -> String {
+	ctx.get_text().unwrap_or_else(|error| panic!("{name}: {error}"))
+}
@@ -0,0 +1,760 @@
+/*
+SPDX-License-Identifier: Apache-2.0 OR MIT
+
+Copyright 2022 The Arboard contributors
+
+The project to which this file belongs is licensed under either of
+the Apache 2.0 or the MIT license at the licensee's choice. The terms
+and conditions of the chosen license apply to this file.
+*/
+
+//! Parsers and serializers for the raw clipboard formats that `arboard` reads and writes.
+//!
+//! These are the same functions that the platform implementations use internally. They are
+//! exposed for code that receives raw clipboard payloads from elsewhere, like drag and drop
+//! handlers, and are available on every platform.
+
+use std::{
+	borrow::Cow,
+	path::{Path, PathBuf},
+};
+
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, CONTROLS};
+
+use crate::{ConversionStage, Error, FileOp, Format};
+
+/// Parses a `text/uri-list` (RFC 2483) into the local paths it contains.
+///
+/// Comment lines and URIs that don't use the `file` scheme are skipped. Both `\n` and `\r\n`
+/// line endings are accepted.
+pub fn parse_uri_list(uri_list: &str) -> Vec<PathBuf> {
+	uri_list
+		.lines()
+		.filter(|line| !line.starts_with('#'))
+		.filter_map(|line| line.strip_prefix("file://"))
+		.map(|path| path.strip_prefix("localhost").unwrap_or(path))
+		.filter_map(|path| percent_decode(path.as_bytes()).decode_utf8().ok())
+		.map(|decoded| path_from_uri_path(&decoded))
+		.collect()
+}
+
+/// Serializes `paths` into a `text/uri-list` with one `file://` URI per line.
+///
+/// Every line ends with `\r\n`, including the last one, as RFC 2483 requires and as GTK and Qt
+/// write them. File managers like Nautilus count the files by these lines. The paths are used as
+/// they are, so they should be absolute.
+pub fn build_uri_list(paths: &[impl AsRef<Path>]) -> String {
+	// The characters that require encoding, which includes £ and € but they can't be added to the set.
+	const ASCII_SET: &AsciiSet = &CONTROLS
+		.add(b'#')
+		.add(b';')
+		.add(b'?')
+		.add(b'[')
+		.add(b']')
+		.add(b' ')
+		.add(b'\"')
+		.add(b'%')
+		.add(b'<')
+		.add(b'>')
+		.add(b'\\')
+		.add(b'^')
+		.add(b'`')
+		.add(b'{')
+		.add(b'|')
+		.add(b'}');
+
+	paths
+		.iter()
+		.map(|path| {
+			format!("file://{}\r\n", percent_encode(&uri_path_bytes(path.as_ref()), ASCII_SET))
+		})
+		.collect()
+}
+
+#[cfg(unix)]
+fn uri_path_bytes(path: &Path) -> Cow<'_, [u8]> {
+	use std::os::unix::ffi::OsStrExt;
+
+	Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn uri_path_bytes(path: &Path) -> Cow<'_, [u8]> {
+	// `C:\Users` becomes `/C:/Users`, as in `file:///C:/Users`.
+	let path = path.to_string_lossy().replace('\\', "/");
+	let path = if path.starts_with('/') { path } else { format!("/{path}") };
+	Cow::Owned(path.into_bytes())
+}
+
+#[cfg(unix)]
+fn path_from_uri_path(path: &str) -> PathBuf {
+	PathBuf::from(path)
+}
+
+#[cfg(not(unix))]
+fn path_from_uri_path(path: &str) -> PathBuf {
+	// `/C:/Users` becomes `C:/Users`.
+	match path.strip_prefix('/') {
+		Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+		_ => PathBuf::from(path),
+	}
+}
+
+/// The size of the `DROPFILES` structure which starts every `CF_HDROP` payload.
+const DROPFILES_SIZE: usize = 20;
+
+/// Parses the `x-special/gnome-copied-files` format, which file managers on GNOME and several
+/// other desktops offer alongside `text/uri-list`: `copy` or `cut` on the first line, followed by
+/// the URIs of the files.
+///
+/// Returns `None` if the first line names neither operation.
+pub fn parse_gnome_copied_files(copied_files: &str) -> Option<(FileOp, Vec<PathBuf>)> {
+	let (op, uri_list) = copied_files.split_once('\n').unwrap_or((copied_files, ""));
+	let op = match op.trim_end_matches('\r') {
+		"copy" => FileOp::Copy,
+		"cut" => FileOp::Cut,
+		_ => return None,
+	};
+
+	Some((op, parse_uri_list(uri_list)))
+}
+
+/// Parses KDE's `application/x-kde-cutselection` marker, which is offered alongside
+/// `text/uri-list` and holds `1` if the files were cut.
+pub fn parse_kde_cut_selection(marker: &[u8]) -> FileOp {
+	match marker.first() {
+		Some(b'1') => FileOp::Cut,
+		_ => FileOp::Copy,
+	}
+}
+
+/// Parses the Windows `Preferred DropEffect` format, a `DROPEFFECT` bit mask which Explorer
+/// offers alongside `CF_HDROP`. Files are considered cut if the mask includes `DROPEFFECT_MOVE`.
+pub fn parse_preferred_drop_effect(drop_effect: &[u8]) -> FileOp {
+	const DROPEFFECT_MOVE: u32 = 2;
+
+	match drop_effect.get(..4) {
+		Some(&[a, b, c, d]) if u32::from_le_bytes([a, b, c, d]) & DROPEFFECT_MOVE != 0 => {
+			FileOp::Cut
+		}
+		_ => FileOp::Copy,
+	}
+}
+
+/// Parses a Windows `CF_HDROP` payload (a `DROPFILES` structure followed by the file names) into
+/// the paths it contains.
+///
+/// Malformed payloads yield the paths which could be read before the malformation. File names in
+/// the ANSI code page are interpreted as UTF-8, replacing invalid sequences, as the code page is
+/// only known on Windows.
+pub fn parse_hdrop(hdrop: &[u8]) -> Vec<PathBuf> {
+	parse_hdrop_with(hdrop, |name| Some(PathBuf::from(String::from_utf8_lossy(name).into_owned())))
+}
+
+/// Parses a `CF_HDROP` payload like [`parse_hdrop`], decoding the file names in the ANSI code
+/// page with `decode_ansi`. The names it can't decode are left out.
+pub(crate) fn parse_hdrop_with(
+	hdrop: &[u8],
+	decode_ansi: impl FnMut(&[u8]) -> Option<PathBuf>,
+) -> Vec<PathBuf> {
+	let Some(header) = hdrop.get(..DROPFILES_SIZE) else {
+		return Vec::new();
+	};
+	let offset = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+	let wide = header[16..20] != [0; 4];
+	let Some(names) = hdrop.get(offset..) else {
+		return Vec::new();
+	};
+
+	if wide {
+		let units: Vec<u16> =
+			names.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+		units
+			.split(|unit| *unit == 0)
+			.take_while(|name| !name.is_empty())
+			.map(path_from_wide)
+			.collect()
+	} else {
+		names
+			.split(|byte| *byte == 0)
+			.take_while(|name| !name.is_empty())
+			.filter_map(decode_ansi)
+			.collect()
+	}
+}
+
+/// Serializes `paths` into a Windows `CF_HDROP` payload with wide file names.
+pub fn build_hdrop(paths: &[impl AsRef<Path>]) -> Vec<u8> {
+	// | DROPFILES | FILENAME | NULL | ... | nth FILENAME | NULL | NULL |
+	let mut hdrop = Vec::with_capacity(DROPFILES_SIZE + 2);
+	// pFiles
+	hdrop.extend_from_slice(&(DROPFILES_SIZE as u32).to_le_bytes());
+	// pt.x, pt.y and fNC
+	hdrop.extend_from_slice(&[0; 12]);
+	// fWide
+	hdrop.extend_from_slice(&1u32.to_le_bytes());
+
+	for path in paths {
+		for unit in path_to_wide(path.as_ref()) {
+			hdrop.extend_from_slice(&unit.to_le_bytes());
+		}
+		hdrop.extend_from_slice(&[0, 0]);
+	}
+	hdrop.extend_from_slice(&[0, 0]);
+
+	hdrop
+}
+
+/// Parses a Windows `FileNameW` payload, the single NUL terminated UTF-16 path that some
+/// applications offer instead of `CF_HDROP`.
+///
+/// Returns `None` if the path is empty.
+pub fn parse_file_name_w(file_name: &[u8]) -> Option<PathBuf> {
+	let units: Vec<u16> = file_name
+		.chunks_exact(2)
+		.map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+		.take_while(|unit| *unit != 0)
+		.collect();
+	(!units.is_empty()).then(|| path_from_wide(&units))
+}
+
+/// Serializes `path` into a Windows `FileNameW` payload.
+pub fn build_file_name_w(path: impl AsRef<Path>) -> Vec<u8> {
+	let mut file_name: Vec<u8> =
+		path_to_wide(path.as_ref()).into_iter().flat_map(u16::to_le_bytes).collect();
+	file_name.extend_from_slice(&[0, 0]);
+	file_name
+}
+
+#[cfg(windows)]
+fn path_from_wide(wide: &[u16]) -> PathBuf {
+	use std::os::windows::ffi::OsStringExt;
+
+	std::ffi::OsString::from_wide(wide).into()
+}
+
+#[cfg(not(windows))]
+fn path_from_wide(wide: &[u16]) -> PathBuf {
+	String::from_utf16_lossy(wide).into()
+}
+
+#[cfg(windows)]
+fn path_to_wide(path: &Path) -> Vec<u16> {
+	use std::os::windows::ffi::OsStrExt;
+
+	path.as_os_str().encode_wide().collect()
+}
+
+#[cfg(not(windows))]
+fn path_to_wide(path: &Path) -> Vec<u16> {
+	path.to_string_lossy().encode_utf16().collect()
+}
+
+/// The contents of a Windows `HTML Format` (CF_HTML) payload, as returned by [`parse_cf_html`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CfHtml {
+	/// The complete HTML document surrounding the fragment.
+	///
+	/// This is the same as `fragment` if the payload doesn't describe the document.
+	pub html: String,
+
+	/// The part of the document that was actually copied.
+	pub fragment: String,
+
+	/// The URL of the document the fragment was copied from, if it was provided.
+	pub source_url: Option<String>,
+}
+
+/// Parses a Windows `HTML Format` payload, which is HTML preceded by a header of byte offsets.
+///
+/// # Errors
+///
+/// Returns [`Error::ConversionFailure`] if the header doesn't locate valid UTF-8 HTML.
+pub fn parse_cf_html(cf_html: &[u8]) -> Result<CfHtml, Error> {
+	let mut start_html = None;
+	let mut end_html = None;
+	let mut start_fragment = None;
+	let mut end_fragment = None;
+	let mut source_url = None;
+
+	for line in cf_html.split(|byte| *byte == b'\n') {
+		let line = String::from_utf8_lossy(line);
+		let line = line.trim_end_matches('\r');
+		// The header ends where the HTML starts.
+		let Some((key, value)) = line.split_once(':').filter(|_| !line.starts_with('<')) else {
+			break;
+		};
+
+		// Offsets of `-1` mark an absent part.
+		let offset = || value.trim().parse::<usize>().ok();
+		match key {
+			"StartHTML" => start_html = offset(),
+			"EndHTML" => end_html = offset(),
+			"StartFragment" => start_fragment = offset(),
+			"EndFragment" => end_fragment = offset(),
+			"SourceURL" => source_url = Some(value.to_owned()),
+			_ => {}
+		}
+	}
+
+	let slice = |start, end| match (start, end) {
+		(Some(start), Some(end)) => cf_html
+			.get(start..end)
+			.ok_or_else(|| cf_html_error("an offset is out of bounds"))
+			.and_then(|part| {
+				String::from_utf8(part.to_vec()).map_err(|error| cf_html_error(error.to_string()))
+			})
+			.map(Some),
+		_ => Ok(None),
+	};
+
+	let html = slice(start_html, end_html)?;
+	let fragment = slice(start_fragment, end_fragment)?;
+
+	match (html, fragment) {
+		(Some(html), Some(fragment)) => Ok(CfHtml { html, fragment, source_url }),
+		(None, Some(part)) | (Some(part), None) => {
+			Ok(CfHtml { html: part.clone(), fragment: part, source_url })
+		}
+		(None, None) => Err(cf_html_error("the header has no offsets")),
+	}
+}
+
+fn cf_html_error(message: impl Into<String>) -> Error {
+	Error::conversion(Format::Html, ConversionStage::Decode, message)
+}
+
+/// How many bytes [`build_cf_html`] adds to the fragment, so that the size of the payload is
+/// known before building it.
+#[cfg(windows)]
+pub(crate) const CF_HTML_OVERHEAD: usize = 181;
+
+/// Serializes the HTML `fragment` into a Windows `HTML Format` payload, wrapping it into a
+/// minimal document.
+pub fn build_cf_html(fragment: &str) -> Vec<u8> {
+	let h_version = "Version:0.9";
+	let h_start_html = "\r\nStartHTML:";
+	let h_end_html = "\r\nEndHTML:";
+	let h_start_frag = "\r\nStartFragment:";
+	let h_end_frag = "\r\nEndFragment:";
+	let c_start_frag = "\r\n<html>\r\n<body>\r\n<!--StartFragment-->\r\n";
+	let c_end_frag = "\r\n<!--EndFragment-->\r\n</body>\r\n</html>";
+	let h_len = h_version.len()
+		+ h_start_html.len()
+		+ 10 + h_end_html.len()
+		+ 10 + h_start_frag.len()
+		+ 10 + h_end_frag.len()
+		+ 10;
+	let n_start_html = h_len + 2;
+	let n_start_frag = h_len + c_start_frag.len();
+	let n_end_frag = n_start_frag + fragment.len();
+	let n_end_html = n_end_frag + c_end_frag.len();
+	format!(
+		"{h_version}{h_start_html}{n_start_html:010}{h_end_html}{n_end_html:010}{h_start_frag}{n_start_frag:010}{h_end_frag}{n_end_frag:010}{c_start_frag}{fragment}{c_end_frag}"
+	)
+	.into_bytes()
+}
+
+/// Guesses the MIME type of `bytes` of an unknown format, see [`Set::auto`](crate::Set::auto).
+///
+/// PNG, JPEG and GIF images are recognized by their signatures when the `image-data` feature is
+/// enabled. Otherwise, valid UTF-8 is considered `text/plain` and anything else
+/// `application/octet-stream`.
+pub fn sniff_mime(bytes: &[u8]) -> &'static str {
+	#[cfg(feature = "image-data")]
+	match image::guess_format(bytes) {
+		Ok(image::ImageFormat::Png) => return "image/png",
+		Ok(image::ImageFormat::Jpeg) => return "image/jpeg",
+		Ok(image::ImageFormat::Gif) => return "image/gif",
+		_ => {}
+	}
+
+	if std::str::from_utf8(bytes).is_ok() {
+		"text/plain"
+	} else {
+		"application/octet-stream"
+	}
+}
+
+/// Converts `html` into plain text, roughly like a browser does when its contents are copied as
+/// text.
+///
+/// This is a simple approximation rather than a full HTML parser. Tags and comments are removed,
+/// along with the contents of `script`, `style` and `title` elements. Runs of whitespace are collapsed
+/// outside of `pre` elements, `br` and block elements like `p`, `div` or `li` start new lines,
+/// and character references like `&amp;` or `&#8364;` are decoded.
+pub fn html_to_text(html: &str) -> String {
+	let mut text = HtmlText::default();
+	let mut rest = html;
+	while let Some(start) = rest.find(['<', '&']) {
+		text.push_str(&rest[..start]);
+		rest = &rest[start..];
+		if rest.starts_with('&') {
+			match decode_character_reference(rest) {
+				Some((c, len)) => {
+					text.push_str(c.encode_utf8(&mut [0; 4]));
+					rest = &rest[len..];
+				}
+				None => {
+					text.push_str("&");
+					rest = &rest[1..];
+				}
+			}
+		} else {
+			rest = text.skip_markup(rest);
+		}
+	}
+	text.push_str(rest);
+
+	let text = text.out.replace('\u{a0}', " ");
+	text.trim_end().to_owned()
+}
+
+/// The text extracted so far by `html_to_text`.
+#[derive(Default)]
+struct HtmlText {
+	out: String,
+	/// How many `pre` elements the text is in, where whitespace is kept as it is.
+	pre_depth: usize,
+	/// Whether whitespace was skipped since the last character, which becomes a single space
+	/// unless a line ends there.
+	pending_space: bool,
+}
+
+impl HtmlText {
+	fn push_str(&mut self, text: &str) {
+		for c in text.chars() {
+			if self.pre_depth > 0 {
+				self.out.push(c);
+			} else if c.is_ascii_whitespace() {
+				self.pending_space = true;
+				continue;
+			} else {
+				let at_line_start = self.out.is_empty() || self.out.ends_with('\n');
+				if self.pending_space && !at_line_start {
+					self.out.push(' ');
+				}
+				self.out.push(c);
+			}
+			self.pending_space = false;
+		}
+	}
+
+	/// Ends the current line, unless the text is at the start of one already.
+	fn end_line(&mut self) {
+		if !self.out.is_empty() && !self.out.ends_with('\n') {
+			self.out.push('\n');
+		}
+		self.pending_space = false;
+	}
+
+	/// Handles the tag or comment at the start of `markup`, returning what follows it.
+	fn skip_markup<'a>(&mut self, markup: &'a str) -> &'a str {
+		if let Some(comment) = markup.strip_prefix("<!--") {
+			return comment.find("-->").map_or("", |end| &comment[end + 3..]);
+		}
+
+		let (closing, tag) = match markup[1..].strip_prefix('/') {
+			Some(tag) => (true, tag),
+			None => (false, &markup[1..]),
+		};
+		let name_len = tag.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(tag.len());
+		if name_len == 0 && !tag.starts_with(['!', '?']) {
+			// Not a tag, like in `a < b`.
+			self.push_str("<");
+			return &markup[1..];
+		}
+		let name = tag[..name_len].to_ascii_lowercase();
+
+		// Attribute values may contain `>` as long as they're quoted.
+		let mut quote = None;
+		let Some(end) = tag.find(|c| match quote {
+			Some(q) if c == q => {
+				quote = None;
+				false
+			}
+			Some(_) => false,
+			None if c == '"' || c == '\'' => {
+				quote = Some(c);
+				false
+			}
+			None => c == '>',
+		}) else {
+			return "";
+		};
+		let rest = &tag[end + 1..];
+
+		match name.as_str() {
+			"script" | "style" | "title" if !closing => {
+				// Their contents aren't text, and end with the first matching closing tag.
+				let closing_tag = format!("</{name}");
+				let end = rest.to_ascii_lowercase().find(&closing_tag);
+				return end.map_or("", |end| self.skip_markup(&rest[end..]));
+			}
+			"pre" if closing => self.pre_depth = self.pre_depth.saturating_sub(1),
+			"pre" => {
+				self.end_line();
+				self.pre_depth += 1;
+			}
+			"br" => {
+				self.out.push('\n');
+				self.pending_space = false;
+			}
+			"p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+				// Paragraphs and headings are separated by an empty line.
+				self.end_line();
+				if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+					self.out.push('\n');
+				}
+			}
+			"div" | "li" | "ul" | "ol" | "tr" | "table" | "blockquote" | "section" | "article"
+			| "header" | "footer" | "hr" | "dt" | "dd" => self.end_line(),
+			"td" | "th" if !closing => {
+				if !self.out.is_empty() && !self.out.ends_with('\n') {
+					self.out.push('\t');
+				}
+				self.pending_space = false;
+			}
+			_ => {}
+		}
+		rest
+	}
+}
+
+/// Decodes the character reference at the start of `text`, returning the character and the
+/// length of the reference.
+fn decode_character_reference(text: &str) -> Option<(char, usize)> {
+	let end = text.bytes().take(32).position(|b| b == b';')?;
+	let name = &text[1..end];
+
+	let c = if let Some(number) = name.strip_prefix('#') {
+		let code = match number.strip_prefix(['x', 'X']) {
+			Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+			None => number.parse().ok()?,
+		};
+		char::from_u32(code).filter(|&c| c != '\0')?
+	} else {
+		match name {
+			"amp" => '&',
+			"lt" => '<',
+			"gt" => '>',
+			"quot" => '"',
+			"apos" => '\'',
+			"nbsp" => '\u{a0}',
+			"copy" => '©',
+			"reg" => '®',
+			"trade" => '™',
+			"euro" => '€',
+			"hellip" => '…',
+			"ndash" => '–',
+			"mdash" => '—',
+			"lsquo" => '‘',
+			"rsquo" => '’',
+			"ldquo" => '“',
+			"rdquo" => '”',
+			"laquo" => '«',
+			"raquo" => '»',
+			"bull" => '•',
+			"middot" => '·',
+			_ => return None,
+		}
+	};
+	Some((c, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn uri_list_round_trip() {
+		let paths = [PathBuf::from("/tmp/white space.txt"), PathBuf::from("/tmp/€uro#1?.png")];
+
+		let uri_list = build_uri_list(&paths);
+		assert_eq!(
+			uri_list,
+			"file:///tmp/white%20space.txt\r\nfile:///tmp/%E2%82%ACuro%231%3F.png\r\n"
+		);
+		assert_eq!(parse_uri_list(&uri_list), paths);
+	}
+
+	#[test]
+	fn uri_list_layout() {
+		// The exact bytes GTK, and with it Nautilus, writes: one CRLF terminated line per file.
+		let paths = [PathBuf::from("/home/a.txt"), PathBuf::from("/home/b.txt")];
+		assert_eq!(
+			build_uri_list(&paths).as_bytes(),
+			b"file:///home/a.txt\r\nfile:///home/b.txt\r\n"
+		);
+		assert_eq!(build_uri_list(&paths[..1]), "file:///home/a.txt\r\n");
+
+		// The trailing line ending doesn't count as another file.
+		for count in 0..=paths.len() {
+			assert_eq!(parse_uri_list(&build_uri_list(&paths[..count])).len(), count);
+		}
+	}
+
+	#[test]
+	fn uri_list_skips_comments_and_foreign_uris() {
+		let uri_list = "# a comment\r\nfile:///tmp/a.txt\r\nhttps://example.com\r\nfile://localhost/tmp/b.txt\r\n";
+		assert_eq!(
+			parse_uri_list(uri_list),
+			[PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.txt")]
+		);
+	}
+
+	#[test]
+	fn gnome_copied_files() {
+		let (op, paths) =
+			parse_gnome_copied_files("cut\nfile:///tmp/a.txt\nfile:///tmp/b%20c.txt").unwrap();
+		assert_eq!(op, FileOp::Cut);
+		assert_eq!(paths, [PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b c.txt")]);
+
+		let (op, paths) = parse_gnome_copied_files("copy\r\nfile:///tmp/a.txt\r\n").unwrap();
+		assert_eq!(op, FileOp::Copy);
+		assert_eq!(paths, [PathBuf::from("/tmp/a.txt")]);
+
+		assert_eq!(parse_gnome_copied_files("file:///tmp/a.txt"), None);
+	}
+
+	#[test]
+	fn cut_markers() {
+		assert_eq!(parse_kde_cut_selection(b"1"), FileOp::Cut);
+		assert_eq!(parse_kde_cut_selection(b"0"), FileOp::Copy);
+		assert_eq!(parse_kde_cut_selection(b""), FileOp::Copy);
+
+		assert_eq!(parse_preferred_drop_effect(&2u32.to_le_bytes()), FileOp::Cut);
+		assert_eq!(parse_preferred_drop_effect(&5u32.to_le_bytes()), FileOp::Copy);
+		assert_eq!(parse_preferred_drop_effect(&[2]), FileOp::Copy);
+	}
+
+	#[test]
+	fn hdrop_round_trip() {
+		let paths = [PathBuf::from("C:\\Users\\Grüße.txt"), PathBuf::from("D:\\🦀")];
+
+		let hdrop = build_hdrop(&paths);
+		assert_eq!(&hdrop[..4], &[20, 0, 0, 0]);
+		assert_eq!(&hdrop[hdrop.len() - 4..], &[0; 4]);
+		assert_eq!(parse_hdrop(&hdrop), paths);
+	}
+
+	#[test]
+	fn hdrop_ansi_and_malformed() {
+		let mut hdrop = vec![20, 0, 0, 0];
+		hdrop.extend_from_slice(&[0; 16]);
+		hdrop.extend_from_slice(b"C:\\a.txt\0C:\\b.txt\0\0");
+		assert_eq!(parse_hdrop(&hdrop), [PathBuf::from("C:\\a.txt"), PathBuf::from("C:\\b.txt")]);
+
+		// Names in a code page other than UTF-8 are decoded by the caller, which may skip them.
+		let mut latin1 = hdrop[..20].to_vec();
+		latin1.extend_from_slice(b"C:\\Gr\xfc\xdfe.txt\0C:\\skipped\0\0");
+		let decode = |name: &[u8]| {
+			let name: String = name.iter().map(|&byte| char::from(byte)).collect();
+			(!name.ends_with("skipped")).then(|| PathBuf::from(name))
+		};
+		assert_eq!(parse_hdrop_with(&latin1, decode), [PathBuf::from("C:\\Grüße.txt")]);
+
+		assert!(parse_hdrop(&hdrop[..10]).is_empty());
+		assert!(
+			parse_hdrop(&[200, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_empty()
+		);
+	}
+
+	#[test]
+	fn file_name_w_round_trip() {
+		let path = PathBuf::from("C:\\Users\\Grüße 🦀.txt");
+
+		let file_name = build_file_name_w(&path);
+		assert_eq!(&file_name[file_name.len() - 2..], &[0, 0]);
+		assert_eq!(parse_file_name_w(&file_name), Some(path));
+		// Anything after the terminator is ignored.
+		assert_eq!(parse_file_name_w(b"C\0:\0\0\0x\0"), Some(PathBuf::from("C:")));
+		assert_eq!(parse_file_name_w(&[0, 0]), None);
+		assert_eq!(parse_file_name_w(&[]), None);
+	}
+
+	#[test]
+	fn cf_html_round_trip() {
+		let fragment = "<b>Grüße</b>";
+
+		let parsed = parse_cf_html(&build_cf_html(fragment)).unwrap();
+		assert_eq!(parsed.fragment, fragment);
+		assert!(parsed.html.starts_with("<html>"));
+		assert!(parsed.html.ends_with("</html>"));
+		assert!(parsed.html.contains(fragment));
+		assert_eq!(parsed.source_url, None);
+		#[cfg(windows)]
+		assert_eq!(build_cf_html(fragment).len(), fragment.len() + CF_HTML_OVERHEAD);
+	}
+
+	#[test]
+	fn cf_html_from_browser() {
+		let html = "<html><body><!--StartFragment--><i>hi</i><!--EndFragment--></body></html>";
+		let header_len = 137;
+		let start_fragment = header_len + html.find("<i>").unwrap();
+		let end_fragment = header_len + html.find("<!--EndFragment").unwrap();
+		let cf_html = format!(
+			"Version:0.9\r\nStartHTML:{header_len:010}\r\nEndHTML:{:010}\r\nStartFragment:{start_fragment:010}\r\nEndFragment:{end_fragment:010}\r\nSourceURL:https://example.com/\r\n{html}",
+			header_len + html.len(),
+		);
+		assert_eq!(cf_html.find("<html>"), Some(header_len));
+
+		let parsed = parse_cf_html(cf_html.as_bytes()).unwrap();
+		assert_eq!(parsed.html, html);
+		assert_eq!(parsed.fragment, "<i>hi</i>");
+		assert_eq!(parsed.source_url.as_deref(), Some("https://example.com/"));
+	}
+
+	#[test]
+	fn html_to_text_structure() {
+		let html = "<html><head><title>Ignored</title><style>p { color: red; }</style></head>\
+			<body><!--StartFragment--><h1>Title</h1><p>Some   <b>bold</b>\n text.</p>\
+			<ul><li>one</li><li>two</li></ul>line<br>break<script>alert('<p>')</script>\
+			<table><tr><td>a</td><td>b</td></tr></table><!--EndFragment--></body></html>";
+		assert_eq!(html_to_text(html), "Title\n\nSome bold text.\n\none\ntwo\nline\nbreak\na\tb");
+	}
+
+	#[test]
+	fn html_to_text_preformatted() {
+		let html = "<p>code:</p><pre>fn main() {\n    println!();\n}</pre>after";
+		assert_eq!(html_to_text(html), "code:\n\nfn main() {\n    println!();\n}after");
+	}
+
+	#[test]
+	fn html_to_text_character_references() {
+		let html =
+			"<a href=\"?a=1&amp;b=2\" title='x > y'>Fish &amp; chips &#8364;5&nbsp;&#x1F980;</a>";
+		assert_eq!(html_to_text(html), "Fish & chips €5 🦀");
+		// Things that merely look like markup are kept.
+		assert_eq!(html_to_text("a < b && c &unknown; &#xZZ;"), "a < b && c &unknown; &#xZZ;");
+		assert_eq!(html_to_text("unterminated <b"), "unterminated");
+	}
+
+	#[test]
+	fn cf_html_rejects_bad_offsets() {
+		assert!(matches!(parse_cf_html(b"<b>no header</b>"), Err(Error::ConversionFailure { .. })));
+		assert!(matches!(
+			parse_cf_html(b"Version:0.9\r\nStartFragment:0000000040\r\nEndFragment:0000009999\r\n"),
+			Err(Error::ConversionFailure { .. })
+		));
+	}
+
+	#[test]
+	fn sniff_mime_of_unknown_data() {
+		assert_eq!(sniff_mime("plain text, even with ümlauts".as_bytes()), "text/plain");
+		assert_eq!(sniff_mime(b""), "text/plain");
+		assert_eq!(sniff_mime(&[0xff, 0x00, 0x81]), "application/octet-stream");
+
+		let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+		let gif = b"GIF89a\x01\0\x01\0";
+		if cfg!(feature = "image-data") {
+			assert_eq!(sniff_mime(png), "image/png");
+			assert_eq!(sniff_mime(&[0xff, 0xd8, 0xff, 0xe0]), "image/jpeg");
+			assert_eq!(sniff_mime(gif), "image/gif");
+		} else {
+			assert_eq!(sniff_mime(png), "application/octet-stream");
+			// Without image support, the GIF signature is just ASCII text.
+			assert_eq!(sniff_mime(gif), "text/plain");
+		}
+	}
+}
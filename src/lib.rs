@@ -10,14 +10,24 @@ and conditions of the chosen license apply to this file.
 #![warn(unreachable_pub)]
 
 mod common;
+#[cfg(test)]
+mod conformance;
+pub mod formats;
 use std::{
-	borrow::Cow,
+	io::Write,
 	path::{Path, PathBuf},
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
 };
 
-pub use common::Error;
 #[cfg(feature = "image-data")]
-pub use common::ImageData;
+pub use common::ImageEncoding;
+pub use common::{
+	ClipboardText, ConversionDetail, ConversionStage, Diagnostics, Error, FileEntry, FileOp,
+	Format, PlacedFormat, SetOutcome, SetReport, TextSource,
+};
+#[cfg(feature = "image-data-png")]
+pub use common::{ImageData, ImageOrBytes};
 
 mod platform;
 
@@ -25,13 +35,23 @@ mod platform;
 	unix,
 	not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
 ))]
-pub use platform::{ClearExtLinux, GetExtLinux, LinuxClipboardKind, SetExtLinux};
+pub use platform::{
+	serve, Capture, ClearExtLinux, ClipboardExtLinux, GetExtLinux, LinuxClipboardKind, ReadStats,
+	RequestAction, SelectionTexts, ServeOutcome, SetExtLinux, TimeoutConfig, X11Quirks,
+};
+
+#[cfg(all(
+	feature = "x11-interop",
+	unix,
+	not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+))]
+pub use platform::X11Atom;
 
 #[cfg(windows)]
-pub use platform::SetExtWindows;
+pub use platform::{GetExtWindows, SetExtWindows, WindowsFormat};
 
 #[cfg(target_os = "macos")]
-pub use platform::SetExtApple;
+pub use platform::{GetExtApple, PasteboardItem, SetExtApple};
 
 /// The OS independent struct for accessing the clipboard.
 ///
@@ -58,6 +78,14 @@ pub use platform::SetExtApple;
 /// that last put data onto it. This means that when the last `Clipboard` instance is dropped, the contents
 /// may become unavailable to other apps. See [SetExtLinux] for more details.
 ///
+/// On X11, reading a selection that any `Clipboard` in the process owns returns what was placed
+/// last straight from memory, without asking the X server to convert it.
+///
+/// All `Clipboard`s of a process share one connection and the data of each selection, so they
+/// may set, wait on and clear different selections from several threads at once. A set which
+/// [`wait`](SetExtLinux::wait)s only returns once its own selection is replaced or cleared,
+/// including by another `Clipboard` of the same process, and never because of another selection.
+///
 /// ## Windows
 ///
 /// The clipboard on Windows is a global object, which may only be opened on one thread at once.
@@ -82,6 +110,88 @@ impl Clipboard {
 		Ok(Clipboard { platform: platform::Clipboard::new()? })
 	}
 
+	/// Creates an instance of the clipboard which waits for other applications as long as
+	/// `timeouts` describes, rather than the defaults.
+	///
+	/// All `Clipboard`s in a process share a single X11 connection, and these timeouts apply to
+	/// all of them, the last ones configured taking precedence. On Wayland, they are ignored.
+	///
+	/// # Errors
+	///
+	/// Same as [`Clipboard::new`].
+	#[cfg(all(
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	pub fn new_with_timeouts(timeouts: TimeoutConfig) -> Result<Self, Error> {
+		Ok(Clipboard { platform: platform::Clipboard::new_with_timeouts(timeouts)? })
+	}
+
+	/// Creates an instance of the clipboard which doesn't share its X11 connection with any other
+	/// `Clipboard`.
+	///
+	/// All `Clipboard`s made with [`Clipboard::new`] use a single connection and a single thread
+	/// serving the contents to other applications, which are shared by the whole process. This
+	/// one opens a connection and starts a thread of its own instead, so that its timeouts,
+	/// server restarts and handover to the clipboard manager when it's dropped don't affect any
+	/// other `Clipboard`, which helps to keep tests in one binary apart. Only the handles made
+	/// from it with [`ClipboardExtLinux::clone_handle`] share its connection. The contents still
+	/// go to the same selections of the same X server, though.
+	///
+	/// Each isolated clipboard costs a connection to the X server and a thread, so prefer
+	/// [`Clipboard::new`] unless the isolation is needed. On Wayland, every `Clipboard` is
+	/// independent already, and this is the same as [`Clipboard::new`].
+	///
+	/// # Errors
+	///
+	/// Same as [`Clipboard::new`].
+	#[cfg(all(
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	pub fn new_isolated() -> Result<Self, Error> {
+		Ok(Clipboard { platform: platform::Clipboard::new_isolated()? })
+	}
+
+	/// Places the text onto the clipboard and keeps it available for up to `ttl`, even after the
+	/// process exited, without requiring the caller to keep a `Clipboard` or a thread around.
+	///
+	/// This is meant for short-lived programs, like CLI tools, which want to copy something and
+	/// move on. The text is on the clipboard once this returns.
+	///
+	/// # Platform-specific behavior
+	///
+	/// On Linux, a detached helper process is forked, which serves the text until it's replaced
+	/// or `ttl` has passed and then exits. On X11, the text is handed over to the clipboard
+	/// manager at that point if one is running. Like the one in the [daemonize example], the
+	/// helper keeps a copy of the memory of the process, so this is best called from programs
+	/// which aren't holding on to a lot of it.
+	///
+	/// On Windows and macOS, the contents outlive the process by themselves, so `ttl` is ignored.
+	///
+	/// # Errors
+	///
+	/// Returns error if the clipboard couldn't be opened, the helper process couldn't be started
+	/// or the text couldn't be set.
+	///
+	/// [daemonize example]: https://github.com/1Password/arboard/blob/master/examples/daemonize.rs
+	pub fn set_and_forget<'a>(text: impl ClipboardText<'a>, ttl: Duration) -> Result<(), Error> {
+		#[cfg(all(
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+		))]
+		return platform::set_and_forget(text.into_cow().into_owned(), ttl);
+
+		#[cfg(not(all(
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+		)))]
+		{
+			let _ = ttl;
+			Clipboard::new()?.set_text(text)
+		}
+	}
+
 	/// Fetches UTF-8 text from the clipboard and returns it.
 	///
 	/// # Errors
@@ -91,15 +201,60 @@ impl Clipboard {
 		self.get().text()
 	}
 
+	/// Fetches UTF-8 text from the clipboard, shortened to at most `max_graphemes` grapheme
+	/// clusters like [`Get::text_preview`] does.
+	///
+	/// Use `get().clipboard(selection).text_preview(max_graphemes)` to read another selection on
+	/// Linux.
+	///
+	/// # Errors
+	///
+	/// Returns error if clipboard is empty or contents are not UTF-8 text.
+	#[cfg(feature = "text-preview")]
+	pub fn get_text_preview(&mut self, max_graphemes: usize) -> Result<String, Error> {
+		self.get().text_preview(max_graphemes)
+	}
+
+	/// Fetches UTF-8 text from the clipboard like [`get_text`](Clipboard::get_text), but gives up
+	/// once `timeout` passed.
+	///
+	/// This is a shorthand for a read with a [`Get::deadline`]. Only Linux waits for the
+	/// application which placed the contents, so the timeout has no effect elsewhere.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::Timeout`] if the text wasn't read in time, otherwise the same errors as
+	/// `get_text`.
+	pub fn get_text_timeout(&mut self, timeout: Duration) -> Result<String, Error> {
+		self.get().deadline(Instant::now() + timeout).text()
+	}
+
 	/// Places the text onto the clipboard. Any valid UTF-8 string is accepted.
 	///
 	/// # Errors
 	///
 	/// Returns error if `text` failed to be stored on the clipboard.
-	pub fn set_text<'a, T: Into<Cow<'a, str>>>(&mut self, text: T) -> Result<(), Error> {
+	pub fn set_text<'a, T: ClipboardText<'a>>(&mut self, text: T) -> Result<(), Error> {
 		self.set().text(text)
 	}
 
+	/// Places `new` onto the clipboard if it holds the `expected` text, or no text at all if
+	/// `expected` is `None`, returning whether it did.
+	///
+	/// This is a shorthand for [`Set::text_if_current`], which also lets the selection be chosen on
+	/// Linux.
+	///
+	/// # Errors
+	///
+	/// Returns error if the clipboard couldn't be read, or `new` failed to be stored on it.
+	pub fn compare_and_set_text<'a, T: ClipboardText<'a>>(
+		&mut self,
+		expected: Option<&str>,
+		new: T,
+	) -> Result<bool, Error> {
+		self.set().text_if_current(expected, new)
+	}
+
 	/// Places the HTML as well as a plain-text alternative onto the clipboard.
 	///
 	/// Any valid UTF-8 string is accepted.
@@ -107,7 +262,7 @@ impl Clipboard {
 	/// # Errors
 	///
 	/// Returns error if both `html` and `alt_text` failed to be stored on the clipboard.
-	pub fn set_html<'a, T: Into<Cow<'a, str>>>(
+	pub fn set_html<'a, T: ClipboardText<'a>>(
 		&mut self,
 		html: T,
 		alt_text: Option<T>,
@@ -125,7 +280,7 @@ impl Clipboard {
 	///
 	/// Returns error if clipboard is empty, contents are not an image, or the contents cannot be
 	/// converted to an appropriate format and stored in the [`ImageData`] type.
-	#[cfg(feature = "image-data")]
+	#[cfg(feature = "image-data-png")]
 	pub fn get_image(&mut self) -> Result<ImageData<'static>, Error> {
 		self.get().image()
 	}
@@ -142,11 +297,53 @@ impl Clipboard {
 	///
 	/// Returns error if `image` cannot be converted to an appropriate format or if it failed to be
 	/// stored on the clipboard.
-	#[cfg(feature = "image-data")]
+	#[cfg(feature = "image-data-png")]
 	pub fn set_image(&mut self, image: ImageData) -> Result<(), Error> {
 		self.set().image(image)
 	}
 
+	/// Sets the targets that text is read from, in order of preference, for all later reads
+	/// through this `Clipboard`, instead of the built-in ones.
+	///
+	/// The targets are X11 targets or Wayland MIME types, like `text/plain;charset=utf-8`. The
+	/// first of them that the contents are offered in is read, and has to be UTF-8 text, except
+	/// for the X11 `STRING` target, which is read as Latin-1. An empty list restores the built-in
+	/// one.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// clipboard.set_text_targets(&["text/x-moz-url", "UTF8_STRING"]);
+	/// let text = clipboard.get_text()?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	#[cfg(all(
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	pub fn set_text_targets(&mut self, targets: &[&str]) {
+		self.platform.read_targets().text = owned_targets(targets);
+	}
+
+	/// Sets the targets that images are read from, in order of preference, for all later reads
+	/// through this `Clipboard`, instead of the built-in ones.
+	///
+	/// The targets are X11 targets or Wayland MIME types, like `image/webp`. The first of them that
+	/// the contents are offered in is read. Formats other than PNG are only decoded with the
+	/// `image-data` feature. An empty list restores the built-in one.
+	#[cfg(all(
+		feature = "image-data-png",
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	pub fn set_image_targets(&mut self, targets: &[&str]) {
+		self.platform.read_targets().image = owned_targets(targets);
+	}
+
 	/// Clears any contents that may be present from the platform's default clipboard,
 	/// regardless of the format of the data.
 	///
@@ -157,11 +354,146 @@ impl Clipboard {
 		self.clear_with().default()
 	}
 
+	/// Clears the platform's default clipboard, but only if it still holds the contents that were
+	/// placed through this crate, returning whether it was cleared.
+	///
+	/// This is meant for removing sensitive contents after a while, without wiping out whatever
+	/// the user copied in the meantime.
+	///
+	/// # Platform-specific behavior
+	///
+	/// On X11, the contents are ours if this process owns the selection, whichever `Clipboard`
+	/// placed them. On Windows and macOS, they are ours if they haven't changed since they were
+	/// last placed through this `Clipboard`. On Wayland, they are ours if they are offered in the
+	/// same formats and with the same text as they were last placed through this `Clipboard`.
+	///
+	/// Except on Windows, where nobody else can change the contents while they are checked,
+	/// contents placed by another application in the short moment between the check and the
+	/// clear are cleared as well.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the clipboard cannot be opened, its owner cannot be queried, or it
+	/// cannot be cleared.
+	pub fn clear_if_owner(&mut self) -> Result<bool, Error> {
+		platform::Clear::new(&mut self.platform).clear_if_owner()
+	}
+
+	/// How long ago the current contents of the platform's default clipboard were placed there,
+	/// or `None` if that isn't known.
+	///
+	/// The age of contents placed through this `Clipboard` is always known, as long as they are
+	/// still on the clipboard.
+	///
+	/// # Platform-specific behavior
+	///
+	/// On X11, other applications are asked for the `TIMESTAMP` at which they took the clipboard,
+	/// which many of them answer. It has a resolution of milliseconds and is converted using the
+	/// X server's current time, so it's unaffected by changes to the system clock. The contents
+	/// placed by any `Clipboard` of this process are known.
+	///
+	/// On Windows and macOS, there is no record of when the contents arrived, so they are only
+	/// known if they changed while this `Clipboard` existed. They are counted from the first call
+	/// after the change, which makes the age only as accurate as this is called often, for
+	/// example when polling.
+	///
+	/// On Wayland, only the age of contents placed through this `Clipboard` is known.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the owner of the clipboard cannot be queried on Linux.
+	pub fn content_age(&mut self) -> Result<Option<Duration>, Error> {
+		self.platform.content_age()
+	}
+
 	/// Begins a "clear" option to remove data from the clipboard.
 	pub fn clear_with(&mut self) -> Clear<'_> {
 		Clear { platform: platform::Clear::new(&mut self.platform) }
 	}
 
+	/// Re-establishes the connection to the display server, if the platform has one that can be
+	/// lost.
+	///
+	/// Long-running programs can call this after an operation failed with
+	/// [`Error::Disconnected`], e.g. after the Wayland compositor restarted, to find out whether
+	/// the clipboard is usable again.
+	///
+	/// # Platform-specific behavior
+	///
+	/// On Wayland, this checks that the compositor can be reached again. Every operation connects
+	/// anew, so nothing else has to be done. On X11, this does the same as
+	/// `ClipboardExtLinux::restart_server`. On Windows and macOS, this does nothing.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::Disconnected`] if the Wayland compositor still can't be reached, or an
+	/// error if a new connection to the X server can't be opened.
+	pub fn reconnect(&mut self) -> Result<(), Error> {
+		#[cfg(all(
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+		))]
+		return self.platform.reconnect();
+
+		#[cfg(not(all(
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+		)))]
+		Ok(())
+	}
+
+	/// Reports which features arboard was built with, which backend this `Clipboard` uses and
+	/// what is known about the display server, for including in bug reports.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let clipboard = Clipboard::new()?;
+	/// eprintln!("{}", clipboard.diagnostics());
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// # Platform-specific behavior
+	///
+	/// On Linux, the environment variables which decide between Wayland and X11 are listed, and
+	/// whether the primary selection is supported. On X11, the server's vendor and release and
+	/// the protocol version are reported. On Wayland, the data control protocol is reported as
+	/// version 2 or later if the compositor supports the primary selection, which was added then.
+	pub fn diagnostics(&self) -> Diagnostics {
+		let mut diagnostics = Diagnostics::new();
+		self.platform.diagnose(&mut diagnostics);
+		diagnostics
+	}
+
+	/// Runs `f` with a time budget for all the reads it does through the [`Budget`].
+	///
+	/// Each read started with [`Budget::get`] gives up once the budget is used up, so that a
+	/// slow read doesn't leave less time for the ones after it than expected, but the whole
+	/// sequence of reads finishes within `total`. See [`Get::deadline`] for which reads this
+	/// affects.
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # use std::time::Duration;
+	/// let mut clipboard = Clipboard::new()?;
+	/// let (html, text) = clipboard.with_budget(Duration::from_millis(16), |budget| {
+	///     let html = budget.get().html().ok();
+	///     let text = budget.get().text()?;
+	///     Ok((html, text))
+	/// })?;
+	/// # Ok::<(), Error>(())
+	/// ```
+	pub fn with_budget<T>(
+		&mut self,
+		total: Duration,
+		f: impl FnOnce(&mut Budget<'_>) -> Result<T, Error>,
+	) -> Result<T, Error> {
+		f(&mut Budget { clipboard: self, deadline: Instant::now() + total })
+	}
+
 	/// Begins a "get" operation to retrieve data from the clipboard.
 	pub fn get(&mut self) -> Get<'_> {
 		Get { platform: platform::Get::new(&mut self.platform) }
@@ -171,6 +503,53 @@ impl Clipboard {
 	pub fn set(&mut self) -> Set<'_> {
 		Set { platform: platform::Set::new(&mut self.platform) }
 	}
+
+	/// Begins a "get" operation configured like the one `config` was taken from with
+	/// [`Get::into_config`].
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	///
+	/// let config = clipboard.get().into_config();
+	/// let first = clipboard.get_with(&config).text()?;
+	/// let second = clipboard.get_with(&config).text()?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn get_with(&mut self, config: &GetConfig) -> Get<'_> {
+		Get { platform: platform::Get::with_config(&mut self.platform, &config.platform) }
+	}
+
+	/// Begins a "set" operation configured like the one `config` was taken from with
+	/// [`Set::into_config`].
+	pub fn set_with(&mut self, config: &SetConfig) -> Set<'_> {
+		Set { platform: platform::Set::with_config(&mut self.platform, &config.platform) }
+	}
+}
+
+/// The targets given to [`Clipboard::set_text_targets`] or [`Clipboard::set_image_targets`], or
+/// `None` to read from the built-in ones.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),))]
+fn owned_targets(targets: &[&str]) -> Option<Vec<String>> {
+	(!targets.is_empty()).then(|| targets.iter().map(|&target| target.to_owned()).collect())
+}
+
+/// The configuration of a [`Get`], which can be used to begin any number of "get" operations
+/// with [`Clipboard::get_with`].
+#[derive(Clone)]
+pub struct GetConfig {
+	platform: platform::GetConfig,
+}
+
+/// The configuration of a [`Set`], which can be used to begin any number of "set" operations
+/// with [`Clipboard::set_with`].
+#[derive(Clone)]
+pub struct SetConfig {
+	platform: platform::SetConfig,
 }
 
 /// A builder for an operation that gets a value from the clipboard.
@@ -180,9 +559,56 @@ pub struct Get<'clipboard> {
 }
 
 impl Get<'_> {
+	/// Ends the "get" operation without fetching anything, keeping its configuration for reuse
+	/// with [`Clipboard::get_with`].
+	///
+	/// A [`deadline`](Get::deadline) is kept as the point in time it is, rather than as the time
+	/// left until then. A [`wait`](Get::wait) isn't kept.
+	pub fn into_config(self) -> GetConfig {
+		GetConfig { platform: self.platform.into_config() }
+	}
+
 	/// Completes the "get" operation by fetching UTF-8 text from the clipboard.
 	pub fn text(self) -> Result<String, Error> {
-		self.platform.text()
+		self.platform.wait_for_change()?.text()
+	}
+
+	/// Completes the "get" operation by fetching UTF-8 text from the clipboard, or the text
+	/// content of the HTML on it if there is no text.
+	///
+	/// Some applications, like certain web apps, only place HTML onto the clipboard. The HTML is
+	/// converted with [`formats::html_to_text`], and the returned [`TextSource`] tells whether
+	/// that happened.
+	pub fn text_or_html_stripped(self) -> Result<(String, TextSource), Error> {
+		self.platform.wait_for_change()?.text_or_html_stripped()
+	}
+
+	/// Completes the "get" operation by fetching at most `max_bytes` of UTF-8 text from the
+	/// clipboard, along with whether the text was cut off.
+	///
+	/// This is meant for showing a preview of what's on the clipboard. Long texts are cut off
+	/// before a character that doesn't fit, so the text may be a few bytes shorter than
+	/// `max_bytes` even when it was cut off.
+	///
+	/// # Platform-specific behavior
+	///
+	/// Only about as much of the text as needed is transferred and converted: on X11 the
+	/// transfer is stopped once enough of the text arrived, and on Wayland, Windows and macOS
+	/// just the start of the text is read. When `arboard` itself owns the X11 selection, the
+	/// whole text is already in memory and cut off there.
+	pub fn text_truncated(self, max_bytes: usize) -> Result<(String, bool), Error> {
+		self.platform.wait_for_change()?.text_truncated(max_bytes)
+	}
+
+	/// Completes the "get" operation by fetching UTF-8 text from the clipboard and shortening it
+	/// to at most `max_graphemes` grapheme clusters for display, like in a clipboard history.
+	///
+	/// Unlike [`text_truncated`](Get::text_truncated), which counts bytes, emoji and letters with
+	/// combining marks are never split. If the text is cut off, the last of the grapheme clusters
+	/// is an ellipsis (`…`). The whole text is read before it's shortened.
+	#[cfg(feature = "text-preview")]
+	pub fn text_preview(self, max_graphemes: usize) -> Result<String, Error> {
+		Ok(common::preview_text(&self.text()?, max_graphemes))
 	}
 
 	/// Completes the "get" operation by fetching image data from the clipboard and returning the
@@ -191,19 +617,209 @@ impl Get<'_> {
 	/// Any image data placed on the clipboard with `set_image` will be possible read back, using
 	/// this function. However it's of not guaranteed that an image placed on the clipboard by any
 	/// other application will be of a supported format.
-	#[cfg(feature = "image-data")]
+	///
+	/// With only the `image-data-png` feature, images are only read as PNG, except for bitmaps
+	/// on Windows, which are read through the `CF_BITMAP` Windows synthesizes and so lose their
+	/// transparency.
+	#[cfg(feature = "image-data-png")]
 	pub fn image(self) -> Result<ImageData<'static>, Error> {
-		self.platform.image()
+		self.platform.wait_for_change()?.image()
+	}
+
+	/// Completes the "get" operation like [`image`](Get::image), but hands out the image as it was
+	/// read if it couldn't be decoded, instead of only an error.
+	///
+	/// This lets an image in a format, or a variant of one, that `arboard` can't decode be saved
+	/// to a file or passed on to a decoder which can. 16-bit PNGs, PNGs with palette or grayscale
+	/// transparency and the first frame of an APNG are decoded.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, ImageOrBytes};
+	/// let mut clipboard = Clipboard::new()?;
+	/// match clipboard.get().image_or_bytes()? {
+	///     ImageOrBytes::Image(image) => println!("{}x{} pixels", image.width, image.height),
+	///     ImageOrBytes::Encoded { format, bytes, error } => {
+	///         println!("{} bytes of {format} couldn't be decoded: {error}", bytes.len())
+	///     }
+	///     _ => {}
+	/// }
+	/// # Ok::<(), arboard::Error>(())
+	/// ```
+	#[cfg(feature = "image-data-png")]
+	pub fn image_or_bytes(self) -> Result<ImageOrBytes, Error> {
+		self.platform.wait_for_change()?.image_or_bytes()
+	}
+
+	/// Sets the largest image, in pixels, that [`Get::image`] decodes.
+	///
+	/// The size of an image on the clipboard is whatever the application which placed it there
+	/// claims. Images with more pixels than this make `image` return [`Error::TooLarge`] before
+	/// any memory is allocated for them. Defaults to 512 megapixels.
+	#[cfg(feature = "image-data-png")]
+	pub fn max_image_pixels(self, max_pixels: u64) -> Self {
+		Self { platform: self.platform.max_image_pixels(max_pixels) }
+	}
+
+	/// Sets a point in time after which the read gives up and returns [`Error::Timeout`], if it
+	/// hasn't completed by then.
+	///
+	/// This only shortens how long a read waits for the application which placed the contents
+	/// on the clipboard, which only happens on Linux. On other platforms, reading doesn't wait for
+	/// the other application and this has no effect.
+	pub fn deadline(self, deadline: Instant) -> Self {
+		Self { platform: self.platform.deadline(deadline) }
+	}
+
+	/// Makes the "get" operation wait for the clipboard to change before reading from it.
+	///
+	/// The clipboard is watched from when this is called, so contents placed there by another
+	/// application in the meantime count as a change, even if that happened before the read was
+	/// completed. This makes it possible to trigger a copy in another application and then read
+	/// its result:
+	///
+	/// ```no_run
+	/// # use arboard::Clipboard;
+	/// # fn trigger_copy() {}
+	/// let mut clipboard = Clipboard::new().unwrap();
+	/// let get = clipboard.get().wait();
+	/// trigger_copy();
+	/// let copied = get.text().unwrap();
+	/// ```
+	///
+	/// Without this, reads return whatever is on the clipboard right away.
+	///
+	/// # Platform-specific behavior
+	///
+	/// On Linux, the clipboard which is watched is the one chosen when this is called. X11 tells
+	/// about new owners of the selection. Without the XFixes extension, the owner is checked every
+	/// few milliseconds instead, which misses an application placing new contents while it
+	/// already owns the selection. Wayland, Windows and macOS are checked every few milliseconds
+	/// too. On Wayland, only the formats that are offered are compared, so new contents are
+	/// missed if they are offered in the very same formats as before. On Windows, the clipboard
+	/// is closed while waiting, so that other applications can change it.
+	pub fn wait(self) -> Self {
+		Self { platform: self.platform.wait(None) }
+	}
+
+	/// Like [`wait`](Get::wait), but gives up with [`Error::Timeout`] if the clipboard didn't
+	/// change once `deadline` passed.
+	pub fn wait_until(self, deadline: Instant) -> Self {
+		Self { platform: self.platform.wait(Some(deadline)) }
 	}
 
 	/// Completes the "get" operation by fetching HTML from the clipboard.
 	pub fn html(self) -> Result<String, Error> {
-		self.platform.html()
+		self.platform.wait_for_change()?.html()
+	}
+
+	/// Completes the "get" operation by fetching both HTML and its plain-text alternative from the
+	/// clipboard, returning whichever of the two are present.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if neither HTML nor text is on the clipboard.
+	pub fn html_and_text(self) -> Result<(Option<String>, Option<String>), Error> {
+		match self.platform.wait_for_change()?.html_and_text()? {
+			(None, None) => Err(Error::content_not_available()),
+			contents => Ok(contents),
+		}
+	}
+
+	/// Completes the "get" operation by writing the clipboard's contents in `format` to `writer`
+	/// as they arrive, returning how many bytes were written.
+	///
+	/// This is meant for tools like `pbpaste`, which pass the contents on without keeping them
+	/// in memory. `format` is the platform's name of the format, like the ones listed in a
+	/// [`SetReport`]:
+	///
+	/// - On Linux: the X11 target or Wayland MIME type, like `image/png`. On X11, each segment of
+	///   an `INCR` transfer is written as soon as it arrives. On Wayland, the contents are copied
+	///   from the source application's pipe, unless a [`deadline`](Get::deadline) is set, which
+	///   makes them read as a whole first.
+	/// - On Windows: the name of a registered format, like `PNG` or `HTML Format`.
+	/// - On macOS: a pasteboard type, like `public.png`.
+	///
+	/// On Windows and macOS, the contents are read as a whole and written at once. They are
+	/// always written as they are, without any conversion.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let mut stdout = std::io::stdout().lock();
+	/// let written = clipboard.get().write_to("image/png", &mut stdout)?;
+	/// eprintln!("{written} bytes");
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if the clipboard doesn't hold `format`, and
+	/// [`Error::Unknown`] if writing to `writer` failed, in which case some of the contents may
+	/// have been written already. Its description names the [`ErrorKind`](std::io::ErrorKind) of
+	/// the write's error, like `BrokenPipe`.
+	pub fn write_to(self, format: &str, writer: &mut dyn Write) -> Result<u64, Error> {
+		self.platform.wait_for_change()?.write_to(format, writer)
 	}
 
 	/// Completes the "get" operation by fetching a list of file paths from the clipboard.
+	///
+	/// On Windows, a single path placed under the `FileNameW` or `FileName` format is read when
+	/// there is no `CF_HDROP`, as some applications only offer those.
 	pub fn file_list(self) -> Result<Vec<PathBuf>, Error> {
-		self.platform.file_list()
+		self.platform.wait_for_change()?.file_list()
+	}
+
+	/// Completes the "get" operation by fetching a list of file paths from the clipboard, along
+	/// with whether each of them exists and is a directory.
+	///
+	/// The paths are looked up on the file system when this is called. Paths which don't exist
+	/// (anymore) are kept in the list.
+	pub fn file_list_detailed(self) -> Result<Vec<FileEntry>, Error> {
+		let paths = self.platform.wait_for_change()?.file_list()?;
+		Ok(paths.into_iter().map(FileEntry::stat).collect())
+	}
+
+	/// Completes the "get" operation by fetching a list of file paths from the clipboard, along
+	/// with whether the files were copied or cut.
+	///
+	/// The operation is taken from:
+	/// - On Linux: GNOME's `x-special/gnome-copied-files` or KDE's `application/x-kde-cutselection`
+	/// - On Windows: The `Preferred DropEffect` format
+	/// - On macOS: Files are always reported as copied, since Finder decides whether to move them
+	///   when pasting.
+	///
+	/// Files without any of these markers are reported as copied.
+	pub fn file_list_with_op(self) -> Result<(Vec<PathBuf>, FileOp), Error> {
+		self.platform.wait_for_change()?.file_list_with_op()
+	}
+}
+
+/// A time budget shared by several reads from the clipboard, see [`Clipboard::with_budget`].
+pub struct Budget<'clipboard> {
+	clipboard: &'clipboard mut Clipboard,
+	deadline: Instant,
+}
+
+impl Budget<'_> {
+	/// Begins a "get" operation which gives up once the budget is used up.
+	pub fn get(&mut self) -> Get<'_> {
+		self.clipboard.get().deadline(self.deadline)
+	}
+
+	/// Returns the point in time at which the budget is used up.
+	pub fn deadline(&self) -> Instant {
+		self.deadline
+	}
+
+	/// Returns how much of the budget is left, which is zero once it's used up.
+	pub fn remaining(&self) -> Duration {
+		self.deadline.saturating_duration_since(Instant::now())
 	}
 }
 
@@ -213,25 +829,188 @@ pub struct Set<'clipboard> {
 	pub(crate) platform: platform::Set<'clipboard>,
 }
 
-impl Set<'_> {
+impl<'clipboard> Set<'clipboard> {
+	/// Ends the "set" operation without changing the clipboard, keeping its configuration for
+	/// reuse with [`Clipboard::set_with`].
+	pub fn into_config(self) -> SetConfig {
+		SetConfig { platform: self.platform.into_config() }
+	}
+
+	/// Only checks the data given to the "set" operation, without changing the clipboard.
+	///
+	/// The data goes through the same conversions as it would be placed with, like encoding images
+	/// or checking [`SetExtWindows::max_format_bytes`], and the completing method returns the
+	/// errors those would. The clipboard is never opened or taken ownership of, so errors which
+	/// only come up while placing the data, like [`Error::ClipboardOccupied`], aren't reported.
+	/// Data given to [`Set::stream`] isn't produced, and options about what happens after the data
+	/// is placed, like [`SetExtLinux::wait`], have no effect.
+	///
+	/// This isn't kept by [`Set::into_config`].
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn validate_only(self) -> Self {
+		Self { platform: self.platform.validate_only() }
+	}
+
+	/// Makes the completing methods return a [`SetReport`] of the formats the data was placed in,
+	/// instead of `()`.
+	///
+	/// As the returned builder can't be configured further, this comes after any other option.
+	/// With [`Set::validate_only`], no formats are listed, as nothing is placed.
+	pub fn with_report(self) -> SetWithReport<'clipboard> {
+		SetWithReport { set: self }
+	}
+
+	/// Makes the completing methods leave the clipboard as it is if it already holds the very
+	/// same data, returning whether they placed it as a [`SetOutcome`].
+	///
+	/// Placing data takes ownership of the clipboard, which wakes up clipboard managers and
+	/// other applications watching it even if nothing changed. An application that syncs the
+	/// clipboard with another device easily ends up in an echo loop: it places what it received,
+	/// notices the clipboard changing, and sends the same contents back, which the other side
+	/// places again. Skipping data that is already there breaks the loop.
+	///
+	/// By default, the data is only compared with contents that were placed through this
+	/// `Clipboard` and are still on the clipboard. This doesn't ask other applications for
+	/// anything: on X11, the contents are still in memory, and on Windows and macOS, checking
+	/// that they are still ours is cheap. On Wayland, the contents are read back to find out
+	/// whether they are still ours. [`SetIfChanged::compare_by_reading`] compares with the
+	/// contents of other applications too.
+	///
+	/// The data has to be in the same formats too, so text isn't the same as HTML with that text
+	/// as its alternative. Data placed with platform specific formats or options, like
+	/// [`SetExtLinux::wait`] or [`SetExtWindows::raw_format`], is always placed, as those can't
+	/// be compared.
+	///
+	/// As the returned builder can't be configured further, this comes after any other option.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error, SetOutcome};
+	/// # fn main() -> Result<(), Error> {
+	/// # let received = String::new();
+	/// let mut clipboard = Clipboard::new()?;
+	/// if clipboard.set().if_changed().compare_by_reading().text(received)? == SetOutcome::Placed {
+	///     // Only new contents are worth telling the other devices about.
+	/// }
+	/// # Ok(())
+	/// # }
+	/// ```
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn if_changed(self) -> SetIfChanged<'clipboard> {
+		SetIfChanged { set: self, read: false }
+	}
+
+	/// Completes the "set" operation by placing text onto the clipboard like [`text`](Set::text),
+	/// but only if the clipboard holds the `expected` text. With `expected` being `None`, the
+	/// text is only placed if the clipboard holds no text, or an empty one. Returns whether the
+	/// text was placed.
+	///
+	/// This lets cooperating applications share the clipboard without overwriting each other:
+	/// each of them only replaces the text it last saw.
+	///
+	/// The clipboard is read and written right after each other, and no other "compare and set"
+	/// of this process runs in between. None of the platforms lets the two happen at once,
+	/// though, so another process may still place something in between.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let seen = clipboard.get_text().ok();
+	/// // ...
+	/// if !clipboard.set().text_if_current(seen.as_deref(), "mine")? {
+	///     // Someone else copied something in the meantime, which is left alone.
+	/// }
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn text_if_current<'a, T: ClipboardText<'a>>(
+		mut self,
+		expected: Option<&str>,
+		text: T,
+	) -> Result<bool, Error> {
+		static COMPARE_AND_SET: Mutex<()> = Mutex::new(());
+		let _guard = COMPARE_AND_SET.lock().unwrap_or_else(PoisonError::into_inner);
+
+		let current = match self.platform.read_back().text() {
+			Ok(text) => Some(text).filter(|text| !text.is_empty()),
+			Err(Error::ContentNotAvailable { .. }) => None,
+			Err(error) => return Err(error),
+		};
+		if current.as_deref() != expected.filter(|text| !text.is_empty()) {
+			return Ok(false);
+		}
+		self.text(text)?;
+		Ok(true)
+	}
+
 	/// Completes the "set" operation by placing text onto the clipboard. Any valid UTF-8 string
 	/// is accepted.
-	pub fn text<'a, T: Into<Cow<'a, str>>>(self, text: T) -> Result<(), Error> {
-		let text = text.into();
+	///
+	/// # Platform-specific behavior
+	///
+	/// The text is placed as it is, without normalizing it or converting line endings. On Windows,
+	/// text ends at its first NUL character for every application reading it, including this one.
+	pub fn text<'a, T: ClipboardText<'a>>(self, text: T) -> Result<(), Error> {
 		self.platform.text(text)
 	}
 
-	/// Completes the "set" operation by placing HTML as well as a plain-text alternative onto the
+	/// Completes the "set" operation by placing text which is already encoded as UTF-8 onto the
 	/// clipboard.
 	///
-	/// Any valid UTF-8 string is accepted.
-	pub fn html<'a, T: Into<Cow<'a, str>>>(
+	/// This saves turning the bytes into a `String` first. On Linux, they are offered as they are
+	/// without being validated again, so it's the caller's responsibility that they are valid
+	/// UTF-8; this is only checked in debug builds. Other platforms convert the bytes and return
+	/// [`Error::ConversionFailure`] if they aren't valid UTF-8.
+	pub fn text_bytes(self, utf8_bytes: Vec<u8>) -> Result<(), Error> {
+		self.platform.text_bytes(utf8_bytes)
+	}
+
+	/// Completes the "set" operation by placing `bytes` in whichever format they turn out to be
+	/// in, as detected by [`formats::sniff_mime`].
+	///
+	/// This is meant for data of an unknown format, like what is piped into a command line tool.
+	/// Text is placed like with [`Set::text_bytes`]. Images are placed as they are, under their
+	/// MIME type or the platform's equivalent of it, and anything else as
+	/// `application/octet-stream`. On Windows, images which can be decoded are placed as
+	/// `CF_DIBV5` and `CF_BITMAP` as well, like with `Set::image`, as most applications only
+	/// paste bitmaps there.
+	pub fn auto(self, bytes: Vec<u8>) -> Result<(), Error> {
+		match formats::sniff_mime(&bytes) {
+			"text/plain" => self.text_bytes(bytes),
+			mime => self.platform.encoded(mime, bytes),
+		}
+	}
+
+	/// Completes the "set" operation by placing data under `mime`, which is produced from `chunks`
+	/// only once it's pasted, for example to copy a large export without keeping all of it in
+	/// memory.
+	///
+	/// On X11, the chunks are pulled from the iterator on a thread of its own as the application
+	/// pasting the data asks for more, and sent incrementally (with `INCR`). If the iterator
+	/// panics, the transfer is abandoned and the application doesn't get the data. As the data
+	/// isn't kept, it can only be pasted once, and reading it back through a `Clipboard` of this
+	/// process returns [`Error::ContentNotAvailable`]. Note that clipboard managers may paste it
+	/// themselves as soon as it's placed. On Wayland, Windows and macOS, the chunks are collected
+	/// up front and the data is placed like with [`Set::auto`], but under `mime`.
+	pub fn stream(
 		self,
-		html: T,
-		alt_text: Option<T>,
+		mime: &str,
+		chunks: impl Iterator<Item = Vec<u8>> + Send + 'static,
 	) -> Result<(), Error> {
-		let html = html.into();
-		let alt_text = alt_text.map(|e| e.into());
+		self.platform.stream(mime, Box::new(chunks))
+	}
+
+	/// Completes the "set" operation by placing HTML as well as a plain-text alternative onto the
+	/// clipboard.
+	///
+	/// Any valid UTF-8 string is accepted.
+	pub fn html<'a, T: ClipboardText<'a>>(self, html: T, alt_text: Option<T>) -> Result<(), Error> {
+		let html = html.into_cow();
+		let alt_text = alt_text.map(ClipboardText::into_cow);
 		self.platform.html(html, alt_text)
 	}
 
@@ -239,20 +1018,288 @@ impl Set<'_> {
 	///
 	/// The chosen output format, depending on the platform is the following:
 	///
-	/// - On macOS: `NSImage` object
+	/// - On macOS: `NSImage` object, along with a `public.png` copy with only the `image-data-png`
+	///   feature, as that's what is read back then
 	/// - On Linux: PNG, under the atom `image/png`
-	/// - On Windows: In order of priority `CF_DIB` and `CF_BITMAP`
-	#[cfg(feature = "image-data")]
+	/// - On Windows: `CF_DIBV5` and `CF_BITMAP`, followed by the registered `PNG` format. The
+	///   bitmaps are placed first so that the image is kept in the clipboard history, unless
+	///   [`SetExtWindows::exclude_from_history`] is used.
+	#[cfg(feature = "image-data-png")]
+	#[allow(rustdoc::broken_intra_doc_links)]
 	pub fn image(self, image: ImageData) -> Result<(), Error> {
 		self.platform.image(image)
 	}
 
+	/// Completes the "set" operation by placing an image onto the clipboard, in the same formats
+	/// as [`Set::image`], but only encoding it once another application pastes it.
+	///
+	/// This keeps encoding a large image, like a screenshot, from blocking the copy. On X11, the
+	/// image is kept as it is and encoded as a PNG on a thread of its own when it's first
+	/// requested. The request is answered once that's done, and the PNG is kept for the requests
+	/// that follow, while other requests keep being served in the meantime. Reading it back
+	/// through a `Clipboard` of this process encodes it as well, and waits for it.
+	///
+	/// On Wayland, Windows and macOS, nothing is deferred: the image is encoded before this
+	/// returns, exactly like with [`Set::image`].
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if the image is empty or `bytes` don't hold exactly
+	/// its RGBA pixels. If it fails to be encoded later, the paste fails instead.
+	#[cfg(feature = "image-data-png")]
+	pub fn image_lazy(self, image: ImageData<'static>) -> Result<(), Error> {
+		self.platform.image_lazy(image)
+	}
+
+	/// Completes the "set" operation by placing a tightly packed RGBA image onto the clipboard,
+	/// in the same formats as [`Set::image`].
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if `rgba` isn't exactly `width * height * 4` bytes long.
+	#[cfg(feature = "image-data-png")]
+	pub fn image_rgba(self, width: usize, height: usize, rgba: &[u8]) -> Result<(), Error> {
+		let expected_len = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(4));
+		if expected_len != Some(rgba.len()) {
+			return Err(Error::conversion(
+				Format::Pixels,
+				ConversionStage::Validate,
+				format!(
+					"{} bytes don't hold the RGBA pixels of a {width}x{height} image",
+					rgba.len()
+				),
+			));
+		}
+		self.platform.image(ImageData { width, height, bytes: rgba.into() })
+	}
+
+	/// Completes the "set" operation by placing an image onto the clipboard, together with a
+	/// scaled down copy of it.
+	///
+	/// The full image is placed onto the clipboard in the same formats as [`Set::image`]. The
+	/// thumbnail is scaled so that neither of its sides exceed `thumb_max_dim` pixels and is
+	/// published as a PNG under the `image/png;thumbnail` format, so that applications which
+	/// only want to show a preview can request it instead of the full image.
+	#[cfg(feature = "image-data")]
+	pub fn image_with_thumbnail(self, full: ImageData, thumb_max_dim: u32) -> Result<(), Error> {
+		let thumbnail = full.thumbnail(thumb_max_dim)?;
+		self.platform.image_with_thumbnail(full, thumbnail)
+	}
+
+	/// Completes the "set" operation by placing an image onto the clipboard in several encodings
+	/// at once, so that applications which only understand one of them can still paste it.
+	///
+	/// The encodings are offered in the given order, which some applications use as the order of
+	/// preference. The formats they are published under depend on the platform:
+	///
+	/// - On macOS: `public.png`, `com.microsoft.bmp` and `public.jpeg`, next to the `NSImage` object
+	///   placed by [`Set::image`]
+	/// - On Linux: `image/png`, `image/bmp` and `image/jpeg`
+	/// - On Windows: the registered `PNG` format, `CF_DIBV5` and the registered `JFIF` format,
+	///   with `CF_DIBV5` and `CF_BITMAP` always placed first, like with [`Set::image`], so that
+	///   the image is kept in the clipboard history
+	///
+	/// An encoding which fails is logged and left out.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if the image couldn't be encoded in any of `encodings`,
+	/// including when `encodings` is empty.
+	#[cfg(feature = "image-data")]
+	pub fn image_multi(self, image: ImageData, encodings: &[ImageEncoding]) -> Result<(), Error> {
+		self.platform.image_multi(image, encodings)
+	}
+
 	/// Completes the "set" operation by placing a list of file paths onto the clipboard.
+	///
+	/// On Windows, a single path is placed under the `FileNameW` format as well.
 	pub fn file_list(self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
 		self.platform.file_list(file_list)
 	}
 }
 
+/// A "set" operation which reports what it placed onto the clipboard, as started with
+/// [`Set::with_report`].
+///
+/// Its methods place the data like the ones of [`Set`] with the same names.
+#[must_use]
+pub struct SetWithReport<'clipboard> {
+	set: Set<'clipboard>,
+}
+
+impl SetWithReport<'_> {
+	/// Places text like [`Set::text`].
+	pub fn text<'a, T: ClipboardText<'a>>(self, text: T) -> Result<SetReport, Error> {
+		self.complete(|set| set.text(text))
+	}
+
+	/// Places text which is already encoded as UTF-8 like [`Set::text_bytes`].
+	pub fn text_bytes(self, utf8_bytes: Vec<u8>) -> Result<SetReport, Error> {
+		self.complete(|set| set.text_bytes(utf8_bytes))
+	}
+
+	/// Places data of an unknown format like [`Set::auto`].
+	pub fn auto(self, bytes: Vec<u8>) -> Result<SetReport, Error> {
+		self.complete(|set| set.auto(bytes))
+	}
+
+	/// Places data produced from `chunks` like [`Set::stream`].
+	pub fn stream(
+		self,
+		mime: &str,
+		chunks: impl Iterator<Item = Vec<u8>> + Send + 'static,
+	) -> Result<SetReport, Error> {
+		self.complete(|set| set.stream(mime, chunks))
+	}
+
+	/// Places HTML and its plain-text alternative like [`Set::html`].
+	pub fn html<'a, T: ClipboardText<'a>>(
+		self,
+		html: T,
+		alt_text: Option<T>,
+	) -> Result<SetReport, Error> {
+		self.complete(|set| set.html(html, alt_text))
+	}
+
+	/// Places an image like [`Set::image`].
+	#[cfg(feature = "image-data-png")]
+	pub fn image(self, image: ImageData) -> Result<SetReport, Error> {
+		self.complete(|set| set.image(image))
+	}
+
+	/// Places an image which is only encoded once it's pasted like [`Set::image_lazy`].
+	#[cfg(feature = "image-data-png")]
+	pub fn image_lazy(self, image: ImageData<'static>) -> Result<SetReport, Error> {
+		self.complete(|set| set.image_lazy(image))
+	}
+
+	/// Places a tightly packed RGBA image like [`Set::image_rgba`].
+	#[cfg(feature = "image-data-png")]
+	pub fn image_rgba(self, width: usize, height: usize, rgba: &[u8]) -> Result<SetReport, Error> {
+		self.complete(|set| set.image_rgba(width, height, rgba))
+	}
+
+	/// Places an image together with a scaled down copy of it like [`Set::image_with_thumbnail`].
+	#[cfg(feature = "image-data")]
+	pub fn image_with_thumbnail(
+		self,
+		full: ImageData,
+		thumb_max_dim: u32,
+	) -> Result<SetReport, Error> {
+		self.complete(|set| set.image_with_thumbnail(full, thumb_max_dim))
+	}
+
+	/// Places an image in several encodings at once like [`Set::image_multi`].
+	#[cfg(feature = "image-data")]
+	pub fn image_multi(
+		self,
+		image: ImageData,
+		encodings: &[ImageEncoding],
+	) -> Result<SetReport, Error> {
+		self.complete(|set| set.image_multi(image, encodings))
+	}
+
+	/// Places a list of files like [`Set::file_list`].
+	pub fn file_list(self, file_list: &[impl AsRef<Path>]) -> Result<SetReport, Error> {
+		self.complete(|set| set.file_list(file_list))
+	}
+
+	/// Completes the operation with `set`, having the platform record what it places.
+	fn complete(self, set: impl FnOnce(Set<'_>) -> Result<(), Error>) -> Result<SetReport, Error> {
+		let mut report = SetReport::default();
+		set(Set { platform: self.set.platform.report_to(&mut report) })?;
+		Ok(report)
+	}
+}
+
+/// A "set" operation which skips data that is on the clipboard already, as started with
+/// [`Set::if_changed`].
+///
+/// Its methods place the data like the ones of [`Set`] with the same names. Only the formats
+/// that can be read back exactly are offered.
+#[must_use]
+pub struct SetIfChanged<'clipboard> {
+	set: Set<'clipboard>,
+	/// Whether to read the contents when they weren't placed through this `Clipboard`.
+	read: bool,
+}
+
+impl SetIfChanged<'_> {
+	/// Compares the data with the contents of other applications as well, by reading them.
+	///
+	/// Reading asks the application which placed the contents for them, which takes a round trip
+	/// and may be slow, so this is opt-in.
+	pub fn compare_by_reading(mut self) -> Self {
+		self.read = true;
+		self
+	}
+
+	/// Places text like [`Set::text`], unless the clipboard holds the same text already.
+	pub fn text<'a, T: ClipboardText<'a>>(mut self, text: T) -> Result<SetOutcome, Error> {
+		let text = text.into_cow();
+		let unchanged = self.holds(|get| {
+			let (held_html, held_text) = get.html_and_text()?;
+			Ok(held_html.is_none() && held_text.as_deref() == Some(&*text))
+		})?;
+		self.complete(unchanged, |set| set.text(text))
+	}
+
+	/// Places text which is already encoded as UTF-8 like [`Set::text_bytes`], unless the
+	/// clipboard holds the same text already.
+	pub fn text_bytes(mut self, utf8_bytes: Vec<u8>) -> Result<SetOutcome, Error> {
+		let unchanged = self.holds(|get| {
+			let (held_html, held_text) = get.html_and_text()?;
+			Ok(held_html.is_none() && held_text.is_some_and(|text| text.as_bytes() == utf8_bytes))
+		})?;
+		self.complete(unchanged, |set| set.text_bytes(utf8_bytes))
+	}
+
+	/// Places HTML like [`Set::html`], unless the clipboard holds the same HTML, and the same
+	/// plain-text alternative if one is given, already.
+	pub fn html<'a, T: ClipboardText<'a>>(
+		mut self,
+		html: T,
+		alt_text: Option<T>,
+	) -> Result<SetOutcome, Error> {
+		let html = html.into_cow();
+		let alt_text = alt_text.map(ClipboardText::into_cow);
+		let unchanged = self.holds(|get| {
+			let (held_html, held_text) = get.html_and_text()?;
+			Ok(held_html.as_deref() == Some(&*html) && held_text.as_deref() == alt_text.as_deref())
+		})?;
+		self.complete(unchanged, |set| set.html(html, alt_text))
+	}
+
+	/// Whether the contents are ours, or may be read, and pass `compare`.
+	///
+	/// Contents that can't be read, for example because they are in another format, count as
+	/// different. `compare` has to tell apart the formats the contents are in, like HTML from
+	/// its plain-text alternative. Data which would be placed with more than itself, like with
+	/// platform specific formats or options, is always placed, as that can't be read back.
+	fn holds(
+		&mut self,
+		compare: impl FnOnce(platform::Get<'_>) -> Result<bool, Error>,
+	) -> Result<bool, Error> {
+		let platform = &mut self.set.platform;
+		if !platform.places_only_data() || (!self.read && !platform.is_owner()?) {
+			return Ok(false);
+		}
+		Ok(compare(platform.read_back()).unwrap_or(false))
+	}
+
+	fn complete(
+		self,
+		unchanged: bool,
+		set: impl FnOnce(Set<'_>) -> Result<(), Error>,
+	) -> Result<SetOutcome, Error> {
+		if unchanged {
+			return Ok(SetOutcome::Unchanged);
+		}
+		set(self.set)?;
+		Ok(SetOutcome::Placed)
+	}
+}
+
 /// A builder for an operation that clears the data from the clipboard.
 #[must_use]
 pub struct Clear<'clipboard> {
@@ -300,6 +1347,55 @@ mod tests {
 			let text = "Some utf8: 🤓 ∑φ(n)<ε 🐔";
 			ctx.set_text(text).unwrap();
 			assert_eq!(ctx.get_text().unwrap(), text);
+
+			ctx.set().text_bytes(text.as_bytes().to_vec()).unwrap();
+			assert_eq!(ctx.get_text().unwrap(), text);
+
+			ctx.set().auto(text.as_bytes().to_vec()).unwrap();
+			assert_eq!(ctx.get_text().unwrap(), text);
+
+			let set_config = ctx.set().into_config();
+			let get_config = ctx.get().into_config();
+			for _ in 0..2 {
+				ctx.set_with(&set_config).text(text).unwrap();
+				assert_eq!(ctx.get_with(&get_config).text().unwrap(), text);
+			}
+
+			// Every platform places the text in at least one format, none of which is empty.
+			let report = ctx.set().with_report().text(text).unwrap();
+			assert!(!report.formats.is_empty());
+			assert!(report.formats.iter().all(|format| format.bytes >= Some(text.len())));
+			assert!(!report.excluded_from_history);
+			assert_eq!(ctx.get_text().unwrap(), text);
+			let report = ctx.set().validate_only().with_report().text("not placed").unwrap();
+			assert_eq!(report, SetReport::default());
+
+			let diagnostics = ctx.diagnostics();
+			assert!(!diagnostics.backend.is_empty() && !diagnostics.backend_reason.is_empty());
+			assert!(diagnostics.to_string().contains(&diagnostics.backend));
+
+			let total = Duration::from_secs(5);
+			let got = ctx
+				.with_budget(total, |budget| {
+					assert!(budget.remaining() <= total);
+					budget.get().text()
+				})
+				.unwrap();
+			assert_eq!(got, text);
+
+			// "Some utf8: " is 11 bytes long, followed by a four byte character.
+			let (start, truncated) = ctx.get().text_truncated(13).unwrap();
+			assert_eq!((start.as_str(), truncated), ("Some utf8: ", true));
+			assert_eq!(ctx.get().text_truncated(text.len()).unwrap(), (text.to_owned(), false));
+			#[cfg(feature = "text-preview")]
+			{
+				assert_eq!(ctx.get_text_preview(12).unwrap(), "Some utf8: \u{2026}");
+				assert_eq!(ctx.get().text_preview(usize::MAX).unwrap(), text);
+			}
+
+			// Nothing was lost, so this must leave the clipboard as it was.
+			ctx.reconnect().unwrap();
+			assert_eq!(ctx.get_text().unwrap(), text);
 		}
 		{
 			let mut ctx = Clipboard::new().unwrap();
@@ -312,7 +1408,7 @@ mod tests {
 
 			match ctx.get_text() {
 				Ok(text) => assert!(text.is_empty()),
-				Err(Error::ContentNotAvailable) => {}
+				Err(Error::ContentNotAvailable { .. }) => {}
 				Err(e) => panic!("unexpected error: {e}"),
 			};
 
@@ -327,9 +1423,14 @@ mod tests {
 
 			match ctx.get_text() {
 				Ok(text) => assert!(text.is_empty()),
-				Err(Error::ContentNotAvailable) => {}
+				Err(Error::ContentNotAvailable { .. }) => {}
 				Err(e) => panic!("unexpected error: {e}"),
 			};
+
+			let (text, source) = ctx.get().text_or_html_stripped().unwrap();
+			if source == TextSource::Html {
+				assert_eq!(text, "hello world!");
+			}
 		}
 		{
 			let mut ctx = Clipboard::new().unwrap();
@@ -339,6 +1440,14 @@ mod tests {
 
 			ctx.set_html(html, Some(alt_text)).unwrap();
 			assert_eq!(ctx.get_text().unwrap(), alt_text);
+
+			let (got_html, got_text) = ctx.get().html_and_text().unwrap();
+			assert!(got_html.unwrap().contains(html));
+			assert_eq!(got_text.as_deref(), Some(alt_text));
+			assert_eq!(
+				ctx.get().text_or_html_stripped().unwrap(),
+				(alt_text.to_owned(), TextSource::Text)
+			);
 		}
 		{
 			let mut ctx = Clipboard::new().unwrap();
@@ -370,8 +1479,24 @@ mod tests {
 
 			ctx.set().file_list(paths).unwrap();
 			assert_eq!(ctx.get().file_list().unwrap().as_slice(), paths);
+
+			let (list, op) = ctx.get().file_list_with_op().unwrap();
+			assert_eq!(list.as_slice(), paths);
+			assert_eq!(op, FileOp::Copy);
+
+			let paths =
+				&[PathBuf::from(this_dir).join("src"), PathBuf::from(this_dir).join("Cargo.toml")];
+			ctx.set().file_list(paths).unwrap();
+			let entries = ctx.get().file_list_detailed().unwrap();
+			assert_eq!(
+				entries,
+				[
+					FileEntry { path: paths[0].clone(), exists: true, is_dir: true },
+					FileEntry { path: paths[1].clone(), exists: true, is_dir: false },
+				]
+			);
 		}
-		#[cfg(feature = "image-data")]
+		#[cfg(feature = "image-data-png")]
 		{
 			let mut ctx = Clipboard::new().unwrap();
 			#[rustfmt::skip]
@@ -385,16 +1510,40 @@ mod tests {
 
 			// Make sure that setting one format overwrites the other.
 			ctx.set_image(img_data.clone()).unwrap();
-			assert!(matches!(ctx.get_text(), Err(Error::ContentNotAvailable)));
+			assert!(matches!(ctx.get_text(), Err(Error::ContentNotAvailable { .. })));
 
 			ctx.set_text("clipboard test").unwrap();
-			assert!(matches!(ctx.get_image(), Err(Error::ContentNotAvailable)));
+			assert!(matches!(ctx.get_image(), Err(Error::ContentNotAvailable { .. })));
 
 			// Test if we get the same image that we put onto the clipboard
 			ctx.set_image(img_data.clone()).unwrap();
 			let got = ctx.get_image().unwrap();
 			assert_eq!(img_data.bytes, got.bytes);
 
+			// The bitmaps placed alongside the image are freed once they are replaced.
+			#[cfg(windows)]
+			{
+				let gdi_objects = crate::platform::gdi_objects();
+				for _ in 0..16 {
+					ctx.set_image(img_data.clone()).unwrap();
+					ctx.get_image().unwrap();
+				}
+				assert_eq!(crate::platform::gdi_objects(), gdi_objects);
+
+				// Clipboard history only keeps images offered as a bitmap before anything else.
+				use crate::SetExtWindows;
+				use windows_sys::Win32::System::Ole::{CF_BITMAP, CF_DIBV5};
+				let png = clipboard_win::register_format("PNG").unwrap().get();
+				let history =
+					clipboard_win::register_format("CanIncludeInClipboardHistory").unwrap().get();
+				let formats = crate::platform::clipboard_formats();
+				assert_eq!(formats[..3], [u32::from(CF_DIBV5), u32::from(CF_BITMAP), png]);
+				assert!(!formats.contains(&history));
+
+				ctx.set().exclude_from_history().image(img_data.clone()).unwrap();
+				assert!(crate::platform::clipboard_formats().contains(&history));
+			}
+
 			#[rustfmt::skip]
 			let big_bytes = vec![
 				255, 100, 100, 255,
@@ -407,24 +1556,233 @@ mod tests {
 			];
 			let bytes_cloned = big_bytes.clone();
 			let big_img_data = ImageData { width: 3, height: 2, bytes: big_bytes.into() };
-			ctx.set_image(big_img_data).unwrap();
+			ctx.set_image(big_img_data.clone()).unwrap();
+			let got = ctx.get_image().unwrap();
+			assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+
+			ctx.set().image_rgba(3, 2, &bytes_cloned).unwrap();
+			let got = ctx.get_image().unwrap();
+			assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+			assert!(matches!(
+				ctx.set().image_rgba(2, 2, &bytes_cloned),
+				Err(Error::ConversionFailure { .. })
+			));
+			assert!(matches!(
+				ctx.set().image_rgba(usize::MAX, 2, &bytes_cloned),
+				Err(Error::ConversionFailure { .. })
+			));
+
+			// A lazily placed image reads back the same, once it's encoded.
+			ctx.set().image_lazy(big_img_data.to_owned_img()).unwrap();
 			let got = ctx.get_image().unwrap();
 			assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+
+			// Validating reports the same errors as setting, but never changes the clipboard.
+			let mismatched = ImageData { width: 2, height: 2, bytes: bytes_cloned.clone().into() };
+			assert!(matches!(
+				ctx.set().validate_only().image(mismatched),
+				Err(Error::ConversionFailure { .. })
+			));
+			ctx.set().validate_only().text("not placed").unwrap();
+			ctx.set().validate_only().html("<b>not placed</b>", None).unwrap();
+			let got = ctx.get_image().unwrap();
+			assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+
+			#[cfg(feature = "image-data")]
+			{
+				// The thumbnail is published alongside the full image, which is still what's read
+				// back.
+				ctx.set().image_with_thumbnail(big_img_data.clone(), 1).unwrap();
+				let got = ctx.get_image().unwrap();
+				assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+
+				// The lossless encodings are read back exactly, whichever the platform prefers.
+				let encodings = [ImageEncoding::Png, ImageEncoding::Bmp, ImageEncoding::Jpeg];
+				ctx.set().image_multi(big_img_data, &encodings).unwrap();
+				let got = ctx.get_image().unwrap();
+				assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+			}
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			ctx.set_text("before the wait").unwrap();
+
+			// A waiting read returns what was placed after the wait started, not what was there.
+			let get = ctx.get().wait_until(Instant::now() + Duration::from_secs(5));
+			let setter = thread::spawn(|| {
+				thread::sleep(Duration::from_millis(100));
+				Clipboard::new().unwrap().set_text("after the wait").unwrap();
+			});
+			assert_eq!(get.text().unwrap(), "after the wait");
+			setter.join().unwrap();
+
+			// Without a change, it gives up once the deadline passed.
+			let timeout = Duration::from_millis(300);
+			let start = Instant::now();
+			let result = ctx.get().wait_until(start + timeout).text();
+			assert!(matches!(result, Err(Error::Timeout)), "{result:?}");
+			assert!(start.elapsed() >= timeout);
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			ctx.set_text("dated").unwrap();
+			thread::sleep(Duration::from_millis(50));
+			let age = ctx.content_age().unwrap().unwrap();
+			assert!(age >= Duration::from_millis(50) && age < Duration::from_secs(5), "{age:?}");
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			ctx.set_text("expiring secret").unwrap();
+			assert!(ctx.clear_if_owner().unwrap());
+			assert!(matches!(ctx.get_text(), Err(Error::ContentNotAvailable { .. })));
+			// There is nothing of ours left to clear.
+			ctx.set_text("expiring secret").unwrap();
+			ctx.clear().unwrap();
+			assert!(!ctx.clear_if_owner().unwrap());
+
+			// What was copied in the meantime is kept. On X11, the whole process is the owner, so
+			// another `Clipboard` can't stand in for another application there.
+			#[cfg(any(windows, target_os = "macos"))]
+			{
+				ctx.set_text("expiring secret").unwrap();
+				Clipboard::new().unwrap().set_text("copied since").unwrap();
+				assert!(!ctx.clear_if_owner().unwrap());
+				assert_eq!(ctx.get_text().unwrap(), "copied since");
+			}
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			let outcome = ctx.set().if_changed().text("echoed").unwrap();
+			assert_eq!(outcome, SetOutcome::Placed);
+			let outcome = ctx.set().if_changed().text("echoed").unwrap();
+			assert_eq!(outcome, SetOutcome::Unchanged);
+			let outcome = ctx.set().if_changed().text_bytes(b"echoed".to_vec()).unwrap();
+			assert_eq!(outcome, SetOutcome::Unchanged);
+			let outcome = ctx.set().if_changed().text("changed").unwrap();
+			assert_eq!(outcome, SetOutcome::Placed);
+			assert_eq!(ctx.get_text().unwrap(), "changed");
+
+			// Text isn't the same as HTML with that text as its alternative.
+			ctx.set_html("<b>changed</b>", Some("changed")).unwrap();
+			let outcome = ctx.set().if_changed().text("changed").unwrap();
+			assert_eq!(outcome, SetOutcome::Placed);
+
+			// Options which place more than the data can't be compared.
+			#[cfg(all(
+				unix,
+				not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+			))]
+			{
+				use crate::SetExtLinux;
+				let outcome = ctx.set().exclude_from_history().if_changed().text("changed");
+				assert_eq!(outcome.unwrap(), SetOutcome::Placed);
+			}
+
+			// Contents of other applications are only compared when reading them is allowed.
+			#[cfg(any(windows, target_os = "macos"))]
+			{
+				Clipboard::new().unwrap().set_text("copied since").unwrap();
+				let outcome = ctx.set().if_changed().compare_by_reading().text("copied since");
+				assert_eq!(outcome.unwrap(), SetOutcome::Unchanged);
+				let outcome = ctx.set().if_changed().text("copied since").unwrap();
+				assert_eq!(outcome, SetOutcome::Placed);
+			}
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			ctx.clear().unwrap();
+			assert!(ctx.compare_and_set_text(None, "first").unwrap());
+			assert!(!ctx.compare_and_set_text(None, "second").unwrap());
+			assert!(!ctx.compare_and_set_text(Some("stale"), "second").unwrap());
+			assert_eq!(ctx.get_text().unwrap(), "first");
+			assert!(ctx.compare_and_set_text(Some("first"), "second").unwrap());
+			assert_eq!(ctx.get_text().unwrap(), "second");
+
+			// An empty text counts as no text.
+			ctx.set_text("").unwrap();
+			assert!(ctx.compare_and_set_text(None, "after empty").unwrap());
+			assert_eq!(ctx.get_text().unwrap(), "after empty");
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			conformance::check_text_round_trips(&mut ctx);
+		}
+		#[cfg(windows)]
+		{
+			use crate::{GetExtWindows, SetExtWindows};
+
+			let mut ctx = Clipboard::new().unwrap();
+			ctx.set_text("kept").unwrap();
+
+			// Data over the limit is refused before anything on the clipboard is replaced. The
+			// UTF-16 copy of the text is twice as large as its UTF-8.
+			let text = "a".repeat(64);
+			let result = ctx.set().max_format_bytes(100).text(text.clone());
+			assert!(matches!(result, Err(Error::TooLarge)), "{result:?}");
+			let result = ctx.set().max_format_bytes(100).html(text.clone(), Some("alt".into()));
+			assert!(matches!(result, Err(Error::TooLarge)), "{result:?}");
+			assert_eq!(ctx.get_text().unwrap(), "kept");
+
+			ctx.set().max_format_bytes(200).text(text.clone()).unwrap();
+			assert_eq!(ctx.get_text().unwrap(), text);
+
+			// Raw formats are placed along with the data, and can be added to it later on.
+			ctx.set().raw_format("arboard test format", b"raw".to_vec()).text("with raw").unwrap();
+			assert_eq!(ctx.get().raw_format("arboard test format").unwrap(), b"raw");
+			ctx.set()
+				.without_clearing()
+				.raw_format("arboard other format", vec![1])
+				.html("<b>added</b>", None)
+				.unwrap();
+			assert_eq!(ctx.get().raw_format("arboard test format").unwrap(), b"raw");
+			assert_eq!(ctx.get().raw_format("arboard other format").unwrap(), [1]);
+			assert_eq!(ctx.get().html().unwrap(), "<b>added</b>");
+			ctx.set_text("replaced").unwrap();
+			let result = ctx.get().raw_format("arboard test format");
+			assert!(matches!(result, Err(Error::ContentNotAvailable { .. })), "{result:?}");
+		}
+		#[cfg(target_os = "macos")]
+		{
+			use crate::{GetExtApple, SetExtApple};
+
+			let mut ctx = Clipboard::new().unwrap();
+			ctx.set().uti("com.example.arboard", b"raw".to_vec()).text("with raw").unwrap();
+			assert_eq!(ctx.get().uti("com.example.arboard").unwrap(), b"raw");
+			assert_eq!(ctx.get_text().unwrap(), "with raw");
+			assert_eq!(ctx.get().item_count().unwrap(), 1);
+			let items = ctx.get().items().unwrap();
+			assert_eq!(items[0].get("com.example.arboard"), Some(&b"raw"[..]));
+
+			ctx.set_text("replaced").unwrap();
+			let result = ctx.get().uti("com.example.arboard");
+			assert!(matches!(result, Err(Error::ContentNotAvailable { .. })), "{result:?}");
 		}
 		#[cfg(all(
 			unix,
 			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
 		))]
 		{
-			use crate::{LinuxClipboardKind, SetExtLinux};
-			use std::sync::atomic::{self, AtomicBool};
+			use crate::{ClipboardExtLinux, LinuxClipboardKind, SetExtLinux, TimeoutConfig};
+			use std::{
+				sync::atomic::{self, AtomicBool},
+				time::Instant,
+			};
 
-			let mut ctx = Clipboard::new().unwrap();
+			let timeouts = TimeoutConfig::default().read(Duration::from_secs(8));
+			let mut ctx = Clipboard::new_with_timeouts(timeouts).unwrap();
 
 			const TEXT1: &str = "I'm a little teapot,";
 			const TEXT2: &str = "short and stout,";
 			const TEXT3: &str = "here is my handle";
 
+			assert!(ctx.supports_selection(LinuxClipboardKind::Clipboard));
+			assert!(ctx.supports_selection(LinuxClipboardKind::Primary));
+			assert_eq!(
+				ctx.supports_selection(LinuxClipboardKind::Secondary),
+				!cfg!(feature = "wayland-data-control")
+					|| std::env::var_os("WAYLAND_DISPLAY").is_none()
+			);
+
 			ctx.set().clipboard(LinuxClipboardKind::Clipboard).text(TEXT1.to_string()).unwrap();
 
 			ctx.set().clipboard(LinuxClipboardKind::Primary).text(TEXT2.to_string()).unwrap();
@@ -450,23 +1808,89 @@ mod tests {
 				);
 			}
 
-			let was_replaced = Arc::new(AtomicBool::new(false));
-
-			let setter = thread::spawn({
-				let was_replaced = was_replaced.clone();
-				move || {
-					thread::sleep(Duration::from_millis(100));
-					let mut ctx = Clipboard::new().unwrap();
-					ctx.set_text("replacement text".to_owned()).unwrap();
-					was_replaced.store(true, atomic::Ordering::Release);
-				}
-			});
+			let owned = ctx.owned_selections().unwrap();
+			assert!(
+				owned.starts_with(&[LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary])
+			);
+			assert_eq!(
+				owned.len(),
+				if ctx.supports_selection(LinuxClipboardKind::Secondary) { 3 } else { 2 }
+			);
+
+			let texts = ctx.get_text_for_each_selection();
+			assert_eq!(texts.get(LinuxClipboardKind::Clipboard), Some(TEXT1));
+			assert_eq!(texts.get(LinuxClipboardKind::Primary), Some(TEXT2));
+
+			assert!(ctx
+				.sync_text(LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary)
+				.unwrap());
+			assert!(!ctx
+				.sync_text(LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary)
+				.unwrap());
+			assert_eq!(TEXT1, &ctx.get().clipboard(LinuxClipboardKind::Primary).text().unwrap());
+
+			// Restarting a running server must leave the contents alone.
+			assert!(ctx.is_healthy());
+			ctx.restart_server().unwrap();
+			assert!(ctx.is_healthy());
+			assert_eq!(ctx.get_text().unwrap(), TEXT1);
+			if let Some(stats) = ctx.last_read_stats() {
+				assert_eq!(stats.bytes, TEXT1.len());
+			}
 
-			ctx.set().wait().text("initial text".to_owned()).unwrap();
+			// A capture brings back all formats of the contents.
+			ctx.set_html("<b>captured</b>", Some("captured")).unwrap();
+			let capture = ctx.capture(LinuxClipboardKind::Clipboard).unwrap();
+			assert!(capture.formats.iter().any(|(format, _)| format == "text/html"));
+			ctx.set_text(TEXT1).unwrap();
+			ctx.restore(&capture).unwrap();
+			assert_eq!(ctx.get().html().unwrap(), "<b>captured</b>");
+			assert_eq!(ctx.get_text().unwrap(), "captured");
+
+			// `set_and_forget` places the text before returning, and its helper stops serving it
+			// once it's replaced rather than waiting for the whole `ttl`.
+			Clipboard::set_and_forget(TEXT3, Duration::from_secs(600)).unwrap();
+			assert_eq!(ctx.get_text().unwrap(), TEXT3);
+			ctx.set_text(TEXT1).unwrap();
+
+			// Waiting sets must block until another clipboard instance replaces the contents,
+			// regardless of the kind of data that is set.
+			let mut assert_waits = |set: &dyn Fn(&mut Clipboard)| {
+				let was_replaced = Arc::new(AtomicBool::new(false));
+
+				let setter = thread::spawn({
+					let was_replaced = was_replaced.clone();
+					move || {
+						thread::sleep(Duration::from_millis(100));
+						let mut ctx = Clipboard::new().unwrap();
+						was_replaced.store(true, atomic::Ordering::Release);
+						ctx.set_text("replacement text".to_owned()).unwrap();
+					}
+				});
+
+				set(&mut ctx);
+
+				assert!(was_replaced.load(atomic::Ordering::Acquire));
+
+				setter.join().unwrap();
+			};
 
-			assert!(was_replaced.load(atomic::Ordering::Acquire));
+			assert_waits(&|ctx| ctx.set().wait().text("initial text".to_owned()).unwrap());
+			assert_waits(&|ctx| ctx.set().wait().html("<b>initial html</b>", None).unwrap());
+			#[cfg(feature = "image-data-png")]
+			assert_waits(&|ctx| {
+				let image =
+					ImageData { width: 1, height: 1, bytes: [255, 0, 0, 255].as_ref().into() };
+				ctx.set().wait().image(image).unwrap()
+			});
 
-			setter.join().unwrap();
+			// `wait_until` must return once its deadline passed, even if nothing replaced the contents.
+			let timeout = Duration::from_millis(300);
+			let start = Instant::now();
+			ctx.set().wait_until(start + timeout).html("<i>unreplaced html</i>", None).unwrap();
+			let elapsed = start.elapsed();
+			assert!(elapsed >= timeout, "returned too early: {elapsed:?}");
+			assert!(elapsed < timeout + Duration::from_secs(1), "returned too late: {elapsed:?}");
 		}
 	}
 
@@ -1,22 +1,73 @@
 use std::{
 	borrow::Cow,
-	os::unix::ffi::OsStrExt,
+	fmt,
+	io::{Read, Write},
+	net::Shutdown,
+	os::unix::{io::AsRawFd, net::UnixStream},
 	path::{Path, PathBuf},
-	time::Instant,
+	str::FromStr,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		mpsc::Receiver,
+		Arc,
+	},
+	time::{Duration, Instant},
 };
 
 #[cfg(feature = "wayland-data-control")]
-use log::{trace, warn};
-use percent_encoding::{percent_decode, percent_encode, AsciiSet, CONTROLS};
+use log::trace;
+use log::warn;
+use parking_lot::Mutex;
 
 #[cfg(feature = "image-data")]
-use crate::ImageData;
-use crate::{common::private, Error};
+use crate::{
+	common::{decode_image, encode_images, from_image_error},
+	ImageEncoding,
+};
+#[cfg(feature = "image-data-png")]
+use crate::{
+	common::{encode_png, DEFAULT_MAX_IMAGE_PIXELS},
+	ImageData, ImageOrBytes,
+};
+use crate::{
+	common::{if_available, private, text_or_html_stripped, Chunks, Recorder},
+	formats, ClipboardText, ConversionStage, Diagnostics, Error, FileOp, Format, SetReport,
+	TextSource,
+};
+
+/// An X11 atom, as used by `x11rb`, which names a selection, a target or a property.
+#[cfg(feature = "x11-interop")]
+pub use x11rb::protocol::xproto::Atom as X11Atom;
 
 // Magic strings used in `Set::exclude_from_history()` on linux
 const KDE_EXCLUSION_MIME: &str = "x-kde-passwordManagerHint";
 const KDE_EXCLUSION_HINT: &[u8] = b"secret";
 
+// Targets which tell whether files were cut, used by `Get::file_list_with_op()`
+const GNOME_COPIED_FILES_MIME: &str = "x-special/gnome-copied-files";
+const KDE_CUT_SELECTION_MIME: &str = "application/x-kde-cutselection";
+
+const UTF8_STRING_TARGET: &str = "UTF8_STRING";
+// Text in ISO Latin-1 encoding
+// See: https://tronche.com/gui/x/icccm/sec-2.html#s-2.6.2
+const STRING_TARGET: &str = "STRING";
+const TEXT_TARGET: &str = "TEXT";
+const HTML_MIME: &str = "text/html";
+const URI_LIST_MIME: &str = "text/uri-list";
+const PNG_MIME: &str = "image/png";
+/// The target Qt applications on X11 prefer for images, which they serve and accept in any format
+/// `QImage` can read. Older Qt 5 versions request it before the standard MIME types, so PNG images
+/// are served under it as well, and it's read as a fallback.
+const QT_IMAGE_MIME: &str = "application/x-qt-image";
+
+/// The targets which are served the `UTF8_STRING` text as well, unless they are offered
+/// explicitly. `TEXT` prefers the Latin-1 `STRING` text if there is one.
+const UTF8_ALIASES: [&str; 4] =
+	["text/plain;charset=utf-8", "text/plain;charset=UTF-8", "text/plain", TEXT_TARGET];
+
+// How often `serve` checks for its shutdown signal and deadline.
+const SERVE_POLL_DUR: Duration = Duration::from_millis(50);
+
 mod x11;
 
 #[cfg(feature = "wayland-data-control")]
@@ -26,66 +77,267 @@ fn into_unknown<E: std::fmt::Display>(error: E) -> Error {
 	Error::Unknown { description: error.to_string() }
 }
 
+/// Decodes an image file of any of the formats read from the clipboard.
 #[cfg(feature = "image-data")]
-fn encode_as_png(image: &ImageData) -> Result<Vec<u8>, Error> {
-	use image::ImageEncoder as _;
+fn decode_image_file(bytes: &[u8], max_pixels: u64) -> Result<ImageData<'static>, Error> {
+	let mut reader =
+		image::io::Reader::new(std::io::Cursor::new(bytes)).with_guessed_format().map_err(
+			|error| Error::conversion(Format::Image, ConversionStage::Decode, error.to_string()),
+		)?;
+	let format = match reader.format() {
+		Some(image::ImageFormat::Png) => Format::Png,
+		Some(image::ImageFormat::Jpeg) => Format::Jpeg,
+		Some(image::ImageFormat::Bmp) => Format::Bitmap,
+		Some(image::ImageFormat::Tiff) => Format::Tiff,
+		_ => Format::Image,
+	};
+	// `decode_image` applies the limits once the dimensions are known.
+	reader.no_limits();
+	let decoder = reader.into_decoder().map_err(|error| from_image_error(error, format))?;
+	decode_image(decoder, format, max_pixels)
+}
 
-	if image.bytes.is_empty() || image.width == 0 || image.height == 0 {
-		return Err(Error::ConversionFailure);
-	}
+/// Without the `image` crate, PNG is the only format which is read from the clipboard.
+#[cfg(all(feature = "image-data-png", not(feature = "image-data")))]
+fn decode_image_file(bytes: &[u8], max_pixels: u64) -> Result<ImageData<'static>, Error> {
+	crate::common::decode_png(bytes, max_pixels)
+}
 
-	let mut png_bytes = Vec::new();
-	let encoder = image::codecs::png::PngEncoder::new(&mut png_bytes);
-	encoder
-		.write_image(
-			image.bytes.as_ref(),
-			image.width as u32,
-			image.height as u32,
-			image::ExtendedColorType::Rgba8,
-		)
-		.map_err(|_| Error::ConversionFailure)?;
+/// Decodes `text/html` contents, which are UTF-8 unless they start with a UTF-16 byte order mark.
+///
+/// UTF-16 HTML is mostly seen when copying from Windows applications running under Wine.
+fn decode_html(bytes: Vec<u8>) -> Result<String, Error> {
+	let from_bytes: fn([u8; 2]) -> u16 = match bytes.get(..2) {
+		Some([0xFF, 0xFE]) => u16::from_le_bytes,
+		Some([0xFE, 0xFF]) => u16::from_be_bytes,
+		_ => return String::from_utf8(bytes).map_err(|error| html_error(error.to_string())),
+	};
+
+	let units = bytes[2..].chunks(2).map(|unit| match *unit {
+		[a, b] => Ok(from_bytes([a, b])),
+		_ => Err(html_error("the UTF-16 text has an odd number of bytes")),
+	});
+	let units = units.collect::<Result<Vec<u16>, Error>>()?;
+
+	String::from_utf16(&units).map_err(|error| html_error(error.to_string()))
+}
 
-	Ok(png_bytes)
+fn html_error(message: impl Into<String>) -> Error {
+	Error::conversion(Format::Html, ConversionStage::Decode, message)
+}
+
+/// Encodes `text` as Latin-1 (ISO 8859-1), as used by X11's `STRING` target.
+///
+/// Returns `None` if `text` contains characters that can't be represented in Latin-1.
+fn encode_latin1(text: &str) -> Option<Vec<u8>> {
+	text.chars().map(|c| u8::try_from(c).ok()).collect()
 }
 
 fn paths_from_uri_list(uri_list: Vec<u8>) -> Vec<PathBuf> {
-	uri_list
-		.split(|char| *char == b'\n')
-		.filter_map(|line| line.strip_prefix(b"file://"))
-		.filter_map(|s| percent_decode(s).decode_utf8().ok())
-		.map(|decoded| PathBuf::from(decoded.as_ref()))
-		.collect()
+	formats::parse_uri_list(&String::from_utf8_lossy(&uri_list))
+}
+
+/// Reads the files on a selection along with whether they were cut, following GNOME's convention
+/// and falling back to `text/uri-list` with KDE's marker. `read` fetches the given target.
+fn file_list_with_op(
+	mut read: impl FnMut(&str) -> Result<Vec<u8>, Error>,
+) -> Result<(Vec<PathBuf>, FileOp), Error> {
+	if let Some(copied_files) = if_available(read(GNOME_COPIED_FILES_MIME))? {
+		let parsed =
+			std::str::from_utf8(&copied_files).ok().and_then(formats::parse_gnome_copied_files);
+		if let Some((op, paths)) = parsed {
+			return Ok((paths, op));
+		}
+	}
+
+	let paths = paths_from_uri_list(read(URI_LIST_MIME)?);
+	let op = if_available(read(KDE_CUT_SELECTION_MIME))?
+		.map_or(FileOp::Copy, |marker| formats::parse_kde_cut_selection(&marker));
+
+	Ok((paths, op))
+}
+
+/// A format under which content placed onto a selection is offered.
+///
+/// Both backends offer exactly what the `*_offers` functions below return, so that the same
+/// content is offered the same way, whichever backend is in use.
+#[derive(Debug, PartialEq, Eq)]
+struct Offer {
+	/// The X11 target or Wayland MIME type.
+	mime: Cow<'static, str>,
+	bytes: SharedBytes,
+	/// Whether the format is listed among the available ones. Only X11 is able to hide formats.
+	advertised: bool,
+}
+
+impl Offer {
+	fn new(mime: impl Into<Cow<'static, str>>, bytes: impl Into<SharedBytes>) -> Self {
+		Self { mime: mime.into(), bytes: bytes.into(), advertised: true }
+	}
+}
+
+/// The bytes of an offer, which are shared instead of copied by the targets they're served
+/// under and whoever reads them back.
+///
+/// A `Vec` is kept as it is so that it can be taken back without copying it once it isn't shared
+/// anymore, and the bytes of an `Arc<str>` are shared with the application which placed it.
+#[derive(Clone, Debug)]
+enum SharedBytes {
+	Vec(Arc<Vec<u8>>),
+	Slice(Arc<[u8]>),
+}
+
+impl SharedBytes {
+	/// Takes the bytes out, which only copies them if they are still shared.
+	fn into_vec(self) -> Vec<u8> {
+		match self {
+			Self::Vec(bytes) => Arc::try_unwrap(bytes).unwrap_or_else(|bytes| (*bytes).clone()),
+			Self::Slice(bytes) => bytes.to_vec(),
+		}
+	}
+}
+
+impl std::ops::Deref for SharedBytes {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match self {
+			Self::Vec(bytes) => bytes,
+			Self::Slice(bytes) => bytes,
+		}
+	}
+}
+
+impl PartialEq for SharedBytes {
+	fn eq(&self, other: &Self) -> bool {
+		**self == **other
+	}
+}
+
+impl Eq for SharedBytes {}
+
+impl From<Vec<u8>> for SharedBytes {
+	fn from(bytes: Vec<u8>) -> Self {
+		Self::Vec(Arc::new(bytes))
+	}
+}
+
+impl From<Arc<str>> for SharedBytes {
+	fn from(text: Arc<str>) -> Self {
+		Self::Slice(text.into())
+	}
+}
+
+/// Starts a list of offers with the history exclusion hint, if requested.
+///
+/// The hint comes first so that clipboard managers going through the formats in order see it
+/// before anything worth saving.
+fn offers_with_capacity(capacity: usize, exclusion: HistoryExclusion) -> Vec<Offer> {
+	let mut offers = Vec::with_capacity(capacity + usize::from(exclusion.is_excluded()));
+	if exclusion.is_excluded() {
+		offers.push(Offer {
+			mime: KDE_EXCLUSION_MIME.into(),
+			bytes: KDE_EXCLUSION_HINT.to_vec().into(),
+			advertised: exclusion != HistoryExclusion::Strict,
+		});
+	}
+	offers
+}
+
+fn text_offers(utf8: impl Into<SharedBytes>, config: &WriteConfig) -> Vec<Offer> {
+	let mut utf8 = utf8.into();
+	if let Some(trailing_newline) = config.trailing_newline {
+		let mut bytes = utf8.into_vec();
+		set_trailing_newline(&mut bytes, trailing_newline);
+		utf8 = bytes.into();
+	}
+	let latin1 = if config.text_aliases {
+		std::str::from_utf8(&utf8).ok().and_then(encode_latin1)
+	} else {
+		None
+	};
+
+	let mut offers = offers_with_capacity(2, config.history_exclusion);
+	offers.push(Offer::new(UTF8_STRING_TARGET, utf8));
+	if let Some(latin1) = latin1 {
+		offers.push(Offer::new(STRING_TARGET, latin1));
+	}
+	offers
+}
+
+/// Appends a `\n` to the text if `trailing_newline` is set and it doesn't end in one, or removes
+/// a single trailing `\n` otherwise.
+fn set_trailing_newline(utf8: &mut Vec<u8>, trailing_newline: bool) {
+	let ends_in_newline = utf8.last() == Some(&b'\n');
+	if trailing_newline && !ends_in_newline {
+		utf8.push(b'\n');
+	} else if !trailing_newline && ends_in_newline {
+		utf8.pop();
+	}
+}
+
+fn html_offers(html: Vec<u8>, alt: Option<Vec<u8>>, config: &WriteConfig) -> Vec<Offer> {
+	let mut offers = offers_with_capacity(2, config.history_exclusion);
+	if let Some(alt) = alt {
+		offers.push(Offer::new(UTF8_STRING_TARGET, alt));
+	}
+	offers.push(Offer::new(HTML_MIME, html));
+	offers
+}
+
+/// Offers already encoded data, like images, each under its MIME type.
+fn encoded_offers(encoded: Vec<(&'static str, Vec<u8>)>, config: &WriteConfig) -> Vec<Offer> {
+	let mut offers = offers_with_capacity(encoded.len(), config.history_exclusion);
+	offers.extend(encoded.into_iter().map(|(mime, bytes)| Offer::new(mime, bytes)));
+	offers
+}
+
+fn file_list_offers(uri_list: String, config: &WriteConfig) -> Vec<Offer> {
+	let mut offers = offers_with_capacity(1, config.history_exclusion);
+	offers.push(Offer::new(URI_LIST_MIME, uri_list.into_bytes()));
+	offers
+}
+
+/// Lists every target under which `offers` are served, together with the data served for it.
+///
+/// This includes the targets `UTF8_STRING` text is also served under, see `UTF8_ALIASES`. X11
+/// resolves these when answering requests instead, as it keeps the offers as they are.
+#[cfg_attr(not(feature = "wayland-data-control"), allow(dead_code))]
+fn served_targets(offers: &[Offer]) -> Vec<(&str, &Offer)> {
+	let mut targets = Vec::with_capacity(offers.len() + UTF8_ALIASES.len());
+	for offer in offers {
+		targets.push((&*offer.mime, offer));
+
+		if offer.mime == UTF8_STRING_TARGET {
+			let latin1 = offers.iter().find(|offer| offer.mime == STRING_TARGET);
+			for alias in UTF8_ALIASES {
+				if offers.iter().any(|offer| offer.mime == alias) {
+					continue;
+				}
+				let served = match latin1 {
+					Some(latin1) if alias == TEXT_TARGET => latin1,
+					_ => offer,
+				};
+				targets.push((alias, served));
+			}
+		}
+	}
+	targets
 }
 
 fn paths_to_uri_list(file_list: &[impl AsRef<Path>]) -> Result<String, Error> {
-	// The characters that require encoding, which includes £ and € but they can't be added to the set.
-	const ASCII_SET: &AsciiSet = &CONTROLS
-		.add(b'#')
-		.add(b';')
-		.add(b'?')
-		.add(b'[')
-		.add(b']')
-		.add(b' ')
-		.add(b'\"')
-		.add(b'%')
-		.add(b'<')
-		.add(b'>')
-		.add(b'\\')
-		.add(b'^')
-		.add(b'`')
-		.add(b'{')
-		.add(b'|')
-		.add(b'}');
-
-	file_list
-		.iter()
-		.filter_map(|path| {
-			path.as_ref().canonicalize().ok().map(|path| {
-				format!("file://{}", percent_encode(path.as_os_str().as_bytes(), ASCII_SET))
-			})
-		})
-		.reduce(|uri_list, uri| uri_list + "\n" + &uri)
-		.ok_or(Error::ConversionFailure)
+	let paths: Vec<_> =
+		file_list.iter().filter_map(|path| path.as_ref().canonicalize().ok()).collect();
+
+	if paths.is_empty() {
+		return Err(Error::conversion(
+			Format::FileList,
+			ConversionStage::Validate,
+			"none of the paths exist",
+		));
+	}
+
+	Ok(formats::build_uri_list(&paths))
 }
 
 /// Clipboard selection
@@ -97,7 +349,8 @@ fn paths_to_uri_list(file_list: &[impl AsRef<Path>]) -> Result<String, Error> {
 ///
 /// See <https://specifications.freedesktop.org/clipboards-spec/clipboards-0.1.txt> for a better
 /// description of the different clipboards.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LinuxClipboardKind {
 	/// Typically used selection for explicit cut/copy/paste actions (ie. windows/macos like
 	/// clipboard behavior)
@@ -117,6 +370,36 @@ pub enum LinuxClipboardKind {
 	Secondary,
 }
 
+impl LinuxClipboardKind {
+	/// All selections, in the order [`Clipboard`](Self::Clipboard), [`Primary`](Self::Primary)
+	/// and [`Secondary`](Self::Secondary).
+	pub const fn all() -> [Self; 3] {
+		[Self::Clipboard, Self::Primary, Self::Secondary]
+	}
+}
+
+impl fmt::Display for LinuxClipboardKind {
+	/// Writes the name of the selection in lowercase, like `primary`, as accepted by `FromStr`.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Self::Clipboard => "clipboard",
+			Self::Primary => "primary",
+			Self::Secondary => "secondary",
+		})
+	}
+}
+
+impl FromStr for LinuxClipboardKind {
+	type Err = Error;
+
+	/// Parses the name of a selection, like `primary`, ignoring case.
+	fn from_str(s: &str) -> Result<Self, Error> {
+		Self::all().into_iter().find(|kind| kind.to_string().eq_ignore_ascii_case(s)).ok_or_else(
+			|| Error::unknown(format!("`{s}` is not one of `clipboard`, `primary` or `secondary`")),
+		)
+	}
+}
+
 pub(crate) enum Clipboard {
 	X11(x11::Clipboard),
 
@@ -124,218 +407,1187 @@ pub(crate) enum Clipboard {
 	WlDataControl(wayland::Clipboard),
 }
 
+/// The kind of backend that `Clipboard::new` settled on.
+#[derive(Clone, Copy)]
+enum Backend {
+	X11,
+	#[cfg(feature = "wayland-data-control")]
+	WlDataControl,
+}
+
+/// Process-wide state of choosing and connecting a backend, which happens under its lock so
+/// that concurrent calls to `Clipboard::new` don't probe or connect more than once.
+struct BackendInit {
+	/// The backend that was chosen by the first successful `Clipboard::new`. It is reused by all
+	/// later ones instead of probing again.
+	backend: Option<Backend>,
+	/// Why `backend` was chosen, for `Clipboard::diagnostics`.
+	reason: String,
+	/// The error of the last failed attempt.
+	last_error: Option<Error>,
+}
+
+static BACKEND_INIT: Mutex<BackendInit> = parking_lot::const_mutex(BackendInit {
+	backend: None,
+	reason: String::new(),
+	last_error: None,
+});
+
+/// The number of failed attempts so far, readable without waiting for `BACKEND_INIT`.
+static FAILED_INITS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of times a Wayland connection was made to probe for the data control protocol.
+#[cfg(all(test, feature = "wayland-data-control"))]
+static WAYLAND_PROBES: AtomicU64 = AtomicU64::new(0);
+
 impl Clipboard {
 	pub(crate) fn new() -> Result<Self, Error> {
+		Self::connect(x11::Clipboard::new)
+	}
+
+	/// Like `new`, but an X11 clipboard gets a connection of its own. Wayland clipboards never
+	/// share one anyway.
+	pub(crate) fn new_isolated() -> Result<Self, Error> {
+		Self::connect(x11::Clipboard::new_isolated)
+	}
+
+	/// Connects to the backend that was chosen before, or chooses one, making X11 clipboards
+	/// with `new_x11`.
+	fn connect(new_x11: fn() -> Result<x11::Clipboard, Error>) -> Result<Self, Error> {
+		let failed_before = FAILED_INITS.load(Ordering::Acquire);
+		let mut init = BACKEND_INIT.lock();
+
+		// Callers which were waiting for an attempt that failed share its error rather than
+		// immediately repeating the whole handshake.
+		if FAILED_INITS.load(Ordering::Acquire) != failed_before {
+			if let Some(error) = &init.last_error {
+				return Err(error.duplicate());
+			}
+		}
+
+		let result = match init.backend {
+			Some(Backend::X11) => new_x11().map(Self::X11),
+			#[cfg(feature = "wayland-data-control")]
+			Some(Backend::WlDataControl) => Ok(Self::WlDataControl(wayland::Clipboard::default())),
+			None => Self::probe(new_x11).map(|(clipboard, reason)| {
+				init.reason = reason;
+				clipboard
+			}),
+		};
+
+		match &result {
+			Ok(clipboard) => {
+				init.backend = Some(clipboard.backend());
+				init.last_error = None;
+			}
+			Err(error) => {
+				init.last_error = Some(error.duplicate());
+				FAILED_INITS.fetch_add(1, Ordering::Release);
+			}
+		}
+
+		result
+	}
+
+	/// Chooses a backend and connects to it, returning why it was chosen as well.
+	fn probe(new_x11: fn() -> Result<x11::Clipboard, Error>) -> Result<(Self, String), Error> {
 		#[cfg(feature = "wayland-data-control")]
-		{
-			if std::env::var_os("WAYLAND_DISPLAY").is_some() {
-				// Wayland is available
-				match wayland::Clipboard::new() {
-					Ok(clipboard) => {
-						trace!("Successfully initialized the Wayland data control clipboard.");
-						return Ok(Self::WlDataControl(clipboard));
-					}
-					Err(e) => warn!(
+		let reason = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+			// Wayland is available
+			#[cfg(test)]
+			WAYLAND_PROBES.fetch_add(1, Ordering::Relaxed);
+			match wayland::Clipboard::new() {
+				Ok(clipboard) => {
+					trace!("Successfully initialized the Wayland data control clipboard.");
+					let reason =
+						"WAYLAND_DISPLAY is set and the data control protocol is supported";
+					return Ok((Self::WlDataControl(clipboard), reason.into()));
+				}
+				Err(e) => {
+					warn!(
 						"Tried to initialize the wayland data control protocol clipboard, but failed. Falling back to the X11 clipboard protocol. The error was: {}",
 						e
-					),
+					);
+					format!("the Wayland data control clipboard failed to initialize: {e}")
 				}
 			}
-		}
-		Ok(Self::X11(x11::Clipboard::new()?))
-	}
-}
+		} else {
+			"WAYLAND_DISPLAY is not set".to_owned()
+		};
+		#[cfg(not(feature = "wayland-data-control"))]
+		let reason = "the `wayland-data-control` feature is disabled".to_owned();
 
-pub(crate) struct Get<'clipboard> {
-	clipboard: &'clipboard mut Clipboard,
-	selection: LinuxClipboardKind,
-}
+		Ok((Self::X11(new_x11()?), reason))
+	}
 
-impl<'clipboard> Get<'clipboard> {
-	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard, selection: LinuxClipboardKind::Clipboard }
+	fn backend(&self) -> Backend {
+		match self {
+			Self::X11(_) => Backend::X11,
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => Backend::WlDataControl,
+		}
 	}
 
-	pub(crate) fn text(self) -> Result<String, Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.get_text(self.selection),
+	pub(crate) fn diagnose(&self, diagnostics: &mut Diagnostics) {
+		diagnostics.backend_reason = BACKEND_INIT.lock().reason.clone();
+		match self {
+			Self::X11(clipboard) => clipboard.diagnose(diagnostics),
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.get_text(self.selection),
+			Self::WlDataControl(clipboard) => clipboard.diagnose(diagnostics),
 		}
+		diagnostics.primary_selection = Some(self.supports_selection(LinuxClipboardKind::Primary));
+		diagnostics.environment =
+			["WAYLAND_DISPLAY", "DISPLAY", "XDG_SESSION_TYPE", "XDG_CURRENT_DESKTOP"]
+				.into_iter()
+				.filter_map(|name| Some((name.to_owned(), std::env::var(name).ok()?)))
+				.collect();
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.get_image(self.selection),
+	fn is_healthy(&self) -> bool {
+		match self {
+			Self::X11(clipboard) => clipboard.is_healthy(),
+			// There is no background thread which could have stopped.
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.get_image(self.selection),
+			Self::WlDataControl(_) => true,
 		}
 	}
 
-	pub(crate) fn html(self) -> Result<String, Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.get_html(self.selection),
+	fn clone_handle(&self) -> Self {
+		match self {
+			Self::X11(clipboard) => Self::X11(clipboard.clone_handle()),
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.get_html(self.selection),
+			Self::WlDataControl(clipboard) => Self::WlDataControl(clipboard.clone_handle()),
 		}
 	}
 
-	pub(crate) fn file_list(self) -> Result<Vec<PathBuf>, Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.get_file_list(self.selection),
+	fn restart_server(&mut self) -> Result<(), Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.restart_server(),
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.get_file_list(self.selection),
+			Self::WlDataControl(_) => Ok(()),
 		}
 	}
-}
 
-/// Linux-specific extensions to the [`Get`](super::Get) builder.
-pub trait GetExtLinux: private::Sealed {
-	/// Sets the clipboard the operation will retrieve data from.
-	///
-	/// If wayland support is enabled and available, attempting to use the Secondary clipboard will
-	/// return an error.
-	fn clipboard(self, selection: LinuxClipboardKind) -> Self;
-}
+	fn last_read_stats(&self) -> Option<ReadStats> {
+		match self {
+			Self::X11(clipboard) => clipboard.last_read_stats(),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => None,
+		}
+	}
 
-impl GetExtLinux for crate::Get<'_> {
-	fn clipboard(mut self, selection: LinuxClipboardKind) -> Self {
-		self.platform.selection = selection;
-		self
+	fn trim_memory(&self) -> Result<usize, Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.trim_memory(),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => Ok(0),
+		}
 	}
-}
 
-/// Configuration on how long to wait for a new X11 copy event is emitted.
-#[derive(Default)]
-pub(crate) enum WaitConfig {
-	/// Waits until the given [`Instant`] has reached.
-	Until(Instant),
+	fn has_clipboard_manager(&self) -> Result<bool, Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.has_clipboard_manager(),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => Ok(false),
+		}
+	}
 
-	/// Waits forever until a new event is reached.
-	Forever,
+	fn owned_selections(&self) -> Result<Vec<LinuxClipboardKind>, Error> {
+		let mut owned = Vec::new();
+		for selection in LinuxClipboardKind::all() {
+			if !self.supports_selection(selection) {
+				continue;
+			}
+			let is_owner = match self {
+				Self::X11(clipboard) => clipboard.is_owner(selection)?,
+				#[cfg(feature = "wayland-data-control")]
+				Self::WlDataControl(clipboard) => clipboard.is_owner(selection)?,
+			};
+			if is_owner {
+				owned.push(selection);
+			}
+		}
+		Ok(owned)
+	}
 
-	/// It shouldn't wait.
-	#[default]
-	None,
-}
+	pub(crate) fn content_age(&self) -> Result<Option<Duration>, Error> {
+		let selection = LinuxClipboardKind::Clipboard;
+		match self {
+			Self::X11(clipboard) => clipboard.content_age(selection),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(clipboard) => clipboard.content_age(selection),
+		}
+	}
 
-pub(crate) struct Set<'clipboard> {
-	clipboard: &'clipboard mut Clipboard,
-	wait: WaitConfig,
-	selection: LinuxClipboardKind,
-	exclude_from_history: bool,
-}
+	fn is_xwayland(&self) -> bool {
+		match self {
+			Self::X11(clipboard) => clipboard.is_xwayland(),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => false,
+		}
+	}
 
-impl<'clipboard> Set<'clipboard> {
-	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self {
-			clipboard,
-			wait: WaitConfig::default(),
-			selection: LinuxClipboardKind::Clipboard,
-			exclude_from_history: false,
+	#[allow(unused_variables)]
+	fn supports_selection(&self, selection: LinuxClipboardKind) -> bool {
+		match self {
+			// All three selections are part of the core protocol.
+			Self::X11(_) => true,
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(clipboard) => clipboard.supports_selection(selection),
 		}
 	}
 
-	pub(crate) fn text(self, text: Cow<'_, str>) -> Result<(), Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => {
-				clipboard.set_text(text, self.selection, self.wait, self.exclude_from_history)
-			}
+	pub(crate) fn new_with_timeouts(timeouts: TimeoutConfig) -> Result<Self, Error> {
+		let clipboard = Self::new()?;
+		match &clipboard {
+			Self::X11(clipboard) => clipboard.set_timeouts(timeouts),
+			// There is nothing to wait for other than the compositor, which
+			// `wl-clipboard-rs` takes care of.
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => {}
+		}
+		Ok(clipboard)
+	}
 
+	pub(crate) fn read_targets(&mut self) -> &mut ReadTargets {
+		match self {
+			Self::X11(clipboard) => &mut clipboard.targets,
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => {
-				clipboard.set_text(text, self.selection, self.wait, self.exclude_from_history)
-			}
+			Self::WlDataControl(clipboard) => &mut clipboard.targets,
 		}
 	}
 
-	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => {
-				clipboard.set_html(html, alt, self.selection, self.wait, self.exclude_from_history)
-			}
+	pub(crate) fn reconnect(&mut self) -> Result<(), Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.restart_server(),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(clipboard) => clipboard.reconnect(),
+		}
+	}
 
+	fn refresh_capabilities(&mut self) -> Result<(), Error> {
+		match self {
+			// Nothing is cached, every selection is always available.
+			Self::X11(_) => Ok(()),
+			// Reconnecting probes the compositor again.
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => {
-				clipboard.set_html(html, alt, self.selection, self.wait, self.exclude_from_history)
-			}
+			Self::WlDataControl(clipboard) => clipboard.reconnect(),
 		}
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn image(self, image: ImageData<'_>) -> Result<(), Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => {
-				clipboard.set_image(image, self.selection, self.wait, self.exclude_from_history)
-			}
+	fn capture(&mut self, selection: LinuxClipboardKind) -> Result<Capture, Error> {
+		let config = ReadConfig { selection, ..ReadConfig::default() };
+		let formats = match self {
+			Self::X11(clipboard) => clipboard.capture(&config)?,
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(clipboard) => clipboard.capture(&config)?,
+		};
+		Ok(Capture { selection, formats })
+	}
 
+	fn serve_target(
+		&mut self,
+		selection: LinuxClipboardKind,
+		target: &str,
+		data: Vec<u8>,
+	) -> Result<(), Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.serve_target(selection, target, data),
+			// The offer handed to the compositor can't be changed, only replaced.
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => {
-				clipboard.set_image(image, self.selection, self.wait, self.exclude_from_history)
-			}
+			Self::WlDataControl(_) => Err(Error::ClipboardNotSupported),
 		}
 	}
 
-	pub(crate) fn file_list(self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
-		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.set_file_list(
-				file_list,
-				self.selection,
-				self.wait,
-				self.exclude_from_history,
-			),
+	fn restore(&mut self, capture: &Capture) -> Result<(), Error> {
+		if capture.formats.is_empty() {
+			return Err(Error::content_not_available());
+		}
 
+		let config = WriteConfig { selection: capture.selection, ..WriteConfig::default() };
+		match self {
+			Self::X11(clipboard) => clipboard.restore(&capture.formats, &config),
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.set_file_list(
-				file_list,
-				self.selection,
-				self.wait,
-				self.exclude_from_history,
-			),
+			Self::WlDataControl(clipboard) => clipboard.restore(&capture.formats, &config),
 		}
 	}
 }
 
-/// Linux specific extensions to the [`Set`](super::Set) builder.
-pub trait SetExtLinux: private::Sealed {
-	/// Whether to wait for the clipboard's contents to be replaced after setting it.
-	///
-	/// The Wayland and X11 clipboards work by having the clipboard content being, at any given
-	/// time, "owned" by a single process, and that process is expected to reply to all the requests
-	/// from any other system process that wishes to access the clipboard's contents. As a
-	/// consequence, when that process exits the contents of the clipboard will effectively be
-	/// cleared since there is no longer anyone around to serve requests for it.
-	///
-	/// This poses a problem for short-lived programs that just want to copy to the clipboard and
-	/// then exit, since they don't want to wait until the user happens to copy something else just
-	/// to finish. To resolve that, whenever the user copies something you can offload the actual
-	/// work to a newly-spawned daemon process which will run in the background (potentially
-	/// outliving the current process) and serve all the requests. That process will then
-	/// automatically and silently exit once the user copies something else to their clipboard so it
-	/// doesn't take up too many resources.
-	///
-	/// To support that pattern, this method will not only have the contents of the clipboard be
-	/// set, but will also wait and continue to serve requests until the clipboard is overwritten.
-	/// As long as you don't exit the current process until that method has returned, you can avoid
-	/// all surprising situations where the clipboard's contents seemingly disappear from under your
-	/// feet.
-	///
-	/// See the [daemonize example] for a demo of how you could implement this.
-	///
-	/// [daemonize example]: https://github.com/1Password/arboard/blob/master/examples/daemonize.rs
-	fn wait(self) -> Self;
+/// Configuration for a single read from the clipboard.
+#[derive(Clone, Copy)]
+pub(crate) struct ReadConfig {
+	pub(crate) selection: LinuxClipboardKind,
 
-	/// Whether or not to wait for the clipboard's content to be replaced after setting it. This waits until the
-	/// `deadline` has exceeded.
-	///
+	/// How many times an unanswered X11 `ConvertSelection` request is re-issued before the
+	/// read gives up.
+	pub(crate) convert_retries: u32,
+
+	/// How many times the whole read is attempted when it fails with [`Error::Unknown`], and
+	/// how long to wait in between, see `GetExtLinux::retry`.
+	pub(crate) attempts: u32,
+	pub(crate) retry_backoff: Duration,
+
+	/// When the read gives up on an owner which takes too long, if earlier than usual.
+	pub(crate) deadline: Option<Instant>,
+
+	/// Whether data is accepted when the X11 owner labels it with a type other than the
+	/// requested target.
+	pub(crate) any_reply_type: bool,
+
+	/// How much of the data is needed, if not all of it. More than this may still be returned.
+	pub(crate) max_bytes: Option<usize>,
+
+	/// The X11 server timestamp the selection is read as of, rather than the current time.
+	pub(crate) time: Option<u32>,
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) max_image_pixels: u64,
+}
+
+impl Default for ReadConfig {
+	fn default() -> Self {
+		Self {
+			selection: LinuxClipboardKind::Clipboard,
+			convert_retries: 1,
+			attempts: 1,
+			retry_backoff: Duration::ZERO,
+			deadline: None,
+			any_reply_type: false,
+			max_bytes: None,
+			time: None,
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: DEFAULT_MAX_IMAGE_PIXELS,
+		}
+	}
+}
+
+/// The configuration of a `Get`, which outlives it.
+pub(crate) type GetConfig = ReadConfig;
+
+/// The targets which text and images are read from in place of the built-in ones, in order of
+/// preference, as set with `Clipboard::set_text_targets` and `Clipboard::set_image_targets`.
+#[derive(Clone, Default)]
+pub(crate) struct ReadTargets {
+	pub(crate) text: Option<Vec<String>>,
+	#[cfg(feature = "image-data-png")]
+	pub(crate) image: Option<Vec<String>>,
+}
+
+pub(crate) struct Get<'clipboard> {
+	clipboard: &'clipboard mut Clipboard,
+	config: ReadConfig,
+	/// The change to wait for before reading, and until when, see `Get::wait`.
+	wait: Option<(Result<ChangeWatch, Error>, Option<Instant>)>,
+}
+
+enum ChangeWatch {
+	X11(Box<x11::ChangeWatch>),
+	#[cfg(feature = "wayland-data-control")]
+	WlDataControl(wayland::ChangeWatch),
+}
+
+impl<'clipboard> Get<'clipboard> {
+	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
+		Self { clipboard, config: ReadConfig::default(), wait: None }
+	}
+
+	pub(crate) fn with_config(clipboard: &'clipboard mut Clipboard, config: &GetConfig) -> Self {
+		Self { clipboard, config: *config, wait: None }
+	}
+
+	pub(crate) fn into_config(self) -> GetConfig {
+		self.config
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn max_image_pixels(mut self, max_pixels: u64) -> Self {
+		self.config.max_image_pixels = max_pixels;
+		self
+	}
+
+	pub(crate) fn deadline(mut self, deadline: Instant) -> Self {
+		self.config.deadline = Some(deadline);
+		self
+	}
+
+	pub(crate) fn wait(mut self, deadline: Option<Instant>) -> Self {
+		let watch = match &*self.clipboard {
+			Clipboard::X11(clipboard) => clipboard
+				.watch_changes(self.config.selection)
+				.map(|watch| ChangeWatch::X11(Box::new(watch))),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => {
+				clipboard.watch_changes(&self.config).map(ChangeWatch::WlDataControl)
+			}
+		};
+		self.wait = Some((watch, deadline));
+		self
+	}
+
+	pub(crate) fn wait_for_change(mut self) -> Result<Self, Error> {
+		match self.wait.take() {
+			Some((Ok(ChangeWatch::X11(watch)), deadline)) => watch.wait(deadline)?,
+			#[cfg(feature = "wayland-data-control")]
+			Some((Ok(ChangeWatch::WlDataControl(watch)), deadline)) => watch.wait(deadline)?,
+			Some((Err(error), _)) => return Err(error),
+			None => {}
+		}
+		Ok(self)
+	}
+
+	pub(crate) fn text(self) -> Result<String, Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_text(&self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_text(&self.config),
+		})
+	}
+
+	pub(crate) fn text_or_html_stripped(self) -> Result<(String, TextSource), Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => {
+				let text = clipboard.get_text(&self.config);
+				text_or_html_stripped(text, || clipboard.get_html(&self.config))
+			}
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => {
+				let text = clipboard.get_text(&self.config);
+				text_or_html_stripped(text, || clipboard.get_html(&self.config))
+			}
+		})
+	}
+
+	pub(crate) fn text_truncated(self, max_bytes: usize) -> Result<(String, bool), Error> {
+		let config = ReadConfig { max_bytes: Some(max_bytes), ..self.config };
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_text_truncated(&config, max_bytes),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_text_truncated(&config, max_bytes),
+		})
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
+		self.image_or_bytes()?.into_image()
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image_or_bytes(self) -> Result<ImageOrBytes, Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_image_or_bytes(&self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_image_or_bytes(&self.config),
+		})
+	}
+
+	pub(crate) fn html(self) -> Result<String, Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_html(&self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_html(&self.config),
+		})
+	}
+
+	pub(crate) fn html_and_text(self) -> Result<(Option<String>, Option<String>), Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => Ok((
+				if_available(clipboard.get_html(&self.config))?,
+				if_available(clipboard.get_text(&self.config))?,
+			)),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => Ok((
+				if_available(clipboard.get_html(&self.config))?,
+				if_available(clipboard.get_text(&self.config))?,
+			)),
+		})
+	}
+
+	pub(crate) fn file_list(self) -> Result<Vec<PathBuf>, Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_file_list(&self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_file_list(&self.config),
+		})
+	}
+
+	#[cfg(feature = "x11-interop")]
+	pub(crate) fn format_atom(self, target: X11Atom) -> Result<Vec<u8>, Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_atom(target, &self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => Err(Error::ClipboardNotSupported),
+		})
+	}
+
+	pub(crate) fn raw_typed(self, target: &str) -> Result<(String, Vec<u8>), Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_typed(target, &self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => {
+				let mut bytes = Vec::new();
+				clipboard.write_to(target, &self.config, &mut bytes)?;
+				Ok((target.to_owned(), bytes))
+			}
+		})
+	}
+
+	/// Unlike the other reads, this isn't retried, as the writer may have been written to.
+	pub(crate) fn write_to(self, target: &str, writer: &mut dyn Write) -> Result<u64, Error> {
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.write_to(target, &self.config, writer),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.write_to(target, &self.config, writer),
+		}
+	}
+
+	pub(crate) fn file_list_with_op(self) -> Result<(Vec<PathBuf>, FileOp), Error> {
+		retry_read(&self.config, || match &mut *self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_file_list_with_op(&self.config),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_file_list_with_op(&self.config),
+		})
+	}
+}
+
+/// Runs `read` until it succeeds or fails with something other than [`Error::Unknown`], which
+/// may be caused by a connection hiccup, or [`Error::OwnerLost`], making at most
+/// `config.attempts` attempts.
+fn retry_read<T>(
+	config: &ReadConfig,
+	mut read: impl FnMut() -> Result<T, Error>,
+) -> Result<T, Error> {
+	let mut attempt = 1;
+	loop {
+		match read() {
+			Err(error @ (Error::Unknown { .. } | Error::OwnerLost))
+				if attempt < config.attempts
+					&& config.deadline.map_or(true, |deadline| {
+						Instant::now() + config.retry_backoff < deadline
+					}) =>
+			{
+				warn!("Reading the clipboard failed, retrying: {error}");
+				std::thread::sleep(config.retry_backoff);
+				attempt += 1;
+			}
+			result => return result,
+		}
+	}
+}
+
+/// Linux-specific extensions to the [`Get`](super::Get) builder.
+pub trait GetExtLinux: private::Sealed {
+	/// Sets the clipboard the operation will retrieve data from.
+	///
+	/// If wayland support is enabled and available, attempting to use the Secondary clipboard will
+	/// return an error.
+	fn clipboard(self, selection: LinuxClipboardKind) -> Self;
+
+	/// Sets how many times the request for the clipboard's contents is re-sent when the owner
+	/// doesn't answer it in time.
+	///
+	/// Some clipboard owners, such as the clipboard bridges of remote desktop tools, drop the
+	/// first request after syncing their own clipboard and only answer a repeated one. By
+	/// default, one repeated request is sent after a second without any answer. Setting this
+	/// to `0` disables retrying altogether. The overall time a read may take is unchanged.
+	///
+	/// This only has an effect on X11.
+	fn convert_retries(self, retries: u32) -> Self;
+
+	/// Makes up to `attempts` attempts at the read when it fails with [`Error::Unknown`] or
+	/// [`Error::OwnerLost`], waiting `backoff` before each new one.
+	///
+	/// This makes reads more robust over unreliable connections to the display server, like X11
+	/// forwarded over SSH, where a read sometimes fails because the connection was reset, and
+	/// when pasting from applications which are quitting. Each attempt connects anew. Other
+	/// errors, like [`Error::ContentNotAvailable`], are returned right away, and no attempt is
+	/// started which couldn't wait out `backoff` before the deadline set with
+	/// [`Get::deadline`](crate::Get::deadline). By default, a single attempt is made.
+	fn retry(self, attempts: u32, backoff: Duration) -> Self;
+
+	/// Reads the selection as it was at the X11 server timestamp `time`, rather than as it is
+	/// when the request reaches its owner.
+	///
+	/// This is meant for reading the [`Primary`](LinuxClipboardKind::Primary) selection in
+	/// response to a pointer event, like accessibility tools do. Mouse selections can change
+	/// quickly, and passing the `time` of the triggering event (ex. a button release) makes sure
+	/// that the text selected back then is read, and not whatever was selected since. Owners
+	/// following the ICCCM refuse the request if the selection wasn't theirs at `time`, in which
+	/// case [`Error::ContentNotAvailable`] is returned.
+	///
+	/// This only has an effect on X11, and not when the selection is owned by this process.
+	fn at_time(self, time: u32) -> Self;
+
+	/// Completes the "get" operation by reading the selection in the X11 target `target`,
+	/// exactly as its owner delivers it.
+	///
+	/// This is meant for applications which interned their targets already, saving the round
+	/// trip to look up the atom of a name. [`ClipboardExtLinux::x11_atom`] gives the atoms that
+	/// arboard interned itself.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ClipboardNotSupported`] on Wayland, which has no atoms, and
+	/// [`Error::ContentNotAvailable`] if the selection isn't offered in `target`.
+	#[cfg(feature = "x11-interop")]
+	fn format_atom(self, target: X11Atom) -> Result<Vec<u8>, Error>;
+
+	/// Completes the "get" operation by reading the selection in `target`, returning the name of
+	/// the type its owner labelled the reply with along with the data.
+	///
+	/// The type usually is `target` itself, but some owners answer with another one, like
+	/// `STRING` for `UTF8_STRING`, or `ATOM` for `TARGETS`. Other reads fail on such replies, so
+	/// this helps telling why an application's contents can't be read. The data is returned as
+	/// it was delivered, whatever its type.
+	///
+	/// On Wayland, which has no reply types, the type is always `target`.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if the selection isn't offered in `target`.
+	fn raw_typed(self, target: &str) -> Result<(String, Vec<u8>), Error>;
+}
+
+impl GetExtLinux for crate::Get<'_> {
+	fn clipboard(mut self, selection: LinuxClipboardKind) -> Self {
+		self.platform.config.selection = selection;
+		self
+	}
+
+	fn convert_retries(mut self, retries: u32) -> Self {
+		self.platform.config.convert_retries = retries;
+		self
+	}
+
+	fn retry(mut self, attempts: u32, backoff: Duration) -> Self {
+		self.platform.config.attempts = attempts;
+		self.platform.config.retry_backoff = backoff;
+		self
+	}
+
+	fn at_time(mut self, time: u32) -> Self {
+		self.platform.config.time = Some(time);
+		self
+	}
+
+	#[cfg(feature = "x11-interop")]
+	fn format_atom(self, target: X11Atom) -> Result<Vec<u8>, Error> {
+		self.platform.wait_for_change()?.format_atom(target)
+	}
+
+	fn raw_typed(self, target: &str) -> Result<(String, Vec<u8>), Error> {
+		self.platform.wait_for_change()?.raw_typed(target)
+	}
+}
+
+/// Configuration on how long to wait for a new X11 copy event is emitted.
+#[derive(Clone, Copy, Default)]
+pub(crate) enum WaitConfig {
+	/// Waits until the given [`Instant`] has reached.
+	Until(Instant),
+
+	/// Waits forever until a new event is reached.
+	Forever,
+
+	/// It shouldn't wait.
+	#[default]
+	None,
+}
+
+/// How the data placed onto a selection is kept out of clipboard managers' histories.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HistoryExclusion {
+	/// The data may be saved by clipboard managers.
+	None,
+	/// The `x-kde-passwordManagerHint` target is offered alongside the data.
+	Hinted,
+	/// Like `Hinted`, but the hint isn't advertised in X11's `TARGETS`.
+	Strict,
+}
+
+impl HistoryExclusion {
+	fn is_excluded(self) -> bool {
+		self != Self::None
+	}
+}
+
+/// How a request for the contents of a selection is answered, as decided by the handler passed to
+/// [`SetExtLinux::on_request`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestAction {
+	/// Answers with these bytes instead of the data that was set, in the same format.
+	Serve(Vec<u8>),
+	/// Answers with the data that is on the selection, as without a handler.
+	ServeStored,
+}
+
+/// Decides how each request for the contents of a selection is answered, see
+/// [`SetExtLinux::on_request`].
+pub(crate) type RequestHandler = Arc<dyn Fn(&str) -> RequestAction + Send + Sync>;
+
+/// Called whenever the contents of a selection were handed out, see [`SetExtLinux::on_paste`].
+pub(crate) type PasteHandler = Arc<dyn Fn() + Send + Sync>;
+
+/// Workarounds for X11 applications which don't request the contents of our selections the
+/// way the ICCCM describes, see [`SetExtLinux::x11_quirks`].
+///
+/// Whenever a workaround changes how a request is answered, this is logged at the `trace` level
+/// together with the `WM_CLASS` of the requesting window, to help telling which applications
+/// need it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct X11Quirks {
+	pub(crate) none_property: bool,
+	pub(crate) mime_reply_type: bool,
+	pub(crate) targets_reply_type: bool,
+}
+
+impl X11Quirks {
+	/// Answers requests which don't name a property to store the reply in by storing it in the
+	/// property named after the requested target, as the ICCCM asks owners to do for obsolete
+	/// requestors. Such requests are refused otherwise.
+	///
+	/// Enabled by default.
+	pub fn none_property(mut self, enabled: bool) -> Self {
+		self.none_property = enabled;
+		self
+	}
+
+	/// Labels text requested as `text/plain`, `text/plain;charset=utf-8` or
+	/// `text/plain;charset=UTF-8` with the requested target as its type, rather than
+	/// `UTF8_STRING`.
+	///
+	/// This is for requestors which only accept a reply whose type is the target they asked for,
+	/// instead of reading it with `AnyPropertyType`.
+	///
+	/// Disabled by default.
+	pub fn mime_reply_type(mut self, enabled: bool) -> Self {
+		self.mime_reply_type = enabled;
+		self
+	}
+
+	/// Labels the reply to a `TARGETS` request with the type `TARGETS`, rather than `ATOM`.
+	///
+	/// This is for requestors which only accept a reply whose type is the target they asked for.
+	/// Most requestors expect `ATOM` as the ICCCM describes, so this is disabled by default.
+	pub fn targets_reply_type(mut self, enabled: bool) -> Self {
+		self.targets_reply_type = enabled;
+		self
+	}
+}
+
+impl Default for X11Quirks {
+	fn default() -> Self {
+		Self { none_property: true, mime_reply_type: false, targets_reply_type: false }
+	}
+}
+
+/// How long the X11 clipboard waits for other applications, see
+/// [`Clipboard::new_with_timeouts`](crate::Clipboard::new_with_timeouts).
+///
+/// The defaults suit a local X server. Over a slow remote connection, like X forwarding over
+/// SSH, longer timeouts avoid giving up on reads which would have succeeded. Shorter ones make
+/// reads from unresponsive applications fail sooner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeoutConfig {
+	pub(crate) read: Duration,
+	pub(crate) incr_segment: Duration,
+	pub(crate) convert_retry: Duration,
+	pub(crate) handover: Duration,
+	pub(crate) poll_interval: Duration,
+	pub(crate) serve_poll_interval: Duration,
+}
+
+impl TimeoutConfig {
+	/// How long a read waits for the owner of a selection to answer.
+	///
+	/// Some owners take multiple seconds to produce large contents like images, so this is
+	/// extended as long as they show signs of working on the request. Defaults to 4 seconds.
+	pub fn read(mut self, timeout: Duration) -> Self {
+		self.read = timeout;
+		self
+	}
+
+	/// How long a read waits for each further segment of contents which are transferred in
+	/// several parts (`INCR`). Defaults to 10 milliseconds.
+	pub fn incr_segment(mut self, timeout: Duration) -> Self {
+		self.incr_segment = timeout;
+		self
+	}
+
+	/// After how long a read without any answer from the owner is requested once more.
+	///
+	/// Some owners, like the clipboard bridges of x2go or older VNC agents, drop the first request
+	/// after syncing their own clipboard. Defaults to 1 second.
+	pub fn convert_retry(mut self, timeout: Duration) -> Self {
+		self.convert_retry = timeout;
+		self
+	}
+
+	/// How long dropping the last `Clipboard` waits for the clipboard manager to take over the
+	/// contents. Defaults to 100 milliseconds.
+	pub fn handover(mut self, timeout: Duration) -> Self {
+		self.handover = timeout;
+		self
+	}
+
+	/// How long a read sleeps between checks for an answer. Defaults to 1 millisecond.
+	pub fn poll_interval(mut self, interval: Duration) -> Self {
+		self.poll_interval = interval;
+		self
+	}
+
+	/// How often selections which are served in the background check whether they should stop
+	/// being served. Defaults to 50 milliseconds.
+	pub fn serve_poll_interval(mut self, interval: Duration) -> Self {
+		self.serve_poll_interval = interval;
+		self
+	}
+}
+
+impl Default for TimeoutConfig {
+	fn default() -> Self {
+		Self {
+			read: Duration::from_millis(4000),
+			incr_segment: Duration::from_millis(10),
+			convert_retry: Duration::from_millis(1000),
+			handover: Duration::from_millis(100),
+			poll_interval: Duration::from_millis(1),
+			serve_poll_interval: SERVE_POLL_DUR,
+		}
+	}
+}
+
+/// How data placed onto a selection is offered, as configured through [`SetExtLinux`].
+#[derive(Clone)]
+pub(crate) struct WriteConfig {
+	pub(crate) selection: LinuxClipboardKind,
+	pub(crate) wait: WaitConfig,
+	pub(crate) history_exclusion: HistoryExclusion,
+	pub(crate) text_aliases: bool,
+	/// Whether text is made to end in a newline, or to not end in one, rather than left as is.
+	pub(crate) trailing_newline: Option<bool>,
+	pub(crate) idle_timeout: Option<Duration>,
+	pub(crate) x11_quirks: X11Quirks,
+	/// Whether the selection is released when the deadline of `WaitConfig::Until` passes.
+	pub(crate) clear_after_wait: bool,
+	/// The selection owned by the clipboard manager the data is handed over to, if it isn't
+	/// `CLIPBOARD_MANAGER`.
+	pub(crate) manager_selection: Option<String>,
+	/// How soon after the previous write to the selection the ownership isn't re-asserted.
+	pub(crate) debounce: Option<Duration>,
+	pub(crate) on_request: Option<RequestHandler>,
+	pub(crate) on_paste: Option<PasteHandler>,
+}
+
+impl Default for WriteConfig {
+	fn default() -> Self {
+		Self {
+			selection: LinuxClipboardKind::Clipboard,
+			wait: WaitConfig::default(),
+			history_exclusion: HistoryExclusion::None,
+			text_aliases: false,
+			trailing_newline: None,
+			idle_timeout: None,
+			x11_quirks: X11Quirks::default(),
+			clear_after_wait: false,
+			manager_selection: None,
+			debounce: None,
+			on_request: None,
+			on_paste: None,
+		}
+	}
+}
+
+/// The configuration of a `Set`, which outlives it.
+pub(crate) type SetConfig = WriteConfig;
+
+pub(crate) struct Set<'clipboard> {
+	clipboard: &'clipboard mut Clipboard,
+	config: WriteConfig,
+	validate_only: bool,
+	report: Recorder<'clipboard>,
+}
+
+// The data is converted into offers here, so that every backend places the same formats.
+impl<'clipboard> Set<'clipboard> {
+	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
+		Self::with_config(clipboard, &WriteConfig::default())
+	}
+
+	pub(crate) fn with_config(clipboard: &'clipboard mut Clipboard, config: &SetConfig) -> Self {
+		Self {
+			clipboard,
+			config: config.clone(),
+			validate_only: false,
+			report: Recorder::default(),
+		}
+	}
+
+	pub(crate) fn into_config(self) -> SetConfig {
+		self.config
+	}
+
+	/// Makes the completing method return once the data is converted, before it's handed to the
+	/// backend.
+	pub(crate) fn validate_only(mut self) -> Self {
+		self.validate_only = true;
+		self
+	}
+
+	pub(crate) fn report_to<'a>(self, report: &'a mut SetReport) -> Set<'a>
+	where
+		'clipboard: 'a,
+	{
+		let excluded = self.config.history_exclusion != HistoryExclusion::None;
+		Set {
+			clipboard: self.clipboard,
+			config: self.config,
+			validate_only: self.validate_only,
+			report: Recorder::new(report, excluded),
+		}
+	}
+
+	/// Whether the data would be offered and served as it is, so that the selection holding the
+	/// same data means it holds what would be placed.
+	pub(crate) fn places_only_data(&self) -> bool {
+		let config = &self.config;
+		matches!(config.wait, WaitConfig::None)
+			&& config.history_exclusion == HistoryExclusion::None
+			&& !config.text_aliases
+			&& config.trailing_newline.is_none()
+			&& config.idle_timeout.is_none()
+			&& config.manager_selection.is_none()
+			&& config.on_request.is_none()
+			&& config.on_paste.is_none()
+	}
+
+	/// Whether the selection is still owned by this process on X11, or still offered just like
+	/// it was placed through this `Clipboard` on Wayland.
+	pub(crate) fn is_owner(&mut self) -> Result<bool, Error> {
+		let selection = self.config.selection;
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.is_owner(selection),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.is_owner(selection),
+		}
+	}
+
+	/// Reads the selection the data would be placed onto.
+	pub(crate) fn read_back(&mut self) -> Get<'_> {
+		let config = ReadConfig { selection: self.config.selection, ..ReadConfig::default() };
+		Get { clipboard: self.clipboard, config, wait: None }
+	}
+
+	pub(crate) fn text<'a>(self, text: impl ClipboardText<'a>) -> Result<(), Error> {
+		let utf8 = match text.into_shared() {
+			Ok(shared) => SharedBytes::from(shared),
+			Err(text) => text.into_owned().into_bytes().into(),
+		};
+		let offers = text_offers(utf8, &self.config);
+		self.place(offers)
+	}
+
+	pub(crate) fn text_bytes(self, utf8_bytes: Vec<u8>) -> Result<(), Error> {
+		debug_assert!(
+			std::str::from_utf8(&utf8_bytes).is_ok(),
+			"`Set::text_bytes` was given bytes which aren't valid UTF-8"
+		);
+
+		let offers = text_offers(utf8_bytes, &self.config);
+		self.place(offers)
+	}
+
+	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
+		let alt = alt.map(|alt| alt.into_owned().into_bytes());
+		let offers = html_offers(html.into_owned().into_bytes(), alt, &self.config);
+		self.place(offers)
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image(self, image: ImageData<'_>) -> Result<(), Error> {
+		#[allow(unused_mut)]
+		let mut images = vec![(PNG_MIME, encode_png(&image)?)];
+
+		// Some Wayland applications prefer lossy images, so a JPEG is offered there as well.
+		#[cfg(all(feature = "wayland-data-control", feature = "image-data"))]
+		if matches!(self.clipboard, Clipboard::WlDataControl(_)) {
+			match ImageEncoding::Jpeg.encode(&image) {
+				Ok(jpeg) => images.push((ImageEncoding::Jpeg.mime_type(), jpeg)),
+				Err(error) => warn!("Failed to encode the image as JPEG: {error}"),
+			}
+		}
+
+		self.encoded_all(images)
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image_lazy(mut self, image: ImageData<'static>) -> Result<(), Error> {
+		match self.clipboard {
+			Clipboard::X11(clipboard) => {
+				// Only the encoding is deferred, so an image which can't be placed is still
+				// reported here.
+				image.validate()?;
+				if self.validate_only {
+					return Ok(());
+				}
+				self.report.format(PNG_MIME, None);
+				let encode = Box::new(move || encode_png(&image));
+				clipboard.set_lazy(PNG_MIME, encode, &self.config)
+			}
+
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => self.image(image),
+		}
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_thumbnail(
+		self,
+		image: ImageData<'_>,
+		thumbnail: ImageData<'_>,
+	) -> Result<(), Error> {
+		let png = encode_png(&image)?;
+		let thumbnail_png = encode_png(&thumbnail)?;
+		self.encoded_all(vec![(PNG_MIME, png), (crate::common::THUMBNAIL_MIME, thumbnail_png)])
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_multi(
+		self,
+		image: ImageData<'_>,
+		encodings: &[ImageEncoding],
+	) -> Result<(), Error> {
+		let images = encode_images(&image, encodings)?
+			.into_iter()
+			.map(|(encoding, bytes)| (encoding.mime_type(), bytes))
+			.collect();
+		self.encoded_all(images)
+	}
+
+	/// Places already encoded data under its MIME type.
+	pub(crate) fn encoded(self, mime: &'static str, bytes: Vec<u8>) -> Result<(), Error> {
+		self.encoded_all(vec![(mime, bytes)])
+	}
+
+	#[cfg(feature = "x11-interop")]
+	pub(crate) fn format_atom(mut self, target: X11Atom, bytes: Vec<u8>) -> Result<(), Error> {
+		if self.validate_only {
+			return Ok(());
+		}
+
+		match self.clipboard {
+			Clipboard::X11(clipboard) => {
+				// Looking up the name takes a round trip, which a report is worth.
+				if self.report.is_recording() {
+					self.report.format(clipboard.target_name(target), Some(bytes.len()));
+				}
+				clipboard.set_atom(target, bytes, &self.config)
+			}
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => Err(Error::ClipboardNotSupported),
+		}
+	}
+
+	pub(crate) fn stream(mut self, mime: &str, chunks: Chunks) -> Result<(), Error> {
+		if self.validate_only {
+			return Ok(());
+		}
+
+		// Wayland does know the size, but X11 only finds out once the data is pasted.
+		self.report.format(mime, None);
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.set_stream(mime, chunks, &self.config),
+
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.set_stream(mime, chunks, &self.config),
+		}
+	}
+
+	fn encoded_all(self, encoded: Vec<(&'static str, Vec<u8>)>) -> Result<(), Error> {
+		let offers = encoded_offers(encoded, &self.config);
+		self.place(offers)
+	}
+
+	pub(crate) fn file_list(self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
+		let uri_list = paths_to_uri_list(file_list)?;
+		let offers = file_list_offers(uri_list, &self.config);
+		self.place(offers)
+	}
+
+	/// Hands the converted data over to the backend, unless it's only validated.
+	fn place(mut self, offers: Vec<Offer>) -> Result<(), Error> {
+		if self.validate_only {
+			return Ok(());
+		}
+
+		// The hint against saving the data is told by the report on its own.
+		for offer in offers.iter().filter(|offer| offer.mime != KDE_EXCLUSION_MIME) {
+			self.report.format(&offer.mime, Some(offer.bytes.len()));
+		}
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.set_offers(offers, &self.config),
+
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.set_offers(&offers, &self.config),
+		}
+	}
+}
+
+/// Linux specific extensions to the [`Set`](super::Set) builder.
+pub trait SetExtLinux: private::Sealed {
+	/// Whether to wait for the clipboard's contents to be replaced after setting it.
+	///
+	/// The Wayland and X11 clipboards work by having the clipboard content being, at any given
+	/// time, "owned" by a single process, and that process is expected to reply to all the requests
+	/// from any other system process that wishes to access the clipboard's contents. As a
+	/// consequence, when that process exits the contents of the clipboard will effectively be
+	/// cleared since there is no longer anyone around to serve requests for it.
+	///
+	/// This poses a problem for short-lived programs that just want to copy to the clipboard and
+	/// then exit, since they don't want to wait until the user happens to copy something else just
+	/// to finish. To resolve that, whenever the user copies something you can offload the actual
+	/// work to a newly-spawned daemon process which will run in the background (potentially
+	/// outliving the current process) and serve all the requests. That process will then
+	/// automatically and silently exit once the user copies something else to their clipboard so it
+	/// doesn't take up too many resources.
+	///
+	/// To support that pattern, this method will not only have the contents of the clipboard be
+	/// set, but will also wait and continue to serve requests until the clipboard is overwritten.
+	/// As long as you don't exit the current process until that method has returned, you can avoid
+	/// all surprising situations where the clipboard's contents seemingly disappear from under your
+	/// feet.
+	///
+	/// Only the selection which was set is waited on: another `Clipboard` of this process setting
+	/// or clearing a different selection meanwhile doesn't end the wait.
+	///
+	/// See the [daemonize example] for a demo of how you could implement this.
+	///
+	/// [daemonize example]: https://github.com/1Password/arboard/blob/master/examples/daemonize.rs
+	fn wait(self) -> Self;
+
+	/// Whether or not to wait for the clipboard's content to be replaced after setting it. This waits until the
+	/// `deadline` has exceeded.
+	///
 	/// This is useful for short-lived programs so it won't block until new contents on the clipboard
 	/// were added.
 	///
 	/// Note: this is a superset of [`wait()`][SetExtLinux::wait] and will overwrite any state
 	/// that was previously set using it.
+	///
+	/// When the deadline passes, the data stays on the clipboard unless
+	/// [`clear_after_wait`](SetExtLinux::clear_after_wait) is used.
 	fn wait_until(self, deadline: Instant) -> Self;
 
+	/// Releases the selection when the deadline of [`wait_until`](SetExtLinux::wait_until)
+	/// passes while the data is still on it, rather than leaving it there.
+	///
+	/// This is meant for sensitive data that should only be available for a limited time. If the
+	/// data was replaced before the deadline, whatever replaced it is left alone.
+	///
+	/// This only has an effect on X11. On Wayland, `wait_until` doesn't wait and the data stays
+	/// on the clipboard until it's replaced or the process exits.
+	fn clear_after_wait(self) -> Self;
+
 	/// Sets the clipboard the operation will store its data to.
 	///
 	/// If wayland support is enabled and available, attempting to use the Secondary clipboard will
@@ -362,30 +1614,232 @@ pub trait SetExtLinux: private::Sealed {
 	/// the desktop clipboard managers' histories by adding the MIME-Type `x-kde-passwordMangagerHint`
 	/// to the clipboard's selection data.
 	///
-	/// This is the most widely adopted convention on Linux.
+	/// This is the most widely adopted convention on Linux. KDE's Klipper honors the hint as
+	/// well, which is also the only way to keep it from saving an entry: its D-Bus interface can
+	/// only replace or clear the current contents or wipe the whole history, so there's no
+	/// separate integration with it.
+	///
+	/// On X11, the hint is listed in the selection's `TARGETS` like any other format, and
+	/// `SAVE_TARGETS` is left out so that clipboard managers aren't invited to save the data.
+	///
+	/// # Lifecycle on X11
+	///
+	/// Excluded data is served like any other while a [`Clipboard`](crate::Clipboard) exists,
+	/// with the hint offered alongside it:
+	///
+	/// - With [`wait`](SetExtLinux::wait), setting returns once the data was replaced. It's
+	///   no longer on the clipboard by then.
+	/// - With [`wait_until`](SetExtLinux::wait_until), setting returns once the data was replaced
+	///   or the deadline passed. In the latter case, the data stays on the clipboard, unless
+	///   [`clear_after_wait`](SetExtLinux::clear_after_wait) is used to release it right away.
+	/// - When the last `Clipboard` is dropped while the data is still on the clipboard, it's
+	///   never handed over to the clipboard manager. The selection is released instead, so that
+	///   managers don't try to rescue the data from the vanishing window.
+	/// - With an [`idle_timeout`](SetExtLinux::idle_timeout), the data keeps being served after
+	///   the last `Clipboard` is dropped, and the selection is released once the timeout expired
+	///   (or the data was replaced). Exiting the process before that leaves it up to the clipboard
+	///   manager whether to respect the hint.
 	fn exclude_from_history(self) -> Self;
+
+	/// Like [`exclude_from_history`](SetExtLinux::exclude_from_history), but on X11 the
+	/// `x-kde-passwordManagerHint` target is additionally left out of the selection's `TARGETS`.
+	///
+	/// The hint can still be requested directly, and the data is still never handed over to the
+	/// clipboard manager. This keeps managers which fetch every advertised target from storing the
+	/// hint itself, at the cost of no longer being recognized by managers that only look for the hint
+	/// in `TARGETS` (like KDE's Klipper).
+	///
+	/// On Wayland, there is no such distinction and this behaves like `exclude_from_history`.
+	fn exclude_from_history_strict(self) -> Self;
+
+	/// Additionally offers text under the legacy X11 `STRING` target, encoded as Latin-1.
+	///
+	/// This is for old applications (like many Motif or Athena based ones) which don't understand
+	/// UTF-8. Text that isn't representable in Latin-1 is only offered as UTF-8. Requests for the
+	/// `TEXT` target are answered with the Latin-1 text instead of the UTF-8 one when it's available.
+	///
+	/// This only has an effect when setting text on X11.
+	fn advertise_text_aliases(self) -> Self;
+
+	/// Appends a `\n` to text that doesn't end in one if `yes` is `true`, or removes the `\n` it
+	/// ends in otherwise, before it's placed.
+	///
+	/// Shells and some editors behave differently depending on whether pasted text ends in a
+	/// newline, like running a pasted command right away. By default, the text is placed as it's
+	/// given. Only that one `\n` is added or removed, so blank lines the text ends with and any
+	/// `\r` before the `\n` are kept. This applies to [`text`](crate::Set::text) and
+	/// [`text_bytes`](crate::Set::text_bytes), not to the text alternative of HTML.
+	fn ensure_trailing_newline(self, yes: bool) -> Self;
+
+	/// Keeps offering the data on X11 after the last [`Clipboard`](crate::Clipboard) is dropped,
+	/// until no other application requested it for `idle_timeout`.
+	///
+	/// Normally the data is handed over to the clipboard manager when the last `Clipboard` goes
+	/// away, and lost if there is none. With an idle timeout, the background thread keeps serving
+	/// the selection instead and releases it once it went unrequested for `idle_timeout`. Every
+	/// request restarts the timer. When all such selections expired, the thread stops and the
+	/// X11 connection is closed. Creating a new `Clipboard` in the meantime keeps them alive
+	/// as usual.
+	///
+	/// This has no effect on Wayland.
+	fn idle_timeout(self, idle_timeout: Duration) -> Self;
+
+	/// Sets which workarounds are used when answering X11 applications that request the data in
+	/// ways the ICCCM doesn't describe. See [`X11Quirks`] for the available ones and which are
+	/// enabled by default.
+	///
+	/// This has no effect on Wayland, where the compositor passes the data on.
+	fn x11_quirks(self, quirks: X11Quirks) -> Self;
+
+	/// Hands the data over to the clipboard manager owning the `name` selection, instead of the
+	/// standard `CLIPBOARD_MANAGER` one, when the last [`Clipboard`](crate::Clipboard) is dropped.
+	///
+	/// This is for setups running a custom clipboard manager under a non-standard selection name.
+	/// The manager is asked to save the data with `SAVE_TARGETS` like a standard one. Only data on
+	/// the [`Clipboard`](LinuxClipboardKind::Clipboard) selection is ever handed over.
+	///
+	/// This has no effect on Wayland.
+	fn manager_selection(self, name: &str) -> Self;
+
+	/// Coalesces this write with the previous one to the same selection if it happened less than
+	/// `window` ago.
+	///
+	/// Normally every write re-asserts the ownership of the selection, which makes clipboard
+	/// managers re-read it and can leave requests that were answered with the old data racing
+	/// with the new one. When the previous write is recent enough and the selection is still
+	/// ours, only the data that is served is swapped instead, and the time answered to
+	/// `TIMESTAMP` requests is advanced so that readers can still tell the contents changed. This
+	/// is meant for applications which set the clipboard on every change, like "copy on select"
+	/// editors.
+	///
+	/// Readers only ever observe either the previous or the new data, never an empty selection.
+	///
+	/// This only has an effect on X11.
+	fn debounce(self, window: Duration) -> Self;
+
+	/// Calls `handler` with the name of the target of every request for the data, to decide how
+	/// it's answered.
+	///
+	/// This makes it possible to compute the answer for each request, like counting how often the
+	/// data was pasted. Requests for `TARGETS`, and for targets the data isn't offered in, aren't
+	/// passed to the handler.
+	///
+	/// The handler is called on the thread which answers the requests, while no lock is held, so
+	/// it may read and set the clipboard. A set which [`wait`](SetExtLinux::wait)s would block
+	/// the thread forever, though, and every other request stays unanswered while the handler
+	/// runs. If the handler panics, the request is refused.
+	///
+	/// This only has an effect on X11. On Wayland, requests are always answered with the data
+	/// that was set.
+	fn on_request(self, handler: impl Fn(&str) -> RequestAction + Send + Sync + 'static) -> Self;
+
+	/// Calls `handler` every time a request for the data was answered, to tell whether and how
+	/// often it was pasted.
+	///
+	/// Requests for `TARGETS`, which applications send just to see what's on the clipboard, and
+	/// requests which couldn't be answered aren't counted. Data placed with
+	/// [`Set::stream`](crate::Set::stream) counts once its transfer finished. Clipboard managers
+	/// saving the data request it like any other application, though.
+	///
+	/// The handler is called after the requestor got its answer, on a thread of its own which
+	/// calls the handlers one after the other. So it doesn't hold up answering the requests, but
+	/// a slow handler delays the ones that follow. A panic in the handler is logged and doesn't
+	/// stop the next calls.
+	///
+	/// This only has an effect on X11.
+	fn on_paste(self, handler: impl Fn() + Send + Sync + 'static) -> Self;
+
+	/// Completes the "set" operation by placing `bytes` under the X11 target `target`, exactly
+	/// as they are.
+	///
+	/// Like [`GetExtLinux::format_atom`], this spares applications which interned their targets
+	/// already the round trip to look up the atom of a name.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ClipboardNotSupported`] on Wayland, which has no atoms.
+	#[cfg(feature = "x11-interop")]
+	fn format_atom(self, target: X11Atom, bytes: Vec<u8>) -> Result<(), Error>;
 }
 
 impl SetExtLinux for crate::Set<'_> {
 	fn wait(mut self) -> Self {
-		self.platform.wait = WaitConfig::Forever;
+		self.platform.config.wait = WaitConfig::Forever;
 		self
 	}
 
 	fn clipboard(mut self, selection: LinuxClipboardKind) -> Self {
-		self.platform.selection = selection;
+		self.platform.config.selection = selection;
 		self
 	}
 
 	fn wait_until(mut self, deadline: Instant) -> Self {
-		self.platform.wait = WaitConfig::Until(deadline);
+		self.platform.config.wait = WaitConfig::Until(deadline);
+		self
+	}
+
+	fn clear_after_wait(mut self) -> Self {
+		self.platform.config.clear_after_wait = true;
 		self
 	}
 
 	fn exclude_from_history(mut self) -> Self {
-		self.platform.exclude_from_history = true;
+		self.platform.config.history_exclusion = HistoryExclusion::Hinted;
+		self
+	}
+
+	fn exclude_from_history_strict(mut self) -> Self {
+		self.platform.config.history_exclusion = HistoryExclusion::Strict;
+		self
+	}
+
+	fn advertise_text_aliases(mut self) -> Self {
+		self.platform.config.text_aliases = true;
 		self
 	}
+
+	fn ensure_trailing_newline(mut self, yes: bool) -> Self {
+		self.platform.config.trailing_newline = Some(yes);
+		self
+	}
+
+	fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+		self.platform.config.idle_timeout = Some(idle_timeout);
+		self
+	}
+
+	fn x11_quirks(mut self, quirks: X11Quirks) -> Self {
+		self.platform.config.x11_quirks = quirks;
+		self
+	}
+
+	fn manager_selection(mut self, name: &str) -> Self {
+		self.platform.config.manager_selection = Some(name.to_owned());
+		self
+	}
+
+	fn debounce(mut self, window: Duration) -> Self {
+		self.platform.config.debounce = Some(window);
+		self
+	}
+
+	fn on_request(
+		mut self,
+		handler: impl Fn(&str) -> RequestAction + Send + Sync + 'static,
+	) -> Self {
+		self.platform.config.on_request = Some(Arc::new(handler));
+		self
+	}
+
+	fn on_paste(mut self, handler: impl Fn() + Send + Sync + 'static) -> Self {
+		self.platform.config.on_paste = Some(Arc::new(handler));
+		self
+	}
+
+	#[cfg(feature = "x11-interop")]
+	fn format_atom(self, target: X11Atom, bytes: Vec<u8>) -> Result<(), Error> {
+		self.platform.format_atom(target, bytes)
+	}
 }
 
 pub(crate) struct Clear<'clipboard> {
@@ -401,6 +1855,15 @@ impl<'clipboard> Clear<'clipboard> {
 		self.clear_inner(LinuxClipboardKind::Clipboard)
 	}
 
+	pub(crate) fn clear_if_owner(self) -> Result<bool, Error> {
+		let selection = LinuxClipboardKind::Clipboard;
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.clear_if_owner(selection),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.clear_if_owner(selection),
+		}
+	}
+
 	fn clear_inner(self, selection: LinuxClipboardKind) -> Result<(), Error> {
 		match self.clipboard {
 			Clipboard::X11(clipboard) => clipboard.clear(selection),
@@ -439,10 +1902,658 @@ impl ClearExtLinux for crate::Clear<'_> {
 	}
 }
 
+/// How the last read went, as returned by [`ClipboardExtLinux::last_read_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadStats {
+	/// The size of the data that was read, in bytes.
+	pub bytes: usize,
+	/// How long the read took, including waiting for the owner.
+	pub duration: Duration,
+	/// Whether the owner sent the data incrementally (with `INCR`).
+	pub used_incr: bool,
+	/// The number of pieces the data arrived in. This is `0` if the data was set by this process,
+	/// in which case it isn't transferred at all.
+	pub chunks: usize,
+}
+
+/// The text of each selection, as returned by
+/// [`ClipboardExtLinux::get_text_for_each_selection`].
+///
+/// A selection is `None` if its text couldn't be read, for example because it's empty, doesn't
+/// contain text or isn't supported by the system.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelectionTexts {
+	/// The text of [`LinuxClipboardKind::Clipboard`].
+	pub clipboard: Option<String>,
+	/// The text of [`LinuxClipboardKind::Primary`].
+	pub primary: Option<String>,
+	/// The text of [`LinuxClipboardKind::Secondary`].
+	pub secondary: Option<String>,
+}
+
+/// The contents of a selection in every format they were offered in, as returned by
+/// [`ClipboardExtLinux::capture`].
+///
+/// With the `serde` feature, this can be serialized, for example to keep the contents across
+/// restarts, and placed onto the clipboard again with [`ClipboardExtLinux::restore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capture {
+	/// The selection the contents were captured from, which they are restored to.
+	pub selection: LinuxClipboardKind,
+	/// The X11 target or Wayland MIME type of each format, together with its data.
+	pub formats: Vec<(String, Vec<u8>)>,
+}
+
+impl Capture {
+	/// Whether the contents were kept out of clipboard managers' histories, like with
+	/// [`SetExtLinux::exclude_from_history`], which KDE's `x-kde-passwordManagerHint` format
+	/// marks them with.
+	pub fn is_excluded_from_history(&self) -> bool {
+		self.formats.iter().any(|(format, _)| format == KDE_EXCLUSION_MIME)
+	}
+
+	/// The formats which hold the contents themselves, leaving out KDE's
+	/// `x-kde-passwordManagerHint`.
+	///
+	/// The hint is kept in [`formats`](Capture::formats), so that
+	/// [`ClipboardExtLinux::restore`] keeps the contents out of the histories again.
+	pub fn content_formats(&self) -> impl Iterator<Item = &(String, Vec<u8>)> {
+		self.formats.iter().filter(|(format, _)| format != KDE_EXCLUSION_MIME)
+	}
+}
+
+impl SelectionTexts {
+	/// Returns the text of `selection`, if it could be read.
+	pub fn get(&self, selection: LinuxClipboardKind) -> Option<&str> {
+		match selection {
+			LinuxClipboardKind::Clipboard => self.clipboard.as_deref(),
+			LinuxClipboardKind::Primary => self.primary.as_deref(),
+			LinuxClipboardKind::Secondary => self.secondary.as_deref(),
+		}
+	}
+}
+
+/// Linux specific extensions to the [`Clipboard`](crate::Clipboard) itself.
+pub trait ClipboardExtLinux: private::Sealed {
+	/// Reads the text of all three selections at once.
+	///
+	/// Failing to read one selection doesn't fail the others, its text is `None` instead.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, ClipboardExtLinux, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	///
+	/// let texts = clipboard.get_text_for_each_selection();
+	/// if texts.clipboard != texts.primary {
+	///     println!("The CLIPBOARD and PRIMARY selections are out of sync");
+	/// }
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn get_text_for_each_selection(&mut self) -> SelectionTexts;
+
+	/// Copies the text of the `from` selection to the `to` selection, unless `to` already contains
+	/// the same text.
+	///
+	/// Returns whether `to` was changed. Not being able to read the text of `to` counts as it being
+	/// different.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the text of `from` can't be read or if writing it to `to` fails.
+	fn sync_text(
+		&mut self,
+		from: LinuxClipboardKind,
+		to: LinuxClipboardKind,
+	) -> Result<bool, Error>;
+
+	/// Whether the background thread which serves our clipboard contents to other applications
+	/// on X11 is still running.
+	///
+	/// The thread stops if the connection to the X server breaks, after which setting the
+	/// clipboard fails until [`restart_server`](ClipboardExtLinux::restart_server) is called.
+	/// This is always `true` on Wayland.
+	fn is_healthy(&self) -> bool;
+
+	/// Starts the background thread serving our clipboard contents on X11 again if it stopped.
+	///
+	/// If the X11 connection is still usable, the thread continues serving the previously set
+	/// contents. Otherwise a new connection is opened, which all `Clipboard`s created from then on
+	/// share; the previously set contents are lost in that case. Other existing `Clipboard`s
+	/// switch over to the new connection when this is called on them.
+	///
+	/// A clipboard from [`Clipboard::new_isolated`](crate::Clipboard::new_isolated) is the
+	/// exception: when its connection broke, only this handle moves to a new connection, and
+	/// every other handle of it opens one of its own once it's restarted.
+	///
+	/// This does nothing if the thread is running or on Wayland.
+	///
+	/// # Errors
+	///
+	/// Returns an error if a new connection to the X server can't be opened.
+	fn restart_server(&mut self) -> Result<(), Error>;
+
+	/// Creates another `Clipboard` which uses the same connection as this one.
+	///
+	/// On X11, all handles share the background thread serving the clipboard contents, no matter
+	/// whether they were created with `clone_handle` or [`Clipboard::new`](crate::Clipboard::new).
+	/// The contents are only handed over to the clipboard manager, and the thread stopped, once
+	/// the last of them is dropped. Handles of a clipboard from
+	/// [`Clipboard::new_isolated`](crate::Clipboard::new_isolated) share its own connection
+	/// instead. Read statistics aren't shared, the new handle starts without any, and it reads
+	/// text and images from the built-in targets.
+	///
+	/// On Wayland, the new handle connects to the compositor on its own for each operation, like
+	/// every other `Clipboard`.
+	fn clone_handle(&self) -> crate::Clipboard;
+
+	/// How the last read of the clipboard's contents through this `Clipboard` went, for example
+	/// to keep track of how long reads take.
+	///
+	/// This is `None` before the first read, and always on Wayland, where the data is transferred
+	/// by the compositor. A read which failed is reported with the time it took before failing.
+	fn last_read_stats(&self) -> Option<ReadStats>;
+
+	/// Drops the data of any selection that was set through this process but has since been
+	/// taken over by another application, returning how many bytes were released. The room to
+	/// spare in what's kept for the other selections is released as well.
+	///
+	/// The data is normally dropped as soon as the loss of the selection is noticed. This is
+	/// for applications which place large payloads onto the clipboard and want to be sure that
+	/// nothing is kept around, for example before measuring their memory use. Whether the memory
+	/// is returned to the operating system is up to the allocator.
+	///
+	/// This always returns `0` on Wayland, where the data is handed to a separate thread that
+	/// drops it as soon as the selection is replaced.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the owners of the selections can't be queried on X11.
+	fn trim_memory(&self) -> Result<usize, Error>;
+
+	/// Whether `selection` can be used with the backend that is in use.
+	///
+	/// This is always `true` on X11. On Wayland, the [`Secondary`](LinuxClipboardKind::Secondary)
+	/// selection is never supported, and the [`Primary`](LinuxClipboardKind::Primary) one only if
+	/// the compositor implements version 2 or later of the data control protocol and a seat
+	/// offers it.
+	///
+	/// The Wayland answer is cached and shared by all `Clipboard`s. Every read or write of the
+	/// primary selection updates it, so a compositor that gains or loses support is noticed on
+	/// the next such operation. [`refresh_capabilities`](ClipboardExtLinux::refresh_capabilities)
+	/// and [`reconnect`](crate::Clipboard::reconnect) probe the compositor again right away.
+	///
+	/// This is meant for deciding whether to offer features like "paste from the primary
+	/// selection" up front, rather than trying and handling [`Error::ClipboardNotSupported`].
+	fn supports_selection(&self, selection: LinuxClipboardKind) -> bool;
+
+	/// Asks the backend again which selections it supports, refreshing the answer given by
+	/// [`supports_selection`](ClipboardExtLinux::supports_selection).
+	///
+	/// This does nothing on X11. On Wayland, it reconnects to the compositor, which is useful
+	/// after a seat or the compositor itself changed.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the compositor can't be reached.
+	fn refresh_capabilities(&mut self) -> Result<(), Error>;
+
+	/// The X11 atom arboard interned on startup for `name`, to be reused instead of interning
+	/// it again.
+	///
+	/// These are the selections (`CLIPBOARD`, `PRIMARY` and `SECONDARY`), the targets of the
+	/// selection protocol (like `TARGETS`, `INCR` and `SAVE_TARGETS`) and the formats arboard
+	/// places, like `UTF8_STRING`, `text/html` or `image/png`. This is `None` for other names,
+	/// and always on Wayland.
+	#[cfg(feature = "x11-interop")]
+	fn x11_atom(&self, name: &str) -> Option<X11Atom>;
+
+	/// Whether the X11 backend is in use with an Xwayland server, which bridges the X11
+	/// selections to the ones of a Wayland compositor.
+	///
+	/// Bridged selections can lag behind the Wayland ones, and the `PRIMARY` selection is only
+	/// kept in sync if the compositor supports it. Applications may want to mention this when
+	/// clipboard contents seem stale, or suggest the `wayland-data-control` feature. This is
+	/// always `false` when the Wayland backend is in use.
+	///
+	/// Xwayland is recognized by the `XWAYLAND` extension, which it offers since version 23.1,
+	/// and for older versions by `WAYLAND_DISPLAY` being set.
+	fn is_xwayland(&self) -> bool;
+
+	/// Whether a clipboard manager is running that the `CLIPBOARD` contents are handed over to
+	/// when the last `Clipboard` is dropped, as described on [`SetExtLinux`].
+	///
+	/// Without one, a process which exits right after copying has to keep serving its contents,
+	/// for example with [`SetExtLinux::wait`] from a daemon. On X11, this checks whether the
+	/// `CLIPBOARD_MANAGER` selection, or the one given with
+	/// [`SetExtLinux::manager_selection`] for the current contents, has an owner. This is always
+	/// `false` on Wayland, where the contents aren't handed over.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the owner of the selection can't be queried on X11.
+	fn has_clipboard_manager(&self) -> Result<bool, Error>;
+
+	/// The selections whose contents were placed by this process and haven't been replaced by
+	/// another application since, in the order of [`LinuxClipboardKind::all`].
+	///
+	/// This helps to check what would be given up on exit, or to clear everything that is still
+	/// owned. On X11, ownership is shared by all `Clipboard`s of the process. On Wayland, a
+	/// selection only counts if it's still offered just like it was last placed through this
+	/// `Clipboard`, which reads back each selection that was placed, and unsupported selections
+	/// are never listed.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the owner of a selection can't be queried on X11, or if a selection
+	/// can't be read back on Wayland.
+	fn owned_selections(&self) -> Result<Vec<LinuxClipboardKind>, Error>;
+
+	/// Reads the contents of `selection` in every format they are offered in.
+	///
+	/// Formats which the owner offers but fails to deliver are left out, as are the X11 targets
+	/// which don't stand for the contents themselves, like `TARGETS`.
+	///
+	/// KDE's `x-kde-passwordManagerHint` is captured like any other format, so that restoring the
+	/// contents keeps them out of clipboard managers' histories as well. Use
+	/// [`Capture::content_formats`] to only list the formats holding the contents.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if the selection is empty or none of its formats
+	/// could be read.
+	fn capture(&mut self, selection: LinuxClipboardKind) -> Result<Capture, Error>;
+
+	/// Places the contents of a [`Capture`] onto its selection again, offering all of its formats
+	/// at once.
+	///
+	/// The data is offered exactly as it was captured. If it contains KDE's
+	/// `x-kde-passwordManagerHint`, it's kept out of clipboard managers' histories like after
+	/// [`SetExtLinux::exclude_from_history`].
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if the capture has no formats.
+	fn restore(&mut self, capture: &Capture) -> Result<(), Error>;
+
+	/// Adds `data` under `target` to what `selection` is offered as, keeping all other targets.
+	///
+	/// Unlike a "set", which replaces everything the selection is offered as, this builds up
+	/// the targets one by one, like for experimenting with what other applications pick. A
+	/// target which is offered already gets the new data. The selection keeps its other
+	/// settings, like a [`wait`](SetExtLinux::wait) of the "set" which placed it, and its
+	/// ownership is re-asserted. If the selection isn't ours, it's taken with only this target.
+	///
+	/// `target` is used exactly as given, so MIME types with parameters, like
+	/// `text/plain;charset=utf-8`, are listed with them by the `TARGETS` of the selection.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, ClipboardExtLinux, Error, LinuxClipboardKind};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// clipboard.set_text("Hello")?;
+	/// clipboard.serve_target(LinuxClipboardKind::Clipboard, "text/html", b"<b>Hello</b>".to_vec())?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ClipboardNotSupported`] on Wayland, where the data offered to the
+	/// compositor can't be added to, or [`Error::ClipboardOccupied`] if the selection can't be
+	/// taken on X11.
+	fn serve_target(
+		&mut self,
+		selection: LinuxClipboardKind,
+		target: &str,
+		data: Vec<u8>,
+	) -> Result<(), Error>;
+}
+
+impl ClipboardExtLinux for crate::Clipboard {
+	fn get_text_for_each_selection(&mut self) -> SelectionTexts {
+		let mut read = |selection| self.get().clipboard(selection).text().ok();
+
+		SelectionTexts {
+			clipboard: read(LinuxClipboardKind::Clipboard),
+			primary: read(LinuxClipboardKind::Primary),
+			secondary: read(LinuxClipboardKind::Secondary),
+		}
+	}
+
+	fn sync_text(
+		&mut self,
+		from: LinuxClipboardKind,
+		to: LinuxClipboardKind,
+	) -> Result<bool, Error> {
+		let text = self.get().clipboard(from).text()?;
+
+		if self.get().clipboard(to).text().is_ok_and(|current| current == text) {
+			return Ok(false);
+		}
+
+		self.set().clipboard(to).text(text)?;
+		Ok(true)
+	}
+
+	fn is_healthy(&self) -> bool {
+		self.platform.is_healthy()
+	}
+
+	fn clone_handle(&self) -> crate::Clipboard {
+		crate::Clipboard { platform: self.platform.clone_handle() }
+	}
+
+	fn restart_server(&mut self) -> Result<(), Error> {
+		self.platform.restart_server()
+	}
+
+	fn last_read_stats(&self) -> Option<ReadStats> {
+		self.platform.last_read_stats()
+	}
+
+	fn trim_memory(&self) -> Result<usize, Error> {
+		self.platform.trim_memory()
+	}
+
+	fn supports_selection(&self, selection: LinuxClipboardKind) -> bool {
+		self.platform.supports_selection(selection)
+	}
+
+	fn refresh_capabilities(&mut self) -> Result<(), Error> {
+		self.platform.refresh_capabilities()
+	}
+
+	#[cfg(feature = "x11-interop")]
+	fn x11_atom(&self, name: &str) -> Option<X11Atom> {
+		match &self.platform {
+			Clipboard::X11(clipboard) => clipboard.well_known_atom(name),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => None,
+		}
+	}
+
+	fn is_xwayland(&self) -> bool {
+		self.platform.is_xwayland()
+	}
+
+	fn has_clipboard_manager(&self) -> Result<bool, Error> {
+		self.platform.has_clipboard_manager()
+	}
+
+	fn owned_selections(&self) -> Result<Vec<LinuxClipboardKind>, Error> {
+		self.platform.owned_selections()
+	}
+
+	fn capture(&mut self, selection: LinuxClipboardKind) -> Result<Capture, Error> {
+		self.platform.capture(selection)
+	}
+
+	fn restore(&mut self, capture: &Capture) -> Result<(), Error> {
+		self.platform.restore(capture)
+	}
+
+	fn serve_target(
+		&mut self,
+		selection: LinuxClipboardKind,
+		target: &str,
+		data: Vec<u8>,
+	) -> Result<(), Error> {
+		self.platform.serve_target(selection, target, data)
+	}
+}
+
+/// Places `text` onto the clipboard from a helper process, which keeps serving it until it's
+/// replaced or `ttl` elapsed, also after this process exited.
+pub(crate) fn set_and_forget(text: String, ttl: Duration) -> Result<(), Error> {
+	let deadline = Instant::now() + ttl;
+	// The backend is chosen here, so that the helper doesn't need any of the state shared between
+	// the threads of this process, which may have been locked by one of them while forking.
+	let wayland = match Clipboard::new_isolated()? {
+		Clipboard::X11(_) => false,
+		#[cfg(feature = "wayland-data-control")]
+		Clipboard::WlDataControl(_) => true,
+	};
+	let (mut placed, mut helper_placed) = UnixStream::pair().map_err(into_unknown)?;
+
+	// SAFETY: The child only forks again and exits, and the grandchild sticks to its own
+	// connection and the text it was given.
+	match unsafe { libc::fork() } {
+		-1 => return Err(into_unknown(std::io::Error::last_os_error())),
+		0 => {
+			drop(placed);
+			// Forking twice detaches the helper from this process and its session, so that it's
+			// not terminated or turned into a zombie along with it.
+			// SAFETY: Only exits or returns in the grandchild.
+			unsafe {
+				libc::setsid();
+				if libc::fork() != 0 {
+					libc::_exit(0);
+				}
+			}
+			let result = serve_forgotten(wayland, text, deadline, &mut helper_placed);
+			if let Err(error) = result {
+				let _ = write!(helper_placed, "\x01{error}");
+			}
+			// SAFETY: Nothing of this process is left to clean up.
+			unsafe { libc::_exit(0) }
+		}
+		child => {
+			drop(helper_placed);
+			// SAFETY: `child` is the intermediate process, which exits right away.
+			while unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) } == -1 {
+				if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+					break;
+				}
+			}
+		}
+	}
+
+	// The helper reports back once the text is on the clipboard, or why it couldn't place it.
+	let mut reply = Vec::new();
+	placed.read_to_end(&mut reply).map_err(into_unknown)?;
+	match reply.split_first() {
+		Some((0, _)) => Ok(()),
+		Some((_, error)) => Err(Error::unknown(String::from_utf8_lossy(error))),
+		None => Err(Error::unknown("The clipboard helper process exited before placing the text")),
+	}
+}
+
+/// Runs in the helper process of `set_and_forget`, reporting to `placed` once the text is on
+/// the clipboard.
+fn serve_forgotten(
+	wayland: bool,
+	text: String,
+	deadline: Instant,
+	placed: &mut UnixStream,
+) -> Result<(), Error> {
+	// The helper mustn't hold on to the terminal or anything else this process had open, like
+	// a pipe its output is read from or its own X11 connection.
+	let null = std::fs::OpenOptions::new().read(true).write(true).open("/dev/null");
+	let null = null.map_err(into_unknown)?;
+	// SAFETY: Only replaces the standard streams.
+	unsafe {
+		for stream in 0..3 {
+			libc::dup2(null.as_raw_fd(), stream);
+		}
+	}
+	drop(null);
+	let open: Vec<i32> = std::fs::read_dir("/proc/self/fd")
+		.map_err(into_unknown)?
+		.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+		.collect();
+	for fd in open.into_iter().filter(|&fd| fd > 2 && fd != placed.as_raw_fd()) {
+		// SAFETY: Nothing in the helper uses the descriptors it inherited.
+		unsafe { libc::close(fd) };
+	}
+	let _ = std::env::set_current_dir("/");
+
+	let config = WriteConfig::default();
+	let offers = text_offers(text.into_bytes(), &config);
+	let report_placed = |placed: &mut UnixStream| {
+		let _ = placed.write_all(&[0]);
+		let _ = placed.shutdown(Shutdown::Both);
+	};
+	if wayland {
+		#[cfg(feature = "wayland-data-control")]
+		return wayland::Clipboard::default().serve_offers_until(
+			&offers,
+			&config,
+			deadline,
+			|| report_placed(placed),
+		);
+	}
+
+	let clipboard = x11::Clipboard::new_isolated()?;
+	let written_at = clipboard.set_offers_tracked(offers, &config)?;
+	report_placed(placed);
+	clipboard.wait_until_replaced(config.selection, written_at, deadline);
+	// Dropping the clipboard hands the contents over to the clipboard manager, if possible.
+	Ok(())
+}
+
+/// The reason why [`serve`] stopped serving the clipboard's contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServeOutcome {
+	/// Something else, either another application or another [`Clipboard`](crate::Clipboard)
+	/// in this process, placed new contents onto the selection.
+	Replaced,
+
+	/// The shutdown signal was received.
+	Shutdown,
+
+	/// The deadline has passed.
+	DeadlineReached,
+}
+
+/// Places `contents` onto `selection` and blocks while serving them to other applications.
+///
+/// This is the primitive for processes whose whole purpose is to keep contents on the clipboard
+/// alive, like the one spawned in the [daemonize example]. Each entry of `contents` is a target
+/// (a MIME type like `text/plain;charset=utf-8` or an X11 target name like `UTF8_STRING`)
+/// together with the bytes that are served for it.
+///
+/// Serving stops, and the reason is returned, when the first of the following happens:
+/// - The contents are replaced on the selection ([`ServeOutcome::Replaced`]).
+/// - A message is received from `shutdown` ([`ServeOutcome::Shutdown`]). Dropping the sender
+///   doesn't count as a signal.
+/// - `deadline`, if any, has passed ([`ServeOutcome::DeadlineReached`]).
+///
+/// # Lifecycle
+///
+/// When this function returns because of the shutdown signal or the deadline on X11, the contents
+/// are still owned by this process. They are then handed over to the clipboard manager (if one is
+/// running) once the last [`Clipboard`](crate::Clipboard) of the process is dropped, exactly as
+/// for any other set operation, and they disappear when that isn't possible. On Wayland, the
+/// selection is cleared before returning, which stops the contents from being served.
+///
+/// [daemonize example]: https://github.com/1Password/arboard/blob/master/examples/daemonize.rs
+pub fn serve(
+	contents: Vec<(String, Vec<u8>)>,
+	selection: LinuxClipboardKind,
+	shutdown: Receiver<()>,
+	deadline: Option<Instant>,
+) -> Result<ServeOutcome, Error> {
+	match Clipboard::new()? {
+		Clipboard::X11(clipboard) => clipboard.serve(contents, selection, &shutdown, deadline),
+		#[cfg(feature = "wayland-data-control")]
+		Clipboard::WlDataControl(clipboard) => clipboard.serve(contents, selection, &shutdown, deadline),
+	}
+}
+
+/// Returns why `serve` should stop, if it should for a reason other than losing the selection.
+fn serve_stop_reason(shutdown: &Receiver<()>, deadline: Option<Instant>) -> Option<ServeOutcome> {
+	if shutdown.try_recv().is_ok() {
+		Some(ServeOutcome::Shutdown)
+	} else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+		Some(ServeOutcome::DeadlineReached)
+	} else {
+		None
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[test]
+	fn retry_read_retries_transient_errors_only() {
+		let config = ReadConfig { attempts: 3, ..ReadConfig::default() };
+		let mut calls = 0;
+		let result = retry_read(&config, || {
+			calls += 1;
+			if calls < 3 {
+				Err(Error::unknown("connection reset"))
+			} else {
+				Ok(calls)
+			}
+		});
+		assert_eq!(result.unwrap(), 3);
+
+		let mut calls = 0;
+		let result = retry_read(&config, || -> Result<(), _> {
+			calls += 1;
+			Err(Error::unknown("connection reset"))
+		});
+		assert!(matches!(result, Err(Error::Unknown { .. })));
+		assert_eq!(calls, 3);
+
+		// An owner which went away may have been replaced by one that answers.
+		let mut calls = 0;
+		let result = retry_read(&config, || {
+			calls += 1;
+			if calls < 2 {
+				Err(Error::OwnerLost)
+			} else {
+				Ok(calls)
+			}
+		});
+		assert_eq!(result.unwrap(), 2);
+
+		let mut calls = 0;
+		let result = retry_read(&config, || -> Result<(), _> {
+			calls += 1;
+			Err(Error::content_not_available())
+		});
+		assert!(matches!(result, Err(Error::ContentNotAvailable { .. })));
+		assert_eq!(calls, 1);
+
+		// By default, and when the deadline leaves no time to wait, only one attempt is made.
+		let configs = [
+			ReadConfig::default(),
+			ReadConfig {
+				retry_backoff: Duration::from_secs(60),
+				deadline: Some(Instant::now() + Duration::from_secs(1)),
+				..config
+			},
+		];
+		for config in configs {
+			let mut calls = 0;
+			let _ = retry_read(&config, || -> Result<(), _> {
+				calls += 1;
+				Err(Error::unknown("connection reset"))
+			});
+			assert_eq!(calls, 1);
+		}
+	}
+
+	#[test]
+	fn clipboard_kind_names() {
+		for kind in LinuxClipboardKind::all() {
+			assert_eq!(kind.to_string().parse::<LinuxClipboardKind>().unwrap(), kind);
+		}
+		assert_eq!("Primary".parse::<LinuxClipboardKind>().unwrap(), LinuxClipboardKind::Primary);
+		assert_eq!(LinuxClipboardKind::Secondary.to_string(), "secondary");
+		assert!("CLIPBOARD".parse::<LinuxClipboardKind>().is_ok());
+		assert!("selection".parse::<LinuxClipboardKind>().is_err());
+	}
+
 	#[test]
 	fn test_decoding_uri_list() {
 		// Test that paths_from_uri_list correctly decodes
@@ -462,4 +2573,276 @@ mod tests {
 		];
 		assert_eq!(paths_from_uri_list(file_list.join("\n").into()), paths);
 	}
+
+	#[test]
+	fn test_decoding_utf16_html() {
+		let html = "<p>Grüße 🦀</p>";
+
+		let mut le = vec![0xFF, 0xFE];
+		le.extend(html.encode_utf16().flat_map(u16::to_le_bytes));
+		assert_eq!(decode_html(le).unwrap(), html);
+
+		let mut be = vec![0xFE, 0xFF];
+		be.extend(html.encode_utf16().flat_map(u16::to_be_bytes));
+		assert_eq!(decode_html(be).unwrap(), html);
+
+		assert_eq!(decode_html(html.as_bytes().to_vec()).unwrap(), html);
+
+		// A dangling byte can't be part of valid UTF-16.
+		assert!(matches!(
+			decode_html(vec![0xFF, 0xFE, b'a']),
+			Err(Error::ConversionFailure { .. })
+		));
+	}
+
+	fn served(offers: &[Offer]) -> Vec<(&str, &[u8])> {
+		served_targets(offers).into_iter().map(|(mime, offer)| (mime, &offer.bytes[..])).collect()
+	}
+
+	#[test]
+	fn test_text_offers() {
+		let utf8 = "Grüße".as_bytes();
+		let offers = text_offers(utf8.to_vec(), &WriteConfig::default());
+		assert_eq!(
+			served(&offers),
+			[
+				("UTF8_STRING", utf8),
+				("text/plain;charset=utf-8", utf8),
+				("text/plain;charset=UTF-8", utf8),
+				("text/plain", utf8),
+				("TEXT", utf8),
+			]
+		);
+
+		let config = WriteConfig {
+			text_aliases: true,
+			history_exclusion: HistoryExclusion::Hinted,
+			..WriteConfig::default()
+		};
+		let latin1 = b"Gr\xfc\xdfe".as_slice();
+		let offers = text_offers(utf8.to_vec(), &config);
+		assert_eq!(
+			served(&offers),
+			[
+				("x-kde-passwordManagerHint", b"secret".as_slice()),
+				("UTF8_STRING", utf8),
+				("text/plain;charset=utf-8", utf8),
+				("text/plain;charset=UTF-8", utf8),
+				("text/plain", utf8),
+				("TEXT", latin1),
+				("STRING", latin1),
+			]
+		);
+		assert!(offers.iter().all(|offer| offer.advertised));
+
+		// Text that Latin-1 can't represent is only offered as UTF-8, rather than mangled.
+		let cjk = "日本語".as_bytes();
+		let offers = text_offers(cjk.to_vec(), &config);
+		assert!(offers.iter().all(|offer| offer.mime != STRING_TARGET));
+		assert_eq!(served(&offers).last(), Some(&("TEXT", cjk)));
+	}
+
+	#[test]
+	fn trailing_newlines_are_ensured_or_stripped() {
+		let with_newline = |text: &str, trailing_newline| {
+			let mut utf8 = text.as_bytes().to_vec();
+			set_trailing_newline(&mut utf8, trailing_newline);
+			String::from_utf8(utf8).unwrap()
+		};
+		assert_eq!(with_newline("ls -l", true), "ls -l\n");
+		assert_eq!(with_newline("ls -l\n", true), "ls -l\n");
+		assert_eq!(with_newline("code\n\n", true), "code\n\n");
+		assert_eq!(with_newline("ls -l\r\n", true), "ls -l\r\n");
+		assert_eq!(with_newline("", true), "\n");
+		assert_eq!(with_newline("ls -l\n", false), "ls -l");
+		assert_eq!(with_newline("one\ntwo\n\n", false), "one\ntwo\n");
+		assert_eq!(with_newline("ls -l\r\n", false), "ls -l\r");
+		assert_eq!(with_newline("\n", false), "");
+		assert_eq!(with_newline("ls -l ", false), "ls -l ");
+
+		// Text is left as is unless asked otherwise, and the aliases are made from the result.
+		let offers = text_offers(b"out\n".to_vec(), &WriteConfig::default());
+		assert_eq!(served(&offers)[0], ("UTF8_STRING", b"out\n".as_slice()));
+		let config = WriteConfig {
+			trailing_newline: Some(false),
+			text_aliases: true,
+			..WriteConfig::default()
+		};
+		let offers = text_offers(b"out\n".to_vec(), &config);
+		assert!(served(&offers).iter().all(|(_, bytes)| *bytes == b"out"));
+	}
+
+	#[test]
+	fn text_is_shared_by_its_targets() {
+		let text = String::from("shared text");
+		let ptr = text.as_ptr();
+		let offers = text_offers(text.into_bytes(), &WriteConfig::default());
+		assert!(served(&offers).len() > 1);
+		assert!(served(&offers).iter().all(|(_, bytes)| bytes.as_ptr() == ptr));
+
+		let text: Arc<str> = "shared text".into();
+		let offers = text_offers(Arc::clone(&text), &WriteConfig::default());
+		assert!(served(&offers).iter().all(|(_, bytes)| bytes.as_ptr() == text.as_ptr()));
+		assert_eq!(offers.into_iter().next().unwrap().bytes.into_vec(), b"shared text");
+	}
+
+	#[test]
+	fn test_encode_latin1() {
+		assert_eq!(encode_latin1("plain ASCII"), Some(b"plain ASCII".to_vec()));
+		assert_eq!(encode_latin1("Grüße, ÿ"), Some(b"Gr\xfc\xdfe, \xff".to_vec()));
+		assert_eq!(encode_latin1(""), Some(Vec::new()));
+
+		assert_eq!(encode_latin1("日本語"), None);
+		// A single character outside of Latin-1 rules out the whole text.
+		assert_eq!(encode_latin1("Grüße €"), None);
+		assert_eq!(encode_latin1("Ā"), None);
+	}
+
+	#[test]
+	fn test_html_offers() {
+		// Without an alternative, the HTML mustn't be offered as plain text.
+		let offers = html_offers(b"<b>hi</b>".to_vec(), None, &WriteConfig::default());
+		assert_eq!(served(&offers), [("text/html", b"<b>hi</b>".as_slice())]);
+
+		let config =
+			WriteConfig { history_exclusion: HistoryExclusion::Strict, ..WriteConfig::default() };
+		let offers = html_offers(b"<b>hi</b>".to_vec(), Some(b"hi".to_vec()), &config);
+		assert_eq!(
+			served(&offers),
+			[
+				("x-kde-passwordManagerHint", b"secret".as_slice()),
+				("UTF8_STRING", b"hi"),
+				("text/plain;charset=utf-8", b"hi"),
+				("text/plain;charset=UTF-8", b"hi"),
+				("text/plain", b"hi"),
+				("TEXT", b"hi"),
+				("text/html", b"<b>hi</b>"),
+			]
+		);
+		assert!(!offers[0].advertised);
+	}
+
+	#[test]
+	fn test_file_and_image_offers() {
+		let uri_list = paths_to_uri_list(&["/"]).unwrap();
+		assert_eq!(uri_list, "file:///\r\n");
+		let offers = file_list_offers(uri_list, &WriteConfig::default());
+		assert_eq!(served(&offers), [("text/uri-list", b"file:///\r\n".as_slice())]);
+
+		#[cfg(feature = "image-data-png")]
+		{
+			let images = vec![("image/png", vec![1]), ("image/png;thumbnail", vec![2])];
+			let offers = encoded_offers(images, &WriteConfig::default());
+			assert_eq!(
+				served(&offers),
+				[("image/png", [1].as_slice()), ("image/png;thumbnail", [2].as_slice())]
+			);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn test_decode_image_files() {
+		use image::{codecs, ExtendedColorType, ImageEncoder};
+
+		#[rustfmt::skip]
+		let rgba = [
+			255, 0, 0, 255,   0, 255, 0, 255,
+			0, 0, 255, 255,   255, 255, 255, 255,
+		];
+		let encode = |encoder: &dyn Fn(&mut Vec<u8>) -> image::ImageResult<()>| {
+			let mut file = Vec::new();
+			encoder(&mut file).unwrap();
+			decode_image_file(&file, DEFAULT_MAX_IMAGE_PIXELS).unwrap()
+		};
+
+		let lossless = [
+			encode(&|file| {
+				codecs::png::PngEncoder::new(file).write_image(
+					&rgba,
+					2,
+					2,
+					ExtendedColorType::Rgba8,
+				)
+			}),
+			encode(&|file| {
+				codecs::webp::WebPEncoder::new_lossless(file).write_image(
+					&rgba,
+					2,
+					2,
+					ExtendedColorType::Rgba8,
+				)
+			}),
+			encode(&|file| {
+				codecs::bmp::BmpEncoder::new(file).write_image(
+					&rgba,
+					2,
+					2,
+					ExtendedColorType::Rgba8,
+				)
+			}),
+		];
+		for image in lossless {
+			assert_eq!((image.width, image.height), (2, 2));
+			assert_eq!(image.bytes, rgba.as_slice());
+		}
+
+		// JPEG is lossy and has no alpha, so only the size and opacity are reliable.
+		let jpeg = encode(&|file| {
+			codecs::jpeg::JpegEncoder::new(file).write_image(
+				&[128; 2 * 2 * 3],
+				2,
+				2,
+				ExtendedColorType::Rgb8,
+			)
+		});
+		assert_eq!((jpeg.width, jpeg.height), (2, 2));
+		assert!(jpeg.bytes.chunks_exact(4).all(|pixel| pixel[3] == 255));
+
+		assert!(matches!(
+			decode_image_file(b"not an image", DEFAULT_MAX_IMAGE_PIXELS),
+			Err(Error::ConversionFailure { .. })
+		));
+	}
+
+	#[test]
+	fn concurrent_construction_connects_once() {
+		use std::sync::{Arc, Barrier};
+
+		const THREAD_COUNT: usize = 16;
+
+		#[cfg(feature = "wayland-data-control")]
+		let probes_before = WAYLAND_PROBES.load(Ordering::Relaxed);
+
+		let barrier = Arc::new(Barrier::new(THREAD_COUNT));
+		let handles: Vec<_> = (0..THREAD_COUNT)
+			.map(|_| {
+				let barrier = barrier.clone();
+				std::thread::spawn(move || {
+					barrier.wait();
+					Clipboard::new().unwrap()
+				})
+			})
+			.collect();
+		let clipboards: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+		#[cfg(feature = "wayland-data-control")]
+		assert!(WAYLAND_PROBES.load(Ordering::Relaxed) - probes_before <= 1);
+
+		match &clipboards[0] {
+			Clipboard::X11(first) => {
+				for clipboard in &clipboards {
+					assert!(
+						matches!(clipboard, Clipboard::X11(other) if other.shares_connection(first))
+					);
+				}
+			}
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => {
+				assert!(clipboards
+					.iter()
+					.all(|clipboard| matches!(clipboard, Clipboard::WlDataControl(_))));
+			}
+		}
+	}
 }
@@ -1,31 +1,83 @@
 use std::{
 	borrow::Cow,
-	io::Read,
-	path::{Path, PathBuf},
+	collections::{hash_map::DefaultHasher, HashSet},
+	hash::{Hash, Hasher},
+	io::{self, Read, Write},
+	path::PathBuf,
+	sync::mpsc::{self, Receiver, RecvTimeoutError},
+	thread,
+	time::{Duration, Instant},
 };
 
+use parking_lot::Mutex;
 use wl_clipboard_rs::{
 	copy::{self, Error as CopyError, MimeSource, MimeType, Options, Source},
 	paste::{self, get_contents, Error as PasteError, Seat},
-	utils::is_primary_selection_supported,
+	utils::{is_primary_selection_supported, PrimarySelectionCheckError},
 };
 
-#[cfg(feature = "image-data")]
-use super::encode_as_png;
 use super::{
-	into_unknown, paths_from_uri_list, paths_to_uri_list, LinuxClipboardKind, WaitConfig,
-	KDE_EXCLUSION_HINT, KDE_EXCLUSION_MIME,
+	decode_html, file_list_with_op, into_unknown, offers_with_capacity, paths_from_uri_list,
+	serve_stop_reason, served_targets, LinuxClipboardKind, Offer, ReadConfig, ReadTargets,
+	ServeOutcome, WaitConfig, WriteConfig, HTML_MIME, SERVE_POLL_DUR, URI_LIST_MIME,
+};
+#[cfg(feature = "image-data-png")]
+use super::{decode_image_file, PNG_MIME};
+#[cfg(feature = "image-data-png")]
+use crate::common::ImageOrBytes;
+use crate::common::{
+	decode_text_prefix, poll_for_change, text_from_utf8, write_contents, write_error, Chunks,
+	Diagnostics, Error, FileOp,
 };
-use crate::common::Error;
-#[cfg(feature = "image-data")]
-use crate::common::ImageData;
 
+/// The image formats which are read from the clipboard, in order of preference.
+///
+/// Some applications, like Fractal and other GTK4 ones, only offer the lossy formats.
 #[cfg(feature = "image-data")]
-const MIME_PNG: &str = "image/png";
+const IMAGE_MIMES: [&str; 4] = [PNG_MIME, "image/webp", "image/jpeg", "image/bmp"];
+#[cfg(all(feature = "image-data-png", not(feature = "image-data")))]
+const IMAGE_MIMES: [&str; 1] = [PNG_MIME];
+
+/// Whether the compositor supported the primary selection when it was last checked, or used.
+///
+/// Compositors may only offer it for some seats, or start to offer it after the application
+/// started, so this is updated by every operation on the primary selection. It's shared by all
+/// `Clipboard`s, as they all talk to the same compositor.
+static PRIMARY_SUPPORTED: Mutex<Option<bool>> = parking_lot::const_mutex(None);
+
+/// Remembers whether an operation on `selection` found the primary selection to be supported.
+fn note_primary_support<T>(selection: LinuxClipboardKind, result: &Result<T, Error>) {
+	if record_primary_support(&PRIMARY_SUPPORTED, selection, result) {
+		log::warn!("The compositor doesn't offer the primary selection on any seat right now");
+	}
+}
 
-const MIME_URI: &str = "text/uri-list";
+/// Updates `supported` like `note_primary_support`, returning whether the primary selection
+/// just stopped being supported, so that it's only warned about once rather than on every
+/// operation.
+fn record_primary_support<T>(
+	supported: &Mutex<Option<bool>>,
+	selection: LinuxClipboardKind,
+	result: &Result<T, Error>,
+) -> bool {
+	if selection != LinuxClipboardKind::Primary {
+		return false;
+	}
+	let now_supported = match result {
+		Ok(_) => true,
+		Err(Error::ClipboardNotSupported) => false,
+		Err(_) => return false,
+	};
+	supported.lock().replace(now_supported) != Some(false) && !now_supported
+}
 
-pub(crate) struct Clipboard {}
+#[derive(Default)]
+pub(crate) struct Clipboard {
+	/// The fingerprints of what was last placed onto each selection through this `Clipboard`,
+	/// and when.
+	placed: Mutex<Vec<(LinuxClipboardKind, u64, Instant)>>,
+	pub(crate) targets: ReadTargets,
+}
 
 impl TryInto<copy::ClipboardType> for LinuxClipboardKind {
 	type Error = Error;
@@ -51,18 +103,38 @@ impl TryInto<paste::ClipboardType> for LinuxClipboardKind {
 	}
 }
 
-fn add_clipboard_exclusions(exclude_from_history: bool, sources: &mut Vec<MimeSource>) {
-	if exclude_from_history {
-		sources.push(MimeSource {
-			source: Source::Bytes(Box::from(KDE_EXCLUSION_HINT)),
-			mime_type: MimeType::Specific(String::from(KDE_EXCLUSION_MIME)),
-		});
-	}
+/// Places `offers` onto the selection.
+fn copy_offers(offers: &[Offer], config: &WriteConfig) -> Result<(), Error> {
+	let mut opts = Options::new();
+	opts.foreground(matches!(config.wait, WaitConfig::Forever));
+	opts.clipboard(config.selection.try_into()?);
+	// The text formats are part of the offers already, see `served_targets`.
+	opts.omit_additional_text_mime_types(true);
+
+	let result =
+		reconnecting(|| opts.clone().copy_multi(mime_sources(offers)).map_err(handle_copy_error));
+	note_primary_support(config.selection, &result);
+	result
 }
 
+/// The sources `wl-clipboard-rs` serves `offers` from.
+fn mime_sources(offers: &[Offer]) -> Vec<MimeSource> {
+	// `wl-clipboard-rs` wants a buffer of its own for every MIME type, which it writes into a
+	// temporary file right away, so these copies don't outlive the call.
+	served_targets(offers)
+		.into_iter()
+		.map(|(mime, offer)| MimeSource {
+			source: Source::Bytes(Box::from(&*offer.bytes)),
+			mime_type: MimeType::Specific(String::from(mime)),
+		})
+		.collect()
+}
 fn handle_copy_error(e: copy::Error) -> Error {
 	match e {
 		CopyError::PrimarySelectionUnsupported => Error::ClipboardNotSupported,
+		CopyError::SocketOpenError(_)
+		| CopyError::WaylandConnection(_)
+		| CopyError::WaylandCommunication(_) => Error::Disconnected,
 		other => into_unknown(other),
 	}
 }
@@ -70,196 +142,590 @@ fn handle_copy_error(e: copy::Error) -> Error {
 fn handle_paste_error(e: paste::Error) -> Error {
 	match e {
 		PasteError::PrimarySelectionUnsupported => Error::ClipboardNotSupported,
+		PasteError::SocketOpenError(_)
+		| PasteError::WaylandConnection(_)
+		| PasteError::WaylandCommunication(_) => Error::Disconnected,
 		other => into_unknown(other),
 	}
 }
 
+/// Checks that the compositor can be reached, and whether it supports the primary selection.
+fn connect() -> Result<(), Error> {
+	match is_primary_selection_supported() {
+		Ok(supported) => {
+			*PRIMARY_SUPPORTED.lock() = Some(supported);
+			Ok(())
+		}
+		Err(
+			PrimarySelectionCheckError::SocketOpenError(_)
+			| PrimarySelectionCheckError::WaylandConnection(_)
+			| PrimarySelectionCheckError::WaylandCommunication(_),
+		) => Err(Error::Disconnected),
+		Err(e) => Err(into_unknown(e)),
+	}
+}
+
+/// Runs `operation`, and once more if it failed because the compositor couldn't be reached.
+///
+/// `wl-clipboard-rs` opens a new connection for every operation, binding the globals and fetching
+/// the current offer again, so this is all it takes to recover from a compositor restart.
+fn reconnecting<T>(mut operation: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+	match operation() {
+		Err(Error::Disconnected) => {
+			log::info!("Lost the connection to the Wayland compositor, reconnecting.");
+			connect()?;
+			operation()
+		}
+		result => result,
+	}
+}
+
 fn handle_clipboard_read<T, F: FnOnce(Vec<u8>) -> Result<T, Error>>(
-	selection: LinuxClipboardKind,
+	config: &ReadConfig,
 	mime: paste::MimeType,
 	into_requested_data: F,
 ) -> Result<T, Error> {
-	let result = get_contents(selection.try_into()?, Seat::Unspecified, mime);
-	match result {
-		Ok((mut pipe, _)) => {
-			let mut buffer = vec![];
-			pipe.read_to_end(&mut buffer).map_err(into_unknown)?;
-			into_requested_data(buffer)
+	into_requested_data(read_pipe(open_pipe(config, mime)?, config)?)
+}
+
+/// Asks the source application for the contents in `mime`, which it writes into the returned
+/// pipe.
+fn open_pipe(config: &ReadConfig, mime: paste::MimeType) -> Result<impl Read + Send, Error> {
+	if config.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+		return Err(Error::Timeout);
+	}
+
+	let selection = config.selection.try_into()?;
+	let pipe = reconnecting(|| match get_contents(selection, Seat::Unspecified, mime) {
+		Ok((pipe, _)) => Ok(pipe),
+		Err(PasteError::ClipboardEmpty) => Err(Error::content_not_available()),
+		Err(PasteError::NoMimeType) => {
+			Err(Error::ContentNotAvailable { tried: tried_mime_types(mime) })
 		}
-		Err(PasteError::ClipboardEmpty) | Err(PasteError::NoMimeType) => {
-			Err(Error::ContentNotAvailable)
+		Err(err) => Err(handle_paste_error(err)),
+	});
+	note_primary_support(config.selection, &pipe);
+	pipe
+}
+
+/// The MIME types a read of `mime` asks for, as told by `ContentNotAvailable`.
+fn tried_mime_types(mime: paste::MimeType) -> Vec<String> {
+	match mime {
+		paste::MimeType::Specific(mime) => vec![mime.to_owned()],
+		// `wl-clipboard-rs` takes whichever of the plain text types is offered.
+		paste::MimeType::Text => vec!["text/plain".to_owned()],
+		paste::MimeType::TextWithPriority(mime) => vec![mime.to_owned(), "text/plain".to_owned()],
+		paste::MimeType::Any => Vec::new(),
+	}
+}
+
+/// Reads the contents the source application writes into `pipe`.
+///
+/// The source may take arbitrarily long to write them, so with a deadline the pipe is read on
+/// a separate thread, which is left to finish on its own if the deadline passes first. With
+/// `max_bytes`, the pipe is closed once one more byte than that was read.
+fn read_pipe(pipe: impl Read + Send + 'static, config: &ReadConfig) -> Result<Vec<u8>, Error> {
+	let limit = config.max_bytes.map_or(u64::MAX, |max| (max as u64).saturating_add(1));
+	let mut pipe = pipe.take(limit);
+
+	let Some(deadline) = config.deadline else {
+		let mut buffer = vec![];
+		pipe.read_to_end(&mut buffer).map_err(into_unknown)?;
+		return Ok(buffer);
+	};
+
+	let (sender, receiver) = mpsc::channel();
+	thread::spawn(move || {
+		let mut buffer = vec![];
+		let result = pipe.read_to_end(&mut buffer).map(|_| buffer);
+		let _ = sender.send(result);
+	});
+	match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+		Ok(result) => result.map_err(into_unknown),
+		Err(_) => {
+			log::info!("Time-out hit while reading the clipboard.");
+			Err(Error::Timeout)
 		}
+	}
+}
+
+/// Lists the MIME types the contents of the selection are offered in.
+fn offered_mime_types(config: &ReadConfig) -> Result<HashSet<String>, Error> {
+	let selection = config.selection.try_into()?;
+	let offered = reconnecting(|| match paste::get_mime_types(selection, Seat::Unspecified) {
+		Ok(offered) => Ok(offered),
+		Err(PasteError::ClipboardEmpty) => Err(Error::content_not_available()),
 		Err(err) => Err(handle_paste_error(err)),
+	});
+	note_primary_support(config.selection, &offered);
+	offered
+}
+
+/// The first of `mimes` which the contents on the selection are offered as.
+fn first_offered<'a>(config: &ReadConfig, mimes: &[&'a str]) -> Result<&'a str, Error> {
+	let offered = offered_mime_types(config)?;
+	mimes.iter().copied().find(|mime| offered.contains(*mime)).ok_or_else(|| {
+		Error::ContentNotAvailable { tried: mimes.iter().map(|&mime| mime.to_owned()).collect() }
+	})
+}
+
+/// A selection which is watched for new contents, see `Get::wait`.
+///
+/// `wl-clipboard-rs` doesn't tell about new offers, so the MIME types that are offered are
+/// compared instead. The contents aren't, as reading them on every check would cost too much for
+/// large ones, like images.
+pub(crate) struct ChangeWatch {
+	config: ReadConfig,
+	offered: Option<u64>,
+}
+
+impl ChangeWatch {
+	/// Waits until the selection is offered in other formats.
+	pub(crate) fn wait(&self, deadline: Option<Instant>) -> Result<(), Error> {
+		let config = ReadConfig { deadline, ..self.config };
+		poll_for_change(deadline, || {
+			let offered = hash_offered_mime_types(&config)?;
+			Ok(offered.is_some() && offered != self.offered)
+		})
 	}
 }
 
+/// Hashes the MIME types the selection is offered in, or returns `None` if it's empty.
+fn hash_offered_mime_types(config: &ReadConfig) -> Result<Option<u64>, Error> {
+	let mut offered: Vec<String> = match offered_mime_types(config) {
+		Ok(offered) => offered.into_iter().collect(),
+		Err(Error::ContentNotAvailable { .. }) => return Ok(None),
+		Err(error) => return Err(error),
+	};
+	offered.sort_unstable();
+
+	let mut hasher = DefaultHasher::new();
+	offered.hash(&mut hasher);
+	Ok(Some(hasher.finish()))
+}
+
+/// Hashes the MIME types the selection is offered in along with the contents of one of them,
+/// or returns `None` if it's empty.
+fn fingerprint(config: &ReadConfig) -> Result<Option<u64>, Error> {
+	let offered = match offered_mime_types(config) {
+		Ok(offered) => offered.into_iter().collect(),
+		Err(Error::ContentNotAvailable { .. }) => return Ok(None),
+		Err(error) => return Err(error),
+	};
+	fingerprint_of(offered, |sample| {
+		match handle_clipboard_read(config, paste::MimeType::Specific(sample), Ok) {
+			Ok(contents) => Ok(contents.into()),
+			Err(Error::ContentNotAvailable { .. }) => Ok(Cow::Borrowed(&[])),
+			Err(error) => Err(error),
+		}
+	})
+}
+
+/// The fingerprint of `offers` once they were placed onto a selection, as `fingerprint` would
+/// compute it.
+fn fingerprint_of_offers(offers: &[Offer]) -> Option<u64> {
+	let targets = served_targets(offers);
+	let offered = targets.iter().map(|(mime, _)| (*mime).to_owned()).collect();
+	let contents = |sample: &str| {
+		let offer = targets.iter().find(|(mime, _)| *mime == sample);
+		Ok(offer.map_or(Cow::Borrowed(&[][..]), |(_, offer)| Cow::Borrowed(&offer.bytes[..])))
+	};
+	fingerprint_of(offered, contents).ok().flatten()
+}
+
+/// Hashes the `offered` MIME types along with the contents of one of them, which are read with
+/// `read`, or returns `None` if there are none.
+fn fingerprint_of<'a>(
+	mut offered: Vec<String>,
+	read: impl FnOnce(&str) -> Result<Cow<'a, [u8]>, Error>,
+) -> Result<Option<u64>, Error> {
+	offered.sort_unstable();
+	offered.dedup();
+
+	// An application may place other contents in the same formats. Reading all of them on every
+	// check would be too much, so just the text is compared when there is some.
+	let Some(sample) = offered.iter().find(|mime| mime.starts_with("text/")).or(offered.first())
+	else {
+		return Ok(None);
+	};
+	let contents = read(sample)?;
+
+	let mut hasher = DefaultHasher::new();
+	(&offered, &*contents).hash(&mut hasher);
+	Ok(Some(hasher.finish()))
+}
+
 impl Clipboard {
 	pub(crate) fn new() -> Result<Self, Error> {
 		// Check if it's possible to communicate with the wayland compositor
 		match is_primary_selection_supported() {
-			// We don't care if the primary clipboard is supported or not, `wl-clipboard-rs` will fail
-			// if not and we don't want to duplicate more of their logic.
-			Ok(_) => Ok(Self {}),
+			// Whether the primary clipboard is supported only matters to `supports_selection`,
+			// `wl-clipboard-rs` fails if it's used without support.
+			Ok(supported) => {
+				*PRIMARY_SUPPORTED.lock() = Some(supported);
+				Ok(Self::default())
+			}
 			Err(e) => Err(into_unknown(e)),
 		}
 	}
 
+	/// Another handle, which doesn't know what was placed through this one.
+	pub(crate) fn clone_handle(&self) -> Self {
+		Self::default()
+	}
+
 	pub(crate) fn clear(&mut self, selection: LinuxClipboardKind) -> Result<(), Error> {
+		let kind = selection;
 		let selection = selection.try_into()?;
-		copy::clear(selection, copy::Seat::All).map_err(handle_copy_error)
+		reconnecting(|| copy::clear(selection, copy::Seat::All).map_err(handle_copy_error))?;
+		self.placed.lock().retain(|(placed, ..)| *placed != kind);
+		Ok(())
 	}
 
-	pub(crate) fn get_text(&mut self, selection: LinuxClipboardKind) -> Result<String, Error> {
-		handle_clipboard_read(selection, paste::MimeType::Text, |contents| {
-			String::from_utf8(contents).map_err(|_| Error::ConversionFailure)
-		})
+	/// When `selection` was placed through this `Clipboard`, if it's still offered just like
+	/// that.
+	///
+	/// `wl-clipboard-rs` doesn't tell when our offer is replaced, so what's offered is compared
+	/// with what was placed instead. Another application placing the very same contents counts
+	/// as them still being ours.
+	fn placed_at(&self, selection: LinuxClipboardKind) -> Result<Option<Instant>, Error> {
+		let placed = self.placed.lock().iter().find(|(placed, ..)| *placed == selection).copied();
+		let Some((_, placed, at)) = placed else {
+			return Ok(None);
+		};
+
+		let config = ReadConfig { selection, ..ReadConfig::default() };
+		Ok((fingerprint(&config)? == Some(placed)).then_some(at))
+	}
+
+	/// Whether `selection` is still offered just like it was last placed through this
+	/// `Clipboard`, which reads it back if anything was placed.
+	pub(crate) fn is_owner(&self, selection: LinuxClipboardKind) -> Result<bool, Error> {
+		Ok(self.placed_at(selection)?.is_some())
+	}
+
+	/// Clears `selection` if it's still offered just like it was last placed through this
+	/// `Clipboard`, returning whether it was.
+	pub(crate) fn clear_if_owner(&mut self, selection: LinuxClipboardKind) -> Result<bool, Error> {
+		if self.placed_at(selection)?.is_none() {
+			return Ok(false);
+		}
+		self.clear(selection)?;
+		Ok(true)
 	}
 
-	pub(crate) fn set_text(
+	/// The age of the contents of `selection`, which is only known for what was placed through
+	/// this `Clipboard`.
+	pub(crate) fn content_age(
 		&self,
-		text: Cow<'_, str>,
 		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
-	) -> Result<(), Error> {
-		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
-		opts.clipboard(selection.try_into()?);
+	) -> Result<Option<Duration>, Error> {
+		Ok(self.placed_at(selection)?.map(|at| at.elapsed()))
+	}
 
-		let mut sources = Vec::with_capacity(if exclude_from_history { 2 } else { 1 });
+	/// Places `offers` onto the selection, remembering them for `placed_at`.
+	pub(super) fn set_offers(&self, offers: &[Offer], config: &WriteConfig) -> Result<(), Error> {
+		copy_offers(offers, config)?;
 
-		sources.push(MimeSource {
-			source: Source::Bytes(text.into_owned().into_bytes().into_boxed_slice()),
-			mime_type: MimeType::Text,
-		});
+		let mut placed = self.placed.lock();
+		placed.retain(|(placed, ..)| *placed != config.selection);
+		if let Some(fingerprint) = fingerprint_of_offers(offers) {
+			placed.push((config.selection, fingerprint, Instant::now()));
+		}
+		Ok(())
+	}
 
-		add_clipboard_exclusions(exclude_from_history, &mut sources);
+	/// Starts watching the selection of `config` for new contents.
+	pub(crate) fn watch_changes(&self, config: &ReadConfig) -> Result<ChangeWatch, Error> {
+		Ok(ChangeWatch { config: *config, offered: hash_offered_mime_types(config)? })
+	}
 
-		opts.copy_multi(sources).map_err(handle_copy_error)
+	/// Connecting checks the compositor's capabilities again as well, as it may have been
+	/// restarted with different ones.
+	pub(crate) fn reconnect(&mut self) -> Result<(), Error> {
+		connect()
 	}
 
-	pub(crate) fn get_html(&mut self, selection: LinuxClipboardKind) -> Result<String, Error> {
-		handle_clipboard_read(selection, paste::MimeType::Specific("text/html"), |contents| {
-			String::from_utf8(contents).map_err(|_| Error::ConversionFailure)
-		})
+	pub(crate) fn diagnose(&self, diagnostics: &mut Diagnostics) {
+		diagnostics.backend = "Wayland data control".into();
+		// `wl-clipboard-rs` doesn't tell the version, but the primary selection came with 2.
+		let version =
+			if self.supports_selection(LinuxClipboardKind::Primary) { "2 or later" } else { "1" };
+		diagnostics.protocol = Some(format!("data control {version}"));
 	}
 
-	pub(crate) fn set_html(
-		&self,
-		html: Cow<'_, str>,
-		alt: Option<Cow<'_, str>>,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
-	) -> Result<(), Error> {
-		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
-		opts.clipboard(selection.try_into()?);
+	/// Whether the compositor's data control protocol offers `selection`.
+	///
+	/// The primary selection was only added in version 2 of the protocol, the secondary one
+	/// doesn't exist on Wayland at all.
+	pub(crate) fn supports_selection(&self, selection: LinuxClipboardKind) -> bool {
+		match selection {
+			LinuxClipboardKind::Clipboard => true,
+			LinuxClipboardKind::Primary => {
+				let cached = *PRIMARY_SUPPORTED.lock();
+				match cached {
+					Some(supported) => supported,
+					None => match is_primary_selection_supported() {
+						Ok(supported) => *PRIMARY_SUPPORTED.lock().get_or_insert(supported),
+						Err(e) => {
+							log::warn!(
+								"Failed to check whether the primary selection is supported: {e}"
+							);
+							false
+						}
+					},
+				}
+			}
+			LinuxClipboardKind::Secondary => false,
+		}
+	}
 
-		let mut sources = {
-			let cap = [true, alt.is_some(), exclude_from_history]
-				.map(|v| usize::from(v as u8))
-				.iter()
-				.sum();
-			Vec::with_capacity(cap)
-		};
+	/// Reads the contents of a selection in `mime` straight into `writer`.
+	///
+	/// With a deadline, the pipe is read on another thread, see `read_pipe`, so the contents
+	/// are collected before they are written.
+	pub(crate) fn write_to(
+		&mut self,
+		mime: &str,
+		config: &ReadConfig,
+		writer: &mut dyn Write,
+	) -> Result<u64, Error> {
+		let mime = paste::MimeType::Specific(mime);
+		if config.deadline.is_some() {
+			let bytes = read_pipe(open_pipe(config, mime)?, config)?;
+			return write_contents(writer, &bytes);
+		}
+		io::copy(&mut open_pipe(config, mime)?, writer).map_err(write_error)
+	}
 
-		if let Some(alt) = alt {
-			sources.push(MimeSource {
-				source: Source::Bytes(alt.into_owned().into_bytes().into_boxed_slice()),
-				mime_type: MimeType::Text,
-			});
+	/// Reads the contents of a selection in every format they are offered in.
+	pub(crate) fn capture(&mut self, config: &ReadConfig) -> Result<Vec<(String, Vec<u8>)>, Error> {
+		let offered = offered_mime_types(config)?;
+
+		let mut formats = Vec::with_capacity(offered.len());
+		for mime in offered {
+			match handle_clipboard_read(config, paste::MimeType::Specific(&mime), Ok) {
+				Ok(bytes) => formats.push((mime, bytes)),
+				Err(Error::ContentNotAvailable { .. }) => log::trace!("Couldn't capture {mime}"),
+				Err(error) => return Err(error),
+			}
 		}
+		// The offered types come without any particular order.
+		formats.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
 
-		sources.push(MimeSource {
-			source: Source::Bytes(html.into_owned().into_bytes().into_boxed_slice()),
-			mime_type: MimeType::Specific(String::from("text/html")),
-		});
+		if formats.is_empty() {
+			Err(Error::content_not_available())
+		} else {
+			Ok(formats)
+		}
+	}
 
-		add_clipboard_exclusions(exclude_from_history, &mut sources);
+	/// Places contents captured with `capture` onto a selection again.
+	pub(crate) fn restore(
+		&mut self,
+		formats: &[(String, Vec<u8>)],
+		config: &WriteConfig,
+	) -> Result<(), Error> {
+		let offers: Vec<_> =
+			formats.iter().map(|(mime, bytes)| Offer::new(mime.clone(), bytes.clone())).collect();
+		self.set_offers(&offers, config)
+	}
 
-		opts.copy_multi(sources).map_err(handle_copy_error)
+	/// The MIME type text is read as: any text type the compositor picks, or the first of the
+	/// ones set with `Clipboard::set_text_targets` which is offered.
+	fn text_mime(&self, config: &ReadConfig) -> Result<paste::MimeType<'_>, Error> {
+		let Some(targets) = &self.targets.text else {
+			return Ok(paste::MimeType::Text);
+		};
+		let targets: Vec<&str> = targets.iter().map(String::as_str).collect();
+		first_offered(config, &targets).map(paste::MimeType::Specific)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn get_image(
-		&mut self,
-		selection: LinuxClipboardKind,
-	) -> Result<ImageData<'static>, Error> {
-		use std::io::Cursor;
-
-		handle_clipboard_read(selection, paste::MimeType::Specific(MIME_PNG), |buffer| {
-			let image = image::io::Reader::new(Cursor::new(buffer))
-				.with_guessed_format()
-				.map_err(|_| Error::ConversionFailure)?
-				.decode()
-				.map_err(|_| Error::ConversionFailure)?;
-			let image = image.into_rgba8();
-
-			Ok(ImageData {
-				width: image.width() as usize,
-				height: image.height() as usize,
-				bytes: image.into_raw().into(),
-			})
-		})
+	pub(crate) fn get_text(&mut self, config: &ReadConfig) -> Result<String, Error> {
+		handle_clipboard_read(config, self.text_mime(config)?, text_from_utf8)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn set_image(
+	pub(crate) fn get_text_truncated(
 		&mut self,
-		image: ImageData,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
-	) -> Result<(), Error> {
-		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
-		opts.clipboard(selection.try_into()?);
-
-		let image = encode_as_png(&image)?;
+		config: &ReadConfig,
+		max_bytes: usize,
+	) -> Result<(String, bool), Error> {
+		handle_clipboard_read(config, self.text_mime(config)?, |contents| {
+			decode_text_prefix(contents, max_bytes)
+		})
+	}
 
-		let mut sources = Vec::with_capacity(if exclude_from_history { 2 } else { 1 });
+	pub(crate) fn get_html(&mut self, config: &ReadConfig) -> Result<String, Error> {
+		handle_clipboard_read(config, paste::MimeType::Specific(HTML_MIME), decode_html)
+	}
 
-		sources.push(MimeSource {
-			source: Source::Bytes(image.into()),
-			mime_type: MimeType::Specific(String::from(MIME_PNG)),
-		});
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn get_image_or_bytes(
+		&mut self,
+		config: &ReadConfig,
+	) -> Result<ImageOrBytes, Error> {
+		let mimes: Vec<&str> = match &self.targets.image {
+			Some(targets) => targets.iter().map(String::as_str).collect(),
+			None => IMAGE_MIMES.to_vec(),
+		};
+		let mime = first_offered(config, &mimes)?;
+
+		handle_clipboard_read(config, paste::MimeType::Specific(mime), |buffer| {
+			Ok(ImageOrBytes::decode(
+				buffer,
+				|bytes| decode_image_file(bytes, config.max_image_pixels),
+				|| mime.to_owned(),
+			))
+		})
+	}
 
-		add_clipboard_exclusions(exclude_from_history, &mut sources);
+	/// Places the data produced by `chunks` under `mime`. The chunks are collected up front, since
+	/// every paste is served from the same buffer.
+	pub(crate) fn set_stream(
+		&self,
+		mime: &str,
+		chunks: Chunks,
+		config: &WriteConfig,
+	) -> Result<(), Error> {
+		let mut offers = offers_with_capacity(1, config.history_exclusion);
+		offers.push(Offer::new(mime.to_owned(), chunks.flatten().collect::<Vec<_>>()));
+		self.set_offers(&offers, config)
+	}
 
-		opts.copy_multi(sources).map_err(handle_copy_error)
+	pub(crate) fn get_file_list(&mut self, config: &ReadConfig) -> Result<Vec<PathBuf>, Error> {
+		handle_clipboard_read(config, paste::MimeType::Specific(URI_LIST_MIME), |contents| {
+			Ok(paths_from_uri_list(contents))
+		})
 	}
 
-	pub(crate) fn get_file_list(
+	pub(crate) fn get_file_list_with_op(
 		&mut self,
-		selection: LinuxClipboardKind,
-	) -> Result<Vec<PathBuf>, Error> {
-		handle_clipboard_read(selection, paste::MimeType::Specific(MIME_URI), |contents| {
-			Ok(paths_from_uri_list(contents))
+		config: &ReadConfig,
+	) -> Result<(Vec<PathBuf>, FileOp), Error> {
+		file_list_with_op(|target| {
+			handle_clipboard_read(config, paste::MimeType::Specific(target), Ok)
 		})
 	}
 
-	pub(crate) fn set_file_list(
+	pub(crate) fn serve(
 		&self,
-		file_list: &[impl AsRef<Path>],
+		contents: Vec<(String, Vec<u8>)>,
 		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
-	) -> Result<(), Error> {
-		let files = paths_to_uri_list(file_list)?;
-
+		shutdown: &Receiver<()>,
+		deadline: Option<Instant>,
+	) -> Result<ServeOutcome, Error> {
 		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
 		opts.clipboard(selection.try_into()?);
 
-		let mut sources = Vec::with_capacity(if exclude_from_history { 2 } else { 1 });
-		sources.push(MimeSource {
-			source: Source::Bytes(files.into_bytes().into_boxed_slice()),
-			mime_type: MimeType::Specific(String::from(MIME_URI)),
+		let sources = contents
+			.into_iter()
+			.map(|(mime, bytes)| MimeSource {
+				source: Source::Bytes(bytes.into_boxed_slice()),
+				mime_type: MimeType::Specific(mime),
+			})
+			.collect();
+		let prepared = opts.prepare_copy_multi(sources).map_err(handle_copy_error)?;
+
+		// `PreparedCopy::serve` can't be interrupted, so it runs on its own thread that keeps
+		// serving in the background if we stop waiting for it.
+		let (done_tx, done_rx) = mpsc::channel();
+		std::thread::spawn(move || {
+			let _ = done_tx.send(prepared.serve());
+		});
+
+		loop {
+			match done_rx.recv_timeout(SERVE_POLL_DUR) {
+				Ok(result) => {
+					return result.map(|()| ServeOutcome::Replaced).map_err(handle_copy_error)
+				}
+				Err(RecvTimeoutError::Disconnected) => {
+					return Err(Error::unknown("The Wayland clipboard serving thread panicked."))
+				}
+				Err(RecvTimeoutError::Timeout) => {}
+			}
+
+			if let Some(reason) = serve_stop_reason(shutdown, deadline) {
+				// Clearing the selection ends `PreparedCopy::serve`, so that the contents stop being
+				// served like on X11, where they go away along with the `Clipboard`.
+				copy::clear(selection.try_into()?, copy::Seat::All).map_err(handle_copy_error)?;
+				return Ok(reason);
+			}
+		}
+	}
+
+	/// Places `offers` and serves them from this thread until they're replaced or `deadline`
+	/// has passed, calling `placed` once they're on the clipboard.
+	pub(super) fn serve_offers_until(
+		&self,
+		offers: &[Offer],
+		config: &WriteConfig,
+		deadline: Instant,
+		placed: impl FnOnce(),
+	) -> Result<(), Error> {
+		let mut opts = Options::new();
+		opts.foreground(true);
+		opts.clipboard(config.selection.try_into()?);
+		opts.omit_additional_text_mime_types(true);
+		let prepared = opts.prepare_copy_multi(mime_sources(offers)).map_err(handle_copy_error)?;
+		placed();
+
+		// Clearing the selection ends `PreparedCopy::serve` once the deadline passed.
+		let clipboard = config.selection.try_into()?;
+		thread::spawn(move || {
+			thread::sleep(deadline.saturating_duration_since(Instant::now()));
+			let _ = copy::clear(clipboard, copy::Seat::All);
 		});
+		prepared.serve().map_err(handle_copy_error)
+	}
+}
 
-		add_clipboard_exclusions(exclude_from_history, &mut sources);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::platform::linux::text_offers;
+
+	#[test]
+	fn placed_offers_match_their_fingerprint() {
+		let config = WriteConfig { text_aliases: true, ..WriteConfig::default() };
+		let offers = text_offers(b"secret".to_vec(), &config);
+		let expected = fingerprint_of_offers(&offers).unwrap();
+
+		// The compositor reports the MIME types in any order.
+		let mut offered: Vec<_> =
+			served_targets(&offers).into_iter().map(|(mime, _)| mime.to_owned()).collect();
+		offered.reverse();
+		let read = |sample: &str| {
+			assert!(sample.starts_with("text/"));
+			Ok(Cow::Owned(b"secret".to_vec()))
+		};
+		assert_eq!(fingerprint_of(offered.clone(), read).unwrap(), Some(expected));
+
+		let read = |_: &str| Ok(Cow::Owned(b"copied since".to_vec()));
+		assert_ne!(fingerprint_of(offered, read).unwrap(), Some(expected));
+		assert_eq!(fingerprint_of(Vec::new(), |_| unreachable!()).unwrap(), None);
+	}
 
-		opts.copy_multi(sources).map_err(handle_copy_error)
+	#[test]
+	fn primary_support_follows_the_last_operation() {
+		// A state of its own, as the global one is used by the other tests.
+		let supported = Mutex::new(None);
+		let record = |selection, result: Result<(), Error>| {
+			record_primary_support(&supported, selection, &result)
+		};
+		let primary = LinuxClipboardKind::Primary;
+		assert!(record(primary, Err(Error::ClipboardNotSupported)));
+		assert_eq!(*supported.lock(), Some(false));
+		// It's only reported the first time.
+		assert!(!record(primary, Err(Error::ClipboardNotSupported)));
+
+		// Other selections and unrelated failures say nothing about the primary selection.
+		assert!(!record(LinuxClipboardKind::Clipboard, Ok(())));
+		assert!(!record(primary, Err(Error::content_not_available())));
+		assert_eq!(*supported.lock(), Some(false));
+
+		assert!(!record(primary, Ok(())));
+		assert_eq!(*supported.lock(), Some(true));
+		// Once it was supported again, losing it is reported anew.
+		assert!(record(primary, Err(Error::ClipboardNotSupported)));
 	}
 }
@@ -13,16 +13,18 @@ and conditions of the chosen license apply to this file.
 // https://freedesktop.org/wiki/ClipboardManager/
 
 use std::{
-	borrow::Cow,
-	cell::RefCell,
-	collections::{hash_map::Entry, HashMap},
-	path::{Path, PathBuf},
+	collections::HashMap,
+	ffi::OsStr,
+	fmt,
+	io::Write,
+	path::PathBuf,
 	sync::{
-		atomic::{AtomicBool, Ordering},
+		atomic::{AtomicBool, AtomicU32, Ordering},
+		mpsc::{self, Receiver, TryRecvError},
 		Arc,
 	},
+	task::Poll,
 	thread::JoinHandle,
-	thread_local,
 	time::{Duration, Instant},
 };
 
@@ -30,31 +32,49 @@ use log::{error, trace, warn};
 use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
 use x11rb::{
 	connection::Connection,
+	errors::ReplyError,
 	protocol::{
+		xfixes::{ConnectionExt as _, SelectionEventMask},
 		xproto::{
-			Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property,
-			PropertyNotifyEvent, SelectionNotifyEvent, SelectionRequestEvent, Time, WindowClass,
+			Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux,
+			EventMask, GetPropertyReply, PropMode, Property, PropertyNotifyEvent,
+			SelectionNotifyEvent, SelectionRequestEvent, Time, Timestamp, Window, WindowClass,
 			SELECTION_NOTIFY_EVENT,
 		},
-		Event,
+		ErrorKind, Event,
 	},
 	rust_connection::RustConnection,
 	wrapper::ConnectionExt as _,
+	x11_utils::X11Error,
 	COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, NONE,
 };
 
-#[cfg(feature = "image-data")]
-use super::encode_as_png;
+#[cfg(feature = "image-data-png")]
+use super::decode_image_file;
+#[cfg(test)]
+use super::text_offers;
 use super::{
-	into_unknown, paths_from_uri_list, paths_to_uri_list, LinuxClipboardKind, WaitConfig,
-	KDE_EXCLUSION_HINT, KDE_EXCLUSION_MIME,
+	decode_html, file_list_with_op, into_unknown, offers_with_capacity, paths_from_uri_list,
+	serve_stop_reason, LinuxClipboardKind, Offer, PasteHandler, ReadConfig, ReadStats, ReadTargets,
+	RequestAction, RequestHandler, ServeOutcome, SharedBytes, TimeoutConfig, WaitConfig,
+	WriteConfig, X11Quirks, HTML_MIME, KDE_EXCLUSION_MIME, PNG_MIME, QT_IMAGE_MIME, STRING_TARGET,
+	TEXT_TARGET, URI_LIST_MIME, UTF8_ALIASES, UTF8_STRING_TARGET,
+};
+#[cfg(feature = "image-data-png")]
+use crate::{common::decode_png, ImageOrBytes};
+use crate::{
+	common::{
+		decode_text_prefix, poll_for_change, text_from_utf8, truncate_text, write_contents, Chunks,
+		Diagnostics, ScopeGuard, THUMBNAIL_MIME,
+	},
+	Error, FileOp,
 };
-#[cfg(feature = "image-data")]
-use crate::ImageData;
-use crate::{common::ScopeGuard, Error};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Where the segments of an `INCR` transfer go as they arrive, see `Inner::read_single_into`.
+type Sink<'a> = &'a mut dyn FnMut(&[u8]) -> Result<()>;
+
 static CLIPBOARD: Mutex<Option<GlobalClipboard>> = parking_lot::const_mutex(None);
 
 x11rb::atom_manager! {
@@ -66,6 +86,7 @@ x11rb::atom_manager! {
 		CLIPBOARD_MANAGER,
 		SAVE_TARGETS,
 		TARGETS,
+		TIMESTAMP,
 		ATOM,
 		INCR,
 
@@ -84,22 +105,91 @@ x11rb::atom_manager! {
 		URI_LIST: b"text/uri-list",
 
 		PNG_MIME: b"image/png",
+		PNG_THUMBNAIL_MIME: THUMBNAIL_MIME.as_bytes(),
+		QT_IMAGE_MIME: QT_IMAGE_MIME.as_bytes(),
 		X_KDE_PASSWORDMANAGERHINT: KDE_EXCLUSION_MIME.as_bytes(),
 
 		// This is just some random name for the property on our window, into which
 		// the clipboard owner writes the data we requested.
 		ARBOARD_CLIPBOARD,
+		// Holds the targets the clipboard manager is asked to save, on our server window.
+		ARBOARD_SAVE_TARGETS,
+		// Changed on our window to wake up the thread serving requests.
+		ARBOARD_WAKEUP,
 	}
 }
 
-thread_local! {
-	static ATOM_NAME_CACHE: RefCell<HashMap<Atom, &'static str>> = Default::default();
+/// How long a failed lookup of an atom's name is remembered before it's tried again.
+const ATOM_NAME_RETRY: Duration = Duration::from_secs(1);
+
+/// The names of atoms, for logging.
+///
+/// Only the names which were looked up successfully are kept for good. A failed lookup is
+/// retried once `retry_after` passed, so that a connection hiccup doesn't leave an atom without
+/// a name, without looking it up again on every log message in the meantime.
+struct AtomNames {
+	names: Mutex<HashMap<Atom, AtomName>>,
+	retry_after: Duration,
+}
+
+enum AtomName {
+	Known(&'static str),
+	Failed(Instant),
+}
+
+impl AtomNames {
+	fn new(retry_after: Duration) -> Self {
+		Self { names: Mutex::new(HashMap::new()), retry_after }
+	}
+
+	/// The name of `atom`, which is looked up with `lookup` if it isn't known yet.
+	fn get(&self, atom: Atom, lookup: impl FnOnce(Atom) -> Result<String>) -> &'static str {
+		const FAILED: &str = "FAILED-TO-GET-THE-ATOM-NAME";
+
+		match self.names.lock().get(&atom) {
+			Some(AtomName::Known(name)) => return name,
+			Some(AtomName::Failed(at)) if at.elapsed() < self.retry_after => return FAILED,
+			_ => {}
+		}
+
+		// The lookup is a round trip to the server, which mustn't hold up the other threads
+		// naming atoms, like the one serving requests.
+		let looked_up = lookup(atom);
+		let mut names = self.names.lock();
+		match looked_up {
+			Ok(name) => {
+				// Another thread may have looked it up in the meantime.
+				if let Some(AtomName::Known(known)) = names.get(&atom) {
+					return known;
+				}
+				// The names are kept for as long as the process runs, like the atoms themselves.
+				let name: &'static str = Box::leak(name.into_boxed_str());
+				names.insert(atom, AtomName::Known(name));
+				name
+			}
+			Err(e) => {
+				trace!("Failed to get the name of atom {atom}: {e}");
+				names.insert(atom, AtomName::Failed(Instant::now()));
+				FAILED
+			}
+		}
+	}
 }
 
-// Some clipboard items, like images, may take a very long time to produce a
-// `SelectionNotify`. Multiple seconds long.
-const LONG_TIMEOUT_DUR: Duration = Duration::from_millis(4000);
-const SHORT_TIMEOUT_DUR: Duration = Duration::from_millis(10);
+/// Targets which don't stand for the contents themselves and are left out of captures.
+///
+/// `TEXT` is left out as well, since the type of its reply names the actual encoding, which
+/// gets lost when it's captured; the contents are captured under that encoding's target anyway.
+const CAPTURE_SKIPPED_TARGETS: [&str; 8] = [
+	"TARGETS",
+	"MULTIPLE",
+	"TIMESTAMP",
+	"SAVE_TARGETS",
+	"DELETE",
+	"INSERT_SELECTION",
+	"INSERT_PROPERTY",
+	TEXT_TARGET,
+];
 
 #[derive(Debug, PartialEq, Eq)]
 enum ManagerHandoverState {
@@ -118,12 +208,15 @@ struct GlobalClipboard {
 struct XContext {
 	conn: RustConnection,
 	win_id: u32,
+	/// How many conversions into the window were abandoned before the owner finished, see
+	/// `Conversion`.
+	abandoned: AtomicU32,
 }
 
 struct Inner {
 	/// The context for the thread which serves clipboard read
 	/// requests coming to us.
-	server: XContext,
+	server: Arc<XContext>,
 	atoms: Atoms,
 
 	clipboard: Selection,
@@ -134,6 +227,42 @@ struct Inner {
 	handover_cv: Condvar,
 
 	serve_stopped: AtomicBool,
+
+	/// When the last `Clipboard` was dropped while selections with an idle timeout were still
+	/// being offered, or `None` while there are `Clipboard`s using this connection.
+	lingering_since: Mutex<Option<Instant>>,
+	/// When the server thread is going to be woken up next to expire lingering selections.
+	linger_wakeup: Mutex<Option<Instant>>,
+
+	/// Shared by all `Clipboard`s using this connection, the last ones configured apply.
+	timeouts: Mutex<TimeoutConfig>,
+
+	/// The streamed data which is being sent to requestors.
+	incr_transfers: Mutex<Vec<IncrTransfer>>,
+	/// The requests for lazily placed data which is still being produced.
+	deferred_requests: Mutex<Vec<DeferredRequest>>,
+	/// Hands the `SetExtLinux::on_paste` handlers of answered requests to the thread calling
+	/// them, which is started with the first one.
+	paste_handlers: Mutex<Option<mpsc::Sender<PasteHandler>>>,
+
+	/// Whether the X server is Xwayland, which bridges its selections to the Wayland ones.
+	xwayland: bool,
+
+	/// Shared by the thread serving requests and the threads using the `Clipboard`s.
+	atom_names: AtomNames,
+
+	/// What a connection of `Clipboard`s made with `Clipboard::new_isolated` keeps track of
+	/// itself, as it isn't registered in `CLIPBOARD`, or `None` for the global ones.
+	isolated: Option<Mutex<Isolated>>,
+}
+
+#[derive(Default)]
+struct Isolated {
+	/// The thread serving requests over the connection.
+	server_handle: Option<JoinHandle<()>>,
+	/// How many `Clipboard`s use the connection. It's only changed under the lock, so that
+	/// exactly one of them sees it drop to zero, no matter how many are dropped at once.
+	clipboards: usize,
 }
 
 impl XContext {
@@ -171,7 +300,52 @@ impl XContext {
 		.map_err(into_unknown)?;
 		conn.flush().map_err(into_unknown)?;
 
-		Ok(Self { conn, win_id })
+		Ok(Self { conn, win_id, abandoned: AtomicU32::new(0) })
+	}
+
+	/// Wakes the thread waiting for events of the window with a `PropertyNotify` for `wakeup`.
+	fn wake(&self, wakeup: Atom) -> Result<()> {
+		self.conn
+			.change_property8(PropMode::REPLACE, self.win_id, wakeup, AtomEnum::INTEGER, &[])
+			.map_err(into_unknown)?;
+		self.conn.flush().map_err(into_unknown)
+	}
+}
+
+/// A conversion of a selection into a property of a reader's window, for the duration of a read.
+///
+/// If the read is given up before the owner finished, like when it times out or its sink fails
+/// or panics, the owner may go on writing `INCR` segments into the property until it notices that
+/// nobody deletes them anymore. Later conversions into the window therefore use another property,
+/// so that they never pick up what was meant for an abandoned one.
+struct Conversion<'a> {
+	reader: &'a XContext,
+	property: Atom,
+	/// Whether the owner is done with the property, by having sent all of the data or refused it.
+	finished: bool,
+	/// The type of the `INCR` segments that arrived so far.
+	reply_type: Atom,
+}
+
+impl<'a> Conversion<'a> {
+	fn start(inner: &Inner, reader: &'a XContext) -> Result<Self> {
+		let property = match reader.abandoned.load(Ordering::Relaxed) {
+			0 => inner.atoms.ARBOARD_CLIPBOARD,
+			abandoned => inner.intern_atom(&format!("ARBOARD_CLIPBOARD_{abandoned}"))?,
+		};
+		Ok(Self { reader, property, finished: false, reply_type: NONE })
+	}
+}
+
+impl Drop for Conversion<'_> {
+	fn drop(&mut self) {
+		if self.finished {
+			return;
+		}
+		trace!("Abandoning an unfinished conversion");
+		self.reader.abandoned.fetch_add(1, Ordering::Relaxed);
+		// Whatever already arrived for the conversion is of no use to the next one.
+		while let Ok(Some(_)) = self.reader.conn.poll_for_event() {}
 	}
 }
 
@@ -180,35 +354,368 @@ struct Selection {
 	data: RwLock<Option<Vec<ClipboardData>>>,
 	/// Mutex around when this selection was last changed by us
 	/// for both use with the below condvar and logging.
+	///
+	/// This is reset to `None` once we lose ownership of the selection.
 	mutex: Mutex<Option<Instant>>,
 	/// A condvar that is notified when the contents of this clipboard are changed.
 	///
 	/// This is associated with `Self::mutex`.
 	data_changed: Condvar,
+	/// How long the data may go unrequested once no `Clipboard` is left, if it
+	/// should outlive the last one at all.
+	idle_timeout: Mutex<Option<Duration>>,
+	/// When the data was last requested by another application.
+	last_request: Mutex<Option<Instant>>,
+	/// The server time the data was placed at, which `TIMESTAMP` requests are answered with.
+	///
+	/// It's recorded by the server thread, see `Inner::stamp_write`.
+	owned_at: Mutex<Option<Timestamp>>,
+	/// The workarounds used when answering requests for the data.
+	quirks: Mutex<X11Quirks>,
+	/// The selection of the clipboard manager the data is handed over to, if it isn't
+	/// `CLIPBOARD_MANAGER`.
+	manager: Mutex<Option<Atom>>,
+	/// Decides how requests for the data are answered, see `SetExtLinux::on_request`.
+	on_request: Mutex<Option<RequestHandler>>,
+	/// Told about every answered request for the data, see `SetExtLinux::on_paste`.
+	on_paste: Mutex<Option<PasteHandler>>,
 }
 
 #[derive(Debug, Clone)]
 struct ClipboardData {
+	bytes: SharedBytes,
+
+	/// The atom representing the format in which the data is encoded.
+	format: Atom,
+
+	/// Whether the format is listed in the response to a `TARGETS` request.
+	advertised: bool,
+
+	/// Where the data comes from instead of `bytes`, if it was placed with `Set::stream`.
+	stream: Option<Stream>,
+
+	/// Where the data comes from instead of `bytes`, if it was placed with `Set::image_lazy`.
+	lazy: Option<Lazy>,
+}
+
+impl ClipboardData {
+	/// The bytes of the data, which are produced first if the data is placed lazily. `None` if
+	/// they couldn't be.
+	///
+	/// Producing them may take a while, so this mustn't be called while the selection is locked.
+	fn into_contents(self) -> Option<Vec<u8>> {
+		match &self.lazy {
+			Some(lazy) => Some(lazy.bytes()?.to_vec()),
+			None => Some(self.bytes.into_vec()),
+		}
+	}
+}
+
+/// Data which was read from a selection.
+struct ReadData {
 	bytes: Vec<u8>,
 
 	/// The atom representing the format in which the data is encoded.
 	format: Atom,
 }
 
+/// Data placed with `Set::stream`. It's produced while it's transferred, so it can only be
+/// transferred once.
+#[derive(Clone)]
+struct Stream(Arc<Mutex<Option<Chunks>>>);
+
+impl fmt::Debug for Stream {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Stream")
+	}
+}
+
+/// Produces the bytes of data which is placed lazily.
+pub(crate) type Produce = Box<dyn FnOnce() -> Result<Vec<u8>> + Send>;
+
+/// Data placed with `Set::image_lazy`. It's produced on a thread of its own when it's first
+/// requested, and the result is kept for the requests that follow.
+#[derive(Clone)]
+struct Lazy(Arc<LazyShared>);
+
+struct LazyShared {
+	state: Mutex<LazyState>,
+	/// Notified once the data was produced, or failed to be.
+	finished: Condvar,
+	/// The connection whose server thread is woken once the data was produced, to answer the
+	/// requests waiting for it.
+	server: Arc<XContext>,
+	wakeup: Atom,
+}
+
+enum LazyState {
+	Pending(Produce),
+	Producing,
+	Produced(Arc<[u8]>),
+	Failed,
+}
+
+impl Lazy {
+	fn new(produce: Produce, server: Arc<XContext>, wakeup: Atom) -> Self {
+		Self(Arc::new(LazyShared {
+			state: Mutex::new(LazyState::Pending(produce)),
+			finished: Condvar::new(),
+			server,
+			wakeup,
+		}))
+	}
+
+	/// The bytes of the data, or `Poll::Ready(None)` if they couldn't be produced. Starts
+	/// producing them if this is the first time they are needed, without waiting for that.
+	fn poll(&self) -> Poll<Option<Arc<[u8]>>> {
+		let mut state = self.0.state.lock();
+		self.start(&mut state);
+		match &*state {
+			LazyState::Produced(bytes) => Poll::Ready(Some(Arc::clone(bytes))),
+			LazyState::Failed => Poll::Ready(None),
+			LazyState::Pending(_) | LazyState::Producing => Poll::Pending,
+		}
+	}
+
+	/// Like `poll`, but waits for the data to be produced. This mustn't be called on the server
+	/// thread, which would stop answering requests until then.
+	fn bytes(&self) -> Option<Arc<[u8]>> {
+		let mut state = self.0.state.lock();
+		self.start(&mut state);
+		while matches!(*state, LazyState::Producing) {
+			self.0.finished.wait(&mut state);
+		}
+		match &*state {
+			LazyState::Produced(bytes) => Some(Arc::clone(bytes)),
+			_ => None,
+		}
+	}
+
+	/// Produces the data on a thread of its own if that didn't happen yet, so that neither the
+	/// server thread nor the locks of the selection are held up by it, and a panic doesn't
+	/// stop serving.
+	fn start(&self, state: &mut LazyState) {
+		if !matches!(state, LazyState::Pending(_)) {
+			return;
+		}
+		let LazyState::Pending(produce) = std::mem::replace(state, LazyState::Producing) else {
+			unreachable!()
+		};
+
+		let shared = Arc::clone(&self.0);
+		std::thread::spawn(move || {
+			let started = Instant::now();
+			let produced = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(produce)) {
+				Ok(Ok(bytes)) => {
+					trace!("Produced the lazily placed data in {:?}", started.elapsed());
+					LazyState::Produced(bytes.into())
+				}
+				Ok(Err(e)) => {
+					error!("Failed to produce the lazily placed data: {e}");
+					LazyState::Failed
+				}
+				Err(_) => {
+					error!("Producing the lazily placed data panicked");
+					LazyState::Failed
+				}
+			};
+			*shared.state.lock() = produced;
+			shared.finished.notify_all();
+			if let Err(e) = shared.server.wake(shared.wakeup) {
+				error!("Failed to wake the clipboard server thread. Error: {}", e);
+			}
+		});
+	}
+}
+
+/// A request for lazily placed data, which is answered once the data was produced.
+struct DeferredRequest {
+	event: SelectionRequestEvent,
+	selection: LinuxClipboardKind,
+	reply_type: Atom,
+	lazy: Lazy,
+}
+
+impl fmt::Debug for Lazy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Lazy")
+	}
+}
+
+/// A transfer of streamed data to a requestor, in `INCR` segments.
+struct IncrTransfer {
+	requestor: Window,
+	property: Atom,
+	format: Atom,
+	/// Told once the transfer finished, see `SetExtLinux::on_paste`.
+	on_paste: Option<PasteHandler>,
+	/// The chunks, which `pull_chunks` takes from the iterator on a thread of its own. `None`
+	/// marks the end of the data.
+	chunks: Receiver<Option<Vec<u8>>>,
+	/// The chunk that is being sent, and how much of it was sent already.
+	chunk: Vec<u8>,
+	sent: usize,
+	/// Whether the requestor asked for a segment which wasn't sent yet, because the chunk it's
+	/// part of hasn't arrived.
+	asked: bool,
+	/// When the requestor last asked for a segment.
+	last_request: Instant,
+}
+
+/// How far `Inner::send_incr_segment` got with a transfer.
+enum IncrProgress {
+	/// The transfer goes on, with or without a segment having been sent.
+	Ongoing,
+	/// The last, empty, segment was sent.
+	Finished,
+	/// The iterator panicked, so the rest of the data will never arrive.
+	Failed,
+}
+
+/// Takes the chunks from `chunks` on a thread of its own, so that an iterator which is slow or
+/// panics doesn't hold up serving the other requests. Each chunk wakes the server thread to send
+/// it, and the thread stops when the transfer is dropped.
+fn pull_chunks(
+	mut chunks: Chunks,
+	server: Arc<XContext>,
+	wakeup: Atom,
+) -> Receiver<Option<Vec<u8>>> {
+	// A single chunk is taken ahead, so that the data isn't produced faster than it's sent.
+	let (sender, receiver) = mpsc::sync_channel(1);
+	std::thread::spawn(move || loop {
+		let chunk = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| chunks.next())) {
+			// An empty chunk would end the transfer early.
+			Ok(Some(chunk)) if chunk.is_empty() => continue,
+			Ok(chunk) => chunk,
+			Err(_) => {
+				error!("The iterator of streamed data panicked, abandoning its transfer");
+				// Waking the server thread lets it notice the dropped sender.
+				drop(sender);
+				let _ = server.wake(wakeup);
+				return;
+			}
+		};
+		let last = chunk.is_none();
+		if sender.send(chunk).is_err() || server.wake(wakeup).is_err() || last {
+			return;
+		}
+	});
+	receiver
+}
+
+/// Drops the transfers whose requestor stopped asking for segments, while not waiting on a chunk.
+fn prune_incr_transfers(transfers: &mut Vec<IncrTransfer>) {
+	transfers.retain(|transfer| {
+		let active = transfer.asked || transfer.last_request.elapsed() < INCR_TRANSFER_IDLE_TIMEOUT;
+		if !active {
+			warn!("Abandoning the transfer of streamed data to a requestor which stopped asking for it");
+		}
+		active
+	});
+}
+
+/// The most data sent in one `INCR` segment, well below the maximum request size.
+const INCR_SEGMENT_BYTES: usize = 64 * 1024;
+
+/// How long a requestor may take to ask for the next segment of streamed data before the
+/// transfer is abandoned.
+const INCR_TRANSFER_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A selection which is watched for new owners, see `Get::wait`.
+pub(crate) struct ChangeWatch {
+	context: XContext,
+	selection: Atom,
+	/// Whether the server tells about new owners with XFixes events. Otherwise, the owner is
+	/// compared to the one there was when the watch started, which doesn't notice the owner
+	/// taking the selection again for other contents.
+	xfixes: bool,
+	owner: Window,
+}
+
+impl ChangeWatch {
+	fn new(selection: Atom) -> Result<Self> {
+		let context = XContext::new()?;
+		let owner = context
+			.conn
+			.get_selection_owner(selection)
+			.map_err(into_unknown)?
+			.reply()
+			.map_err(into_unknown)?
+			.owner;
+
+		let xfixes = context.conn.xfixes_query_version(1, 0).ok().and_then(|v| v.reply().ok());
+		if xfixes.is_some() {
+			context
+				.conn
+				.xfixes_select_selection_input(
+					context.win_id,
+					selection,
+					SelectionEventMask::SET_SELECTION_OWNER,
+				)
+				.map_err(into_unknown)?;
+			context.conn.flush().map_err(into_unknown)?;
+		} else {
+			log::debug!("XFixes isn't available, polling the selection owner for changes.");
+		}
+
+		Ok(Self { context, selection, xfixes: xfixes.is_some(), owner })
+	}
+
+	/// Waits until the selection got a new owner, which also happens when its owner places
+	/// other contents onto it.
+	pub(crate) fn wait(&self, deadline: Option<Instant>) -> Result<()> {
+		let conn = &self.context.conn;
+		poll_for_change(deadline, || {
+			if !self.xfixes {
+				let owner = conn
+					.get_selection_owner(self.selection)
+					.map_err(into_unknown)?
+					.reply()
+					.map_err(into_unknown)?
+					.owner;
+				return Ok(owner != NONE && owner != self.owner);
+			}
+
+			let mut changed = false;
+			while let Some(event) = conn.poll_for_event().map_err(into_unknown)? {
+				if let Event::XfixesSelectionNotify(event) = event {
+					// Clearing the selection also sets its owner, to none.
+					changed |= event.selection == self.selection && event.owner != NONE;
+				}
+			}
+			Ok(changed)
+		})
+	}
+}
+
 enum ReadSelNotifyResult {
-	GotData(Vec<u8>),
+	/// The data, and the type the owner labelled it with.
+	GotData(Vec<u8>, Atom),
 	IncrStarted,
 	EventNotRecognized,
 }
 
 impl Inner {
-	fn new() -> Result<Self> {
+	fn new(timeouts: TimeoutConfig, isolated: bool) -> Result<Self> {
 		let server = XContext::new()?;
 		let atoms =
 			Atoms::new(&server.conn).map_err(into_unknown)?.reply().map_err(into_unknown)?;
 
+		let has_extension = server
+			.conn
+			.query_extension(XWAYLAND_EXTENSION)
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.is_some_and(|reply| reply.present);
+		let xwayland = is_xwayland(has_extension, std::env::var_os("WAYLAND_DISPLAY").as_deref());
+		if xwayland {
+			log::info!(
+				"The X server is Xwayland, so the clipboard is bridged to the Wayland compositor's."
+			);
+		}
+
 		Ok(Self {
-			server,
+			server: Arc::new(server),
 			atoms,
 			clipboard: Selection::default(),
 			primary: Selection::default(),
@@ -216,6 +723,15 @@ impl Inner {
 			handover_state: Mutex::new(ManagerHandoverState::Idle),
 			handover_cv: Condvar::new(),
 			serve_stopped: AtomicBool::new(false),
+			lingering_since: Mutex::new(None),
+			linger_wakeup: Mutex::new(None),
+			timeouts: Mutex::new(timeouts),
+			incr_transfers: Mutex::new(Vec::new()),
+			deferred_requests: Mutex::new(Vec::new()),
+			paste_handlers: Mutex::new(None),
+			xwayland,
+			atom_names: AtomNames::new(ATOM_NAME_RETRY),
+			isolated: isolated.then(|| Mutex::new(Isolated::default())),
 		})
 	}
 
@@ -226,7 +742,36 @@ impl Inner {
 	///
 	/// See `ask_clipboard_manager_to_request_our_data` for more details on why
 	/// this is important and specification references.
-	fn clear(&self, selection: LinuxClipboardKind) -> Result<()> {
+	///
+	/// The data we offered is dropped as well, so that a cleared selection is never
+	/// handed over to the clipboard manager afterwards.
+	fn clear(&self, kind: LinuxClipboardKind) -> Result<()> {
+		let selection = self.selection_of(kind);
+		// Keep the same locking order as `write` and `SelectionClear`. Giving up the selection
+		// while holding the lock keeps a concurrent `write` from placing data in between, which
+		// would then be dropped right away.
+		let mut data_guard = selection.data.write();
+		self.disown(kind)?;
+		*data_guard = None;
+		let mut guard = selection.mutex.lock();
+		*guard = None;
+		selection.data_changed.notify_all();
+		drop(guard);
+		drop(data_guard);
+
+		if kind == LinuxClipboardKind::Clipboard {
+			// A handover which is already underway is left to finish or time out.
+			let mut handover_state = self.handover_state.lock();
+			if *handover_state == ManagerHandoverState::Finished {
+				*handover_state = ManagerHandoverState::Idle;
+			}
+		}
+		Ok(())
+	}
+
+	/// Relinquishes the selection without touching the data, for callers which
+	/// already hold its locks.
+	fn disown(&self, selection: LinuxClipboardKind) -> Result<()> {
 		let selection = self.atom_of(selection);
 
 		self.server
@@ -237,136 +782,536 @@ impl Inner {
 		self.server.conn.flush().map_err(into_unknown)
 	}
 
-	fn write(
-		&self,
-		data: Vec<ClipboardData>,
-		clipboard_selection: LinuxClipboardKind,
-		wait: WaitConfig,
-	) -> Result<()> {
+	/// Releases `selection` like `disown`, and asks the X server whether nobody owns it anymore.
+	/// Returns whether that's the case.
+	///
+	/// Requests on a connection are processed in order, so the answer already accounts for the
+	/// release. Another application, like a clipboard manager that noticed the selection
+	/// changing, may have taken it in the meantime, in which case the data it got is out of our
+	/// hands.
+	fn disown_confirmed(&self, selection: LinuxClipboardKind) -> Result<bool> {
+		self.disown(selection)?;
+		Ok(self.owner_of(selection)? == NONE)
+	}
+
+	/// Drops the data of all selections which are no longer ours, in case losing them wasn't
+	/// processed yet, and the spare capacity of the data lists of the others. Returns how many
+	/// bytes of data were released.
+	fn trim_memory(&self) -> Result<usize> {
+		let mut released = 0;
+		for kind in LinuxClipboardKind::all() {
+			let selection = self.selection_of(kind);
+			// Holding the lock keeps `write` from taking the selection in the meantime.
+			let mut data_guard = selection.data.write();
+			let Some(data) = data_guard.as_mut() else {
+				continue;
+			};
+			if self.is_owner(kind)? {
+				// Targets added with `add_target` may have left the list with room to spare.
+				data.shrink_to_fit();
+				continue;
+			}
+
+			trace!("Dropping the data of the {kind:?} selection, which we no longer own");
+			let data = data_guard.take().unwrap_or_default();
+			released += data.iter().map(|data| data.bytes.len()).sum::<usize>();
+
+			// Keep the same locking order as `write` and `SelectionClear`.
+			let mut guard = selection.mutex.lock();
+			*guard = None;
+			selection.data_changed.notify_all();
+		}
+		Ok(released)
+	}
+
+	/// Whether any selection we own should be served beyond the last `Clipboard`.
+	fn has_idle_timeouts(&self) -> bool {
+		[&self.clipboard, &self.primary, &self.secondary].into_iter().any(|selection| {
+			selection.data.read().is_some() && selection.idle_timeout.lock().is_some()
+		})
+	}
+
+	/// Wakes up the thread serving requests by touching a property on its window.
+	fn wake_server(&self) -> Result<()> {
+		self.server.wake(self.atoms.ARBOARD_WAKEUP)
+	}
+
+	/// Wakes up the thread serving requests at `at`, unless it's going to be woken up by then
+	/// already.
+	fn wake_server_at(&self, at: Instant) {
+		let mut scheduled = self.linger_wakeup.lock();
+		let now = Instant::now();
+		if scheduled.is_some_and(|scheduled| scheduled > now && scheduled <= at) {
+			return;
+		}
+		*scheduled = Some(at);
+
+		let server = Arc::clone(&self.server);
+		let wakeup = self.atoms.ARBOARD_WAKEUP;
+		std::thread::spawn(move || {
+			std::thread::sleep(at.saturating_duration_since(Instant::now()));
+			// The window is gone if the server thread stopped in the meantime.
+			let _ = server.wake(wakeup);
+		});
+	}
+
+	/// Releases the selections which weren't requested for longer than their idle timeout since
+	/// `lingering_since`. Selections without an idle timeout are released right away.
+	///
+	/// Returns when the next of the remaining selections expires, or `None` if there is nothing
+	/// left to serve.
+	fn expire_idle_selections(&self, lingering_since: Instant) -> Option<Instant> {
+		let mut next_expiry: Option<Instant> = None;
+		for kind in LinuxClipboardKind::all() {
+			let selection = self.selection_of(kind);
+			let mut data_guard = selection.data.write();
+			if data_guard.is_none() {
+				continue;
+			}
+
+			let idle_since =
+				selection.last_request.lock().map_or(lingering_since, |at| at.max(lingering_since));
+			let expiry =
+				selection.idle_timeout.lock().map(|idle_timeout| idle_since + idle_timeout);
+			if let Some(expiry) = expiry.filter(|&expiry| expiry > Instant::now()) {
+				next_expiry = Some(next_expiry.map_or(expiry, |next| next.min(expiry)));
+				continue;
+			}
+
+			trace!("The {kind:?} selection went unrequested for too long, releasing it");
+			*data_guard = None;
+			if let Err(e) = self.disown(kind) {
+				warn!("Failed to release the {kind:?} selection: {e}");
+			}
+
+			// Keep the same locking order as `write` and `SelectionClear`.
+			let mut guard = selection.mutex.lock();
+			*guard = None;
+			selection.data_changed.notify_all();
+		}
+		next_expiry
+	}
+
+	fn write(&self, data: Vec<ClipboardData>, config: &WriteConfig) -> Result<()> {
+		self.write_tracked(data, config)?;
+		Ok(())
+	}
+
+	/// Like `write`, returning when the data was written, which tells it apart from later writes
+	/// to the selection.
+	fn write_tracked(&self, data: Vec<ClipboardData>, config: &WriteConfig) -> Result<Instant> {
 		if self.serve_stopped.load(Ordering::Relaxed) {
 			return Err(Error::unknown("The clipboard handler thread seems to have stopped. Logging messages may reveal the cause. (See the `log` crate.)"));
 		}
 
 		let server_win = self.server.win_id;
+		let manager = match &config.manager_selection {
+			Some(name) => Some(self.intern_atom(name)?),
+			None => None,
+		};
 
 		// Just setting the data, and the `serve_requests` will take care of the rest.
+		let clipboard_selection = config.selection;
 		let selection = self.selection_of(clipboard_selection);
 		let mut data_guard = selection.data.write();
+		let recent = config.debounce.is_some_and(|window| {
+			selection.mutex.lock().is_some_and(|written_at| written_at.elapsed() < window)
+		});
+		// The last write is only forgotten once the `SelectionClear` was handled, which may not
+		// have happened yet, so the server is asked whether the selection is still ours.
+		let coalesced = recent && self.is_owner(clipboard_selection)?;
 		*data_guard = Some(data);
+		self.stamp_write(clipboard_selection)?;
+		*selection.idle_timeout.lock() = config.idle_timeout;
+		*selection.quirks.lock() = config.x11_quirks;
+		*selection.manager.lock() = manager;
+		*selection.on_request.lock() = config.on_request.clone();
+		*selection.on_paste.lock() = config.on_paste.clone();
 
 		// ICCCM version 2, section 2.6.1.3 states that we should re-assert ownership whenever data
 		// changes.
-		self.server
-			.conn
-			.set_selection_owner(server_win, self.atom_of(clipboard_selection), Time::CURRENT_TIME)
-			.map_err(|_| Error::ClipboardOccupied)?;
+		//
+		// The data, including any history exclusion hint, has to be fully in place before taking
+		// ownership: clipboard managers request `TARGETS` as soon as they're notified of the new
+		// owner, and some of them (ex. Klipper) only look for the hint in that first reply.
+		if coalesced {
+			trace!("Coalescing the write with the previous one to the {clipboard_selection:?} selection");
+			self.server.conn.flush().map_err(into_unknown)?;
+		} else {
+			self.server
+				.conn
+				.set_selection_owner(
+					server_win,
+					self.atom_of(clipboard_selection),
+					Time::CURRENT_TIME,
+				)
+				.map_err(|_| Error::ClipboardOccupied)?;
 
-		self.server.conn.flush().map_err(into_unknown)?;
+			self.server.conn.flush().map_err(into_unknown)?;
+		}
 
 		// Lock the mutex to both ensure that no wakers of `data_changed` can wake us between
 		// dropping the `data_guard` and calling `wait[_for]` and that we don't we wake other
 		// threads in that position.
 		let mut guard = selection.mutex.lock();
 		// Record the time we modify the selection.
-		*guard = Some(Instant::now());
+		let written_at = Instant::now();
+		*guard = Some(written_at);
 
 		// Notify any existing waiting threads that we have changed the data in the selection.
 		// It is important that the mutex is locked to prevent this notification getting lost.
 		selection.data_changed.notify_all();
 
-		match wait {
+		// The condvar may wake up spuriously, so only stop waiting once our write was actually
+		// replaced, either by another write in this process or by losing the selection.
+		match config.wait {
 			WaitConfig::None => {}
 			WaitConfig::Forever => {
 				drop(data_guard);
-				selection.data_changed.wait(&mut guard);
+				while *guard == Some(written_at) {
+					selection.data_changed.wait(&mut guard);
+				}
 			}
 
 			WaitConfig::Until(deadline) => {
 				drop(data_guard);
-				selection.data_changed.wait_until(&mut guard, deadline);
+				while *guard == Some(written_at) {
+					if selection.data_changed.wait_until(&mut guard, deadline).timed_out() {
+						break;
+					}
+				}
+				if config.clear_after_wait && *guard == Some(written_at) {
+					drop(guard);
+					trace!("The deadline passed, releasing the {clipboard_selection:?} selection");
+					self.release_written(clipboard_selection, written_at)?;
+				}
+			}
+		}
+
+		Ok(written_at)
+	}
+
+	/// Has the server thread record the server time at which new data is placed onto `kind`.
+	///
+	/// The X server tells the time along with the `PropertyNotify` for a property named after the
+	/// selection on the server's window. As it's changed before ownership is taken, the event
+	/// arrives ahead of any request for the new data. Until then, `TIMESTAMP` isn't answered.
+	fn stamp_write(&self, kind: LinuxClipboardKind) -> Result<()> {
+		*self.selection_of(kind).owned_at.lock() = None;
+		self.server
+			.conn
+			.change_property8(
+				PropMode::REPLACE,
+				self.server.win_id,
+				self.atom_of(kind),
+				AtomEnum::INTEGER,
+				&[],
+			)
+			.map_err(into_unknown)?;
+		Ok(())
+	}
+
+	/// Releases the selection if it still holds the data that was written at `written_at`.
+	fn release_written(&self, kind: LinuxClipboardKind, written_at: Instant) -> Result<()> {
+		let selection = self.selection_of(kind);
+		// Keep the same locking order as `write` and `SelectionClear`.
+		let mut data_guard = selection.data.write();
+		let mut guard = selection.mutex.lock();
+		if *guard != Some(written_at) {
+			// It was replaced in the meantime, which must be left alone.
+			return Ok(());
+		}
+
+		*data_guard = None;
+		*guard = None;
+		selection.data_changed.notify_all();
+		self.disown(kind)
+	}
+
+	/// Adds `data` to what the selection is offered as, replacing the data of the same target,
+	/// or places it on its own if the selection isn't ours.
+	fn add_target(&self, kind: LinuxClipboardKind, data: ClipboardData) -> Result<()> {
+		let selection = self.selection_of(kind);
+		// Holding the lock while checking for ownership keeps the data from being added to a
+		// selection which was lost, but whose `SelectionClear` wasn't handled yet.
+		let mut data_guard = selection.data.write();
+		let owned = data_guard.is_some() && self.is_owner(kind)?;
+		match data_guard.as_mut().filter(|_| owned) {
+			Some(data_list) => {
+				data_list.retain(|existing| existing.format != data.format);
+				data_list.push(data);
+
+				// Ownership is re-asserted for the changed data, like in `write`. Requests which
+				// wait for the selection to be replaced keep waiting, as it's still the same
+				// selection.
+				self.stamp_write(kind)?;
+				self.server
+					.conn
+					.set_selection_owner(self.server.win_id, self.atom_of(kind), Time::CURRENT_TIME)
+					.map_err(into_unknown)?;
+				drop(data_guard);
+			}
+			None => {
+				drop(data_guard);
+				self.write(vec![data], &WriteConfig { selection: kind, ..WriteConfig::default() })?;
 			}
 		}
 
+		// Taking the selection isn't answered by the server, so whether it worked is asked for.
+		if !self.is_owner(kind)? {
+			return Err(Error::ClipboardOccupied);
+		}
 		Ok(())
 	}
 
 	/// `formats` must be a slice of atoms, where each atom represents a target format.
 	/// The first format from `formats`, which the clipboard owner supports will be the
 	/// format of the return value.
-	fn read(&self, formats: &[Atom], selection: LinuxClipboardKind) -> Result<ClipboardData> {
-		// if we are the current owner, we can get the current clipboard ourselves
-		if self.is_owner(selection)? {
-			let data = self.selection_of(selection).data.read();
-			if let Some(data_list) = &*data {
-				for data in data_list {
-					for format in formats {
-						if *format == data.format {
-							return Ok(data.clone());
-						}
-					}
-				}
-			}
-			return Err(Error::ContentNotAvailable);
+	///
+	/// `stats` is filled in with how the data was transferred.
+	///
+	/// While we own the selection, the data is taken from memory, see `read_owned`.
+	fn read(
+		&self,
+		formats: &[Atom],
+		config: &ReadConfig,
+		stats: &mut ReadStats,
+	) -> Result<ReadData> {
+		let selection = config.selection;
+		if let Some((bytes, format)) = self.read_owned(selection, formats)? {
+			stats.bytes = bytes.len();
+			return Ok(ReadData { bytes, format });
 		}
-		// if let Some(data) = self.data.read().clone() {
-		//     return Ok(data)
-		// }
+
 		let reader = XContext::new()?;
 
 		trace!("Trying to get the clipboard data.");
+		let mut tried = 0;
 		for format in formats {
-			match self.read_single(&reader, selection, *format) {
+			if config.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+				return Err(Error::Timeout);
+			}
+			tried += 1;
+			match self.read_single(&reader, config, *format, stats) {
 				Ok(bytes) => {
-					return Ok(ClipboardData { bytes, format: *format });
+					stats.bytes = bytes.len();
+					return Ok(ReadData { bytes, format: *format });
 				}
-				Err(Error::ContentNotAvailable) => {
+				Err(Error::ContentNotAvailable { .. }) => {
 					continue;
 				}
 				Err(e) => return Err(e),
 			}
 		}
-		Err(Error::ContentNotAvailable)
+		Err(self.not_available(&formats[..tried]))
+	}
+
+	/// The age of the contents of `selection`, from when its owner took it according to the
+	/// `TIMESTAMP` target, or `None` if the owner doesn't tell.
+	fn content_age(&self, selection: LinuxClipboardKind) -> Result<Option<Duration>> {
+		{
+			let _data = self.selection_of(selection).data.read();
+			if self.is_owner(selection)? {
+				let written = *self.selection_of(selection).mutex.lock();
+				return Ok(written.map(|written| written.elapsed()));
+			}
+		}
+
+		let reader = XContext::new()?;
+		// The owner answers with an `INTEGER` rather than a `TIMESTAMP`.
+		let config = ReadConfig { selection, any_reply_type: true, ..ReadConfig::default() };
+		let mut stats = ReadStats::default();
+		let taken_at = match self.read_single(&reader, &config, self.atoms.TIMESTAMP, &mut stats) {
+			Ok(bytes) => match bytes.get(..4) {
+				Some(bytes) => u32::from_ne_bytes(bytes.try_into().unwrap()),
+				None => return Ok(None),
+			},
+			Err(Error::ContentNotAvailable { .. }) => return Ok(None),
+			Err(error) => return Err(error),
+		};
+		Ok(server_time_age(taken_at, self.server_time(&reader)?))
+	}
+
+	/// Asks the X server for its current time, which it tells along with every change of a
+	/// property on the `context`'s window.
+	fn server_time(&self, context: &XContext) -> Result<Timestamp> {
+		context
+			.conn
+			.change_property8(
+				PropMode::APPEND,
+				context.win_id,
+				self.atoms.ARBOARD_CLIPBOARD,
+				AtomEnum::STRING,
+				&[],
+			)
+			.map_err(into_unknown)?;
+		context.conn.flush().map_err(into_unknown)?;
+
+		let timeouts = *self.timeouts.lock();
+		let deadline = Instant::now() + timeouts.read;
+		while Instant::now() < deadline {
+			match context.conn.poll_for_event().map_err(into_unknown)? {
+				Some(Event::PropertyNotify(event))
+					if event.atom == self.atoms.ARBOARD_CLIPBOARD =>
+				{
+					return Ok(event.time);
+				}
+				Some(_) => {}
+				None => std::thread::sleep(timeouts.poll_interval),
+			}
+		}
+		Err(Error::unknown("The X server didn't tell its current time"))
+	}
+
+	/// A `ContentNotAvailable` error telling that the selection couldn't be read as any of the
+	/// `tried` targets.
+	fn not_available(&self, tried: &[Atom]) -> Error {
+		let tried = tried
+			.iter()
+			.map(|&target| self.atom_name(target).unwrap_or_else(|_| format!("atom {target}")))
+			.collect();
+		Error::ContentNotAvailable { tried }
 	}
 
 	fn read_single(
 		&self,
 		reader: &XContext,
-		selection: LinuxClipboardKind,
+		config: &ReadConfig,
 		target_format: Atom,
+		stats: &mut ReadStats,
 	) -> Result<Vec<u8>> {
-		// Delete the property so that we can detect (using property notify)
-		// when the selection owner receives our request.
-		reader
-			.conn
-			.delete_property(reader.win_id, self.atoms.ARBOARD_CLIPBOARD)
-			.map_err(into_unknown)?;
+		Ok(self.read_single_into(reader, config, target_format, stats, None)?.0)
+	}
 
-		// request to convert the clipboard selection to our data type(s)
-		reader
-			.conn
-			.convert_selection(
-				reader.win_id,
-				self.atom_of(selection),
-				target_format,
-				self.atoms.ARBOARD_CLIPBOARD,
-				Time::CURRENT_TIME,
-			)
-			.map_err(into_unknown)?;
-		reader.conn.sync().map_err(into_unknown)?;
+	/// Reads the selection in `target`, returning the type the reply was labelled with along with
+	/// the data. The data placed by this process is labelled with the format it was placed in.
+	fn read_typed(
+		&self,
+		target: Atom,
+		config: &ReadConfig,
+		stats: &mut ReadStats,
+	) -> Result<(Vec<u8>, Atom)> {
+		if let Some((bytes, format)) = self.read_owned(config.selection, &[target])? {
+			stats.bytes = bytes.len();
+			return Ok((bytes, format));
+		}
 
-		trace!("Finished `convert_selection`");
+		let reader = XContext::new()?;
+		let (bytes, reply_type) = self.read_single_into(&reader, config, target, stats, None)?;
+		stats.bytes = bytes.len();
+		Ok((bytes, reply_type))
+	}
+
+	/// Reads the selection in `target` into `writer` as it arrives, returning how many bytes
+	/// were written.
+	fn write_to(
+		&self,
+		target: Atom,
+		config: &ReadConfig,
+		stats: &mut ReadStats,
+		writer: &mut dyn Write,
+	) -> Result<u64> {
+		let mut written = 0;
+		let mut sink = |chunk: &[u8]| {
+			written += write_contents(writer, chunk)?;
+			Ok(())
+		};
+
+		if let Some((bytes, _)) = self.read_owned(config.selection, &[target])? {
+			sink(&bytes)?;
+			stats.bytes = bytes.len();
+			return Ok(written);
+		}
+
+		let reader = XContext::new()?;
+		let (rest, _) = self.read_single_into(&reader, config, target, stats, Some(&mut sink))?;
+		sink(&rest)?;
+		stats.bytes = written as usize;
+		Ok(written)
+	}
+
+	/// Takes the data of `selection` in the first of `formats` it's served in from memory, along
+	/// with the format it was placed in, if we own the selection. `None` if we don't.
+	///
+	/// No conversion has to be requested then: all `Clipboard`s of the process write through this
+	/// `Inner`, so it always holds what was placed last. The formats are looked up like for a
+	/// request from another application, so aliases like `TEXT` are read too. Streamed data can't
+	/// be read back, though, as that would use it up.
+	fn read_owned(
+		&self,
+		selection: LinuxClipboardKind,
+		formats: &[Atom],
+	) -> Result<Option<(Vec<u8>, Atom)>> {
+		let data = {
+			// Holding the lock while checking for ownership keeps another `Clipboard` from
+			// writing in between, so the data read is the one we own the selection with.
+			let data = self.selection_of(selection).data.read();
+			if !self.is_owner(selection)? {
+				return Ok(None);
+			}
+			data.as_deref()
+				.and_then(|data_list| {
+					formats.iter().find_map(|&format| {
+						self.data_for_target(data_list, format).filter(|data| data.stream.is_none())
+					})
+				})
+				.cloned()
+		};
+
+		// Producing lazily placed data may take a while, which mustn't keep other `Clipboard`s
+		// from placing data.
+		let data = data.ok_or_else(|| self.not_available(formats))?;
+		let format = data.format;
+		let bytes = data.into_contents().ok_or_else(|| self.not_available(formats))?;
+		Ok(Some((bytes, format)))
+	}
+
+	/// Reads the selection in `target_format`, handing each `INCR` segment to `sink` as it
+	/// arrives rather than collecting it, if there is one. What wasn't handed over is returned,
+	/// along with the type the owner labelled the data with.
+	fn read_single_into(
+		&self,
+		reader: &XContext,
+		config: &ReadConfig,
+		target_format: Atom,
+		stats: &mut ReadStats,
+		mut sink: Option<Sink<'_>>,
+	) -> Result<(Vec<u8>, Atom)> {
+		let mut conversion = Conversion::start(self, reader)?;
+		let property = conversion.property;
+		self.request_conversion(reader, config, target_format, property)?;
 
 		let mut incr_data: Vec<u8> = Vec::new();
 		let mut using_incr = false;
 
-		let mut timeout_end = Instant::now() + LONG_TIMEOUT_DUR;
+		// Whether the owner showed any sign of handling our request. Once it did,
+		// the request must not be sent again or we would confuse an ongoing transfer.
+		let mut owner_responded = false;
+		let mut retries_left = config.convert_retries;
+
+		let timeouts = *self.timeouts.lock();
+		let mut timeout_end = Instant::now() + timeouts.read;
+		let mut retry_at = Instant::now() + timeouts.convert_retry;
 
 		while Instant::now() < timeout_end {
+			if config.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+				log::info!("The deadline passed while reading the clipboard.");
+				return Err(Error::Timeout);
+			}
+
+			// This is checked whether or not events arrive, as unrelated ones mustn't hold it off.
+			if !owner_responded && retries_left > 0 && Instant::now() >= retry_at {
+				log::debug!("The clipboard owner didn't answer our request, sending it again.");
+				retries_left -= 1;
+				retry_at = Instant::now() + timeouts.convert_retry;
+				self.request_conversion(reader, config, target_format, property)?;
+			}
+
 			let event = reader.conn.poll_for_event().map_err(into_unknown)?;
 			let event = match event {
 				Some(e) => e,
 				None => {
-					std::thread::sleep(Duration::from_millis(1));
+					std::thread::sleep(timeouts.poll_interval);
 					continue;
 				}
 			};
@@ -374,20 +1319,38 @@ impl Inner {
 				// The first response after requesting a selection.
 				Event::SelectionNotify(event) => {
 					trace!("Read SelectionNotify");
+					if event.property != NONE && event.property != property {
+						trace!("Ignoring the answer to an abandoned conversion");
+						continue;
+					}
+					owner_responded = true;
+					// A refusal ends the conversion as well.
+					conversion.finished = event.property == NONE;
 					let result = self.handle_read_selection_notify(
 						reader,
+						config,
 						target_format,
 						&mut using_incr,
 						&mut incr_data,
 						event,
 					)?;
 					match result {
-						ReadSelNotifyResult::GotData(data) => return Ok(data),
+						ReadSelNotifyResult::GotData(data, reply_type) => {
+							stats.chunks += 1;
+							conversion.finished = true;
+							return Ok((data, reply_type));
+						}
 						ReadSelNotifyResult::IncrStarted => {
+							stats.used_incr = true;
+							if sink.is_some() {
+								// Nothing is collected, so the size the owner announced
+								// doesn't need to be reserved.
+								incr_data = Vec::new();
+							}
 							// This means we received an indication that an the
 							// data is going to be sent INCRementally. Let's
 							// reset our timeout.
-							timeout_end += SHORT_TIMEOUT_DUR;
+							timeout_end += timeouts.incr_segment;
 						}
 						ReadSelNotifyResult::EventNotRecognized => (),
 					}
@@ -396,23 +1359,78 @@ impl Inner {
 				// will be sent in INCR segments, each segment is transferred in
 				// a PropertyNotify event.
 				Event::PropertyNotify(event) => {
+					if event.atom == property && event.state == Property::NEW_VALUE {
+						owner_responded = true;
+					}
+					let segment_type =
+						if config.any_reply_type { AtomEnum::ANY.into() } else { target_format };
+					let read_before = incr_data.len();
 					let result = self.handle_read_property_notify(
-						reader,
-						target_format,
+						&mut conversion,
+						segment_type,
 						using_incr,
 						&mut incr_data,
 						&mut timeout_end,
 						event,
 					)?;
+					if incr_data.len() > read_before {
+						stats.chunks += 1;
+					}
+					if let Some(sink) = &mut sink {
+						sink(&incr_data)?;
+						incr_data.clear();
+					}
 					if result {
-						return Ok(incr_data);
+						conversion.finished = true;
+						return Ok((incr_data, conversion.reply_type));
+					}
+					if config.max_bytes.is_some_and(|max| incr_data.len() > max) {
+						// Leaving the transfer unfinished is fine: the reader's window is destroyed
+						// along with its connection, which tells the owner to stop sending, and
+						// `conversion` keeps later reads into the window apart from it until then.
+						trace!("Stopping the INCR transfer, enough data has been read");
+						return Ok((incr_data, conversion.reply_type));
 					}
 				}
+				Event::Error(error) if owner_lost(&error) => {
+					log::info!("The clipboard owner went away while it was read: {error:?}");
+					return Err(Error::OwnerLost);
+				}
 				_ => log::trace!("An unexpected event arrived while reading the clipboard."),
 			}
 		}
 		log::info!("Time-out hit while reading the clipboard.");
-		Err(Error::ContentNotAvailable)
+		Err(Error::content_not_available())
+	}
+
+	/// Asks the owner of the selection to convert its contents to `target_format` and write
+	/// them to `property` on the `reader`'s window.
+	fn request_conversion(
+		&self,
+		reader: &XContext,
+		config: &ReadConfig,
+		target_format: Atom,
+		property: Atom,
+	) -> Result<()> {
+		// Delete the property so that we can detect (using property notify)
+		// when the selection owner receives our request.
+		reader.conn.delete_property(reader.win_id, property).map_err(into_unknown)?;
+
+		// request to convert the clipboard selection to our data type(s)
+		reader
+			.conn
+			.convert_selection(
+				reader.win_id,
+				self.atom_of(config.selection),
+				target_format,
+				property,
+				config.time.unwrap_or(Time::CURRENT_TIME.into()),
+			)
+			.map_err(into_unknown)?;
+		reader.conn.sync().map_err(into_unknown)?;
+
+		trace!("Finished `convert_selection`");
+		Ok(())
 	}
 
 	fn atom_of(&self, selection: LinuxClipboardKind) -> Atom {
@@ -441,16 +1459,38 @@ impl Inner {
 	}
 
 	fn is_owner(&self, selection: LinuxClipboardKind) -> Result<bool> {
-		let current = self
+		Ok(self.owner_of(selection)? == self.server.win_id)
+	}
+
+	/// The window which owns `selection`, or `NONE`.
+	fn owner_of(&self, selection: LinuxClipboardKind) -> Result<Window> {
+		Ok(self
 			.server
 			.conn
 			.get_selection_owner(self.atom_of(selection))
 			.map_err(into_unknown)?
 			.reply()
 			.map_err(into_unknown)?
-			.owner;
+			.owner)
+	}
+
+	/// The atom of `target`, which is only interned if it wasn't on startup.
+	fn target_atom(&self, target: &str) -> Result<Atom> {
+		match self.known_atom(target) {
+			Some(atom) => Ok(atom),
+			None => self.intern_atom(target),
+		}
+	}
 
-		Ok(current == self.server.win_id)
+	fn intern_atom(&self, name: &str) -> Result<Atom> {
+		Ok(self
+			.server
+			.conn
+			.intern_atom(false, name.as_bytes())
+			.map_err(into_unknown)?
+			.reply()
+			.map_err(into_unknown)?
+			.atom)
 	}
 
 	fn atom_name(&self, atom: x11rb::protocol::xproto::Atom) -> Result<String> {
@@ -466,25 +1506,13 @@ impl Inner {
 		.map_err(into_unknown)
 	}
 	fn atom_name_dbg(&self, atom: x11rb::protocol::xproto::Atom) -> &'static str {
-		ATOM_NAME_CACHE.with(|cache| {
-			let mut cache = cache.borrow_mut();
-			match cache.entry(atom) {
-				Entry::Occupied(entry) => *entry.get(),
-				Entry::Vacant(entry) => {
-					let s = self
-						.atom_name(atom)
-						.map(|s| Box::leak(s.into_boxed_str()) as &str)
-						.unwrap_or("FAILED-TO-GET-THE-ATOM-NAME");
-					entry.insert(s);
-					s
-				}
-			}
-		})
+		self.atom_names.get(atom, |atom| self.atom_name(atom))
 	}
 
 	fn handle_read_selection_notify(
 		&self,
 		reader: &XContext,
+		config: &ReadConfig,
 		target_format: u32,
 		using_incr: &mut bool,
 		incr_data: &mut Vec<u8>,
@@ -496,7 +1524,7 @@ impl Inner {
 		// According to: https://tronche.com/gui/x/icccm/sec-2.html#s-2.4
 		// the target must be set to the same as what we requested.
 		if event.property == NONE || event.target != target_format {
-			return Err(Error::ContentNotAvailable);
+			return Err(Error::content_not_available());
 		}
 		if self.kind_of(event.selection).is_none() {
 			log::info!("Received a SelectionNotify for a selection other than CLIPBOARD, PRIMARY or SECONDARY. This is unexpected.");
@@ -507,18 +1535,31 @@ impl Inner {
 			return Ok(ReadSelNotifyResult::EventNotRecognized);
 		}
 		// request the selection
-		let mut reply = reader
-			.conn
-			.get_property(true, event.requestor, event.property, event.target, 0, u32::MAX / 4)
-			.map_err(into_unknown)?
-			.reply()
-			.map_err(into_unknown)?;
+		let mut reply = get_whole_property(
+			&reader.conn,
+			event.requestor,
+			event.property,
+			event.target,
+			read_length(config),
+			config.max_bytes,
+		)?;
 
 		// trace!("Property.type: {:?}", self.atom_name(reply.type_));
 
 		// we found something
 		if reply.type_ == target_format {
-			Ok(ReadSelNotifyResult::GotData(reply.value))
+			Ok(ReadSelNotifyResult::GotData(reply.value, reply.type_))
+		} else if config.any_reply_type && reply.type_ != self.atoms.INCR && reply.type_ != NONE {
+			// The type didn't match, so the data has to be fetched again under its actual type.
+			let reply = get_whole_property(
+				&reader.conn,
+				event.requestor,
+				event.property,
+				reply.type_,
+				read_length(config),
+				config.max_bytes,
+			)?;
+			Ok(ReadSelNotifyResult::GotData(reply.value, reply.type_))
 		} else if reply.type_ == self.atoms.INCR {
 			// Note that we call the get_property again because we are
 			// indicating that we are ready to receive the data by deleting the
@@ -536,7 +1577,7 @@ impl Inner {
 				)
 				.map_err(into_unknown)?
 				.reply()
-				.map_err(into_unknown)?;
+				.map_err(read_error)?;
 			log::trace!("Receiving INCR segments");
 			*using_incr = true;
 			if reply.value_len == 4 {
@@ -553,29 +1594,38 @@ impl Inner {
 	/// Returns Ok(true) when the incr_data is ready
 	fn handle_read_property_notify(
 		&self,
-		reader: &XContext,
-		target_format: u32,
+		conversion: &mut Conversion<'_>,
+		segment_type: u32,
 		using_incr: bool,
 		incr_data: &mut Vec<u8>,
 		timeout_end: &mut Instant,
 		event: PropertyNotifyEvent,
 	) -> Result<bool> {
-		if event.atom != self.atoms.ARBOARD_CLIPBOARD || event.state != Property::NEW_VALUE {
+		if event.atom != conversion.property || event.state != Property::NEW_VALUE {
 			return Ok(false);
 		}
 		if !using_incr {
 			// This must mean the selection owner received our request, and is
-			// now preparing the data
+			// now preparing the data. Owners of large images can take longer than
+			// the initial timeout before starting a transfer, so as long as they
+			// show signs of life, keep waiting for them.
+			*timeout_end = (*timeout_end).max(Instant::now() + self.timeouts.lock().read);
 			return Ok(false);
 		}
-		let reply = reader
-			.conn
-			.get_property(true, event.window, event.atom, target_format, 0, u32::MAX / 4)
-			.map_err(into_unknown)?
-			.reply()
-			.map_err(into_unknown)?;
+		let reply = get_whole_property(
+			&conversion.reader.conn,
+			event.window,
+			event.atom,
+			segment_type,
+			u32::MAX / 4,
+			None,
+		)?;
 
 		// log::trace!("Received segment. value_len {}", reply.value_len,);
+		// The empty segment which ends the transfer doesn't always carry the type.
+		if reply.value_len > 0 || conversion.reply_type == NONE {
+			conversion.reply_type = reply.type_;
+		}
 		if reply.value_len == 0 {
 			// This indicates that all the data has been sent.
 			return Ok(true);
@@ -583,13 +1633,13 @@ impl Inner {
 		incr_data.extend(reply.value);
 
 		// Let's reset our timeout, since we received a valid chunk.
-		*timeout_end = Instant::now() + SHORT_TIMEOUT_DUR;
+		*timeout_end = Instant::now() + self.timeouts.lock().incr_segment;
 
 		// Not yet complete
 		Ok(false)
 	}
 
-	fn handle_selection_request(&self, event: SelectionRequestEvent) -> Result<()> {
+	fn handle_selection_request(&self, mut event: SelectionRequestEvent) -> Result<()> {
 		let selection = match self.kind_of(event.selection) {
 			Some(kind) => kind,
 			None => {
@@ -598,24 +1648,53 @@ impl Inner {
 			}
 		};
 
+		let quirks = *self.selection_of(selection).quirks.lock();
+		if log::log_enabled!(log::Level::Trace) {
+			trace!(
+				"Request for {} from {}",
+				self.atom_name_dbg(event.target),
+				self.requestor_name(event.requestor)
+			);
+		}
+
+		// ICCCM section 2.2: obsolete requestors leave out the property, in which case the owner
+		// should use the target's atom as the property.
+		if event.property == NONE && quirks.none_property {
+			self.trace_quirk(event.requestor, "none_property");
+			event.property = event.target;
+		}
+
 		let success;
-		// we are asked for a list of supported conversion targets
-		if event.target == self.atoms.TARGETS {
+		// Streamed data only counts as pasted once its transfer finished.
+		let mut streamed = false;
+		if event.property == NONE {
+			trace!("Refusing a request without a property to store the reply in");
+			success = false;
+		} else if event.target == self.atoms.TARGETS {
+			// we are asked for a list of supported conversion targets
 			trace!("Handling TARGETS, dst property is {}", self.atom_name_dbg(event.property));
 
 			let data = self.selection_of(selection).data.read();
 			let (data_targets, excluded) = if let Some(data_list) = &*data {
-				// Estimation based on current data types, plus the other UTF-8 ones, plus `SAVE_TARGETS`.
-				let mut targets = Vec::with_capacity(data_list.len() + 3);
+				// Estimation based on current data types, plus the other UTF-8 ones and `TEXT`, plus
+				// `SAVE_TARGETS`.
+				let mut targets = Vec::with_capacity(data_list.len() + 4);
 				let mut excluded = false;
+				let stored = |format| data_list.iter().any(|data| data.format == format);
 
 				for data in data_list {
-					targets.push(data.format);
+					if data.advertised {
+						targets.push(data.format);
+					}
 					if data.format == self.atoms.UTF8_STRING {
 						// When we are storing a UTF8 string,
 						// add all equivalent formats to the supported targets
-						targets.push(self.atoms.UTF8_MIME_0);
-						targets.push(self.atoms.UTF8_MIME_1);
+						targets.extend(self.utf8_alias_atoms().filter(|alias| !stored(*alias)));
+					}
+					if data.format == self.atoms.PNG_MIME
+						&& data.advertised && !stored(self.atoms.QT_IMAGE_MIME)
+					{
+						targets.push(self.atoms.QT_IMAGE_MIME);
 					}
 
 					if data.format == self.atoms.X_KDE_PASSWORDMANAGERHINT {
@@ -630,6 +1709,7 @@ impl Inner {
 
 			let mut targets = data_targets;
 			targets.push(self.atoms.TARGETS);
+			targets.push(self.atoms.TIMESTAMP);
 
 			// NB: `SAVE_TARGETS` in this context is a marker atom which infomrs the clipboard manager
 			// we support this operation and _may_ use it in the future. To try and keep the manager's
@@ -639,41 +1719,134 @@ impl Inner {
 			//
 			// Note that even if we don't advertise it, some managers may respond to it anyways so this is
 			// only half of exclusion handling. See `ask_clipboard_manager_to_request_our_data` for more.
+			//
+			// The hint itself is still in `data_list` when it was hidden from `TARGETS` via
+			// `exclude_from_history_strict`, so `excluded` doesn't depend on what was advertised.
 			if !excluded {
 				targets.push(self.atoms.SAVE_TARGETS);
 			}
 
+			let reply_type = if quirks.targets_reply_type {
+				self.trace_quirk(event.requestor, "targets_reply_type");
+				self.atoms.TARGETS
+			} else {
+				// TODO: change to `AtomEnum::ATOM`
+				self.atoms.ATOM
+			};
+
 			self.server
 				.conn
 				.change_property32(
 					PropMode::REPLACE,
 					event.requestor,
 					event.property,
-					// TODO: change to `AtomEnum::ATOM`
-					self.atoms.ATOM,
+					reply_type,
 					&targets,
 				)
 				.map_err(into_unknown)?;
 			self.server.conn.flush().map_err(into_unknown)?;
 			success = true;
+		} else if event.target == self.atoms.TIMESTAMP {
+			// ICCCM section 2.6.2 has the time the selection was taken at answered as an `INTEGER`.
+			let data = self.selection_of(selection).data.read();
+			let owned_at = *self.selection_of(selection).owned_at.lock();
+			success = match owned_at.filter(|_| data.is_some()) {
+				Some(owned_at) => {
+					self.server
+						.conn
+						.change_property32(
+							PropMode::REPLACE,
+							event.requestor,
+							event.property,
+							AtomEnum::INTEGER,
+							&[owned_at],
+						)
+						.map_err(into_unknown)?;
+					self.server.conn.flush().map_err(into_unknown)?;
+					true
+				}
+				None => false,
+			};
 		} else {
 			trace!("Handling request for (probably) the clipboard contents.");
+			let on_request = self.selection_of(selection).on_request.lock().clone();
+			let offered = || {
+				let data = self.selection_of(selection).data.read();
+				data.as_deref()
+					.is_some_and(|data| self.data_for_target(data, event.target).is_some())
+			};
+			let action =
+				match on_request {
+					// The handler may read or set the clipboard, so nothing is locked while it runs.
+					Some(on_request) if offered() => {
+						let target = self.atom_name(event.target)?;
+						match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+							on_request(&target)
+						})) {
+							Ok(action) => action,
+							Err(_) => {
+								error!("The handler given to `on_request` panicked, refusing the request");
+								return self.notify_requestor(&event, false);
+							}
+						}
+					}
+					_ => RequestAction::ServeStored,
+				};
+
 			let data = self.selection_of(selection).data.read();
 			if let Some(data_list) = &*data {
-				success = match data_list.iter().find(|d| d.format == event.target) {
+				success = match self.data_for_target(data_list, event.target) {
+					Some(ClipboardData { format, stream: Some(stream), .. })
+						if action == RequestAction::ServeStored =>
+					{
+						streamed = true;
+						self.start_incr_transfer(&event, selection, *format, stream)?
+					}
 					Some(data) => {
-						self.server
-							.conn
-							.change_property8(
-								PropMode::REPLACE,
-								event.requestor,
-								event.property,
-								event.target,
-								&data.bytes,
-							)
-							.map_err(into_unknown)?;
-						self.server.conn.flush().map_err(into_unknown)?;
-						true
+						let mime_alias = [
+							self.atoms.UTF8_MIME_0,
+							self.atoms.UTF8_MIME_1,
+							self.atoms.TEXT_MIME_UNKNOWN,
+						]
+						.contains(&event.target);
+						let reply_type = if quirks.mime_reply_type
+							&& mime_alias && data.format == self.atoms.UTF8_STRING
+						{
+							self.trace_quirk(event.requestor, "mime_reply_type");
+							event.target
+						} else if event.target == self.atoms.QT_IMAGE_MIME {
+							// Labelled with the target, like Qt labels the images it serves under it.
+							event.target
+						} else {
+							data.format
+						};
+
+						match (&action, &data.lazy) {
+							(RequestAction::Serve(bytes), _) => {
+								self.reply(&event, reply_type, bytes)?
+							}
+							(RequestAction::ServeStored, None) => {
+								self.reply(&event, reply_type, &data.bytes)?
+							}
+							// Lazily placed data is produced by the first request for it.
+							(RequestAction::ServeStored, Some(lazy)) => match lazy.poll() {
+								Poll::Ready(Some(bytes)) => {
+									self.reply(&event, reply_type, &bytes)?
+								}
+								Poll::Ready(None) => false,
+								Poll::Pending => {
+									trace!("Answering the request once the lazily placed data is produced");
+									let lazy = lazy.clone();
+									self.deferred_requests.lock().push(DeferredRequest {
+										event,
+										selection,
+										reply_type,
+										lazy,
+									});
+									return Ok(());
+								}
+							},
+						}
 					}
 					None => false,
 				};
@@ -684,6 +1857,26 @@ impl Inner {
 				success = false;
 			}
 		}
+		self.notify_requestor(&event, success)?;
+		let metadata = [self.atoms.TARGETS, self.atoms.TIMESTAMP].contains(&event.target);
+		if success && !streamed && !metadata {
+			self.pasted(self.selection_of(selection).on_paste.lock().clone());
+		}
+		Ok(())
+	}
+
+	/// Stores `bytes` in the property of the requestor, returning `true` for `notify_requestor`.
+	fn reply(&self, event: &SelectionRequestEvent, reply_type: Atom, bytes: &[u8]) -> Result<bool> {
+		self.server
+			.conn
+			.change_property8(PropMode::REPLACE, event.requestor, event.property, reply_type, bytes)
+			.map_err(into_unknown)?;
+		self.server.conn.flush().map_err(into_unknown)?;
+		Ok(true)
+	}
+
+	/// Tells the requestor that its request was answered, or refused if `success` is `false`.
+	fn notify_requestor(&self, event: &SelectionRequestEvent, success: bool) -> Result<()> {
 		// on failure we notify the requester of it
 		let property = if success { event.property } else { AtomEnum::NONE.into() };
 		// tell the requestor that we finished sending data
@@ -704,10 +1897,296 @@ impl Inner {
 				},
 			)
 			.map_err(into_unknown)?;
-
 		self.server.conn.flush().map_err(into_unknown)
 	}
 
+	/// Calls `on_paste` for data that was pasted, once the requestor has its answer.
+	///
+	/// The handler runs on a thread of its own, so that neither a slow nor a panicking handler
+	/// holds up serving the requests.
+	fn pasted(&self, on_paste: Option<PasteHandler>) {
+		let Some(on_paste) = on_paste else {
+			return;
+		};
+		let mut handlers = self.paste_handlers.lock();
+		let sender = handlers.get_or_insert_with(|| {
+			let (sender, receiver) = mpsc::channel::<PasteHandler>();
+			// The thread stops once the `Inner` is dropped, along with the sender.
+			std::thread::spawn(move || {
+				for on_paste in receiver {
+					if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| on_paste()))
+						.is_err()
+					{
+						error!("The handler given to `on_paste` panicked");
+					}
+				}
+			});
+			sender
+		});
+		let _ = sender.send(on_paste);
+	}
+
+	/// Answers the requests for lazily placed data which was produced, or failed to be, in the
+	/// meantime. The thread producing it wakes the server thread once it's done.
+	fn answer_deferred_requests(&self) -> Result<()> {
+		let mut deferred = self.deferred_requests.lock();
+		let mut i = 0;
+		while i < deferred.len() {
+			let Poll::Ready(bytes) = deferred[i].lazy.poll() else {
+				i += 1;
+				continue;
+			};
+			let request = deferred.swap_remove(i);
+			let success = match bytes {
+				Some(bytes) => self.reply(&request.event, request.reply_type, &bytes)?,
+				None => false,
+			};
+			self.notify_requestor(&request.event, success)?;
+			if success {
+				self.pasted(self.selection_of(request.selection).on_paste.lock().clone());
+			}
+		}
+		Ok(())
+	}
+
+	/// Answers a request for streamed data by starting an `INCR` transfer, whose segments are
+	/// sent as the requestor deletes the property. Returns `false` if the data was already
+	/// transferred.
+	fn start_incr_transfer(
+		&self,
+		event: &SelectionRequestEvent,
+		selection: LinuxClipboardKind,
+		format: Atom,
+		stream: &Stream,
+	) -> Result<bool> {
+		let Some(chunks) = stream.0.lock().take() else {
+			trace!("Refusing a request for streamed data, which was already transferred");
+			return Ok(false);
+		};
+
+		let mut transfers = self.incr_transfers.lock();
+		prune_incr_transfers(&mut transfers);
+
+		// ICCCM section 2.7.2: the requestor deletes the property to ask for each segment.
+		let conn = &self.server.conn;
+		conn.change_window_attributes(
+			event.requestor,
+			&ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+		)
+		.map_err(into_unknown)?;
+		// The size isn't known up front, so the lower bound on it is 0.
+		conn.change_property32(
+			PropMode::REPLACE,
+			event.requestor,
+			event.property,
+			self.atoms.INCR,
+			&[0],
+		)
+		.map_err(into_unknown)?;
+		conn.flush().map_err(into_unknown)?;
+
+		trace!("Streaming the data in INCR segments");
+		transfers.push(IncrTransfer {
+			requestor: event.requestor,
+			property: event.property,
+			format,
+			on_paste: self.selection_of(selection).on_paste.lock().clone(),
+			chunks: pull_chunks(chunks, Arc::clone(&self.server), self.atoms.ARBOARD_WAKEUP),
+			chunk: Vec::new(),
+			sent: 0,
+			asked: false,
+			last_request: Instant::now(),
+		});
+		Ok(true)
+	}
+
+	/// Notes that a requestor asked for the next segment of streamed data if `event` tells that it
+	/// deleted the property, and sends the segments that were asked for and whose chunks arrived.
+	///
+	/// This runs for every `PropertyNotify`, including those waking the server thread when a
+	/// chunk arrived. The transfer ends with an empty segment once there are no chunks left.
+	fn continue_incr_transfers(&self, event: PropertyNotifyEvent) -> Result<()> {
+		let mut transfers = self.incr_transfers.lock();
+		prune_incr_transfers(&mut transfers);
+		if event.state == Property::DELETE {
+			if let Some(transfer) = transfers.iter_mut().find(|transfer| {
+				transfer.requestor == event.window && transfer.property == event.atom
+			}) {
+				transfer.asked = true;
+				transfer.last_request = Instant::now();
+			}
+		}
+
+		let conn = &self.server.conn;
+		let mut i = 0;
+		while i < transfers.len() {
+			let finished = match self.send_incr_segment(&mut transfers[i])? {
+				IncrProgress::Ongoing => {
+					i += 1;
+					continue;
+				}
+				IncrProgress::Finished => {
+					trace!("Finished streaming the data");
+					true
+				}
+				// The requestor gives up on its own once it stops receiving segments.
+				IncrProgress::Failed => false,
+			};
+			let IncrTransfer { requestor, on_paste, .. } = transfers.swap_remove(i);
+			if finished {
+				self.pasted(on_paste);
+			}
+			if !transfers.iter().any(|transfer| transfer.requestor == requestor) {
+				conn.change_window_attributes(
+					requestor,
+					&ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+				)
+				.map_err(into_unknown)?;
+			}
+		}
+		conn.flush().map_err(into_unknown)
+	}
+
+	/// Sends the segment of `transfer` that was asked for, if its chunk arrived.
+	fn send_incr_segment(&self, transfer: &mut IncrTransfer) -> Result<IncrProgress> {
+		if !transfer.asked {
+			return Ok(IncrProgress::Ongoing);
+		}
+		if transfer.sent == transfer.chunk.len() {
+			match transfer.chunks.try_recv() {
+				Ok(chunk) => {
+					// Without a chunk, the empty segment that ends the transfer is sent.
+					transfer.chunk = chunk.unwrap_or_default();
+					transfer.sent = 0;
+				}
+				Err(TryRecvError::Empty) => return Ok(IncrProgress::Ongoing),
+				Err(TryRecvError::Disconnected) => return Ok(IncrProgress::Failed),
+			}
+		}
+
+		let end = transfer.chunk.len().min(transfer.sent + INCR_SEGMENT_BYTES);
+		self.server
+			.conn
+			.change_property8(
+				PropMode::REPLACE,
+				transfer.requestor,
+				transfer.property,
+				transfer.format,
+				&transfer.chunk[transfer.sent..end],
+			)
+			.map_err(into_unknown)?;
+		transfer.asked = false;
+		if end == transfer.sent {
+			return Ok(IncrProgress::Finished);
+		}
+		transfer.sent = end;
+		Ok(IncrProgress::Ongoing)
+	}
+
+	/// Logs that the workaround `quirk` changed how a request from `requestor` is answered.
+	fn trace_quirk(&self, requestor: Window, quirk: &str) {
+		if log::log_enabled!(log::Level::Trace) {
+			trace!("Applying the {quirk} quirk for {}", self.requestor_name(requestor));
+		}
+	}
+
+	/// Describes a requesting window by its `WM_CLASS`, if it has one, for logging.
+	fn requestor_name(&self, requestor: Window) -> String {
+		let class = self
+			.server
+			.conn
+			.get_property(false, requestor, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.filter(|reply| !reply.value.is_empty());
+
+		match class {
+			// `WM_CLASS` holds the instance and the class name, each terminated by a NUL byte.
+			Some(class) => format!(
+				"{} (window {requestor:#x})",
+				String::from_utf8_lossy(&class.value).trim_end_matches('\0').replace('\0', ".")
+			),
+			None => format!("window {requestor:#x}"),
+		}
+	}
+
+	/// Finds the data to serve for a request of `target`. The data's format is the type of the reply.
+	fn data_for_target<'a>(
+		&self,
+		data_list: &'a [ClipboardData],
+		target: Atom,
+	) -> Option<&'a ClipboardData> {
+		let find = |format| data_list.iter().find(|data| data.format == format);
+
+		if let Some(data) = find(target) {
+			return Some(data);
+		}
+
+		// ICCCM section 2.6.2 lets the owner pick the encoding for `TEXT`, as long as the reply's
+		// type names the one it used. Legacy text in `STRING` is preferred, since requestors asking
+		// for `TEXT` are most likely too old to understand UTF-8.
+		if target == self.atoms.TEXT {
+			return find(self.atoms.STRING).or_else(|| find(self.atoms.UTF8_STRING));
+		}
+
+		if self.utf8_alias_atoms().any(|alias| alias == target) {
+			return find(self.atoms.UTF8_STRING);
+		}
+
+		// Qt reads whatever format `QImage` recognizes under its own target, see `QT_IMAGE_MIME`.
+		if target == self.atoms.QT_IMAGE_MIME {
+			return find(self.atoms.PNG_MIME);
+		}
+
+		None
+	}
+
+	/// The atoms of `UTF8_ALIASES`.
+	fn utf8_alias_atoms(&self) -> impl Iterator<Item = Atom> + '_ {
+		UTF8_ALIASES.into_iter().filter_map(|alias| self.known_atom(alias))
+	}
+
+	/// Returns the atom of a target that was interned on startup.
+	fn known_atom(&self, target: &str) -> Option<Atom> {
+		let atoms = &self.atoms;
+		let known = [
+			(UTF8_STRING_TARGET, atoms.UTF8_STRING),
+			("text/plain;charset=utf-8", atoms.UTF8_MIME_0),
+			("text/plain;charset=UTF-8", atoms.UTF8_MIME_1),
+			("text/plain", atoms.TEXT_MIME_UNKNOWN),
+			(STRING_TARGET, atoms.STRING),
+			(TEXT_TARGET, atoms.TEXT),
+			(HTML_MIME, atoms.HTML),
+			(URI_LIST_MIME, atoms.URI_LIST),
+			(PNG_MIME, atoms.PNG_MIME),
+			(THUMBNAIL_MIME, atoms.PNG_THUMBNAIL_MIME),
+			(QT_IMAGE_MIME, atoms.QT_IMAGE_MIME),
+			(KDE_EXCLUSION_MIME, atoms.X_KDE_PASSWORDMANAGERHINT),
+		];
+		known.into_iter().find(|(name, _)| *name == target).map(|(_, atom)| atom)
+	}
+
+	/// The selection owned by the clipboard manager that the `CLIPBOARD` data is handed over to.
+	fn manager_atom(&self) -> Atom {
+		let manager = *self.selection_of(LinuxClipboardKind::Clipboard).manager.lock();
+		manager.unwrap_or(self.atoms.CLIPBOARD_MANAGER)
+	}
+
+	/// Whether the selection of the clipboard manager, which the `CLIPBOARD` data would be handed
+	/// over to, has an owner.
+	fn has_clipboard_manager(&self) -> Result<bool> {
+		let owner = self
+			.server
+			.conn
+			.get_selection_owner(self.manager_atom())
+			.map_err(into_unknown)?
+			.reply()
+			.map_err(into_unknown)?
+			.owner;
+
+		Ok(owner != NONE)
+	}
+
 	fn ask_clipboard_manager_to_request_our_data(&self) -> Result<()> {
 		if self.server.win_id == 0 {
 			// This shouldn't really ever happen but let's just check.
@@ -725,7 +2204,13 @@ impl Inner {
 			return Ok(());
 		}
 
-		match &*self.selection_of(selection).data.read() {
+		// With an idle timeout, the data keeps being served until it expired, at which point
+		// `expire_idle_selections` releases the selection, so it's not handed over.
+		if self.selection_of(selection).idle_timeout.lock().is_some() {
+			return Ok(());
+		}
+
+		let saved_targets = match &*self.selection_of(selection).data.read() {
 			Some(data) => {
 				// If the data we are serving intended to be excluded, then don't bother asking the clipboard
 				// manager to save it. This is for several reasons:
@@ -745,19 +2230,40 @@ impl Inner {
 					//
 					// By removing the owner, the manager doesn't think it needs to pick up our window's data serving once
 					// its destroyed and cleanly lets the data disappear based off the previously advertised exclusion hint.
-					if let Err(e) = self.clear(selection) {
-						warn!("failed to release sensitive data's clipboard ownership: {e}; it may end up persisted!");
-						// This is still not an error because we werent going to handoff anything to the manager.
+					//
+					// The window is only destroyed once the X server confirmed that the selection has no owner, so
+					// that a clipboard manager racing to take the selection in between is noticed.
+					//
+					// Failing is still not an error because we werent going to handoff anything to the manager.
+					match self.disown_confirmed(selection) {
+						Ok(true) => {}
+						Ok(false) => warn!("sensitive data's clipboard selection was taken by another owner right after releasing it; it may end up persisted!"),
+						Err(e) => warn!("failed to release sensitive data's clipboard ownership: {e}; it may end up persisted!"),
 					}
 
 					return Ok(());
 				}
+
+				self.saved_targets(data)
 			}
 			None => {
 				// If we don't have any data, there's nothing to do.
 				return Ok(());
 			}
-		}
+		};
+
+		// Without a list of targets, the manager saves every target we advertise, including the
+		// aliases of text which only take up time within the handover timeout.
+		self.server
+			.conn
+			.change_property32(
+				PropMode::REPLACE,
+				self.server.win_id,
+				self.atoms.ARBOARD_SAVE_TARGETS,
+				AtomEnum::ATOM,
+				&saved_targets,
+			)
+			.map_err(into_unknown)?;
 
 		// It's important that we lock the state before sending the request
 		// because we don't want the request server thread to lock the state
@@ -769,22 +2275,31 @@ impl Inner {
 			.conn
 			.convert_selection(
 				self.server.win_id,
-				self.atoms.CLIPBOARD_MANAGER,
+				self.manager_atom(),
 				self.atoms.SAVE_TARGETS,
-				self.atoms.ARBOARD_CLIPBOARD,
+				self.atoms.ARBOARD_SAVE_TARGETS,
 				Time::CURRENT_TIME,
 			)
 			.map_err(into_unknown)?;
 		self.server.conn.flush().map_err(into_unknown)?;
 
 		*handover_state = ManagerHandoverState::InProgress;
-		let max_handover_duration = Duration::from_millis(100);
+		let max_handover_duration = self.timeouts.lock().handover;
 
 		// Note that we are using a parking_lot condvar here, which doesn't wake up
 		// spuriously
 		let result = self.handover_cv.wait_for(&mut handover_state, max_handover_duration);
+		let finished = *handover_state == ManagerHandoverState::Finished;
+		drop(handover_state);
+
+		// The manager read the list when it started saving, if it ever will.
+		self.server
+			.conn
+			.delete_property(self.server.win_id, self.atoms.ARBOARD_SAVE_TARGETS)
+			.map_err(into_unknown)?;
+		self.server.conn.flush().map_err(into_unknown)?;
 
-		if *handover_state == ManagerHandoverState::Finished {
+		if finished {
 			return Ok(());
 		}
 		if result.timed_out() {
@@ -794,7 +2309,125 @@ impl Inner {
 
 		unreachable!("This is a bug! The handover was not finished and the condvar didn't time out, yet the condvar wait ended.")
 	}
-}
+
+	/// The targets of `data` worth keeping once we're gone: the ones it was placed under, without
+	/// the hint against saving it or the targets text is also served under.
+	fn saved_targets(&self, data: &[ClipboardData]) -> Vec<Atom> {
+		let has_utf8 = data.iter().any(|data| data.format == self.atoms.UTF8_STRING);
+		data.iter()
+			.filter(|data| data.advertised && data.format != self.atoms.X_KDE_PASSWORDMANAGERHINT)
+			.map(|data| data.format)
+			.filter(|format| {
+				!(has_utf8
+					&& (*format == self.atoms.STRING
+						|| self.utf8_alias_atoms().any(|alias| alias == *format)))
+			})
+			.collect()
+	}
+}
+
+/// How much of a property to request at once, in 32-bit units.
+///
+/// With `max_bytes`, at least one byte more than that is fetched, to tell whether there was more.
+fn read_length(config: &ReadConfig) -> u32 {
+	config.max_bytes.map_or(u32::MAX / 4, |max| {
+		u32::try_from(max / 4 + 1).map_or(u32::MAX / 4, |units| units.min(u32::MAX / 4))
+	})
+}
+
+/// Whether the X server's `error`, about a request made while reading a selection, means that
+/// the selection's owner went away in the meantime, like when it was quitting.
+///
+/// The owner's window is gone then, or the atoms it was asked about are reported as invalid.
+fn owner_lost(error: &X11Error) -> bool {
+	matches!(error.error_kind, ErrorKind::Window | ErrorKind::Atom)
+}
+
+/// Turns the failure of a request made while reading a selection into an [`Error`], telling
+/// [`Error::OwnerLost`] apart from the other ones.
+fn read_error(error: ReplyError) -> Error {
+	match error {
+		ReplyError::X11Error(error) if owner_lost(&error) => {
+			log::info!("The clipboard owner went away while it was read: {error:?}");
+			Error::OwnerLost
+		}
+		error => into_unknown(error),
+	}
+}
+
+/// Reads and deletes a property of type `type_`, requesting `chunk` 32-bit units at a time.
+///
+/// A single reply holds at most `u32::MAX / 4` units, so larger properties are read from
+/// successive offsets until nothing is left after them, or more than `max_bytes` were read. The
+/// X server only deletes the property along with the read which reaches its end. If the type
+/// doesn't match, the reply tells the actual type without any data.
+///
+/// Each read continues right after the data that arrived so far, as some X servers hand out
+/// less than was asked for in a reply.
+fn get_whole_property(
+	conn: &RustConnection,
+	window: Window,
+	property: Atom,
+	type_: Atom,
+	chunk: u32,
+	max_bytes: Option<usize>,
+) -> Result<GetPropertyReply> {
+	let get = |offset| {
+		conn.get_property(true, window, property, type_, offset, chunk)
+			.map_err(into_unknown)?
+			.reply()
+			.map_err(read_error)
+	};
+
+	let mut reply = get(0)?;
+	let any_type = type_ == Atom::from(AtomEnum::ANY);
+	while (any_type || reply.type_ == type_)
+		&& reply.bytes_after > 0
+		&& max_bytes.map_or(true, |max| reply.value.len() <= max)
+	{
+		// Offsets count 32-bit units, which every reply but the last one consists of.
+		if reply.value.len() % 4 != 0 {
+			return Err(Error::unknown("The X server cut a property reply off mid-unit"));
+		}
+		let offset = u32::try_from(reply.value.len() / 4).map_err(|_| {
+			Error::unknown("The clipboard contents are larger than X11 properties can be")
+		})?;
+		let next = get(offset)?;
+		if next.value.is_empty() {
+			return Err(Error::unknown("The X server stopped handing out a property midway"));
+		}
+		reply.value.extend(next.value);
+		reply.bytes_after = next.bytes_after;
+	}
+	if reply.format != 0 {
+		reply.value_len =
+			u32::try_from(reply.value.len() / usize::from(reply.format / 8)).unwrap_or(u32::MAX);
+	}
+	Ok(reply)
+}
+
+/// Unregisters the clipboard once it's only kept alive for lingering selections, so that the
+/// next `Clipboard` opens a new connection. Returns `false` if a `Clipboard` started using the
+/// connection again in the meantime.
+fn stop_lingering(context: &Arc<Inner>) -> bool {
+	let mut global_cb = CLIPBOARD.lock();
+	if context.lingering_since.lock().is_none() {
+		return false;
+	}
+	if global_cb.as_ref().is_some_and(|global_cb| Arc::ptr_eq(&global_cb.inner, context)) {
+		// The server thread is this one, so its handle is just detached.
+		*global_cb = None;
+	}
+	true
+}
+
+fn spawn_server(context: Arc<Inner>) -> JoinHandle<()> {
+	std::thread::spawn(move || {
+		if let Err(error) = serve_requests(context) {
+			error!("Worker thread errored with: {}", error);
+		}
+	})
+}
 
 fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>> {
 	fn handover_finished(clip: &Arc<Inner>, mut handover_state: MutexGuard<ManagerHandoverState>) {
@@ -817,7 +2450,22 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 	let mut notified = false;
 
 	loop {
-		match context.server.conn.wait_for_event().map_err(into_unknown)? {
+		// While lingering, the thread is woken up when the next selection expires, so that it
+		// can keep blocking on events in between.
+		let lingering_since = *context.lingering_since.lock();
+		if let Some(since) = lingering_since {
+			match context.expire_idle_selections(since) {
+				Some(next_expiry) => context.wake_server_at(next_expiry),
+				None if stop_lingering(&context) => {
+					trace!("All lingering selections expired, stopping the server thread.");
+					return Ok(());
+				}
+				None => {}
+			}
+		}
+		let event = context.server.conn.wait_for_event().map_err(into_unknown)?;
+
+		match event {
 			Event::DestroyNotify(_) => {
 				// This window is being destroyed.
 				trace!("Clipboard server window is being destroyed x_x");
@@ -832,6 +2480,8 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 				if let Some(selection) = context.kind_of(event.selection) {
 					let selection = context.selection_of(selection);
 					let mut data_guard = selection.data.write();
+					// The data is dropped right away rather than when the selection is written to
+					// next, so that a large payload doesn't stay around.
 					*data_guard = None;
 
 					// It is important that this mutex is locked at the time of calling
@@ -839,7 +2489,8 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 					// thread has unlocked its `data_guard` and is just about to sleep.
 					// It is also important that the RwLock is kept write-locked for the same
 					// reason.
-					let _guard = selection.mutex.lock();
+					let mut guard = selection.mutex.lock();
+					*guard = None;
 					selection.data_changed.notify_all();
 				}
 			}
@@ -849,6 +2500,10 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 					context.atom_name_dbg(event.selection),
 					context.atom_name_dbg(event.target),
 				);
+				if let Some(kind) = context.kind_of(event.selection) {
+					*context.selection_of(kind).last_request.lock() = Some(Instant::now());
+				}
+
 				// Someone is requesting the clipboard content from us.
 				if let Err(e) = context.handle_selection_request(event) {
 					error!("Failed to handle selection request: {e}");
@@ -876,8 +2531,8 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 				// Considering that this thread is not responsible for reading
 				// clipboard contents, this must come from the clipboard manager
 				// signaling that the data was handed over successfully.
-				if event.selection != context.atoms.CLIPBOARD_MANAGER {
-					error!("Received a `SelectionNotify` from a selection other than the CLIPBOARD_MANAGER. This is unexpected in this thread.");
+				if event.selection != context.manager_atom() {
+					error!("Received a `SelectionNotify` from a selection other than the clipboard manager's. This is unexpected in this thread.");
 					continue;
 				}
 				let handover_state = context.handover_state.lock();
@@ -899,6 +2554,19 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 					}
 				}
 			}
+			Event::PropertyNotify(event) => {
+				if event.window == context.server.win_id && event.state == Property::NEW_VALUE {
+					if let Some(kind) = context.kind_of(event.atom) {
+						*context.selection_of(kind).owned_at.lock() = Some(event.time);
+					}
+				}
+				if let Err(e) = context.continue_incr_transfers(event) {
+					error!("Failed to send streamed data: {e}");
+				}
+				if let Err(e) = context.answer_deferred_requests() {
+					error!("Failed to send lazily placed data: {e}");
+				}
+			}
 			_event => {
 				// May be useful for debugging but nothing else really.
 				// trace!("Received unwanted event: {:?}", event);
@@ -907,271 +2575,2323 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 	}
 }
 
+/// How long ago the server time `then` was, given that it's `now` on the server.
+///
+/// Server time counts milliseconds and wraps around about every 49.7 days, which the wrapping
+/// difference accounts for. `CurrentTime`, which some owners give instead of the time they took
+/// the selection at, and times that appear to be in the future tell nothing.
+fn server_time_age(then: Timestamp, now: Timestamp) -> Option<Duration> {
+	if then == u32::from(Time::CURRENT_TIME) {
+		return None;
+	}
+	let elapsed = now.wrapping_sub(then);
+	(elapsed <= i32::MAX as u32).then(|| Duration::from_millis(elapsed.into()))
+}
+
+/// The extension which Xwayland offers since version 23.1.
+const XWAYLAND_EXTENSION: &[u8] = b"XWAYLAND";
+
+/// Whether the X server is Xwayland, given whether it offers the `XWAYLAND` extension and the
+/// `WAYLAND_DISPLAY` of the environment. Older versions of Xwayland don't offer the extension, but
+/// an X connection alongside a Wayland display almost always goes through it.
+fn is_xwayland(has_extension: bool, wayland_display: Option<&OsStr>) -> bool {
+	has_extension || wayland_display.is_some_and(|display| !display.is_empty())
+}
+
 pub(crate) struct Clipboard {
 	inner: Arc<Inner>,
+	/// How the last read through this `Clipboard` went.
+	last_read: Mutex<Option<ReadStats>>,
+	pub(crate) targets: ReadTargets,
 }
 
 impl Clipboard {
 	pub(crate) fn new() -> Result<Self> {
 		let mut global_cb = CLIPBOARD.lock();
 		if let Some(global_cb) = &*global_cb {
-			return Ok(Self { inner: Arc::clone(&global_cb.inner) });
+			// Keeps the server thread from stopping if it's only serving lingering selections.
+			*global_cb.inner.lingering_since.lock() = None;
+			return Ok(Self::with_inner(Arc::clone(&global_cb.inner)));
 		}
 		// At this point we know that the clipboard does not exist.
-		let ctx = Arc::new(Inner::new()?);
-		let join_handle;
-		{
-			let ctx = Arc::clone(&ctx);
-			join_handle = std::thread::spawn(move || {
-				if let Err(error) = serve_requests(ctx) {
-					error!("Worker thread errored with: {}", error);
-				}
-			});
-		}
+		let ctx = Arc::new(Inner::new(TimeoutConfig::default(), false)?);
+		let join_handle = spawn_server(Arc::clone(&ctx));
 		*global_cb = Some(GlobalClipboard { inner: Arc::clone(&ctx), server_handle: join_handle });
-		Ok(Self { inner: ctx })
+		Ok(Self::with_inner(ctx))
 	}
 
-	fn add_clipboard_exclusions(&self, exclude_from_history: bool, data: &mut Vec<ClipboardData>) {
-		if exclude_from_history {
-			data.push(ClipboardData {
-				bytes: KDE_EXCLUSION_HINT.to_vec(),
-				format: self.inner.atoms.X_KDE_PASSWORDMANAGERHINT,
-			})
+	/// A `Clipboard` with a connection and server thread of its own, which aren't shared with
+	/// the ones from `new`. Only the handles made from it with `clone_handle` share them.
+	pub(crate) fn new_isolated() -> Result<Self> {
+		let inner = Arc::new(Inner::new(TimeoutConfig::default(), true)?);
+		let server_handle = spawn_server(Arc::clone(&inner));
+		if let Some(isolated) = &inner.isolated {
+			isolated.lock().server_handle = Some(server_handle);
+		}
+		Ok(Self::with_inner(inner))
+	}
+
+	fn with_inner(inner: Arc<Inner>) -> Self {
+		if let Some(isolated) = &inner.isolated {
+			isolated.lock().clipboards += 1;
+		}
+		Self { inner, last_read: Mutex::new(None), targets: ReadTargets::default() }
+	}
+
+	/// Another handle to the same `Inner`, which keeps the server running until it's dropped too.
+	pub(crate) fn clone_handle(&self) -> Self {
+		Self::with_inner(Arc::clone(&self.inner))
+	}
+
+	pub(crate) fn set_timeouts(&self, timeouts: TimeoutConfig) {
+		*self.inner.timeouts.lock() = timeouts;
+	}
+
+	pub(crate) fn is_healthy(&self) -> bool {
+		!self.inner.serve_stopped.load(Ordering::Relaxed)
+	}
+
+	pub(crate) fn is_xwayland(&self) -> bool {
+		self.inner.xwayland
+	}
+
+	pub(crate) fn diagnose(&self, diagnostics: &mut Diagnostics) {
+		diagnostics.backend = if self.inner.xwayland { "X11 (Xwayland)" } else { "X11" }.into();
+		let setup = self.inner.server.conn.setup();
+		diagnostics.server =
+			Some(format!("{} {}", String::from_utf8_lossy(&setup.vendor), setup.release_number));
+		diagnostics.protocol =
+			Some(format!("X{}.{}", setup.protocol_major_version, setup.protocol_minor_version));
+	}
+
+	pub(crate) fn restart_server(&mut self) -> Result<()> {
+		if self.inner.isolated.is_some() {
+			return self.restart_isolated_server();
+		}
+		let mut global_cb = CLIPBOARD.lock();
+
+		// Another `Clipboard` may have already restarted it on a new connection.
+		if let Some(global_cb) = &*global_cb {
+			if !Arc::ptr_eq(&global_cb.inner, &self.inner)
+				&& !global_cb.inner.serve_stopped.load(Ordering::Relaxed)
+			{
+				self.inner = Arc::clone(&global_cb.inner);
+				return Ok(());
+			}
+		}
+
+		if self.is_healthy() {
+			return Ok(());
+		}
+
+		let inner = self.restartable_inner()?;
+		let server_handle = spawn_server(Arc::clone(&inner));
+		let previous =
+			global_cb.replace(GlobalClipboard { inner: Arc::clone(&inner), server_handle });
+		if let Some(previous) = previous {
+			// The previous thread already stopped, or is just about to.
+			if previous.server_handle.join().is_err() {
+				error!("The previous clipboard server thread panicked.");
+			}
+		}
+
+		self.inner = inner;
+		Ok(())
+	}
+
+	/// Like `restart_server`, for an isolated connection.
+	///
+	/// Other handles of the clipboard keep using the current connection, which only matters if it
+	/// broke: then each of them has to be restarted, and opens a new connection of its own.
+	fn restart_isolated_server(&mut self) -> Result<()> {
+		if self.is_healthy() {
+			return Ok(());
+		}
+
+		let inner = self.restartable_inner()?;
+		let server_handle = spawn_server(Arc::clone(&inner));
+		let previous = if Arc::ptr_eq(&inner, &self.inner) {
+			self.isolated().server_handle.replace(server_handle)
+		} else {
+			let mut isolated = self.isolated();
+			isolated.clipboards -= 1;
+			let previous = isolated.server_handle.take();
+			drop(isolated);
+
+			self.inner = inner;
+			let mut isolated = self.isolated();
+			isolated.clipboards += 1;
+			isolated.server_handle = Some(server_handle);
+			previous
+		};
+		if let Some(previous) = previous {
+			// The previous thread already stopped, or is just about to.
+			if previous.join().is_err() {
+				error!("The previous clipboard server thread panicked.");
+			}
 		}
+		Ok(())
+	}
+
+	/// The state of the isolated connection this `Clipboard` uses.
+	fn isolated(&self) -> MutexGuard<'_, Isolated> {
+		self.inner.isolated.as_ref().expect("the connection isn't isolated").lock()
+	}
+
+	/// The `Inner` to restart the stopped server thread with, which is the current one unless
+	/// its connection broke.
+	fn restartable_inner(&self) -> Result<Arc<Inner>> {
+		// The thread also stops when the connection broke, in which case it can't be reused.
+		let window_alive = self
+			.inner
+			.server
+			.conn
+			.get_window_attributes(self.inner.server.win_id)
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.is_some();
+
+		if window_alive {
+			trace!("Restarting the clipboard server thread");
+			self.inner.serve_stopped.store(false, Ordering::Relaxed);
+			Ok(Arc::clone(&self.inner))
+		} else {
+			trace!("Restarting the clipboard server thread on a new connection");
+			let timeouts = *self.inner.timeouts.lock();
+			Ok(Arc::new(Inner::new(timeouts, self.inner.isolated.is_some())?))
+		}
+	}
+
+	/// Whether both clipboards are served over the same X11 connection.
+	#[cfg(test)]
+	pub(crate) fn shares_connection(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.inner, &other.inner)
+	}
+
+	/// Places `offers` onto the selection.
+	pub(super) fn set_offers(&self, offers: Vec<Offer>, config: &WriteConfig) -> Result<()> {
+		let data = self.data_of_offers(offers)?;
+		self.inner.write(data, config)
+	}
+
+	/// Like `set_offers`, returning when the offers were placed, for `wait_until_replaced`.
+	pub(super) fn set_offers_tracked(
+		&self,
+		offers: Vec<Offer>,
+		config: &WriteConfig,
+	) -> Result<Instant> {
+		let data = self.data_of_offers(offers)?;
+		self.inner.write_tracked(data, config)
+	}
+
+	/// Blocks until the data placed onto `selection` at `written_at` is replaced or lost, or
+	/// `deadline` has passed.
+	pub(super) fn wait_until_replaced(
+		&self,
+		selection: LinuxClipboardKind,
+		written_at: Instant,
+		deadline: Instant,
+	) {
+		let selection = self.inner.selection_of(selection);
+		let mut guard = selection.mutex.lock();
+		while *guard == Some(written_at) {
+			if selection.data_changed.wait_until(&mut guard, deadline).timed_out() {
+				break;
+			}
+		}
+	}
+
+	fn data_of_offers(&self, offers: Vec<Offer>) -> Result<Vec<ClipboardData>> {
+		offers
+			.into_iter()
+			.map(|offer| {
+				let format = self.inner.target_atom(&offer.mime)?;
+				Ok(ClipboardData {
+					bytes: offer.bytes,
+					format,
+					advertised: offer.advertised,
+					stream: None,
+					lazy: None,
+				})
+			})
+			.collect()
 	}
 
 	pub(crate) fn clear(&self, selection: LinuxClipboardKind) -> Result<()> {
 		self.inner.clear(selection)
 	}
 
-	pub(crate) fn get_text(&self, selection: LinuxClipboardKind) -> Result<String> {
-		let formats = [
+	/// Whether this process owns `selection`, so that reading it doesn't involve the X server.
+	pub(crate) fn is_owner(&self, selection: LinuxClipboardKind) -> Result<bool> {
+		self.inner.is_owner(selection)
+	}
+
+	/// Clears `selection` if this process still owns it, returning whether it did.
+	pub(crate) fn clear_if_owner(&self, selection: LinuxClipboardKind) -> Result<bool> {
+		// Giving up the selection only if it's still ours would need the server time at which
+		// it was taken, so another application taking it right after the check isn't noticed.
+		if !self.inner.is_owner(selection)? {
+			return Ok(false);
+		}
+		self.inner.clear(selection)?;
+		Ok(true)
+	}
+
+	/// Reads the first of `formats` that the selection is offered in, keeping track of how the
+	/// read went for `last_read_stats`.
+	fn read(&self, formats: &[Atom], config: &ReadConfig) -> Result<ReadData> {
+		let started = Instant::now();
+		let mut stats = ReadStats::default();
+		let result = self.inner.read(formats, config, &mut stats);
+		stats.duration = started.elapsed();
+		*self.last_read.lock() = Some(stats);
+		result
+	}
+
+	/// Reads the selection in `target`, whichever type the owner labels the reply with, and
+	/// returns the name of that type along with the data.
+	pub(crate) fn get_typed(&self, target: &str, config: &ReadConfig) -> Result<(String, Vec<u8>)> {
+		let target = self.inner.target_atom(target)?;
+		let config = ReadConfig { any_reply_type: true, ..*config };
+		let started = Instant::now();
+		let mut stats = ReadStats::default();
+		let result = self.inner.read_typed(target, &config, &mut stats);
+		stats.duration = started.elapsed();
+		*self.last_read.lock() = Some(stats);
+		let (bytes, reply_type) = result?;
+		Ok((self.inner.atom_name(reply_type)?, bytes))
+	}
+
+	/// Reads `selection` in `target` straight into `writer`, keeping track of how the read went
+	/// for `last_read_stats`.
+	pub(crate) fn write_to(
+		&self,
+		target: &str,
+		config: &ReadConfig,
+		writer: &mut dyn Write,
+	) -> Result<u64> {
+		let target = self.inner.target_atom(target)?;
+		let started = Instant::now();
+		let mut stats = ReadStats::default();
+		let result = self.inner.write_to(target, config, &mut stats, writer);
+		stats.duration = started.elapsed();
+		*self.last_read.lock() = Some(stats);
+		result
+	}
+
+	pub(crate) fn last_read_stats(&self) -> Option<ReadStats> {
+		*self.last_read.lock()
+	}
+
+	pub(crate) fn trim_memory(&self) -> Result<usize> {
+		self.inner.trim_memory()
+	}
+
+	pub(crate) fn has_clipboard_manager(&self) -> Result<bool> {
+		self.inner.has_clipboard_manager()
+	}
+
+	pub(crate) fn content_age(&self, selection: LinuxClipboardKind) -> Result<Option<Duration>> {
+		self.inner.content_age(selection)
+	}
+
+	/// Starts watching `selection` for new contents.
+	pub(crate) fn watch_changes(&self, selection: LinuxClipboardKind) -> Result<ChangeWatch> {
+		ChangeWatch::new(self.inner.atom_of(selection))
+	}
+
+	/// Reads the contents of a selection in every format its owner offers.
+	pub(crate) fn capture(&self, config: &ReadConfig) -> Result<Vec<(String, Vec<u8>)>> {
+		let inner = &self.inner;
+		if inner.is_owner(config.selection)? {
+			// Our own contents are captured as they were placed, without the formats they are
+			// additionally served under.
+			let data_list = inner.selection_of(config.selection).data.read().clone();
+			let data_list = data_list.ok_or_else(Error::content_not_available)?;
+			return data_list
+				.into_iter()
+				.filter(|data| data.stream.is_none())
+				.filter_map(|data| Some((data.format, data.into_contents()?)))
+				.map(|(format, bytes)| Ok((inner.atom_name(format)?, bytes)))
+				.collect();
+		}
+
+		// Owners label some replies with a type other than the target, starting with `TARGETS`.
+		let config = ReadConfig { any_reply_type: true, ..*config };
+		let targets = self.read(&[inner.atoms.TARGETS], &config)?.bytes;
+
+		let mut formats = Vec::new();
+		for target in targets.chunks_exact(4) {
+			let target = Atom::from_ne_bytes(target.try_into().unwrap());
+			let name = inner.atom_name(target)?;
+			if CAPTURE_SKIPPED_TARGETS.contains(&name.as_str()) {
+				continue;
+			}
+			match self.read(&[target], &config) {
+				Ok(data) => formats.push((name, data.bytes)),
+				// Owners don't always deliver every format they advertise.
+				Err(Error::ContentNotAvailable { .. }) => {
+					trace!("Couldn't capture the {name} target")
+				}
+				Err(error) => return Err(error),
+			}
+		}
+
+		if formats.is_empty() {
+			Err(Error::content_not_available())
+		} else {
+			Ok(formats)
+		}
+	}
+
+	/// Places contents captured with `capture` onto a selection again.
+	pub(crate) fn restore(
+		&self,
+		formats: &[(String, Vec<u8>)],
+		config: &WriteConfig,
+	) -> Result<()> {
+		let offers = formats
+			.iter()
+			.map(|(target, bytes)| Offer::new(target.clone(), bytes.clone()))
+			.collect();
+		self.set_offers(offers, config)
+	}
+
+	pub(crate) fn serve_target(
+		&self,
+		selection: LinuxClipboardKind,
+		target: &str,
+		bytes: Vec<u8>,
+	) -> Result<()> {
+		if self.inner.serve_stopped.load(Ordering::Relaxed) {
+			return Err(Error::unknown("The clipboard handler thread seems to have stopped. Logging messages may reveal the cause. (See the `log` crate.)"));
+		}
+		let data = ClipboardData {
+			bytes: bytes.into(),
+			format: self.inner.intern_atom(target)?,
+			advertised: true,
+			stream: None,
+			lazy: None,
+		};
+		self.inner.add_target(selection, data)
+	}
+
+	/// Places `bytes` under the already interned `target`, next to the history exclusion hint.
+	#[cfg(feature = "x11-interop")]
+	pub(crate) fn set_atom(
+		&self,
+		target: Atom,
+		bytes: Vec<u8>,
+		config: &WriteConfig,
+	) -> Result<()> {
+		let mut data = self.data_of_offers(offers_with_capacity(1, config.history_exclusion))?;
+		data.push(ClipboardData {
+			bytes: bytes.into(),
+			format: target,
+			advertised: true,
+			stream: None,
+			lazy: None,
+		});
+		self.inner.write(data, config)
+	}
+
+	/// Reads the selection in `target`, whichever type the owner labels the reply with.
+	#[cfg(feature = "x11-interop")]
+	pub(crate) fn get_atom(&self, target: Atom, config: &ReadConfig) -> Result<Vec<u8>> {
+		let config = ReadConfig { any_reply_type: true, ..*config };
+		Ok(self.read(&[target], &config)?.bytes)
+	}
+
+	/// The name of `target` for a `SetReport`, which is looked up once.
+	#[cfg(feature = "x11-interop")]
+	pub(crate) fn target_name(&self, target: Atom) -> &'static str {
+		self.inner.atom_name_dbg(target)
+	}
+
+	/// The atom interned on startup for `name`, among the selections, the targets of the
+	/// protocol and the formats arboard places.
+	#[cfg(feature = "x11-interop")]
+	pub(crate) fn well_known_atom(&self, name: &str) -> Option<Atom> {
+		let atoms = &self.inner.atoms;
+		let protocol = [
+			("CLIPBOARD", atoms.CLIPBOARD),
+			("PRIMARY", atoms.PRIMARY),
+			("SECONDARY", atoms.SECONDARY),
+			("CLIPBOARD_MANAGER", atoms.CLIPBOARD_MANAGER),
+			("SAVE_TARGETS", atoms.SAVE_TARGETS),
+			("TARGETS", atoms.TARGETS),
+			("TIMESTAMP", atoms.TIMESTAMP),
+			("ATOM", atoms.ATOM),
+			("INCR", atoms.INCR),
+		];
+		let found = protocol.into_iter().find(|(known, _)| *known == name);
+		found.map(|(_, atom)| atom).or_else(|| self.inner.known_atom(name))
+	}
+
+	/// The targets text is read from, in order of preference.
+	fn text_formats(&self) -> Result<Vec<Atom>> {
+		if let Some(targets) = &self.targets.text {
+			return targets.iter().map(|target| self.inner.target_atom(target)).collect();
+		}
+		Ok(vec![
 			self.inner.atoms.UTF8_STRING,
 			self.inner.atoms.UTF8_MIME_0,
 			self.inner.atoms.UTF8_MIME_1,
 			self.inner.atoms.STRING,
 			self.inner.atoms.TEXT,
 			self.inner.atoms.TEXT_MIME_UNKNOWN,
-		];
-		let result = self.inner.read(&formats, selection)?;
+		])
+	}
+
+	pub(crate) fn get_text(&self, config: &ReadConfig) -> Result<String> {
+		let result = self.read(&self.text_formats()?, config)?;
 		if result.format == self.inner.atoms.STRING {
 			// ISO Latin-1
 			// See: https://stackoverflow.com/questions/28169745/what-are-the-options-to-convert-iso-8859-1-latin-1-to-a-string-utf-8
 			Ok(result.bytes.into_iter().map(|c| c as char).collect())
 		} else {
-			String::from_utf8(result.bytes).map_err(|_| Error::ConversionFailure)
+			text_from_utf8(result.bytes)
 		}
 	}
 
+	pub(crate) fn get_text_truncated(
+		&self,
+		config: &ReadConfig,
+		max_bytes: usize,
+	) -> Result<(String, bool)> {
+		let result = self.read(&self.text_formats()?, config)?;
+		if result.format == self.inner.atoms.STRING {
+			// Every Latin-1 character takes up at least one byte in UTF-8 as well.
+			let text = result.bytes.into_iter().take(max_bytes.saturating_add(1));
+			Ok(truncate_text(text.map(|c| c as char).collect(), max_bytes))
+		} else {
+			decode_text_prefix(result.bytes, max_bytes)
+		}
+	}
+
+	/// Places text like `Set::text` does, which the tests do a lot.
+	#[cfg(test)]
 	pub(crate) fn set_text(
 		&self,
-		message: Cow<'_, str>,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
+		message: std::borrow::Cow<'_, str>,
+		config: &WriteConfig,
 	) -> Result<()> {
-		let mut data = Vec::with_capacity(if exclude_from_history { 2 } else { 1 });
-		data.push(ClipboardData {
-			bytes: message.into_owned().into_bytes(),
-			format: self.inner.atoms.UTF8_STRING,
-		});
-
-		self.add_clipboard_exclusions(exclude_from_history, &mut data);
-
-		self.inner.write(data, selection, wait)
+		self.set_offers(text_offers(message.into_owned().into_bytes(), config), config)
 	}
 
-	pub(crate) fn get_html(&self, selection: LinuxClipboardKind) -> Result<String> {
+	pub(crate) fn get_html(&self, config: &ReadConfig) -> Result<String> {
 		let formats = [self.inner.atoms.HTML];
-		let result = self.inner.read(&formats, selection)?;
-		String::from_utf8(result.bytes).map_err(|_| Error::ConversionFailure)
+		let result = self.read(&formats, config)?;
+		decode_html(result.bytes)
 	}
 
-	pub(crate) fn set_html(
-		&self,
-		html: Cow<'_, str>,
-		alt: Option<Cow<'_, str>>,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
-	) -> Result<()> {
-		let mut data = {
-			let cap = [true, alt.is_some(), exclude_from_history]
-				.map(|v| usize::from(v as u8))
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn get_image_or_bytes(&self, config: &ReadConfig) -> Result<ImageOrBytes> {
+		let formats = match &self.targets.image {
+			Some(targets) => targets
 				.iter()
-				.sum();
-			Vec::with_capacity(cap)
+				.map(|target| self.inner.target_atom(target))
+				.collect::<Result<_>>()?,
+			// Qt's own target is only asked for when there's no PNG, as it may hold any format.
+			None => vec![self.inner.atoms.PNG_MIME, self.inner.atoms.QT_IMAGE_MIME],
 		};
-
-		if let Some(alt_text) = alt {
-			data.push(ClipboardData {
-				bytes: alt_text.into_owned().into_bytes(),
-				format: self.inner.atoms.UTF8_STRING,
-			});
-		}
-		data.push(ClipboardData {
-			bytes: html.into_owned().into_bytes(),
-			format: self.inner.atoms.HTML,
-		});
-
-		self.add_clipboard_exclusions(exclude_from_history, &mut data);
-
-		self.inner.write(data, selection, wait)
+		let result = self.read(&formats, config)?;
+
+		let is_png = result.format == self.inner.atoms.PNG_MIME;
+		Ok(ImageOrBytes::decode(
+			result.bytes,
+			|bytes| {
+				if is_png {
+					decode_png(bytes, config.max_image_pixels)
+				} else {
+					decode_image_file(bytes, config.max_image_pixels)
+				}
+			},
+			|| self.inner.atom_name(result.format).unwrap_or_default(),
+		))
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn get_image(&self, selection: LinuxClipboardKind) -> Result<ImageData<'static>> {
-		let formats = [self.inner.atoms.PNG_MIME];
-		let bytes = self.inner.read(&formats, selection)?.bytes;
-
-		let cursor = std::io::Cursor::new(&bytes);
-		let mut reader = image::io::Reader::new(cursor);
-		reader.set_format(image::ImageFormat::Png);
-		let image = match reader.decode() {
-			Ok(img) => img.into_rgba8(),
-			Err(_e) => return Err(Error::ConversionFailure),
-		};
-		let (w, h) = image.dimensions();
-		let image_data =
-			ImageData { width: w as usize, height: h as usize, bytes: image.into_raw().into() };
-		Ok(image_data)
+	/// Places data under `mime` which is only produced from `chunks` once it's requested.
+	pub(crate) fn set_stream(
+		&self,
+		mime: &str,
+		chunks: Chunks,
+		config: &WriteConfig,
+	) -> Result<()> {
+		let mut offers = offers_with_capacity(1, config.history_exclusion);
+		offers.push(Offer::new(mime.to_owned(), Vec::new()));
+		let mut data = self.data_of_offers(offers)?;
+		if let Some(streamed) = data.last_mut() {
+			streamed.stream = Some(Stream(Arc::new(Mutex::new(Some(chunks)))));
+		}
+		self.inner.write(data, config)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn set_image(
+	/// Places data under `mime` which is only produced by `produce` once it's requested.
+	#[cfg_attr(not(feature = "image-data-png"), allow(dead_code))]
+	pub(crate) fn set_lazy(
 		&self,
-		image: ImageData,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
+		mime: &str,
+		produce: Produce,
+		config: &WriteConfig,
 	) -> Result<()> {
-		let encoded = encode_as_png(&image)?;
-		let mut data = Vec::with_capacity(if exclude_from_history { 2 } else { 1 });
-
-		data.push(ClipboardData { bytes: encoded, format: self.inner.atoms.PNG_MIME });
-
-		self.add_clipboard_exclusions(exclude_from_history, &mut data);
-
-		self.inner.write(data, selection, wait)
+		let mut offers = offers_with_capacity(1, config.history_exclusion);
+		offers.push(Offer::new(mime.to_owned(), Vec::new()));
+		let mut data = self.data_of_offers(offers)?;
+		if let Some(lazy) = data.last_mut() {
+			let server = Arc::clone(&self.inner.server);
+			lazy.lazy = Some(Lazy::new(produce, server, self.inner.atoms.ARBOARD_WAKEUP));
+		}
+		self.inner.write(data, config)
 	}
 
-	pub(crate) fn get_file_list(&self, selection: LinuxClipboardKind) -> Result<Vec<PathBuf>> {
-		let result = self.inner.read(&[self.inner.atoms.URI_LIST], selection)?;
+	pub(crate) fn get_file_list(&self, config: &ReadConfig) -> Result<Vec<PathBuf>> {
+		let result = self.read(&[self.inner.atoms.URI_LIST], config)?;
 
 		Ok(paths_from_uri_list(result.bytes))
 	}
 
-	pub(crate) fn set_file_list(
+	pub(crate) fn get_file_list_with_op(
 		&self,
-		file_list: &[impl AsRef<Path>],
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
-		exclude_from_history: bool,
-	) -> Result<()> {
-		let files = paths_to_uri_list(file_list)?;
-		let mut data = Vec::with_capacity(if exclude_from_history { 2 } else { 1 });
+		config: &ReadConfig,
+	) -> Result<(Vec<PathBuf>, FileOp)> {
+		file_list_with_op(|target| {
+			let format = self.inner.intern_atom(target)?;
+			Ok(self.read(&[format], config)?.bytes)
+		})
+	}
+}
 
-		data.push(ClipboardData { bytes: files.into_bytes(), format: self.inner.atoms.URI_LIST });
-		self.add_clipboard_exclusions(exclude_from_history, &mut data);
+impl Clipboard {
+	pub(crate) fn serve(
+		&self,
+		contents: Vec<(String, Vec<u8>)>,
+		selection: LinuxClipboardKind,
+		shutdown: &Receiver<()>,
+		deadline: Option<Instant>,
+	) -> Result<ServeOutcome> {
+		let data = contents
+			.into_iter()
+			.map(|(target, bytes)| {
+				Ok(ClipboardData {
+					bytes: bytes.into(),
+					format: self.inner.intern_atom(&target)?,
+					advertised: true,
+					stream: None,
+					lazy: None,
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		let written_at =
+			self.inner.write_tracked(data, &WriteConfig { selection, ..WriteConfig::default() })?;
+
+		let selection = self.inner.selection_of(selection);
+		loop {
+			{
+				let mut guard = selection.mutex.lock();
+				// Either the selection was lost or another `Clipboard` in this process wrote to it.
+				if *guard != Some(written_at) {
+					return Ok(ServeOutcome::Replaced);
+				}
+				let poll_interval = self.inner.timeouts.lock().serve_poll_interval;
+				selection.data_changed.wait_for(&mut guard, poll_interval);
+			}
 
-		self.inner.write(data, selection, wait)
+			if let Some(reason) = serve_stop_reason(shutdown, deadline) {
+				return Ok(reason);
+			}
+		}
 	}
 }
 
 impl Drop for Clipboard {
 	fn drop(&mut self) {
 		// There are always at least 3 owners:
-		// the global, the server thread, and one `Clipboard::inner`
+		// the global, the server thread, and one `Clipboard::inner`.
+		// Every other `Clipboard`, including those from `clone_handle`, adds one more, so only the
+		// last of them gets to hand the data over and tear the server down.
 		const MIN_OWNERS: usize = 3;
 
+		if self.inner.isolated.is_some() {
+			// Isolated connections aren't kept in the global, so the `Clipboard`s using them are
+			// counted instead.
+			let mut isolated = self.isolated();
+			isolated.clipboards -= 1;
+			if isolated.clipboards == 0 {
+				let server_handle = isolated.server_handle.take();
+				drop(isolated);
+				self.shut_down(|| server_handle);
+			}
+			return;
+		}
+
 		// We start with locking the global guard to prevent race
 		// conditions below.
 		let mut global_cb = CLIPBOARD.lock();
-		if Arc::strong_count(&self.inner) == MIN_OWNERS {
+		// After `restart_server` opened a new connection, the global belongs to another `Inner`.
+		let is_global =
+			global_cb.as_ref().is_some_and(|global_cb| Arc::ptr_eq(&global_cb.inner, &self.inner));
+		if is_global && Arc::strong_count(&self.inner) == MIN_OWNERS {
 			// If the are the only owners of the clipboard are ourselves and
 			// the global object, then we should destroy the global object,
 			// and send the data to the clipboard manager
+			self.shut_down(|| {
+				let GlobalClipboard { inner, server_handle } = global_cb.take()?;
+				drop(inner);
+				Some(server_handle)
+			});
+		}
+	}
+}
 
-			if let Err(e) = self.inner.ask_clipboard_manager_to_request_our_data() {
-				error!("Could not hand the clipboard data over to the clipboard manager: {}", e);
-			}
-			let global_cb = global_cb.take();
-			if let Err(e) = self.inner.server.conn.destroy_window(self.inner.server.win_id) {
-				error!("Failed to destroy the clipboard window. Error: {}", e);
-				return;
+impl Clipboard {
+	/// Hands the data over to the clipboard manager and stops the server thread, whose handle
+	/// `take_server` gives up, once the last `Clipboard` using the connection is dropped.
+	fn shut_down(&mut self, take_server: impl FnOnce() -> Option<JoinHandle<()>>) {
+		if let Err(e) = self.inner.ask_clipboard_manager_to_request_our_data() {
+			error!("Could not hand the clipboard data over to the clipboard manager: {}", e);
+		}
+
+		// Selections with an idle timeout keep being served by the server thread, which
+		// tears everything down by itself once they expired.
+		if self.inner.has_idle_timeouts() {
+			trace!("Keeping the clipboard server thread alive for selections with an idle timeout");
+			*self.inner.lingering_since.lock() = Some(Instant::now());
+			if let Err(e) = self.inner.wake_server() {
+				error!("Failed to wake the clipboard server thread. Error: {}", e);
 			}
-			if let Err(e) = self.inner.server.conn.flush() {
-				error!("Failed to flush the clipboard window. Error: {}", e);
-				return;
+			return;
+		}
+
+		let server_handle = take_server();
+		if let Err(e) = self.inner.server.conn.destroy_window(self.inner.server.win_id) {
+			error!("Failed to destroy the clipboard window. Error: {}", e);
+			return;
+		}
+		if let Err(e) = self.inner.server.conn.flush() {
+			error!("Failed to flush the clipboard window. Error: {}", e);
+			return;
+		}
+		if let Some(server_handle) = server_handle {
+			if let Err(e) = server_handle.join() {
+				// Let's try extracting the error message
+				let message;
+				if let Some(msg) = e.downcast_ref::<&'static str>() {
+					message = Some((*msg).to_string());
+				} else if let Some(msg) = e.downcast_ref::<String>() {
+					message = Some(msg.clone());
+				} else {
+					message = None;
+				}
+				if let Some(message) = message {
+					error!("The clipboard server thread panicked. Panic message: '{}'", message,);
+				} else {
+					error!("The clipboard server thread panicked.");
+				}
 			}
-			if let Some(global_cb) = global_cb {
-				let GlobalClipboard { inner, server_handle } = global_cb;
-				drop(inner);
 
-				if let Err(e) = server_handle.join() {
-					// Let's try extracting the error message
-					let message;
-					if let Some(msg) = e.downcast_ref::<&'static str>() {
-						message = Some((*msg).to_string());
-					} else if let Some(msg) = e.downcast_ref::<String>() {
-						message = Some(msg.clone());
-					} else {
-						message = None;
+			// By this point the global, unless the connection is isolated, has dropped its reference
+			// to `Inner` and the background thread has exited which means it also dropped its reference.
+			// Therefore `self.inner` should be the last strong count.
+			//
+			// Note: The following is all best effort and is only for logging. Nothing is guaranteed to execute
+			// or log.
+			#[cfg(debug_assertions)]
+			if let Some(inner) = Arc::get_mut(&mut self.inner) {
+				use std::io::IsTerminal;
+
+				let mut change_timestamps = Vec::with_capacity(2);
+				let mut collect_changed = |sel: &mut Mutex<Option<Instant>>| {
+					if let Some(changed) = sel.get_mut() {
+						change_timestamps.push(*changed);
 					}
-					if let Some(message) = message {
-						error!(
-							"The clipboard server thread panicked. Panic message: '{}'",
-							message,
-						);
+				};
+
+				collect_changed(&mut inner.clipboard.mutex);
+				collect_changed(&mut inner.primary.mutex);
+				collect_changed(&mut inner.secondary.mutex);
+
+				change_timestamps.sort();
+				if let Some(last) = change_timestamps.last() {
+					let elapsed = last.elapsed().as_millis();
+					// This number has no meaning, its just a guess for how long
+					// might be reasonable to give a clipboard manager a chance to
+					// save contents based ~roughly on the handoff timeout.
+					if elapsed > 100 {
+						return;
+					}
+
+					// If the app isn't running in a terminal don't print, use log instead.
+					// Printing has a higher chance of being seen though, so its our default.
+					// Its also close enough to a `debug_assert!` that it shouldn't come across strange.
+					let msg = format!("Clipboard was dropped very quickly after writing ({elapsed}ms); clipboard managers may not have seen the contents\nConsider keeping `Clipboard` in more persistent state somewhere or keeping the contents alive longer using `SetLinuxExt` and/or threads.");
+					if std::io::stderr().is_terminal() {
+						eprintln!("{msg}");
 					} else {
-						error!("The clipboard server thread panicked.");
+						log::warn!("{msg}");
 					}
 				}
+			}
+		}
+	}
+}
 
-				// By this point we've dropped the Global's reference to `Inner` and the background
-				// thread has exited which means it also dropped its reference. Therefore `self.inner` should
-				// be the last strong count.
-				//
-				// Note: The following is all best effort and is only for logging. Nothing is guaranteed to execute
-				// or log.
-				#[cfg(debug_assertions)]
-				if let Some(inner) = Arc::get_mut(&mut self.inner) {
-					use std::io::IsTerminal;
-
-					let mut change_timestamps = Vec::with_capacity(2);
-					let mut collect_changed = |sel: &mut Mutex<Option<Instant>>| {
-						if let Some(changed) = sel.get_mut() {
-							change_timestamps.push(*changed);
-						}
-					};
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::platform::linux::{encoded_offers, HistoryExclusion};
+	use std::sync::atomic::AtomicUsize;
+
+	/// Calls `done` until it returns `true`, for at most 5 seconds, returning whether it did.
+	fn poll_until(mut done: impl FnMut() -> bool) -> bool {
+		let deadline = Instant::now() + Duration::from_secs(5);
+		while Instant::now() < deadline {
+			if done() {
+				return true;
+			}
+			std::thread::sleep(Duration::from_millis(10));
+		}
+		false
+	}
 
-					collect_changed(&mut inner.clipboard.mutex);
-					collect_changed(&mut inner.primary.mutex);
-					collect_changed(&mut inner.secondary.mutex);
-
-					change_timestamps.sort();
-					if let Some(last) = change_timestamps.last() {
-						let elapsed = last.elapsed().as_millis();
-						// This number has no meaning, its just a guess for how long
-						// might be reasonable to give a clipboard manager a chance to
-						// save contents based ~roughly on the handoff timeout.
-						if elapsed > 100 {
-							return;
-						}
+	/// Stands in for another application requesting data from our selections over its own
+	/// connection, so that the replies of `handle_selection_request` can be inspected.
+	struct Requestor {
+		context: XContext,
+	}
 
-						// If the app isn't running in a terminal don't print, use log instead.
-						// Printing has a higher chance of being seen though, so its our default.
-						// Its also close enough to a `debug_assert!` that it shouldn't come across strange.
-						let msg = format!("Clipboard was dropped very quickly after writing ({elapsed}ms); clipboard managers may not have seen the contents\nConsider keeping `Clipboard` in more persistent state somewhere or keeping the contents alive longer using `SetLinuxExt` and/or threads.");
-						if std::io::stderr().is_terminal() {
-							eprintln!("{msg}");
-						} else {
-							log::warn!("{msg}");
-						}
+	impl Requestor {
+		fn new() -> Self {
+			Self { context: XContext::new().unwrap() }
+		}
+
+		fn atom(&self, name: &str) -> Atom {
+			self.context.conn.intern_atom(false, name.as_bytes()).unwrap().reply().unwrap().atom
+		}
+
+		fn atom_name(&self, atom: Atom) -> String {
+			let reply = self.context.conn.get_atom_name(atom).unwrap().reply().unwrap();
+			String::from_utf8(reply.name).unwrap()
+		}
+
+		/// Converts `selection` to `TARGETS`, returning the names of the targets it offers.
+		fn targets(&self, selection: &str) -> Vec<String> {
+			let (reply_type, targets) = self.request(selection, "TARGETS").unwrap();
+			assert_eq!(reply_type, "ATOM");
+			targets
+				.chunks_exact(4)
+				.map(|atom| self.atom_name(u32::from_ne_bytes(atom.try_into().unwrap())))
+				.collect()
+		}
+
+		/// Converts `selection` to `target`, returning the type and contents of the reply or
+		/// `None` if the owner refused the conversion.
+		fn request(&self, selection: &str, target: &str) -> Option<(String, Vec<u8>)> {
+			self.request_into(selection, target, Some("ARBOARD_TEST_REQUESTOR"))
+		}
+
+		/// Like `request`, but has the reply stored in `property`, or leaves it up to the owner
+		/// like obsolete requestors do if it's `None`.
+		fn request_into(
+			&self,
+			selection: &str,
+			target: &str,
+			property: Option<&str>,
+		) -> Option<(String, Vec<u8>)> {
+			let conn = &self.context.conn;
+			let property = property.map_or(NONE, |property| self.atom(property));
+			conn.convert_selection(
+				self.context.win_id,
+				self.atom(selection),
+				self.atom(target),
+				property,
+				Time::CURRENT_TIME,
+			)
+			.unwrap();
+			conn.flush().unwrap();
+
+			let deadline = Instant::now() + Duration::from_secs(5);
+			while Instant::now() < deadline {
+				match conn.poll_for_event().unwrap() {
+					Some(Event::SelectionNotify(event)) if event.property == NONE => return None,
+					Some(Event::SelectionNotify(event)) => {
+						let reply = conn
+							.get_property(
+								true,
+								self.context.win_id,
+								event.property,
+								AtomEnum::ANY,
+								0,
+								u32::MAX / 4,
+							)
+							.unwrap()
+							.reply()
+							.unwrap();
+						return Some((self.atom_name(reply.type_), reply.value));
+					}
+					Some(_) => {}
+					None => std::thread::sleep(Duration::from_millis(1)),
+				}
+			}
+			panic!("no reply to the request of {target} from {selection}");
+		}
+	}
+
+	#[test]
+	fn properties_larger_than_a_reply_are_read_in_full() {
+		let context = XContext::new().unwrap();
+		let property = context.conn.intern_atom(false, b"ARBOARD_TEST_PROPERTY").unwrap();
+		let property = property.reply().unwrap().atom;
+		let bytes: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+		let put = || {
+			context
+				.conn
+				.change_property8(
+					PropMode::REPLACE,
+					context.win_id,
+					property,
+					AtomEnum::STRING,
+					&bytes,
+				)
+				.unwrap();
+		};
+		let get = |max_bytes| {
+			let read_config = ReadConfig { max_bytes, ..Default::default() };
+			get_whole_property(
+				&context.conn,
+				context.win_id,
+				property,
+				AtomEnum::STRING.into(),
+				// Only 64 bytes fit into each reply, like `u32::MAX / 4` units would for more than
+				// a gigabyte.
+				read_length(&read_config).min(16),
+				max_bytes,
+			)
+			.unwrap()
+		};
+
+		put();
+		let reply = get(None);
+		assert_eq!(reply.value, bytes);
+		assert_eq!(reply.value_len, 1000);
+		assert_eq!(reply.bytes_after, 0);
+		// The last read deleted the property.
+		assert_eq!(get(None).type_, NONE);
+
+		// Reading stops once there is more than allowed, leaving the property in place.
+		put();
+		let reply = get(Some(100));
+		assert!(reply.value.len() > 100 && reply.value.len() < 1000);
+		assert!(reply.bytes_after > 0);
+		assert_eq!(get(None).value, bytes);
+
+		// `INCR` segments of any type are read in full as well.
+		put();
+		let reply = get_whole_property(
+			&context.conn,
+			context.win_id,
+			property,
+			AtomEnum::ANY.into(),
+			16,
+			None,
+		)
+		.unwrap();
+		assert_eq!((reply.type_, reply.value), (AtomEnum::STRING.into(), bytes.clone()));
+		assert_eq!(get(None).type_, NONE);
+	}
+
+	#[test]
+	fn failed_atom_name_lookups_are_retried() {
+		let failing = |_| Err(Error::unknown("connection reset"));
+		let resolving = |atom| Ok(format!("ATOM_{atom}"));
+
+		let names = AtomNames::new(Duration::ZERO);
+		assert_eq!(names.get(1, failing), "FAILED-TO-GET-THE-ATOM-NAME");
+		assert_eq!(names.get(1, resolving), "ATOM_1");
+		// Successful lookups are kept.
+		assert_eq!(names.get(1, failing), "ATOM_1");
+
+		// Within the retry delay, the failure is remembered.
+		let names = AtomNames::new(Duration::from_secs(60));
+		assert_eq!(names.get(2, failing), "FAILED-TO-GET-THE-ATOM-NAME");
+		assert_eq!(names.get(2, resolving), "FAILED-TO-GET-THE-ATOM-NAME");
+		assert_eq!(names.get(3, resolving), "ATOM_3");
+
+		// Nothing is locked while looking up a name, which may take a while.
+		let nested = |atom| Ok(format!("{}_{atom}", names.get(5, resolving)));
+		assert_eq!(names.get(4, nested), "ATOM_5_4");
+	}
+
+	#[test]
+	fn server_time_ages() {
+		let second = Duration::from_secs(1);
+		assert_eq!(server_time_age(5_000, 6_000), Some(second));
+		assert_eq!(server_time_age(5_000, 5_000), Some(Duration::ZERO));
+		// The server time wrapped around since the selection was taken.
+		assert_eq!(server_time_age(u32::MAX - 499, 500), Some(second));
+
+		assert_eq!(server_time_age(0, 6_000), None);
+		assert_eq!(server_time_age(6_000, 5_000), None);
+	}
+
+	#[test]
+	fn xwayland_detection() {
+		let display = Some(OsStr::new("wayland-0"));
+		assert!(is_xwayland(true, None));
+		assert!(is_xwayland(true, display));
+		assert!(is_xwayland(false, display));
+
+		assert!(!is_xwayland(false, None));
+		// An empty variable is the same as an unset one to Wayland clients.
+		assert!(!is_xwayland(false, Some(OsStr::new(""))));
+	}
+
+	#[test]
+	fn text_target_reply_type() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let set = |text: &str, text_aliases| {
+			clipboard
+				.set_text(text.into(), &WriteConfig { text_aliases, ..WriteConfig::default() })
+				.unwrap()
+		};
+
+		set("Grüße", false);
+		let reply = requestor.request("CLIPBOARD", "TEXT");
+		assert_eq!(reply, Some(("UTF8_STRING".to_owned(), "Grüße".as_bytes().to_vec())));
+
+		set("Grüße", true);
+		let reply = requestor.request("CLIPBOARD", "TEXT");
+		assert_eq!(reply, Some(("STRING".to_owned(), b"Gr\xfc\xdfe".to_vec())));
+
+		// Text that isn't representable in Latin-1 is still served as UTF-8.
+		set("日本語", true);
+		let reply = requestor.request("CLIPBOARD", "TEXT");
+		assert_eq!(reply, Some(("UTF8_STRING".to_owned(), "日本語".as_bytes().to_vec())));
+	}
+
+	#[test]
+	fn text_is_served_under_bare_text_plain() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let config =
+			WriteConfig { selection: LinuxClipboardKind::Secondary, ..WriteConfig::default() };
+
+		clipboard.set_text("pasted in a terminal".into(), &config).unwrap();
+
+		// Some applications only look for `text/plain` and ignore `UTF8_STRING`, so it's advertised
+		// even though the text isn't stored under it.
+		let targets = requestor.targets("SECONDARY");
+		assert!(targets.iter().any(|target| target == "text/plain"));
+
+		let (_, bytes) = requestor.request("SECONDARY", "text/plain").unwrap();
+		assert_eq!(bytes, b"pasted in a terminal");
+	}
+
+	#[test]
+	fn png_is_served_under_the_qt_image_target() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let png = b"\x89PNG\r\n\x1a\nnot really".to_vec();
+
+		let config = WriteConfig::default();
+		clipboard
+			.set_offers(encoded_offers(vec![(PNG_MIME, png.clone())], &config), &config)
+			.unwrap();
+
+		let targets = requestor.targets("CLIPBOARD");
+		assert!(targets.iter().any(|target| target == QT_IMAGE_MIME));
+
+		let reply = requestor.request("CLIPBOARD", QT_IMAGE_MIME);
+		assert_eq!(reply, Some((QT_IMAGE_MIME.to_owned(), png)));
+
+		// Text isn't an image, so it isn't served under the target.
+		clipboard.set_text("not an image".into(), &WriteConfig::default()).unwrap();
+		assert_eq!(requestor.request("CLIPBOARD", QT_IMAGE_MIME), None);
+	}
+
+	#[test]
+	fn exclusion_hint_in_first_targets_reply() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let config =
+			WriteConfig { history_exclusion: HistoryExclusion::Hinted, ..WriteConfig::default() };
+
+		clipboard.set_text("hunter2".into(), &config).unwrap();
+
+		// The very first request after taking ownership must already see the hint.
+		let targets = requestor.targets("CLIPBOARD");
+		assert!(targets.iter().any(|target| target == KDE_EXCLUSION_MIME));
+		assert!(!targets.iter().any(|target| target == "SAVE_TARGETS"));
+	}
+
+	#[test]
+	fn request_quirks() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let set = |quirks| {
+			let config = WriteConfig {
+				selection: LinuxClipboardKind::Secondary,
+				x11_quirks: quirks,
+				..WriteConfig::default()
+			};
+			clipboard.set_text("quirky".into(), &config).unwrap();
+		};
+		let utf8 = Some(("UTF8_STRING".to_owned(), b"quirky".to_vec()));
+		let targets_type = |reply: Option<(String, Vec<u8>)>| reply.unwrap().0;
+
+		set(X11Quirks::default());
+		// Obsolete requestors which don't name a property get the reply in the target's property.
+		assert_eq!(requestor.request_into("SECONDARY", "UTF8_STRING", None), utf8);
+		// Requestors which name the target as property and then reuse it for `TARGETS`.
+		assert_eq!(requestor.request_into("SECONDARY", "UTF8_STRING", Some("UTF8_STRING")), utf8);
+		let reply = requestor.request_into("SECONDARY", "TARGETS", Some("UTF8_STRING"));
+		assert_eq!(targets_type(reply), "ATOM");
+		let reply = requestor.request("SECONDARY", "text/plain");
+		assert_eq!(reply.unwrap().0, "UTF8_STRING");
+
+		set(X11Quirks::default().none_property(false));
+		assert_eq!(requestor.request_into("SECONDARY", "UTF8_STRING", None), None);
+
+		set(X11Quirks::default().mime_reply_type(true).targets_reply_type(true));
+		let reply = requestor.request("SECONDARY", "text/plain;charset=utf-8");
+		assert_eq!(reply, Some(("text/plain;charset=utf-8".to_owned(), b"quirky".to_vec())));
+		// `TEXT` must still name the encoding that was used.
+		assert_eq!(requestor.request("SECONDARY", "TEXT"), utf8);
+		assert_eq!(targets_type(requestor.request("SECONDARY", "TARGETS")), "TARGETS");
+	}
+
+	#[test]
+	fn request_handler_answers_each_request() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let reply = |target| requestor.request("SECONDARY", target).map(|(_, bytes)| bytes);
+		let config = |on_request: RequestHandler| WriteConfig {
+			selection: LinuxClipboardKind::Secondary,
+			on_request: Some(on_request),
+			..WriteConfig::default()
+		};
+
+		let targets = Arc::new(Mutex::new(Vec::new()));
+		let on_request = Arc::new({
+			let targets = targets.clone();
+			move |target: &str| {
+				let mut targets = targets.lock();
+				targets.push(target.to_owned());
+				match targets.len() {
+					3 => RequestAction::ServeStored,
+					pastes => RequestAction::Serve(format!("paste {pastes}").into_bytes()),
+				}
+			}
+		});
+		clipboard.set_text("stored".into(), &config(on_request)).unwrap();
+
+		assert_eq!(reply("UTF8_STRING"), Some(b"paste 1".to_vec()));
+		assert_eq!(reply("text/plain;charset=utf-8"), Some(b"paste 2".to_vec()));
+		assert_eq!(reply("UTF8_STRING"), Some(b"stored".to_vec()));
+		// Neither `TARGETS` nor targets the data isn't offered in are passed to the handler.
+		assert!(reply("TARGETS").is_some());
+		assert_eq!(reply("image/png"), None);
+		assert_eq!(*targets.lock(), ["UTF8_STRING", "text/plain;charset=utf-8", "UTF8_STRING"]);
+
+		// The handler may set the clipboard, and the request is answered with what it set.
+		let on_request = Arc::new(|_: &str| {
+			let config =
+				WriteConfig { selection: LinuxClipboardKind::Secondary, ..WriteConfig::default() };
+			Clipboard::new().unwrap().set_text("replaced".into(), &config).unwrap();
+			RequestAction::ServeStored
+		});
+		clipboard.set_text("original".into(), &config(on_request)).unwrap();
+		assert_eq!(reply("UTF8_STRING"), Some(b"replaced".to_vec()));
+		assert_eq!(reply("UTF8_STRING"), Some(b"replaced".to_vec()));
+	}
+
+	#[test]
+	fn panicking_request_handler_refuses_the_request() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let panicked = Arc::new(AtomicBool::new(false));
+		let on_request = Arc::new({
+			let panicked = panicked.clone();
+			move |_: &str| {
+				if !panicked.swap(true, Ordering::SeqCst) {
+					panic!("the request handler panicked");
+				}
+				RequestAction::ServeStored
+			}
+		});
+		let config = WriteConfig {
+			selection: LinuxClipboardKind::Secondary,
+			on_request: Some(on_request),
+			..WriteConfig::default()
+		};
+		clipboard.set_text("stored".into(), &config).unwrap();
+
+		// Only the request the handler panicked on is refused, the selection keeps being served.
+		assert_eq!(requestor.request("SECONDARY", "UTF8_STRING"), None);
+		assert!(panicked.load(Ordering::SeqCst));
+		assert!(clipboard.is_healthy());
+		let reply = requestor.request("SECONDARY", "UTF8_STRING");
+		assert_eq!(reply, Some(("UTF8_STRING".to_owned(), b"stored".to_vec())));
+	}
+
+	#[test]
+	fn paste_handler_counts_answered_requests() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let pastes = Arc::new(AtomicUsize::new(0));
+		let config = WriteConfig {
+			selection: LinuxClipboardKind::Secondary,
+			on_paste: Some(Arc::new({
+				let pastes = pastes.clone();
+				move || {
+					// A panicking handler neither stops the requests from being answered nor the
+					// handler from being called again.
+					if pastes.fetch_add(1, Ordering::SeqCst) == 0 {
+						panic!("the handler broke");
 					}
 				}
+			})),
+			..WriteConfig::default()
+		};
+		clipboard.set_text("counted".into(), &config).unwrap();
+
+		assert!(requestor.request("SECONDARY", "UTF8_STRING").is_some());
+		assert!(requestor.request("SECONDARY", "text/plain;charset=utf-8").is_some());
+		assert!(requestor.request("SECONDARY", "TARGETS").is_some());
+		assert!(requestor.request("SECONDARY", "image/png").is_none());
+		assert!(poll_until(|| pastes.load(Ordering::SeqCst) == 2));
+
+		// Streamed data counts once the transfer finished, not when it started.
+		let (release, blocked) = mpsc::channel::<()>();
+		let chunks = std::iter::once(()).map(move |()| {
+			let _ = blocked.recv();
+			b"streamed".to_vec()
+		});
+		clipboard.set_stream("text/csv", Box::new(chunks), &config).unwrap();
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let format = clipboard.inner.intern_atom("text/csv").unwrap();
+		std::thread::scope(|scope| {
+			let reading = scope.spawn(|| {
+				let reader = XContext::new().unwrap();
+				let mut stats = ReadStats::default();
+				clipboard.inner.read_single(&reader, &read_config, format, &mut stats)
+			});
+			assert!(poll_until(|| !clipboard.inner.incr_transfers.lock().is_empty()));
+			assert_eq!(pastes.load(Ordering::SeqCst), 2);
+			release.send(()).unwrap();
+			assert_eq!(reading.join().unwrap().unwrap(), b"streamed");
+		});
+		assert!(poll_until(|| pastes.load(Ordering::SeqCst) == 3));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn exclusion_lifecycle() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let secret = Some(("UTF8_STRING".to_owned(), b"hunter2".to_vec()));
+		let config = |selection, clear_after_wait| WriteConfig {
+			selection,
+			wait: WaitConfig::Until(Instant::now() + Duration::from_millis(200)),
+			history_exclusion: HistoryExclusion::Hinted,
+			clear_after_wait,
+			..WriteConfig::default()
+		};
+
+		// When the deadline passes, the data stays on the clipboard by default...
+		clipboard.set_text("hunter2".into(), &config(LinuxClipboardKind::Primary, false)).unwrap();
+		assert!(clipboard.inner.is_owner(LinuxClipboardKind::Primary).unwrap());
+		assert_eq!(requestor.request("PRIMARY", "UTF8_STRING"), secret);
+
+		// ...or is released with `clear_after_wait`.
+		clipboard.set_text("hunter2".into(), &config(LinuxClipboardKind::Primary, true)).unwrap();
+		assert!(!clipboard.inner.is_owner(LinuxClipboardKind::Primary).unwrap());
+		assert_eq!(requestor.request("PRIMARY", "UTF8_STRING"), None);
+
+		// Data that replaced ours before the deadline is left alone.
+		std::thread::scope(|scope| {
+			scope.spawn(|| {
+				let config = config(LinuxClipboardKind::Primary, true);
+				clipboard.set_text("hunter2".into(), &config).unwrap();
+			});
+			while requestor.request("PRIMARY", "UTF8_STRING") != secret {
+				std::thread::sleep(Duration::from_millis(1));
 			}
+			let config =
+				WriteConfig { selection: LinuxClipboardKind::Primary, ..Default::default() };
+			clipboard.set_text("replacement".into(), &config).unwrap();
+		});
+		let reply = requestor.request("PRIMARY", "UTF8_STRING");
+		assert_eq!(reply, Some(("UTF8_STRING".to_owned(), b"replacement".to_vec())));
+
+		// When the last `Clipboard` goes away, excluded data is released rather than handed over...
+		let config =
+			WriteConfig { history_exclusion: HistoryExclusion::Hinted, ..WriteConfig::default() };
+		clipboard.set_text("hunter2".into(), &config).unwrap();
+		clipboard.inner.ask_clipboard_manager_to_request_our_data().unwrap();
+		assert_eq!(clipboard.inner.owner_of(LinuxClipboardKind::Clipboard).unwrap(), NONE);
+
+		// ...unless it should keep being served for an idle timeout.
+		let config = WriteConfig { idle_timeout: Some(Duration::from_secs(1)), ..config };
+		clipboard.set_text("hunter2".into(), &config).unwrap();
+		clipboard.inner.ask_clipboard_manager_to_request_our_data().unwrap();
+		assert!(clipboard.inner.is_owner(LinuxClipboardKind::Clipboard).unwrap());
+		assert_eq!(requestor.request("CLIPBOARD", "UTF8_STRING"), secret);
+		clipboard.inner.clear(LinuxClipboardKind::Clipboard).unwrap();
+	}
+
+	#[test]
+	fn idle_timeout_keeps_serving_after_the_last_clipboard_without_handing_over() {
+		let clipboard = Clipboard::new_isolated().unwrap();
+		let inner = Arc::clone(&clipboard.inner);
+		let manager = Requestor::new();
+		let conn = &manager.context.conn;
+		let manager_selection = manager.atom("ARBOARD_TEST_IDLE_MANAGER");
+		conn.set_selection_owner(manager.context.win_id, manager_selection, Time::CURRENT_TIME)
+			.unwrap();
+		conn.flush().unwrap();
+
+		let config = WriteConfig {
+			manager_selection: Some("ARBOARD_TEST_IDLE_MANAGER".to_owned()),
+			idle_timeout: Some(Duration::from_millis(500)),
+			..WriteConfig::default()
+		};
+		clipboard.set_text("lingering".into(), &config).unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..config };
+		clipboard.set_text("lingering".into(), &config).unwrap();
+		drop(clipboard);
+
+		// The selection is still served after the last `Clipboard` is gone...
+		let reply = manager.request("SECONDARY", "UTF8_STRING");
+		assert_eq!(reply, Some(("UTF8_STRING".to_owned(), b"lingering".to_vec())));
+
+		// ...until it went unrequested for long enough, after which the server thread stops.
+		assert!(poll_until(|| inner.serve_stopped.load(Ordering::Relaxed)));
+		assert!(inner.clipboard.data.read().is_none());
+		assert!(inner.secondary.data.read().is_none());
+
+		// The clipboard manager was never asked to save the lingering data.
+		while let Some(event) = conn.poll_for_event().unwrap() {
+			assert!(!matches!(event, Event::SelectionRequest(_)), "the data was handed over");
 		}
 	}
+
+	#[test]
+	fn handover_to_custom_manager_selection() {
+		let clipboard = Clipboard::new().unwrap();
+		// The requestor poses as a clipboard manager under a non-standard selection name.
+		let manager = Requestor::new();
+		let conn = &manager.context.conn;
+		let manager_selection = manager.atom("ARBOARD_TEST_MANAGER");
+		conn.set_selection_owner(manager.context.win_id, manager_selection, Time::CURRENT_TIME)
+			.unwrap();
+		conn.flush().unwrap();
+
+		let config = WriteConfig {
+			manager_selection: Some("ARBOARD_TEST_MANAGER".to_owned()),
+			..WriteConfig::default()
+		};
+		clipboard.set_text("saved".into(), &config).unwrap();
+		clipboard.inner.ask_clipboard_manager_to_request_our_data().unwrap();
+
+		let deadline = Instant::now() + Duration::from_secs(5);
+		let request = loop {
+			assert!(Instant::now() < deadline, "the manager was never asked to save the data");
+			match conn.poll_for_event().unwrap() {
+				Some(Event::SelectionRequest(event)) => break event,
+				Some(_) => {}
+				None => std::thread::sleep(Duration::from_millis(1)),
+			}
+		};
+		assert_eq!(request.selection, manager_selection);
+		assert_eq!(manager.atom_name(request.target), "SAVE_TARGETS");
+		assert!(clipboard.has_clipboard_manager().unwrap());
+
+		// Without an owner of its selection, there's no clipboard manager to hand over to.
+		conn.set_selection_owner(NONE, manager_selection, Time::CURRENT_TIME).unwrap();
+		conn.sync().unwrap();
+		assert!(!clipboard.has_clipboard_manager().unwrap());
+		clipboard.inner.clear(LinuxClipboardKind::Clipboard).unwrap();
+	}
+
+	#[test]
+	fn save_targets_lists_only_the_contents() {
+		let clipboard = Clipboard::new().unwrap();
+		let manager = Requestor::new();
+		let conn = &manager.context.conn;
+		let manager_selection = manager.atom("ARBOARD_TEST_SAVE_MANAGER");
+		conn.set_selection_owner(manager.context.win_id, manager_selection, Time::CURRENT_TIME)
+			.unwrap();
+		conn.flush().unwrap();
+
+		let config = WriteConfig {
+			manager_selection: Some("ARBOARD_TEST_SAVE_MANAGER".to_owned()),
+			text_aliases: true,
+			..WriteConfig::default()
+		};
+		clipboard.set_text("saved".into(), &config).unwrap();
+		// Leave enough time to answer like a clipboard manager would.
+		clipboard.set_timeouts(TimeoutConfig::default().handover(Duration::from_secs(5)));
+
+		let server_win = clipboard.inner.server.win_id;
+		std::thread::scope(|scope| {
+			scope.spawn(|| clipboard.inner.ask_clipboard_manager_to_request_our_data().unwrap());
+
+			let deadline = Instant::now() + Duration::from_secs(5);
+			let request = loop {
+				assert!(Instant::now() < deadline, "the manager was never asked to save the data");
+				match conn.poll_for_event().unwrap() {
+					Some(Event::SelectionRequest(event)) => break event,
+					Some(_) => {}
+					None => std::thread::sleep(Duration::from_millis(1)),
+				}
+			};
+			assert_eq!(request.requestor, server_win);
+
+			let reply = conn
+				.get_property(false, server_win, request.property, AtomEnum::ATOM, 0, 1024)
+				.unwrap()
+				.reply()
+				.unwrap();
+			let targets: Vec<_> =
+				reply.value32().unwrap().map(|target| manager.atom_name(target)).collect();
+			assert_eq!(targets, ["UTF8_STRING"]);
+
+			// Save the contents and report back, as a clipboard manager would.
+			assert!(manager.request("CLIPBOARD", "UTF8_STRING").is_some());
+			let notify = SelectionNotifyEvent {
+				response_type: SELECTION_NOTIFY_EVENT,
+				sequence: 0,
+				time: request.time,
+				requestor: server_win,
+				selection: manager_selection,
+				target: request.target,
+				property: request.property,
+			};
+			conn.send_event(false, server_win, EventMask::NO_EVENT, notify).unwrap();
+			conn.flush().unwrap();
+		});
+
+		// The list is cleaned up once the handover is done.
+		let reply = conn
+			.get_property(
+				false,
+				server_win,
+				manager.atom("ARBOARD_SAVE_TARGETS"),
+				AtomEnum::ANY,
+				0,
+				1,
+			)
+			.unwrap()
+			.reply()
+			.unwrap();
+		assert_eq!(reply.type_, u32::from(AtomEnum::NONE));
+		clipboard.set_timeouts(TimeoutConfig::default());
+		clipboard.inner.clear(LinuxClipboardKind::Clipboard).unwrap();
+	}
+
+	#[test]
+	fn rapid_sets_are_never_observed_half_done() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let values = [b"first".to_vec(), b"second".to_vec()];
+
+		for debounce in [None, Some(Duration::from_millis(50))] {
+			let config = WriteConfig {
+				selection: LinuxClipboardKind::Primary,
+				debounce,
+				..Default::default()
+			};
+			clipboard.set_text("first".into(), &config).unwrap();
+
+			let done = AtomicBool::new(false);
+			std::thread::scope(|scope| {
+				scope.spawn(|| {
+					for i in 0..1000 {
+						let text = if i % 2 == 0 { "second" } else { "first" };
+						clipboard.set_text(text.into(), &config).unwrap();
+					}
+					done.store(true, Ordering::Relaxed);
+				});
+				while !done.load(Ordering::Relaxed) {
+					let (_, bytes) = requestor.request("PRIMARY", "UTF8_STRING").unwrap();
+					assert!(values.contains(&bytes), "observed {bytes:?}");
+				}
+			});
+		}
+		clipboard.inner.clear(LinuxClipboardKind::Primary).unwrap();
+	}
+
+	#[test]
+	fn debounced_sets_take_back_a_lost_selection() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let config = WriteConfig {
+			selection: LinuxClipboardKind::Secondary,
+			debounce: Some(Duration::from_secs(60)),
+			..Default::default()
+		};
+		clipboard.set_text("first".into(), &config).unwrap();
+
+		// Stand in for the server thread not having processed the `SelectionClear` yet.
+		let selection = clipboard.inner.selection_of(LinuxClipboardKind::Secondary);
+		let data = selection.data.write();
+		let conn = &requestor.context.conn;
+		conn.set_selection_owner(
+			requestor.context.win_id,
+			requestor.atom("SECONDARY"),
+			Time::CURRENT_TIME,
+		)
+		.unwrap();
+		conn.sync().unwrap();
+		drop(data);
+
+		clipboard.set_text("second".into(), &config).unwrap();
+		assert!(clipboard.is_owner(LinuxClipboardKind::Secondary).unwrap());
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn coalesced_sets_advance_the_timestamp() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let config = WriteConfig {
+			selection: LinuxClipboardKind::Secondary,
+			debounce: Some(Duration::from_secs(60)),
+			..Default::default()
+		};
+		let timestamp = || {
+			let (reply_type, bytes) = requestor.request("SECONDARY", "TIMESTAMP").unwrap();
+			assert_eq!(reply_type, "INTEGER");
+			u32::from_ne_bytes(bytes[..4].try_into().unwrap())
+		};
+
+		clipboard.set_text("first".into(), &config).unwrap();
+		assert!(requestor.targets("SECONDARY").iter().any(|target| target == "TIMESTAMP"));
+		let first = timestamp();
+
+		// The server time only advances every millisecond, so the sets are repeated until it did.
+		assert!(poll_until(|| {
+			clipboard.set_text("second".into(), &config).unwrap();
+			timestamp() > first
+		}));
+		let reply = requestor.request("SECONDARY", "UTF8_STRING");
+		assert_eq!(reply, Some(("UTF8_STRING".to_owned(), b"second".to_vec())));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn interleaved_sets_and_gets_across_clipboards() {
+		let clipboards = [Clipboard::new().unwrap(), Clipboard::new().unwrap()];
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+
+		std::thread::scope(|scope| {
+			for (i, clipboard) in clipboards.iter().enumerate() {
+				let (config, read_config) = (&config, &read_config);
+				scope.spawn(move || {
+					for n in 0..500 {
+						clipboard.set_text(format!("{i}:{n}").into(), config).unwrap();
+						// Whichever `Clipboard` wrote last, the text is read from memory in full.
+						let text = clipboard.get_text(read_config).unwrap();
+						let (writer, count) = text.split_once(':').unwrap();
+						assert!(writer == "0" || writer == "1", "read {text:?}");
+						assert!(count.parse::<u32>().is_ok(), "read {text:?}");
+						assert_eq!(clipboard.last_read_stats().unwrap().chunks, 0);
+					}
+				});
+			}
+		});
+
+		// Both handles agree on the final contents.
+		let last = clipboards[0].get_text(&read_config).unwrap();
+		assert_eq!(clipboards[1].get_text(&read_config).unwrap(), last);
+		clipboards[0].clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn dropping_cloned_handles_keeps_the_server_running() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+
+		let clones: Vec<_> = (0..4).map(|_| clipboard.clone_handle()).collect();
+		assert!(clones.iter().all(|clone| Arc::ptr_eq(&clone.inner, &clipboard.inner)));
+		clones[0].set_text("cloned".into(), &config).unwrap();
+		drop(clones);
+
+		// The server thread still answers other applications for the remaining handle.
+		assert!(clipboard.is_healthy());
+		assert!(CLIPBOARD
+			.lock()
+			.as_ref()
+			.is_some_and(|global_cb| Arc::ptr_eq(&global_cb.inner, &clipboard.inner)));
+		let requestor = Requestor::new();
+		let (_, bytes) = requestor.request("SECONDARY", "UTF8_STRING").unwrap();
+		assert_eq!(bytes, b"cloned");
+		assert_eq!(clipboard.get_text(&read_config).unwrap(), "cloned");
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn isolated_clipboards_have_their_own_connection() {
+		let shared = Clipboard::new().unwrap();
+		let isolated = Clipboard::new_isolated().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+
+		assert!(!isolated.shares_connection(&shared));
+		assert!(isolated.shares_connection(&isolated.clone_handle()));
+		assert!(CLIPBOARD
+			.lock()
+			.as_ref()
+			.is_some_and(|global_cb| Arc::ptr_eq(&global_cb.inner, &shared.inner)));
+
+		// The other connection reads it from the isolated one like from another application.
+		isolated.set_text("isolated".into(), &config).unwrap();
+		assert_eq!(shared.get_text(&read_config).unwrap(), "isolated");
+		assert!(!shared.is_owner(LinuxClipboardKind::Secondary).unwrap());
+
+		// Dropping the last handle of the isolated clipboard stops its own server only, even when
+		// the handles are dropped at once.
+		let clones: Vec<_> = (0..4).map(|_| isolated.clone_handle()).collect();
+		drop(isolated);
+		assert!(clones[0].is_healthy());
+		assert_eq!(shared.get_text(&read_config).unwrap(), "isolated");
+		let barrier = std::sync::Barrier::new(clones.len());
+		std::thread::scope(|scope| {
+			for clone in clones {
+				let barrier = &barrier;
+				scope.spawn(move || {
+					barrier.wait();
+					drop(clone);
+				});
+			}
+		});
+		assert_eq!(shared.inner.owner_of(LinuxClipboardKind::Secondary).unwrap(), NONE);
+		assert!(shared.is_healthy());
+	}
+
+	#[test]
+	fn served_targets_are_added_to_the_selection() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		clipboard.set_text("text only".into(), &config).unwrap();
+
+		clipboard
+			.serve_target(LinuxClipboardKind::Secondary, "text/html", b"<i>first</i>".to_vec())
+			.unwrap();
+		clipboard
+			.serve_target(LinuxClipboardKind::Secondary, "text/html", b"<b>html</b>".to_vec())
+			.unwrap();
+
+		let requestor = Requestor::new();
+		assert_eq!(requestor.request("SECONDARY", "UTF8_STRING").unwrap().1, b"text only");
+		assert_eq!(requestor.request("SECONDARY", "text/html").unwrap().1, b"<b>html</b>");
+		assert_eq!(clipboard.get_text(&read_config).unwrap(), "text only");
+		assert_eq!(clipboard.get_html(&read_config).unwrap(), "<b>html</b>");
+
+		// Once the selection is lost, the target is placed on its own instead of next to the text.
+		let conn = &requestor.context.conn;
+		conn.set_selection_owner(
+			requestor.context.win_id,
+			requestor.atom("SECONDARY"),
+			Time::CURRENT_TIME,
+		)
+		.unwrap();
+		conn.sync().unwrap();
+		let selection = clipboard.inner.selection_of(LinuxClipboardKind::Secondary);
+		assert!(poll_until(|| selection.data.read().is_none()));
+		clipboard
+			.serve_target(LinuxClipboardKind::Secondary, "text/html", b"<u>alone</u>".to_vec())
+			.unwrap();
+		assert!(clipboard.is_owner(LinuxClipboardKind::Secondary).unwrap());
+		assert_eq!(requestor.request("SECONDARY", "text/html").unwrap().1, b"<u>alone</u>");
+		assert!(requestor.request("SECONDARY", "UTF8_STRING").is_none());
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	#[cfg(feature = "x11-interop")]
+	fn atoms_are_used_without_looking_up_their_names() {
+		let clipboard = Clipboard::new().unwrap();
+		assert_eq!(clipboard.well_known_atom("CLIPBOARD"), Some(clipboard.inner.atoms.CLIPBOARD));
+		assert_eq!(clipboard.well_known_atom("text/html"), Some(clipboard.inner.atoms.HTML));
+		assert_eq!(clipboard.well_known_atom("ARBOARD_CLIPBOARD"), None);
+
+		let target = clipboard.inner.intern_atom("application/x-arboard-test").unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		clipboard.set_atom(target, b"raw".to_vec(), &config).unwrap();
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		assert_eq!(clipboard.get_atom(target, &read_config).unwrap(), b"raw");
+
+		let requestor = Requestor::new();
+		let reply = requestor.request("SECONDARY", "application/x-arboard-test");
+		assert_eq!(reply, Some(("application/x-arboard-test".to_owned(), b"raw".to_vec())));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn text_is_read_from_the_configured_targets() {
+		let mut clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let offers = vec![
+			Offer::new(UTF8_STRING_TARGET.to_owned(), b"plain".to_vec()),
+			Offer::new("text/x-arboard-test".to_owned(), b"custom".to_vec()),
+		];
+		clipboard.set_offers(offers, &config).unwrap();
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		assert_eq!(clipboard.get_text(&read_config).unwrap(), "plain");
+
+		// Targets which aren't offered are skipped.
+		let targets = ["text/x-arboard-missing".to_owned(), "text/x-arboard-test".to_owned()];
+		clipboard.targets.text = Some(targets.to_vec());
+		assert_eq!(clipboard.get_text(&read_config).unwrap(), "custom");
+		assert_eq!(clipboard.get_text_truncated(&read_config, 3).unwrap(), ("cus".into(), true));
+
+		clipboard.targets.text = Some(vec!["text/x-arboard-missing".to_owned()]);
+		let result = clipboard.get_text(&read_config);
+		assert!(matches!(result, Err(Error::ContentNotAvailable { .. })), "{result:?}");
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn vanished_owners_are_told_apart() {
+		let error = |error_kind| X11Error {
+			error_kind,
+			error_code: 0,
+			sequence: 0,
+			bad_value: 0,
+			minor_opcode: 0,
+			major_opcode: 0,
+			extension_name: None,
+			request_name: None,
+		};
+		for kind in [ErrorKind::Window, ErrorKind::Atom] {
+			assert!(matches!(read_error(error(kind).into()), Error::OwnerLost));
+		}
+		assert!(matches!(read_error(error(ErrorKind::Alloc).into()), Error::Unknown { .. }));
+
+		// The window of an owner which quit is gone along with its connection.
+		let owner = XContext::new().unwrap();
+		let window = owner.win_id;
+		drop(owner);
+		let reader = XContext::new().unwrap();
+		let property = AtomEnum::PRIMARY.into();
+		let result =
+			get_whole_property(&reader.conn, window, property, AtomEnum::ANY.into(), 1, None);
+		assert!(matches!(result, Err(Error::OwnerLost)), "{result:?}");
+	}
+
+	#[test]
+	fn mime_parameters_survive_the_atom_round_trip() {
+		let clipboard = Clipboard::new().unwrap();
+		let inner = &clipboard.inner;
+		for mime in ["text/plain;charset=utf-8", "text/x-custom; charset=\"ISO-8859-1\""] {
+			let atom = inner.intern_atom(mime).unwrap();
+			assert_eq!(inner.atom_name(atom).unwrap(), mime);
+		}
+		let atom = inner.atoms.UTF8_MIME_0;
+		assert_eq!(inner.atom_name(atom).unwrap(), "text/plain;charset=utf-8");
+
+		// Other applications see the target exactly as it was given, too.
+		let target = "text/x-custom; charset=\"ISO-8859-1\"";
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		clipboard.set_text("text".into(), &config).unwrap();
+		clipboard.serve_target(LinuxClipboardKind::Secondary, target, b"caf\xe9".to_vec()).unwrap();
+		let requestor = Requestor::new();
+		let targets = requestor.targets("SECONDARY");
+		assert!(targets.iter().any(|served| served == target), "{targets:?}");
+		let reply = requestor.request("SECONDARY", target);
+		assert_eq!(reply, Some((target.to_owned(), b"caf\xe9".to_vec())));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn waiting_on_a_selection_ignores_other_selections() {
+		let clipboards = [Clipboard::new().unwrap(), Clipboard::new().unwrap()];
+		let config = WriteConfig {
+			selection: LinuxClipboardKind::Secondary,
+			wait: WaitConfig::Forever,
+			..Default::default()
+		};
+		let other = WriteConfig { selection: LinuxClipboardKind::Clipboard, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+
+		let done = AtomicBool::new(false);
+		std::thread::scope(|scope| {
+			scope.spawn(|| {
+				clipboards[0].set_text("waiting".into(), &config).unwrap();
+				done.store(true, Ordering::SeqCst);
+			});
+			assert!(poll_until(|| clipboards[1]
+				.get_text(&read_config)
+				.is_ok_and(|text| text == "waiting")));
+
+			// Setting and clearing another selection, from another handle, doesn't wake the set.
+			for _ in 0..20 {
+				clipboards[1].set_text("other".into(), &other).unwrap();
+				clipboards[1].clear(LinuxClipboardKind::Clipboard).unwrap();
+			}
+			assert!(!done.load(Ordering::SeqCst));
+			assert_eq!(clipboards[1].get_text(&read_config).unwrap(), "waiting");
+
+			// Clearing the selection it waits on does.
+			clipboards[1].clear(LinuxClipboardKind::Secondary).unwrap();
+		});
+		assert!(done.load(Ordering::SeqCst));
+	}
+
+	#[test]
+	fn waiting_sets_on_different_selections_wake_independently() {
+		let clipboards = [Clipboard::new().unwrap(), Clipboard::new().unwrap()];
+		let kinds = [LinuxClipboardKind::Secondary, LinuxClipboardKind::Clipboard];
+		let woken = [AtomicBool::new(false), AtomicBool::new(false)];
+		let read =
+			|kind| clipboards[0].get_text(&ReadConfig { selection: kind, ..Default::default() });
+
+		std::thread::scope(|scope| {
+			for ((clipboard, kind), woken) in clipboards.iter().zip(kinds).zip(&woken) {
+				scope.spawn(move || {
+					let config = WriteConfig {
+						selection: kind,
+						wait: WaitConfig::Forever,
+						..Default::default()
+					};
+					clipboard.set_text(format!("{kind:?}").into(), &config).unwrap();
+					woken.store(true, Ordering::SeqCst);
+				});
+			}
+			for kind in kinds {
+				assert!(poll_until(|| read(kind).is_ok_and(|text| text == format!("{kind:?}"))));
+			}
+
+			// Replacing the first selection only ends the set waiting on it.
+			let config = WriteConfig { selection: kinds[0], ..Default::default() };
+			clipboards[1].set_text("replaced".into(), &config).unwrap();
+			assert!(poll_until(|| woken[0].load(Ordering::SeqCst)));
+			assert!(!woken[1].load(Ordering::SeqCst));
+			assert_eq!(read(kinds[1]).unwrap(), format!("{:?}", kinds[1]));
+
+			// Clearing the second one from the other handle ends its set as well.
+			clipboards[0].clear(kinds[1]).unwrap();
+		});
+
+		assert!(woken.iter().all(|woken| woken.load(Ordering::SeqCst)));
+		clipboards[0].clear(kinds[0]).unwrap();
+	}
+
+	#[test]
+	fn lazy_data_is_produced_once_requested() {
+		use std::sync::atomic::AtomicUsize;
+
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let produced = Arc::new(AtomicUsize::new(0));
+		let produce = {
+			let produced = Arc::clone(&produced);
+			Box::new(move || {
+				produced.fetch_add(1, Ordering::SeqCst);
+				Ok(b"lazy".to_vec())
+			})
+		};
+		clipboard.set_lazy("text/csv", produce, &config).unwrap();
+		assert_eq!(produced.load(Ordering::SeqCst), 0);
+
+		// Skip taking our own contents from memory, like another application pasting them.
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let format = clipboard.inner.intern_atom("text/csv").unwrap();
+		let reader = XContext::new().unwrap();
+		let mut stats = ReadStats::default();
+		for _ in 0..2 {
+			let bytes =
+				clipboard.inner.read_single(&reader, &read_config, format, &mut stats).unwrap();
+			assert_eq!(bytes, b"lazy");
+		}
+		assert_eq!(clipboard.read(&[format], &read_config).unwrap().bytes, b"lazy");
+		assert_eq!(produced.load(Ordering::SeqCst), 1);
+
+		// Data which fails to be produced is refused.
+		let produce = Box::new(|| Err(Error::unknown("failed")));
+		clipboard.set_lazy("text/csv", produce, &config).unwrap();
+		let result = clipboard.inner.read_single(&reader, &read_config, format, &mut stats);
+		assert!(matches!(result, Err(Error::ContentNotAvailable { .. })));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn slow_and_panicking_lazy_data_doesnt_hold_up_serving() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let (release, blocked) = mpsc::channel::<()>();
+		let produce = Box::new(move || -> Result<Vec<u8>> {
+			let _ = blocked.recv();
+			panic!("producing broke")
+		});
+		clipboard.set_lazy("text/csv", produce, &config).unwrap();
+
+		let format = clipboard.inner.intern_atom("text/csv").unwrap();
+		std::thread::scope(|scope| {
+			let reading = scope.spawn(|| {
+				let reader = XContext::new().unwrap();
+				let mut stats = ReadStats::default();
+				clipboard.inner.read_single(&reader, &read_config, format, &mut stats)
+			});
+
+			// Other requests are answered while the data is produced.
+			assert!(poll_until(|| !clipboard.inner.deferred_requests.lock().is_empty()));
+			let requestor = Requestor::new();
+			let (_, targets) = requestor.request("SECONDARY", "TARGETS").unwrap();
+			assert!(!targets.is_empty());
+
+			release.send(()).unwrap();
+			assert!(reading.join().unwrap().is_err());
+		});
+
+		// The panic failed the paste, not the thread serving the requests.
+		assert!(poll_until(|| clipboard.inner.deferred_requests.lock().is_empty()));
+		assert!(clipboard.is_healthy());
+		clipboard.set_text("still served".into(), &config).unwrap();
+		let requestor = Requestor::new();
+		let (_, bytes) = requestor.request("SECONDARY", "UTF8_STRING").unwrap();
+		assert_eq!(bytes, b"still served");
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn streamed_data_is_sent_incrementally() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		// An empty chunk must not end the transfer early, and a large one is split up.
+		let chunks = vec![b"first,".to_vec(), Vec::new(), vec![b'x'; INCR_SEGMENT_BYTES * 3 / 2]];
+		let expected = chunks.concat();
+		clipboard.set_stream("text/csv", Box::new(chunks.into_iter()), &config).unwrap();
+
+		// Reading it back would use it up.
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let format = clipboard.inner.intern_atom("text/csv").unwrap();
+		let result = clipboard.read(&[format], &read_config);
+		assert!(
+			matches!(result, Err(Error::ContentNotAvailable { tried }) if tried == ["text/csv"])
+		);
+
+		// Skip taking our own contents from memory, like another application pasting them.
+		let reader = XContext::new().unwrap();
+		let mut stats = ReadStats::default();
+		let bytes = clipboard.inner.read_single(&reader, &read_config, format, &mut stats).unwrap();
+		assert_eq!(bytes, expected);
+		assert!(stats.used_incr);
+		assert!(stats.chunks >= 3, "received {} chunks", stats.chunks);
+		assert!(clipboard.inner.incr_transfers.lock().is_empty());
+
+		// The data is gone once it was pasted.
+		let result = clipboard.inner.read_single(&reader, &read_config, format, &mut stats);
+		assert!(matches!(result, Err(Error::ContentNotAvailable { .. })));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn incr_segments_are_written_as_they_arrive() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let chunks = vec![b"first,".to_vec(), vec![b'x'; INCR_SEGMENT_BYTES * 3 / 2]];
+		let expected = chunks.concat();
+		clipboard.set_stream("text/csv", Box::new(chunks.into_iter()), &config).unwrap();
+
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let format = clipboard.inner.intern_atom("text/csv").unwrap();
+		let reader = XContext::new().unwrap();
+		let mut stats = ReadStats::default();
+		let mut written = Vec::new();
+		let mut sink = |segment: &[u8]| {
+			written.push(segment.to_vec());
+			Ok(())
+		};
+		let (rest, _) = clipboard
+			.inner
+			.read_single_into(&reader, &read_config, format, &mut stats, Some(&mut sink))
+			.unwrap();
+		assert!(rest.is_empty());
+		assert!(stats.used_incr);
+		assert!(written.iter().filter(|segment| !segment.is_empty()).count() >= 2);
+		assert_eq!(written.concat(), expected);
+
+		// Our own contents are written from memory.
+		clipboard.set_text("owned".into(), &config).unwrap();
+		let mut written = Vec::new();
+		let count = clipboard.write_to("UTF8_STRING", &read_config, &mut written).unwrap();
+		assert_eq!((count, &*written), (5, &b"owned"[..]));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn slow_and_panicking_streams_dont_hold_up_serving() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let (release, blocked) = mpsc::channel::<()>();
+		let slow = std::iter::once(()).map(move |()| {
+			let _ = blocked.recv();
+			b"slow".to_vec()
+		});
+		let broken = std::iter::once(()).map(|()| -> Vec<u8> { panic!("the stream broke") });
+		clipboard.set_stream("text/csv", Box::new(slow.chain(broken)), &config).unwrap();
+
+		let format = clipboard.inner.intern_atom("text/csv").unwrap();
+		std::thread::scope(|scope| {
+			let reading = scope.spawn(|| {
+				let reader = XContext::new().unwrap();
+				let mut stats = ReadStats::default();
+				clipboard.inner.read_single(&reader, &read_config, format, &mut stats)
+			});
+
+			// Other requests are answered while the iterator is stuck.
+			let requestor = Requestor::new();
+			assert!(poll_until(|| !clipboard.inner.incr_transfers.lock().is_empty()));
+			let (_, targets) = requestor.request("SECONDARY", "TARGETS").unwrap();
+			assert!(!targets.is_empty());
+
+			release.send(()).unwrap();
+			assert!(reading.join().unwrap().is_err());
+		});
+
+		// The panic ended the transfer, not the thread serving the requests.
+		assert!(poll_until(|| clipboard.inner.incr_transfers.lock().is_empty()));
+		assert!(clipboard.is_healthy());
+		clipboard.set_text("still served".into(), &config).unwrap();
+		let requestor = Requestor::new();
+		let (_, bytes) = requestor.request("SECONDARY", "UTF8_STRING").unwrap();
+		assert_eq!(bytes, b"still served");
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn reply_types_are_told() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		clipboard.set_text("typed".into(), &config).unwrap();
+
+		// The MIME aliases of the text are answered with the type `UTF8_STRING`.
+		let reader = XContext::new().unwrap();
+		let mut stats = ReadStats::default();
+		let mime = clipboard.inner.intern_atom("text/plain;charset=utf-8").unwrap();
+		let any = ReadConfig { any_reply_type: true, ..read_config };
+		let (bytes, reply_type) =
+			clipboard.inner.read_single_into(&reader, &any, mime, &mut stats, None).unwrap();
+		assert_eq!(
+			(bytes.as_slice(), reply_type),
+			(&b"typed"[..], clipboard.inner.atoms.UTF8_STRING)
+		);
+
+		// Our own contents are labelled with the format they were placed in, and are read under
+		// the same aliases they are served under.
+		for target in ["UTF8_STRING", "TEXT", "text/plain;charset=utf-8"] {
+			let typed = clipboard.get_typed(target, &read_config).unwrap();
+			assert_eq!(typed, ("UTF8_STRING".to_owned(), b"typed".to_vec()));
+			let mut written = Vec::new();
+			assert_eq!(clipboard.write_to(target, &read_config, &mut written).unwrap(), 5);
+			assert_eq!(written, b"typed");
+		}
+		assert!(matches!(
+			clipboard.get_typed("image/png", &read_config),
+			Err(Error::ContentNotAvailable { .. })
+		));
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn abandoned_reads_dont_disturb_the_next_one() {
+		let clipboard = Clipboard::new().unwrap();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let chunks = vec![b"first,".to_vec(), vec![b'x'; INCR_SEGMENT_BYTES * 4]];
+		clipboard.set_stream("text/csv", Box::new(chunks.into_iter()), &config).unwrap();
+
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		let csv = clipboard.inner.intern_atom("text/csv").unwrap();
+		let reader = XContext::new().unwrap();
+		let mut stats = ReadStats::default();
+		// Like a caller which is cancelled as soon as the first segment arrived.
+		let mut sink = |segment: &[u8]| match segment.is_empty() {
+			true => Ok(()),
+			false => Err(Error::unknown("cancelled")),
+		};
+		let result = clipboard.inner.read_single_into(
+			&reader,
+			&read_config,
+			csv,
+			&mut stats,
+			Some(&mut sink),
+		);
+		assert!(matches!(result, Err(Error::Unknown { .. })));
+		assert!(stats.used_incr);
+
+		// The owner is still in the middle of the transfer, but the next read into the same
+		// window only sees its own data.
+		clipboard.set_text("fresh".into(), &config).unwrap();
+		let utf8 = clipboard.inner.atoms.UTF8_STRING;
+		for _ in 0..2 {
+			let bytes =
+				clipboard.inner.read_single(&reader, &read_config, utf8, &mut stats).unwrap();
+			assert_eq!(bytes, b"fresh");
+		}
+		assert_eq!(reader.abandoned.load(Ordering::Relaxed), 1);
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn unavailable_reads_tell_the_tried_targets() {
+		let clipboard = Clipboard::new().unwrap();
+		let read_config =
+			ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		clipboard
+			.set_text(
+				"no html".into(),
+				&WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() },
+			)
+			.unwrap();
+
+		let error = clipboard.get_html(&read_config).unwrap_err();
+		assert!(matches!(&error, Error::ContentNotAvailable { tried } if *tried == [HTML_MIME]));
+		assert!(error.to_string().ends_with("Tried: text/html"), "{error}");
+		clipboard.clear(LinuxClipboardKind::Secondary).unwrap();
+	}
+
+	#[test]
+	fn cleared_selection_is_not_handed_over() {
+		let clipboard = Clipboard::new().unwrap();
+		clipboard.set_text("stale".into(), &WriteConfig::default()).unwrap();
+		*clipboard.inner.handover_state.lock() = ManagerHandoverState::Finished;
+
+		clipboard.clear(LinuxClipboardKind::Clipboard).unwrap();
+		let selection = clipboard.inner.selection_of(LinuxClipboardKind::Clipboard);
+		assert!(selection.data.read().is_none());
+		assert!(selection.mutex.lock().is_none());
+		assert_eq!(*clipboard.inner.handover_state.lock(), ManagerHandoverState::Idle);
+
+		// Dropping the last `Clipboard` now doesn't ask the clipboard manager for anything.
+		clipboard.inner.ask_clipboard_manager_to_request_our_data().unwrap();
+		assert_eq!(*clipboard.inner.handover_state.lock(), ManagerHandoverState::Idle);
+	}
+
+	#[test]
+	fn serve_stops_when_shut_down_past_its_deadline_or_replaced() {
+		let selection = LinuxClipboardKind::Secondary;
+		let contents = || vec![(UTF8_STRING_TARGET.to_owned(), b"served".to_vec())];
+		let requestor = Requestor::new();
+		let served = || {
+			requestor
+				.request("SECONDARY", "UTF8_STRING")
+				.is_some_and(|(_, bytes)| bytes == b"served")
+		};
+
+		let (shutdown, shutdown_rx) = mpsc::channel();
+		let server = std::thread::spawn(move || {
+			Clipboard::new().unwrap().serve(contents(), selection, &shutdown_rx, None)
+		});
+		assert!(poll_until(served));
+		shutdown.send(()).unwrap();
+		assert_eq!(server.join().unwrap().unwrap(), ServeOutcome::Shutdown);
+
+		// Dropping the sender doesn't count as a signal.
+		let (shutdown, shutdown_rx) = mpsc::channel::<()>();
+		drop(shutdown);
+		let deadline = Instant::now() + Duration::from_millis(200);
+		let outcome =
+			Clipboard::new().unwrap().serve(contents(), selection, &shutdown_rx, Some(deadline));
+		assert_eq!(outcome.unwrap(), ServeOutcome::DeadlineReached);
+		assert!(Instant::now() >= deadline);
+
+		let (_shutdown, shutdown_rx) = mpsc::channel();
+		let server = std::thread::spawn(move || {
+			Clipboard::new().unwrap().serve(contents(), selection, &shutdown_rx, None)
+		});
+		assert!(poll_until(served));
+		let conn = &requestor.context.conn;
+		conn.set_selection_owner(
+			requestor.context.win_id,
+			requestor.atom("SECONDARY"),
+			Time::CURRENT_TIME,
+		)
+		.unwrap();
+		conn.sync().unwrap();
+		assert_eq!(server.join().unwrap().unwrap(), ServeOutcome::Replaced);
+	}
+
+	#[test]
+	fn trim_memory_releases_data_of_lost_selections() {
+		let clipboard = Clipboard::new().unwrap();
+		let requestor = Requestor::new();
+		let config = WriteConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+		clipboard.set_text("x".repeat(1 << 20).into(), &config).unwrap();
+		clipboard
+			.serve_target(LinuxClipboardKind::Secondary, "text/html", b"<i>x</i>".to_vec())
+			.unwrap();
+
+		// Nothing is released while the selection is still ours, but the list of data is
+		// trimmed to what it holds.
+		let selection = clipboard.inner.selection_of(LinuxClipboardKind::Secondary);
+		assert_eq!(clipboard.trim_memory().unwrap(), 0);
+		let data = selection.data.read();
+		assert!(data.as_ref().is_some_and(|data| data.capacity() == data.len()));
+		drop(data);
+
+		// Another connection takes the selection for real.
+		let conn = &requestor.context.conn;
+		conn.set_selection_owner(
+			requestor.context.win_id,
+			requestor.atom("SECONDARY"),
+			Time::CURRENT_TIME,
+		)
+		.unwrap();
+		conn.sync().unwrap();
+		assert!(poll_until(|| selection.data.read().is_none()));
+		assert!(!clipboard.is_owner(LinuxClipboardKind::Secondary).unwrap());
+
+		// Stand in for data whose loss the server thread hasn't processed yet.
+		let stale = ClipboardData {
+			bytes: vec![0; 1 << 20].into(),
+			format: clipboard.inner.atoms.UTF8_STRING,
+			advertised: true,
+			stream: None,
+			lazy: None,
+		};
+		*selection.data.write() = Some(vec![stale]);
+		assert_eq!(clipboard.trim_memory().unwrap(), 1 << 20);
+		assert!(selection.data.read().is_none());
+	}
+
+	#[test]
+	fn capture_content_formats_leave_out_the_exclusion_hint() {
+		use crate::{ClearExtLinux, ClipboardExtLinux, SetExtLinux};
+
+		let mut clipboard = crate::Clipboard::new().unwrap();
+		let selection = LinuxClipboardKind::Secondary;
+		clipboard.set().clipboard(selection).exclude_from_history().text("hunter2").unwrap();
+		let excluded = clipboard.capture(selection).unwrap();
+		assert!(excluded.formats.iter().any(|(format, _)| format == KDE_EXCLUSION_MIME));
+		assert!(excluded.is_excluded_from_history());
+		let formats: Vec<_> =
+			excluded.content_formats().map(|(format, _)| format.as_str()).collect();
+		assert_eq!(formats, [UTF8_STRING_TARGET]);
+
+		clipboard.set().clipboard(selection).text("public").unwrap();
+		let public = clipboard.capture(selection).unwrap();
+		assert!(!public.is_excluded_from_history());
+		assert_eq!(public.content_formats().count(), public.formats.len());
+		clipboard.clear_with().clipboard(selection).unwrap();
+	}
+
+	#[test]
+	fn get_text_timeout_gives_up_on_a_silent_owner() {
+		let mut clipboard = crate::Clipboard::new().unwrap();
+		clipboard.set_text("answered").unwrap();
+		assert_eq!(clipboard.get_text_timeout(Duration::from_secs(5)).unwrap(), "answered");
+
+		// The requestor takes the selection but never answers requests for it.
+		let owner = Requestor::new();
+		let conn = &owner.context.conn;
+		conn.set_selection_owner(owner.context.win_id, owner.atom("CLIPBOARD"), Time::CURRENT_TIME)
+			.unwrap();
+		conn.sync().unwrap();
+
+		let start = Instant::now();
+		let result = clipboard.get_text_timeout(Duration::from_millis(300));
+		assert!(matches!(result, Err(Error::Timeout)), "{result:?}");
+		assert!(start.elapsed() < Duration::from_secs(2));
+	}
+
+	#[test]
+	fn unanswered_conversion_is_sent_again_despite_other_events() {
+		let clipboard = Clipboard::new_isolated().unwrap();
+		clipboard.set_timeouts(
+			TimeoutConfig::default()
+				.read(Duration::from_secs(5))
+				.convert_retry(Duration::from_millis(100)),
+		);
+		let owner = Requestor::new();
+		let conn = &owner.context.conn;
+		conn.set_selection_owner(owner.context.win_id, owner.atom("SECONDARY"), Time::CURRENT_TIME)
+			.unwrap();
+		conn.sync().unwrap();
+
+		std::thread::scope(|scope| {
+			let reading = scope.spawn(|| {
+				let config =
+					ReadConfig { selection: LinuxClipboardKind::Secondary, ..Default::default() };
+				clipboard.get_text(&config)
+			});
+
+			let deadline = Instant::now() + Duration::from_secs(5);
+			let next_request = || loop {
+				assert!(Instant::now() < deadline, "the request was never sent again");
+				match conn.poll_for_event().unwrap() {
+					Some(Event::SelectionRequest(event)) => break event,
+					Some(_) => {}
+					None => std::thread::sleep(Duration::from_millis(1)),
+				}
+			};
+
+			// The first request goes unanswered, while the reader keeps getting unrelated events.
+			let first = next_request();
+			let noise = owner.atom("ARBOARD_TEST_NOISE");
+			let request = loop {
+				assert!(Instant::now() < deadline, "the request was never sent again");
+				conn.change_property8(
+					PropMode::REPLACE,
+					first.requestor,
+					noise,
+					AtomEnum::INTEGER,
+					&[],
+				)
+				.unwrap();
+				conn.flush().unwrap();
+				if let Some(Event::SelectionRequest(event)) = conn.poll_for_event().unwrap() {
+					break event;
+				}
+			};
+			assert_eq!(request.target, first.target);
+
+			conn.change_property8(
+				PropMode::REPLACE,
+				request.requestor,
+				request.property,
+				request.target,
+				b"answered",
+			)
+			.unwrap();
+			let notify = SelectionNotifyEvent {
+				response_type: SELECTION_NOTIFY_EVENT,
+				sequence: 0,
+				time: request.time,
+				requestor: request.requestor,
+				selection: request.selection,
+				target: request.target,
+				property: request.property,
+			};
+			conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify).unwrap();
+			conn.flush().unwrap();
+
+			assert_eq!(reading.join().unwrap().unwrap(), "answered");
+		});
+	}
 }
@@ -9,33 +9,79 @@ and conditions of the chosen license apply to this file.
 */
 
 #[cfg(feature = "image-data")]
-use crate::common::ImageData;
-use crate::common::{private, Error};
+use crate::common::{decode_image, encode_images, from_image_error, ImageEncoding, THUMBNAIL_MIME};
+#[cfg(feature = "image-data-png")]
+use crate::common::{encode_png, ImageData, ImageOrBytes, DEFAULT_MAX_IMAGE_PIXELS};
+use crate::common::{
+	if_available, poll_for_change, private, text_or_html_stripped, truncate_text, write_contents,
+	Arrivals, Chunks, ClipboardText, ConversionStage, Diagnostics, Error, FileOp, Format, Recorder,
+	SetReport, TextSource,
+};
 use objc2::{
 	msg_send,
 	rc::{autoreleasepool, Retained},
 	runtime::ProtocolObject,
-	ClassType,
+	AllocAnyThread, ClassType,
 };
 use objc2_app_kit::{
-	NSPasteboard, NSPasteboardTypeHTML, NSPasteboardTypeString,
-	NSPasteboardURLReadingFileURLsOnlyKey,
+	NSAttributedStringAppKitDocumentFormats, NSPasteboard, NSPasteboardItem, NSPasteboardTypeHTML,
+	NSPasteboardTypeRTF, NSPasteboardTypeString, NSPasteboardURLReadingFileURLsOnlyKey,
+};
+use objc2_foundation::{
+	ns_string, NSArray, NSAttributedString, NSData, NSDictionary, NSInteger, NSNumber, NSString,
+	NSURL,
 };
-use objc2_foundation::{ns_string, NSArray, NSDictionary, NSNumber, NSString, NSURL};
 use std::{
 	borrow::Cow,
+	io::Write,
 	panic::{RefUnwindSafe, UnwindSafe},
 	path::{Path, PathBuf},
+	ptr::NonNull,
+	time::{Duration, Instant},
 };
 
+/// Converts an RTF document to the plain text it shows.
+fn plain_text_from_rtf(rtf: &NSData) -> Result<String, Error> {
+	let string = unsafe {
+		NSAttributedString::initWithRTF_documentAttributes(NSAttributedString::alloc(), rtf, None)
+	}
+	.ok_or_else(|| {
+		Error::conversion(Format::Rtf, ConversionStage::Decode, "the RTF document couldn't be read")
+	})?;
+	Ok(string.string().to_string())
+}
+
+/// Creates an `NSString` holding exactly `text`.
+///
+/// `NSString::from_str` decodes UTF-8, which drops a byte order mark at the start of the text, so
+/// the text is handed over as UTF-16 instead.
+fn ns_string_exact(text: &str) -> Retained<NSString> {
+	let units: Vec<u16> = text.encode_utf16().collect();
+	let characters = NonNull::from(units.as_slice()).cast();
+	unsafe { NSString::initWithCharacters_length(NSString::alloc(), characters, units.len()) }
+}
+
+/// Reads the string of the given type from `item`.
+///
+/// Plain text is decoded from its UTF-8 data when that is valid, since `stringForType` drops a
+/// byte order mark at its start as well.
+fn item_string(item: &NSPasteboardItem, type_: &NSString) -> Option<Retained<NSString>> {
+	if type_ == unsafe { NSPasteboardTypeString } {
+		let data = unsafe { item.dataForType(type_) };
+		if let Some(Ok(text)) = data.map(|data| String::from_utf8(data.to_vec())) {
+			return Some(ns_string_exact(&text));
+		}
+	}
+	unsafe { item.stringForType(type_) }
+}
+
 /// Returns an NSImage object on success.
-#[cfg(feature = "image-data")]
+#[cfg(feature = "image-data-png")]
 fn image_from_pixels(
 	pixels: Vec<u8>,
 	width: usize,
 	height: usize,
 ) -> Retained<objc2_app_kit::NSImage> {
-	use objc2::AllocAnyThread;
 	use objc2_app_kit::NSImage;
 	use objc2_core_foundation::CGFloat;
 	use objc2_core_graphics::{
@@ -91,8 +137,37 @@ fn image_from_pixels(
 	unsafe { NSImage::initWithCGImage_size(NSImage::alloc(), &cg_image, size) }
 }
 
+/// The type images are read from the pasteboard as.
+#[cfg(feature = "image-data")]
+fn image_type() -> &'static NSString {
+	unsafe { objc2_app_kit::NSPasteboardTypeTIFF }
+}
+
+#[cfg(feature = "image-data")]
+fn decode_pasteboard_image(bytes: &[u8], max_pixels: u64) -> Result<ImageData<'static>, Error> {
+	let decoder = image::codecs::tiff::TiffDecoder::new(std::io::Cursor::new(bytes))
+		.map_err(|error| from_image_error(error, Format::Tiff))?;
+	decode_image(decoder, Format::Tiff, max_pixels)
+}
+
+/// Without the `image` crate to decode TIFF, images are read as PNG, which `Set::image` places
+/// next to the `NSImage` then.
+#[cfg(all(feature = "image-data-png", not(feature = "image-data")))]
+fn image_type() -> &'static NSString {
+	unsafe { objc2_app_kit::NSPasteboardTypePNG }
+}
+
+#[cfg(all(feature = "image-data-png", not(feature = "image-data")))]
+fn decode_pasteboard_image(bytes: &[u8], max_pixels: u64) -> Result<ImageData<'static>, Error> {
+	crate::common::decode_png(bytes, max_pixels)
+}
+
 pub(crate) struct Clipboard {
 	pasteboard: Retained<NSPasteboard>,
+	/// The pasteboard's change count since contents were last placed through this `Clipboard`.
+	placed: Option<NSInteger>,
+	/// When the contents with each change count were first noticed.
+	arrivals: Arrivals<NSInteger>,
 }
 
 unsafe impl Send for Clipboard {}
@@ -115,14 +190,30 @@ impl Clipboard {
 			unsafe { msg_send![NSPasteboard::class(), generalPasteboard] };
 
 		if let Some(pasteboard) = pasteboard {
-			Ok(Clipboard { pasteboard })
+			let arrivals = Arrivals::new(unsafe { pasteboard.changeCount() });
+			Ok(Clipboard { pasteboard, placed: None, arrivals })
 		} else {
 			Err(Error::ClipboardNotSupported)
 		}
 	}
 
+	/// Clears the pasteboard, taking ownership of it to place new contents.
 	fn clear(&mut self) {
-		unsafe { self.pasteboard.clearContents() };
+		// The change count is only increased by clearing, not by the writes that follow.
+		let change_count = unsafe { self.pasteboard.clearContents() };
+		self.placed = Some(change_count);
+		self.arrivals.placed(change_count);
+	}
+
+	pub(crate) fn diagnose(&self, diagnostics: &mut Diagnostics) {
+		diagnostics.backend = "NSPasteboard".into();
+		diagnostics.backend_reason = "the only backend on macOS".into();
+	}
+
+	/// The age of the contents, which is only known if they were placed through this `Clipboard`
+	/// or have changed since it was created.
+	pub(crate) fn content_age(&mut self) -> Result<Option<Duration>, Error> {
+		Ok(self.arrivals.age(unsafe { self.pasteboard.changeCount() }))
 	}
 
 	fn string_from_type(&self, type_: &'static NSString) -> Result<String, Error> {
@@ -136,12 +227,97 @@ impl Clipboard {
 				.ok_or_else(|| Error::unknown("NSPasteboard#pasteboardItems errored"))?;
 
 			for item in contents {
-				if let Some(string) = unsafe { item.stringForType(type_) } {
+				if let Some(string) = item_string(&item, type_) {
 					return Ok(string.to_string());
 				}
 			}
 
-			Err(Error::ContentNotAvailable)
+			Err(Error::content_not_available())
+		})
+	}
+
+	/// Reads the first plain text string, or the text of the first RTF document when there is
+	/// none. Some editors, like TextEdit depending on its settings, only place rich text.
+	fn text(&self) -> Result<String, Error> {
+		match unsafe { self.string_from_type(NSPasteboardTypeString) } {
+			Err(Error::ContentNotAvailable { .. }) => {}
+			text => return text,
+		}
+
+		autoreleasepool(|_| {
+			let contents = unsafe { self.pasteboard.pasteboardItems() }
+				.ok_or_else(|| Error::unknown("NSPasteboard#pasteboardItems errored"))?;
+
+			for item in contents {
+				if let Some(rtf) = unsafe { item.dataForType(NSPasteboardTypeRTF) } {
+					return plain_text_from_rtf(&rtf);
+				}
+			}
+
+			Err(Error::content_not_available())
+		})
+	}
+
+	/// Reads the start of the first string of the given type as at most `max_bytes` of UTF-8.
+	fn string_prefix_from_type(
+		&self,
+		type_: &'static NSString,
+		max_bytes: usize,
+	) -> Result<(String, bool), Error> {
+		autoreleasepool(|_| {
+			let contents = unsafe { self.pasteboard.pasteboardItems() }
+				.ok_or_else(|| Error::unknown("NSPasteboard#pasteboardItems errored"))?;
+
+			for item in contents {
+				let Some(string) = item_string(&item, type_) else {
+					continue;
+				};
+
+				// Every UTF-16 unit takes up at least one byte in UTF-8, so a longer string can be
+				// sliced before converting it, without splitting a surrogate pair.
+				let mut end = string.length().min(max_bytes.saturating_add(1));
+				let sliced = end < string.length();
+				if sliced
+					&& (0xD800..=0xDBFF).contains(&unsafe { string.characterAtIndex(end - 1) })
+				{
+					end -= 1;
+				}
+				let text = if sliced {
+					unsafe { string.substringToIndex(end) }.to_string()
+				} else {
+					string.to_string()
+				};
+
+				let (text, truncated) = truncate_text(text, max_bytes);
+				return Ok((text, truncated || sliced));
+			}
+
+			Err(Error::content_not_available())
+		})
+	}
+
+	/// Every item on the pasteboard, with the data of each of its types.
+	fn items(&self) -> Result<Vec<PasteboardItem>, Error> {
+		autoreleasepool(|_| {
+			let contents = unsafe { self.pasteboard.pasteboardItems() }
+				.ok_or_else(|| Error::unknown("NSPasteboard#pasteboardItems errored"))?;
+
+			let items = contents
+				.iter()
+				.map(|item| {
+					// Types whose data was promised by an application that can't provide it
+					// anymore are left out.
+					let types = unsafe { item.types() }
+						.iter()
+						.filter_map(|type_| {
+							let data = unsafe { item.dataForType(&type_) }?;
+							Some((type_.to_string(), data.to_vec()))
+						})
+						.collect();
+					PasteboardItem { types }
+				})
+				.collect();
+			Ok(items)
 		})
 	}
 
@@ -196,51 +372,172 @@ impl Clipboard {
 	// }
 }
 
+/// One of the items on the pasteboard, as returned by [`GetExtApple::items`].
+///
+/// Most applications place a single item, holding the same contents in several types. Some,
+/// like Finder when copying several files, place one item for each thing that was copied.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PasteboardItem {
+	/// The uniform type identifier of each type the item offers, together with its data.
+	pub types: Vec<(String, Vec<u8>)>,
+}
+
+impl PasteboardItem {
+	/// The data of the item under `uti`, if it offers that type.
+	pub fn get(&self, uti: &str) -> Option<&[u8]> {
+		self.types.iter().find(|(type_, _)| type_ == uti).map(|(_, data)| data.as_slice())
+	}
+}
+
+/// The configuration of a `Get`, which outlives it.
+#[derive(Clone)]
+pub(crate) struct GetConfig {
+	#[cfg(feature = "image-data-png")]
+	max_image_pixels: u64,
+}
+
 pub(crate) struct Get<'clipboard> {
 	clipboard: &'clipboard Clipboard,
+	/// The pasteboard's change count when `Get::wait` was called, and until when to wait for it
+	/// to change.
+	wait: Option<(NSInteger, Option<Instant>)>,
+	#[cfg(feature = "image-data-png")]
+	max_image_pixels: u64,
 }
 
 impl<'clipboard> Get<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard }
+		Self {
+			clipboard,
+			wait: None,
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: DEFAULT_MAX_IMAGE_PIXELS,
+		}
+	}
+
+	#[cfg_attr(not(feature = "image-data-png"), allow(unused_variables))]
+	pub(crate) fn with_config(clipboard: &'clipboard mut Clipboard, config: &GetConfig) -> Self {
+		Self {
+			clipboard,
+			wait: None,
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: config.max_image_pixels,
+		}
+	}
+
+	pub(crate) fn into_config(self) -> GetConfig {
+		GetConfig {
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: self.max_image_pixels,
+		}
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn max_image_pixels(mut self, max_pixels: u64) -> Self {
+		self.max_image_pixels = max_pixels;
+		self
+	}
+
+	// Reading doesn't wait for the application which placed the contents, so there is nothing a
+	// deadline could cut short.
+	pub(crate) fn deadline(self, _deadline: Instant) -> Self {
+		self
+	}
+
+	pub(crate) fn wait(mut self, deadline: Option<Instant>) -> Self {
+		let change_count = unsafe { self.clipboard.pasteboard.changeCount() };
+		self.wait = Some((change_count, deadline));
+		self
+	}
+
+	pub(crate) fn wait_for_change(mut self) -> Result<Self, Error> {
+		if let Some((change_count, deadline)) = self.wait.take() {
+			poll_for_change(deadline, || {
+				Ok(unsafe { self.clipboard.pasteboard.changeCount() } != change_count)
+			})?;
+		}
+		Ok(self)
 	}
 
 	pub(crate) fn text(self) -> Result<String, Error> {
-		unsafe { self.clipboard.string_from_type(NSPasteboardTypeString) }
+		self.clipboard.text()
+	}
+
+	pub(crate) fn text_or_html_stripped(self) -> Result<(String, TextSource), Error> {
+		let text = self.clipboard.text();
+		text_or_html_stripped(text, || unsafe {
+			self.clipboard.string_from_type(NSPasteboardTypeHTML)
+		})
+	}
+
+	pub(crate) fn text_truncated(self, max_bytes: usize) -> Result<(String, bool), Error> {
+		unsafe { self.clipboard.string_prefix_from_type(NSPasteboardTypeString, max_bytes) }
 	}
 
 	pub(crate) fn html(self) -> Result<String, Error> {
 		unsafe { self.clipboard.string_from_type(NSPasteboardTypeHTML) }
 	}
 
-	#[cfg(feature = "image-data")]
+	pub(crate) fn html_and_text(self) -> Result<(Option<String>, Option<String>), Error> {
+		let html = unsafe { self.clipboard.string_from_type(NSPasteboardTypeHTML) };
+		let text = unsafe { self.clipboard.string_from_type(NSPasteboardTypeString) };
+		Ok((if_available(html)?, if_available(text)?))
+	}
+
+	#[cfg(feature = "image-data-png")]
 	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
-		use objc2_app_kit::NSPasteboardTypeTIFF;
-		use std::io::Cursor;
+		self.image_or_bytes()?.into_image()
+	}
 
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image_or_bytes(self) -> Result<ImageOrBytes, Error> {
 		// XXX: There does not appear to be an alternative for obtaining images without the need for
 		// autorelease behavior.
-		let image = autoreleasepool(|_| {
-			let image_data = unsafe { self.clipboard.pasteboard.dataForType(NSPasteboardTypeTIFF) }
-				.ok_or(Error::ContentNotAvailable)?;
+		autoreleasepool(|_| {
+			let image_data = unsafe { self.clipboard.pasteboard.dataForType(image_type()) }
+				.ok_or_else(Error::content_not_available)?;
 
-			// SAFETY: The data is not modified while in use here.
-			let data = Cursor::new(unsafe { image_data.as_bytes_unchecked() });
+			// SAFETY: The data is not modified while in use here. It's only copied if it can't be
+			// decoded, so that reading an image doesn't copy it.
+			let bytes = unsafe { image_data.as_bytes_unchecked() };
+			Ok(ImageOrBytes::decode(
+				bytes,
+				|bytes| decode_pasteboard_image(bytes, self.max_image_pixels),
+				|| image_type().to_string(),
+			))
+		})
+	}
 
-			let reader = image::io::Reader::with_format(data, image::ImageFormat::Tiff);
-			reader.decode().map_err(|_| Error::ConversionFailure)
-		})?;
+	pub(crate) fn uti(self, uti: &str) -> Result<Vec<u8>, Error> {
+		autoreleasepool(|_| {
+			let data = unsafe { self.clipboard.pasteboard.dataForType(&NSString::from_str(uti)) }
+				.ok_or_else(Error::content_not_available)?;
+			Ok(data.to_vec())
+		})
+	}
 
-		let rgba = image.into_rgba8();
-		let (width, height) = rgba.dimensions();
+	/// The pasteboard hands out the data of a type as a whole, so it's written at once.
+	pub(crate) fn write_to(self, uti: &str, writer: &mut dyn Write) -> Result<u64, Error> {
+		write_contents(writer, &self.uti(uti)?)
+	}
 
-		Ok(ImageData {
-			width: width as usize,
-			height: height as usize,
-			bytes: rgba.into_raw().into(),
+	pub(crate) fn item_count(self) -> Result<usize, Error> {
+		autoreleasepool(|_| {
+			let contents = unsafe { self.clipboard.pasteboard.pasteboardItems() }
+				.ok_or_else(|| Error::unknown("NSPasteboard#pasteboardItems errored"))?;
+			Ok(contents.count())
 		})
 	}
 
+	pub(crate) fn items(self) -> Result<Vec<PasteboardItem>, Error> {
+		self.clipboard.items()
+	}
+
+	pub(crate) fn file_list_with_op(self) -> Result<(Vec<PathBuf>, FileOp), Error> {
+		Ok((self.file_list()?, FileOp::Copy))
+	}
+
 	pub(crate) fn file_list(self) -> Result<Vec<PathBuf>, Error> {
 		autoreleasepool(|_| {
 			let class_array = NSArray::from_slice(&[NSURL::class()]);
@@ -266,29 +563,84 @@ impl<'clipboard> Get<'clipboard> {
 						.collect::<Vec<_>>()
 				})
 				.filter(|file_list| !file_list.is_empty())
-				.ok_or(Error::ContentNotAvailable)
+				.ok_or_else(Error::content_not_available)
 		})
 	}
 }
 
+/// The configuration of a `Set`, which outlives it.
+#[derive(Clone)]
+pub(crate) struct SetConfig {
+	exclude_from_history: bool,
+}
+
 pub(crate) struct Set<'clipboard> {
 	clipboard: &'clipboard mut Clipboard,
 	exclude_from_history: bool,
+	/// The data to place under other types as well, given with `SetExtApple::uti`.
+	raw_types: Vec<(String, Vec<u8>)>,
+	validate_only: bool,
+	report: Recorder<'clipboard>,
 }
 
+// Every completing method converts the data before clearing the pasteboard, so that a failed
+// conversion leaves it untouched.
 impl<'clipboard> Set<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard, exclude_from_history: false }
+		Self {
+			clipboard,
+			exclude_from_history: false,
+			raw_types: Vec::new(),
+			validate_only: false,
+			report: Recorder::default(),
+		}
+	}
+
+	pub(crate) fn with_config(clipboard: &'clipboard mut Clipboard, config: &SetConfig) -> Self {
+		Self {
+			clipboard,
+			exclude_from_history: config.exclude_from_history,
+			raw_types: Vec::new(),
+			validate_only: false,
+			report: Recorder::default(),
+		}
+	}
+
+	pub(crate) fn into_config(self) -> SetConfig {
+		SetConfig { exclude_from_history: self.exclude_from_history }
+	}
+
+	pub(crate) fn validate_only(mut self) -> Self {
+		self.validate_only = true;
+		self
+	}
+
+	pub(crate) fn report_to<'a>(self, report: &'a mut SetReport) -> Set<'a>
+	where
+		'clipboard: 'a,
+	{
+		Set {
+			clipboard: self.clipboard,
+			exclude_from_history: self.exclude_from_history,
+			raw_types: self.raw_types,
+			validate_only: self.validate_only,
+			report: Recorder::new(report, self.exclude_from_history),
+		}
 	}
 
-	pub(crate) fn text(self, data: Cow<'_, str>) -> Result<(), Error> {
+	pub(crate) fn text<'a>(mut self, data: impl ClipboardText<'a>) -> Result<(), Error> {
+		let data = data.into_cow();
+		if self.validate_only {
+			return Ok(());
+		}
 		self.clipboard.clear();
 
-		let string_array = NSArray::from_retained_slice(&[ProtocolObject::from_retained(
-			NSString::from_str(&data),
-		)]);
+		let string_array =
+			NSArray::from_retained_slice(&[ProtocolObject::from_retained(ns_string_exact(&data))]);
 		let success = unsafe { self.clipboard.pasteboard.writeObjects(&string_array) };
+		self.report.format("public.utf8-plain-text", Some(data.len()));
 
+		let success = success && self.place_raw_types();
 		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
 
 		if success {
@@ -298,7 +650,54 @@ impl<'clipboard> Set<'clipboard> {
 		}
 	}
 
-	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
+	/// Whether nothing but the data itself would be placed, so that the pasteboard holding the
+	/// same data means it holds what would be placed.
+	pub(crate) fn places_only_data(&self) -> bool {
+		self.raw_types.is_empty() && !self.exclude_from_history
+	}
+
+	/// Whether the contents on the pasteboard are still the ones last placed through this
+	/// `Clipboard`.
+	pub(crate) fn is_owner(&mut self) -> Result<bool, Error> {
+		let change_count = unsafe { self.clipboard.pasteboard.changeCount() };
+		Ok(self.clipboard.placed == Some(change_count))
+	}
+
+	pub(crate) fn read_back(&mut self) -> Get<'_> {
+		Get::new(self.clipboard)
+	}
+
+	/// Places the data given with `SetExtApple::uti` next to what was just written, returning
+	/// whether all of it was placed.
+	fn place_raw_types(&mut self) -> bool {
+		for (uti, bytes) in std::mem::take(&mut self.raw_types) {
+			let data = NSData::with_bytes(&bytes);
+			let placed = unsafe {
+				self.clipboard.pasteboard.setData_forType(Some(&data), &NSString::from_str(&uti))
+			};
+			if !placed {
+				return false;
+			}
+			self.report.format(&uti, Some(bytes.len()));
+		}
+		true
+	}
+
+	pub(crate) fn text_bytes(self, utf8_bytes: Vec<u8>) -> Result<(), Error> {
+		let text = String::from_utf8(utf8_bytes).map_err(|error| {
+			Error::conversion(Format::Text, ConversionStage::Validate, error.to_string())
+		})?;
+		self.text(text)
+	}
+
+	pub(crate) fn html(
+		mut self,
+		html: Cow<'_, str>,
+		alt: Option<Cow<'_, str>>,
+	) -> Result<(), Error> {
+		if self.validate_only {
+			return Ok(());
+		}
 		self.clipboard.clear();
 		// Text goes to the clipboard as UTF-8 but may be interpreted as Windows Latin 1.
 		// This wrapping forces it to be interpreted as UTF-8.
@@ -313,16 +712,19 @@ impl<'clipboard> Set<'clipboard> {
 		// Make sure that we pass a pointer to the string and not the object itself.
 		let mut success =
 			unsafe { self.clipboard.pasteboard.setString_forType(&html_nss, NSPasteboardTypeHTML) };
+		self.report.format("public.html", Some(html.len()));
 		if success {
 			if let Some(alt_text) = alt {
-				let alt_nss = NSString::from_str(&alt_text);
+				let alt_nss = ns_string_exact(&alt_text);
 				// Similar to the primary string, we only want a pointer here too.
 				success = unsafe {
 					self.clipboard.pasteboard.setString_forType(&alt_nss, NSPasteboardTypeString)
 				};
+				self.report.format("public.utf8-plain-text", Some(alt_text.len()));
 			}
 		}
 
+		let success = success && self.place_raw_types();
 		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
 
 		if success {
@@ -332,16 +734,117 @@ impl<'clipboard> Set<'clipboard> {
 		}
 	}
 
-	#[cfg(feature = "image-data")]
+	/// Places already encoded data under the type matching its MIME type.
+	/// The chunks are collected up front, which the pasteboard requires.
+	pub(crate) fn stream(self, mime: &str, chunks: Chunks) -> Result<(), Error> {
+		self.encoded(mime, chunks.flatten().collect())
+	}
+
+	pub(crate) fn encoded(mut self, mime: &str, bytes: Vec<u8>) -> Result<(), Error> {
+		let uti = match mime {
+			"image/png" => "public.png",
+			"image/jpeg" => "public.jpeg",
+			"image/gif" => "com.compuserve.gif",
+			_ => "public.data",
+		};
+		if self.validate_only {
+			return Ok(());
+		}
+
+		self.clipboard.clear();
+
+		self.report.format(uti, Some(bytes.len()));
+		let bytes = NSData::with_bytes(&bytes);
+		let success = unsafe {
+			self.clipboard.pasteboard.setData_forType(Some(&bytes), &NSString::from_str(uti))
+		};
+
+		let success = success && self.place_raw_types();
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#setData:forType: returned false"))
+		}
+	}
+
+	#[cfg(feature = "image-data-png")]
 	pub(crate) fn image(self, data: ImageData) -> Result<(), Error> {
+		#[cfg(feature = "image-data")]
+		let encoded = Vec::new();
+		// The image is read back as PNG without the `image` crate, see `image_type`.
+		#[cfg(not(feature = "image-data"))]
+		let encoded = vec![("public.png", encode_png(&data)?)];
+		self.image_inner(data, encoded)
+	}
+
+	/// The pasteboard is written right away, so the image is encoded here, like with `image`.
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image_lazy(self, data: ImageData<'static>) -> Result<(), Error> {
+		self.image(data)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_thumbnail(
+		self,
+		data: ImageData,
+		thumbnail: ImageData,
+	) -> Result<(), Error> {
+		let thumbnail = encode_png(&thumbnail)?;
+		self.image_inner(data, vec![(THUMBNAIL_MIME, thumbnail)])
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_multi(
+		self,
+		data: ImageData,
+		encodings: &[ImageEncoding],
+	) -> Result<(), Error> {
+		let encoded = encode_images(&data, encodings)?
+			.into_iter()
+			.map(|(encoding, bytes)| {
+				let uti = match encoding {
+					ImageEncoding::Png => "public.png",
+					ImageEncoding::Bmp => "com.microsoft.bmp",
+					ImageEncoding::Jpeg => "public.jpeg",
+				};
+				(uti, bytes)
+			})
+			.collect();
+		self.image_inner(data, encoded)
+	}
+
+	/// Places `data` onto the pasteboard as an `NSImage`, together with already encoded copies
+	/// of it under the given types.
+	#[cfg(feature = "image-data-png")]
+	fn image_inner(mut self, data: ImageData, encoded: Vec<(&str, Vec<u8>)>) -> Result<(), Error> {
+		data.validate()?;
+		if self.validate_only {
+			return Ok(());
+		}
 		let pixels = data.bytes.into();
 		let image = image_from_pixels(pixels, data.width, data.height);
 
 		self.clipboard.clear();
 
 		let image_array = NSArray::from_retained_slice(&[ProtocolObject::from_retained(image)]);
-		let success = unsafe { self.clipboard.pasteboard.writeObjects(&image_array) };
+		let mut success = unsafe { self.clipboard.pasteboard.writeObjects(&image_array) };
+		// AppKit converts the `NSImage` itself.
+		self.report.format("public.tiff", None);
 
+		for (ty, bytes) in encoded {
+			if !success {
+				break;
+			}
+			self.report.format(ty, Some(bytes.len()));
+			let bytes = NSData::with_bytes(&bytes);
+			success = unsafe {
+				self.clipboard.pasteboard.setData_forType(Some(&bytes), &NSString::from_str(ty))
+			};
+		}
+
+		let success = success && self.place_raw_types();
 		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
 
 		if success {
@@ -353,9 +856,7 @@ impl<'clipboard> Set<'clipboard> {
 		}
 	}
 
-	pub(crate) fn file_list(self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
-		self.clipboard.clear();
-
+	pub(crate) fn file_list(mut self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
 		let uri_list = file_list
 			.iter()
 			.filter_map(|path| {
@@ -369,12 +870,22 @@ impl<'clipboard> Set<'clipboard> {
 			.collect::<Vec<_>>();
 
 		if uri_list.is_empty() {
-			return Err(Error::ConversionFailure);
+			return Err(Error::conversion(
+				Format::FileList,
+				ConversionStage::Validate,
+				"none of the paths exist",
+			));
+		}
+		if self.validate_only {
+			return Ok(());
 		}
 
+		self.clipboard.clear();
 		let objects = NSArray::from_retained_slice(&uri_list);
 		let success = unsafe { self.clipboard.pasteboard.writeObjects(&objects) };
+		self.report.format("public.file-url", None);
 
+		let success = success && self.place_raw_types();
 		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
 
 		if success {
@@ -396,8 +907,18 @@ impl<'clipboard> Clear<'clipboard> {
 
 	pub(crate) fn clear(self) -> Result<(), Error> {
 		self.clipboard.clear();
+		self.clipboard.placed = None;
 		Ok(())
 	}
+
+	pub(crate) fn clear_if_owner(self) -> Result<bool, Error> {
+		let change_count = unsafe { self.clipboard.pasteboard.changeCount() };
+		if self.clipboard.placed != Some(change_count) {
+			return Ok(false);
+		}
+		self.clear()?;
+		Ok(true)
+	}
 }
 
 fn add_clipboard_exclusions(clipboard: &mut Clipboard, exclude_from_history: bool) {
@@ -421,6 +942,25 @@ pub trait SetExtApple: private::Sealed {
 	///
 	/// See http://nspasteboard.org/ for details about the community standard.
 	fn exclude_from_history(self) -> Self;
+
+	/// Places `bytes` under the uniform type identifier `uti` as well, exactly as they are,
+	/// after the data of the completing method.
+	///
+	/// This can be called several times to place several types, which are placed in the order
+	/// they were given. They aren't kept by [`Set::into_config`](crate::Set::into_config).
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error, SetExtApple};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let state = br#"{"selection":[3,7]}"#.to_vec();
+	/// clipboard.set().uti("com.example.editor-state", state).text("copied text")?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn uti(self, uti: &str, bytes: Vec<u8>) -> Self;
 }
 
 impl SetExtApple for crate::Set<'_> {
@@ -428,4 +968,122 @@ impl SetExtApple for crate::Set<'_> {
 		self.platform.exclude_from_history = true;
 		self
 	}
+
+	fn uti(mut self, uti: &str, bytes: Vec<u8>) -> Self {
+		self.platform.raw_types.push((uti.to_owned(), bytes));
+		self
+	}
+}
+
+/// Apple-specific extensions to the [`Get`](crate::Get) builder.
+pub trait GetExtApple: private::Sealed {
+	/// Completes the "get" operation by fetching the data of the first item on the pasteboard
+	/// which offers the uniform type identifier `uti`, exactly as it is.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error, GetExtApple};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let pdf = clipboard.get().uti("com.adobe.pdf")?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if no item offers `uti`.
+	fn uti(self, uti: &str) -> Result<Vec<u8>, Error>;
+
+	/// Completes the "get" operation by counting the items on the pasteboard, which is `0` when
+	/// it's empty.
+	fn item_count(self) -> Result<usize, Error>;
+
+	/// Completes the "get" operation by fetching every item on the pasteboard, together with
+	/// the data of all types it offers.
+	///
+	/// This reads all of the data, which may make applications that only promised it convert
+	/// it. When only one type is needed, [`uti`](GetExtApple::uti) is cheaper. An empty
+	/// pasteboard has no items.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error, GetExtApple};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// for item in clipboard.get().items()? {
+	///     if let Some(url) = item.get("public.file-url") {
+	///         println!("{}", String::from_utf8_lossy(url));
+	///     }
+	/// }
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn items(self) -> Result<Vec<PasteboardItem>, Error>;
+}
+
+impl GetExtApple for crate::Get<'_> {
+	fn uti(self, uti: &str) -> Result<Vec<u8>, Error> {
+		self.platform.wait_for_change()?.uti(uti)
+	}
+
+	fn item_count(self) -> Result<usize, Error> {
+		self.platform.wait_for_change()?.item_count()
+	}
+
+	fn items(self) -> Result<Vec<PasteboardItem>, Error> {
+		self.platform.wait_for_change()?.items()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// What TextEdit places for a rich text selection, without its plain text alternative.
+	const RTF: &str = r"{\rtf1\ansi\ansicpg1252\cocoartf2761
+{\fonttbl\f0\fswiss\fcharset0 Helvetica;}
+{\colortbl;\red255\green255\blue255;}
+\pard\tx560\pardirnatural\partightenfactor0
+
+\f0\fs24 \cf0 Hello, {\b world}! caf\'e9}";
+
+	#[test]
+	fn text_falls_back_to_rtf() {
+		let rtf = NSData::with_bytes(RTF.as_bytes());
+		assert_eq!(plain_text_from_rtf(&rtf).unwrap(), "Hello, world! café");
+
+		let mut clipboard = Clipboard::new().unwrap();
+		clipboard.clear();
+		unsafe { clipboard.pasteboard.setData_forType(Some(&rtf), NSPasteboardTypeRTF) };
+		assert_eq!(Get::new(&mut clipboard).text().unwrap(), "Hello, world! café");
+
+		// Plain text is still preferred when both are present.
+		unsafe {
+			clipboard.pasteboard.setString_forType(ns_string!("plain"), NSPasteboardTypeString)
+		};
+		assert_eq!(Get::new(&mut clipboard).text().unwrap(), "plain");
+		clipboard.clear();
+	}
+
+	#[test]
+	fn items_hold_every_type() {
+		let mut clipboard = Clipboard::new().unwrap();
+		clipboard.clear();
+		assert_eq!(Get::new(&mut clipboard).item_count().unwrap(), 0);
+		assert!(Get::new(&mut clipboard).items().unwrap().is_empty());
+
+		let mut set = Set::new(&mut clipboard);
+		set.raw_types.push(("com.example.arboard".into(), b"raw".to_vec()));
+		set.text("text").unwrap();
+		assert_eq!(Get::new(&mut clipboard).uti("com.example.arboard").unwrap(), b"raw");
+
+		let items = Get::new(&mut clipboard).items().unwrap();
+		assert_eq!(items.len(), 1);
+		assert_eq!(items[0].get("com.example.arboard"), Some(&b"raw"[..]));
+		assert_eq!(items[0].get("public.utf8-plain-text"), Some(&b"text"[..]));
+		clipboard.clear();
+	}
 }
@@ -9,51 +9,241 @@ and conditions of the chosen license apply to this file.
 */
 
 #[cfg(feature = "image-data")]
-use crate::common::ImageData;
-use crate::common::{private, Error};
+use crate::common::{decode_image, from_image_error, ImageEncoding};
+#[cfg(feature = "image-data-png")]
+use crate::common::{
+	decode_png, encode_png, ImageData, ImageOrBytes, DEFAULT_MAX_IMAGE_PIXELS, THUMBNAIL_MIME,
+};
+use crate::{
+	common::{
+		if_available, poll_for_change, private, text_from_utf8, text_or_html_stripped,
+		truncate_text, write_contents, Arrivals, Chunks, ClipboardText, ConversionStage,
+		Diagnostics, Error, FileOp, Format, Recorder, ScopeGuard, SetReport, TextSource,
+	},
+	formats,
+};
 use std::{
 	borrow::Cow,
-	io,
+	ffi::OsString,
+	io::{self, Write},
 	marker::PhantomData,
-	os::windows::{fs::OpenOptionsExt, io::AsRawHandle},
+	num::NonZeroU32,
+	os::windows::{ffi::OsStringExt, fs::OpenOptionsExt, io::AsRawHandle},
 	path::{Path, PathBuf},
 	thread,
-	time::Duration,
+	time::{Duration, Instant},
 };
 use windows_sys::Win32::{
-	Foundation::{GetLastError, GlobalFree, HANDLE, HGLOBAL, POINT, S_OK},
+	Foundation::{GetLastError, GlobalFree, HANDLE, HGLOBAL},
+	Globalization::{
+		GetLocaleInfoW, MultiByteToWideChar, CP_ACP, LOCALE_IDEFAULTANSICODEPAGE,
+		LOCALE_RETURN_NUMBER,
+	},
 	Storage::FileSystem::{GetFinalPathNameByHandleW, FILE_FLAG_BACKUP_SEMANTICS, VOLUME_NAME_DOS},
 	System::{
 		DataExchange::SetClipboardData,
-		Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GHND},
-		Ole::CF_HDROP,
+		Memory::{GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GHND},
+		Ole::{CF_HDROP, CF_LOCALE},
 	},
-	UI::Shell::{PathCchStripPrefix, DROPFILES},
+	UI::Shell::PathCchStripPrefix,
 };
 
-#[cfg(feature = "image-data")]
+#[cfg(feature = "image-data-png")]
 mod image_data {
 	use super::*;
 	use crate::common::ScopeGuard;
+	#[cfg(feature = "image-data")]
 	use image::codecs::bmp::BmpDecoder;
-	use image::codecs::png::PngDecoder;
-	use image::codecs::png::PngEncoder;
-	use image::DynamicImage;
-	use image::ExtendedColorType;
-	use image::ImageDecoder;
-	use image::ImageEncoder;
 	use std::{convert::TryInto, mem::size_of, ptr::copy_nonoverlapping};
 	use windows_sys::Win32::{
+		Foundation::HWND,
 		Graphics::Gdi::{
-			DeleteObject, BITMAPV5HEADER, BI_BITFIELDS, BI_RGB, HGDIOBJ, LCS_GM_IMAGES,
+			CreateDIBitmap, DeleteObject, GetDC, GetDIBits, GetObjectW, ReleaseDC, BITMAP,
+			BITMAPINFO, BITMAPINFOHEADER, BITMAPV5HEADER, BI_BITFIELDS, BI_RGB, CBM_INIT,
+			DIB_RGB_COLORS, HBITMAP, HGDIOBJ, LCS_GM_IMAGES,
+		},
+		System::{
+			DataExchange::GetClipboardData,
+			Ole::{CF_BITMAP, CF_DIBV5},
 		},
-		System::Ole::CF_DIBV5,
 	};
 
+	/// A GDI bitmap, which is deleted when dropped unless it was handed over to the clipboard.
+	pub(super) struct OwnedBitmap(HBITMAP);
+
+	impl Drop for OwnedBitmap {
+		fn drop(&mut self) {
+			unsafe { DeleteObject(self.0 as HGDIOBJ) };
+		}
+	}
+
+	/// The header of a top-down, 32-bit bitmap without color masks, which is what GDI converts
+	/// from and to.
+	fn bgra_bitmap_info(width: i32, height: i32) -> BITMAPINFO {
+		BITMAPINFO {
+			bmiHeader: BITMAPINFOHEADER {
+				biSize: size_of::<BITMAPINFOHEADER>() as u32,
+				biWidth: width,
+				// A negative height means that the rows are stored from the top down.
+				biHeight: -height,
+				biPlanes: 1,
+				biBitCount: 32,
+				biCompression: BI_RGB,
+				biSizeImage: 0,
+				biXPelsPerMeter: 0,
+				biYPelsPerMeter: 0,
+				biClrUsed: 0,
+				biClrImportant: 0,
+			},
+			// SAFETY: The color table is unused for 32-bit bitmaps.
+			bmiColors: unsafe { std::mem::zeroed() },
+		}
+	}
+
+	/// Creates a device dependent bitmap of the image, for applications which only accept
+	/// `CF_BITMAP`.
+	pub(super) fn create_bitmap(image: &ImageData) -> Result<OwnedBitmap, Error> {
+		let info = bgra_bitmap_info(image.width as i32, image.height as i32);
+		let mut pixels = image.bytes.to_vec();
+		for pixel in pixels.chunks_exact_mut(4) {
+			pixel.swap(0, 2);
+		}
+
+		unsafe {
+			let hdc = GetDC(<HWND as ResultValue>::NULL);
+			if hdc.failure() {
+				return Err(last_error("Could not get the screen's device context"));
+			}
+			let _release = ScopeGuard::new(|| {
+				ReleaseDC(<HWND as ResultValue>::NULL, hdc);
+			});
+
+			let hbitmap = CreateDIBitmap(
+				hdc,
+				&info.bmiHeader,
+				CBM_INIT as u32,
+				pixels.as_ptr().cast(),
+				&info,
+				DIB_RGB_COLORS,
+			);
+			if hbitmap.failure() {
+				Err(last_error("CreateDIBitmap failed with error"))
+			} else {
+				Ok(OwnedBitmap(hbitmap))
+			}
+		}
+	}
+
+	/// Places a bitmap from `create_bitmap` onto the clipboard as `CF_BITMAP`.
+	///
+	/// The clipboard owns the bitmap once it was placed, otherwise it's deleted.
+	pub(super) fn add_cf_bitmap(
+		_open_clipboard: &OpenClipboard,
+		bitmap: OwnedBitmap,
+	) -> Result<(), Error> {
+		if unsafe { SetClipboardData(CF_BITMAP as u32, bitmap.0 as HANDLE) }.failure() {
+			Err(last_error("SetClipboardData failed with error"))
+		} else {
+			std::mem::forget(bitmap);
+			Ok(())
+		}
+	}
+
+	/// Reads the `CF_BITMAP` on the clipboard, for when it's offered without a DIB.
+	///
+	/// The bitmap belongs to the clipboard, so it's only read and never deleted.
+	pub(super) fn read_cf_bitmap(
+		_open_clipboard: &OpenClipboard,
+		max_pixels: u64,
+	) -> Result<ImageData<'static>, Error> {
+		let hbitmap = unsafe { GetClipboardData(CF_BITMAP as u32) } as HBITMAP;
+		if hbitmap.failure() {
+			return Err(Error::content_not_available());
+		}
+
+		let mut bitmap: BITMAP = unsafe { std::mem::zeroed() };
+		let size = size_of::<BITMAP>() as i32;
+		if unsafe { GetObjectW(hbitmap as HGDIOBJ, size, (&mut bitmap as *mut BITMAP).cast()) } == 0
+		{
+			return Err(last_error("Could not get the clipboard bitmap's size"));
+		}
+		let (width, height) = (bitmap.bmWidth, bitmap.bmHeight.abs());
+		if u64::from(width.unsigned_abs()) * u64::from(height.unsigned_abs()) > max_pixels {
+			return Err(Error::TooLarge);
+		}
+
+		let mut info = bgra_bitmap_info(width, height);
+		let mut pixels = vec![0u8; 4 * width as usize * height as usize];
+		unsafe {
+			let hdc = GetDC(<HWND as ResultValue>::NULL);
+			if hdc.failure() {
+				return Err(last_error("Could not get the screen's device context"));
+			}
+			let _release = ScopeGuard::new(|| {
+				ReleaseDC(<HWND as ResultValue>::NULL, hdc);
+			});
+
+			let lines = GetDIBits(
+				hdc,
+				hbitmap,
+				0,
+				height as u32,
+				pixels.as_mut_ptr().cast(),
+				&mut info,
+				DIB_RGB_COLORS,
+			);
+			if lines != height {
+				return Err(last_error("GetDIBits failed with error"));
+			}
+		}
+
+		// Device dependent bitmaps rarely carry an alpha channel, in which case it's left at zero.
+		let opaque = pixels.chunks_exact(4).all(|pixel| pixel[3] == 0);
+		for pixel in pixels.chunks_exact_mut(4) {
+			pixel.swap(0, 2);
+			if opaque {
+				pixel[3] = 255;
+			}
+		}
+
+		Ok(ImageData { width: width as usize, height: height as usize, bytes: pixels.into() })
+	}
+
+	/// Decodes an image file which `Set::auto` places, so that it can be placed as a bitmap as
+	/// well. `None` if files in `mime` aren't decoded.
+	pub(super) fn decode_file(
+		mime: &str,
+		bytes: &[u8],
+		max_pixels: u64,
+	) -> Option<Result<ImageData<'static>, Error>> {
+		match mime {
+			"image/png" => Some(decode_png(bytes, max_pixels)),
+			#[cfg(feature = "image-data")]
+			"image/jpeg" => Some(
+				image::codecs::jpeg::JpegDecoder::new(std::io::Cursor::new(bytes))
+					.map_err(|error| from_image_error(error, Format::Jpeg))
+					.and_then(|decoder| decode_image(decoder, Format::Jpeg, max_pixels)),
+			),
+			_ => None,
+		}
+	}
+
+	/// The size of the `CF_DIBV5` bitmap `image` is placed as.
+	pub(super) fn dib_size(image: &ImageData) -> usize {
+		size_of::<BITMAPV5HEADER>().saturating_add(image.bytes.len())
+	}
+
+	/// The most pixels an image may have to be placed as a `CF_DIBV5` of at most `max_bytes`.
+	pub(super) fn max_dib_pixels(max_bytes: usize) -> u64 {
+		let pixels = max_bytes.saturating_sub(size_of::<BITMAPV5HEADER>()) / 4;
+		u64::try_from(pixels).unwrap_or(u64::MAX).min(DEFAULT_MAX_IMAGE_PIXELS)
+	}
+
+	/// Places the image onto the clipboard as `CF_DIBV5`, returning the size of the bitmap.
 	pub(super) fn add_cf_dibv5(
-		_open_clipboard: OpenClipboard,
+		_open_clipboard: &OpenClipboard,
 		image: ImageData,
-	) -> Result<(), Error> {
+	) -> Result<usize, Error> {
 		// This constant is missing in windows-rs
 		// https://github.com/microsoft/windows-rs/issues/2711
 		#[allow(non_upper_case_globals)]
@@ -95,10 +285,10 @@ mod image_data {
 		let image = flip_v(image);
 
 		let data_size = header_size + image.bytes.len();
-		let hdata = unsafe { global_alloc(data_size)? };
+		let global = OwnedGlobal::alloc(data_size)?;
 		unsafe {
-			let data_ptr = global_lock(hdata)?;
-			let _unlock = ScopeGuard::new(|| global_unlock_checked(hdata));
+			let data_ptr = global_lock(global.0)?;
+			let _unlock = ScopeGuard::new(|| global_unlock_checked(global.0));
 
 			copy_nonoverlapping::<u8>(
 				(&header as *const BITMAPV5HEADER).cast(),
@@ -121,49 +311,21 @@ mod image_data {
 			}
 		}
 
-		if unsafe { SetClipboardData(CF_DIBV5 as u32, hdata as HANDLE) }.failure() {
-			unsafe { DeleteObject(hdata as HGDIOBJ) };
-			Err(last_error("SetClipboardData failed with error"))
-		} else {
-			Ok(())
-		}
+		global.set_clipboard_data(CF_DIBV5 as u32)?;
+		Ok(data_size)
 	}
 
-	pub(super) fn add_png_file(image: &ImageData) -> Result<(), Error> {
-		// Try encoding the image as PNG.
-		let mut buf = Vec::new();
-		let encoder = PngEncoder::new(&mut buf);
-
-		encoder
-			.write_image(
-				&image.bytes,
-				image.width as u32,
-				image.height as u32,
-				ExtendedColorType::Rgba8,
-			)
-			.map_err(|_| Error::ConversionFailure)?;
-
-		// Register PNG format.
-		let format_id = match clipboard_win::register_format("PNG") {
+	/// Places an already encoded image file onto the clipboard, under the registered format
+	/// `format_name`.
+	pub(super) fn add_file(buf: &[u8], format_name: &str) -> Result<(), Error> {
+		let format_id = match clipboard_win::register_format(format_name) {
 			Some(format_id) => format_id.into(),
-			None => return Err(last_error("Cannot register PNG clipboard format.")),
+			None => {
+				return Err(last_error(&format!("Cannot register {format_name} clipboard format.")))
+			}
 		};
 
-		let data_size = buf.len();
-		let hdata = unsafe { global_alloc(data_size)? };
-
-		unsafe {
-			let pixels_dst = global_lock(hdata)?;
-			copy_nonoverlapping::<u8>(buf.as_ptr(), pixels_dst, data_size);
-			global_unlock_checked(hdata);
-		}
-
-		if unsafe { SetClipboardData(format_id, hdata as HANDLE) }.failure() {
-			unsafe { DeleteObject(hdata as HGDIOBJ) };
-			Err(last_error("SetClipboardData failed with error"))
-		} else {
-			Ok(())
-		}
+		OwnedGlobal::with_bytes(buf)?.set_clipboard_data(format_id)
 	}
 
 	// https://learn.microsoft.com/en-us/windows/win32/api/wingdi/ns-wingdi-bitmapv5header
@@ -175,6 +337,7 @@ mod image_data {
 	//
 	// Apparently, it's our job as the consumer to do the right thing. This method fiddles
 	// with the header a bit in these cases, then `image` handles the rest.
+	#[cfg(feature = "image-data")]
 	fn maybe_tweak_header(dibv5: &mut [u8]) {
 		assert!(dibv5.len() >= size_of::<BITMAPV5HEADER>());
 		let src = dibv5.as_mut_ptr().cast::<BITMAPV5HEADER>();
@@ -195,7 +358,11 @@ mod image_data {
 		}
 	}
 
-	pub(super) fn read_cf_dibv5(dibv5: &mut [u8]) -> Result<ImageData<'static>, Error> {
+	#[cfg(feature = "image-data")]
+	pub(super) fn read_cf_dibv5(
+		dibv5: &mut [u8],
+		max_pixels: u64,
+	) -> Result<ImageData<'static>, Error> {
 		// The DIBV5 format is a BITMAPV5HEADER followed by the pixel data according to
 		// https://docs.microsoft.com/en-us/windows/win32/dataxchg/standard-clipboard-formats
 
@@ -206,27 +373,23 @@ mod image_data {
 		maybe_tweak_header(dibv5);
 
 		let decoder = BmpDecoder::new_without_file_header(std::io::Cursor::new(&*dibv5))
-			.map_err(|_| Error::ConversionFailure)?;
-		let (width, height) = decoder.dimensions();
-		let bytes = DynamicImage::from_decoder(decoder)
-			.map_err(|_| Error::ConversionFailure)?
-			.into_rgba8()
-			.into_raw();
-
-		Ok(ImageData { width: width as usize, height: height as usize, bytes: bytes.into() })
+			.map_err(|error| from_image_error(error, Format::Bitmap))?;
+		decode_image(decoder, Format::Bitmap, max_pixels)
 	}
 
-	pub(super) fn read_png(data: &[u8]) -> Result<ImageData<'static>, Error> {
-		let decoder =
-			PngDecoder::new(std::io::Cursor::new(data)).map_err(|_| Error::ConversionFailure)?;
-		let (width, height) = decoder.dimensions();
-
-		let bytes = DynamicImage::from_decoder(decoder)
-			.map_err(|_| Error::ConversionFailure)?
-			.into_rgba8()
-			.into_raw();
-
-		Ok(ImageData { width: width as usize, height: height as usize, bytes: bytes.into() })
+	/// Decodes `dibv5` like `read_cf_dibv5`, keeping the bytes as they were read if that fails.
+	#[cfg(feature = "image-data")]
+	pub(super) fn decode_cf_dibv5(mut dibv5: Vec<u8>, max_pixels: u64) -> ImageOrBytes {
+		// Only the header is fixed up in place, so it's all that has to be set aside.
+		let header_len = dibv5.len().min(size_of::<BITMAPV5HEADER>());
+		let header = dibv5[..header_len].to_vec();
+		match read_cf_dibv5(&mut dibv5, max_pixels) {
+			Ok(image) => ImageOrBytes::Image(image),
+			Err(error) => {
+				dibv5[..header_len].copy_from_slice(&header);
+				ImageOrBytes::Encoded { format: "CF_DIBV5".to_owned(), bytes: dibv5, error }
+			}
+		}
 	}
 
 	/// Converts the RGBA (u8) pixel data into the bitmap-native ARGB (u32)
@@ -348,6 +511,17 @@ mod image_data {
 		}
 	}
 
+	#[test]
+	fn dibs_are_held_to_the_format_limit() {
+		let header_size = size_of::<BITMAPV5HEADER>();
+		assert_eq!(max_dib_pixels(header_size + 400), 100);
+		assert_eq!(max_dib_pixels(0), 0);
+		assert_eq!(max_dib_pixels(usize::MAX), DEFAULT_MAX_IMAGE_PIXELS);
+
+		let image = ImageData { width: 10, height: 10, bytes: vec![0; 400].into() };
+		assert_eq!(dib_size(&image), header_size + 400);
+	}
+
 	#[test]
 	fn conversion_between_win_and_rgba() {
 		const DATA: [u8; 16] =
@@ -371,6 +545,7 @@ mod image_data {
 	}
 
 	#[test]
+	#[cfg(feature = "image-data")]
 	fn firefox_dibv5() {
 		// A 5x5 sample of https://commons.wikimedia.org/wiki/File:PNG_transparency_demonstration_1.png
 		let mut raw = vec![
@@ -386,7 +561,7 @@ mod image_data {
 		];
 
 		let before = raw.clone();
-		let image = read_cf_dibv5(&mut raw).unwrap();
+		let image = read_cf_dibv5(&mut raw, DEFAULT_MAX_IMAGE_PIXELS).unwrap();
 
 		// Not expecting any header fiddling to happen here. This is a bitmap in 24-bit format, with a header
 		// that says as much
@@ -407,6 +582,7 @@ mod image_data {
 	}
 
 	#[test]
+	#[cfg(feature = "image-data")]
 	fn chrome_dibv5() {
 		// A 5x5 sample of https://commons.wikimedia.org/wiki/File:PNG_transparency_demonstration_1.png
 		// (interestingly, the same sample as in the Firefox test - despite the pixel data being
@@ -425,7 +601,7 @@ mod image_data {
 		];
 
 		let before = raw.clone();
-		let image = read_cf_dibv5(&mut raw).unwrap();
+		let image = read_cf_dibv5(&mut raw, DEFAULT_MAX_IMAGE_PIXELS).unwrap();
 
 		// Chrome's header is dodgy. Expect that we fiddled with it.
 		assert_ne!(raw, before);
@@ -445,12 +621,81 @@ mod image_data {
 	}
 }
 
-unsafe fn global_alloc(bytes: usize) -> Result<HGLOBAL, Error> {
-	let hdata = GlobalAlloc(GHND, bytes);
-	if hdata.is_null() {
-		Err(last_error("Could not allocate global memory object"))
-	} else {
-		Ok(hdata)
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use windows_sys::Win32::System::{Memory::GlobalFlags, Ole::CF_UNICODETEXT};
+
+	// This constant is only exported under the `Win32_System_WindowsProgramming` feature.
+	const GMEM_INVALID_HANDLE: u32 = 0x8000;
+
+	#[test]
+	fn global_memory_is_freed_when_it_is_not_placed() {
+		for _ in 0..16 {
+			let global = OwnedGlobal::with_bytes(&[1; 64]).unwrap();
+			let handle = global.0;
+			// The clipboard isn't open on this thread, so the data can't be placed.
+			assert!(global.set_clipboard_data(CF_UNICODETEXT.into()).is_err());
+			assert_eq!(unsafe { GlobalFlags(handle) }, GMEM_INVALID_HANDLE);
+		}
+	}
+}
+
+/// The formats on the clipboard, in the order they were placed in.
+#[cfg(all(test, feature = "image-data-png"))]
+pub(crate) fn clipboard_formats() -> Vec<u32> {
+	let _clipboard = clipboard_win::Clipboard::new_attempts(10).unwrap();
+	clipboard_win::raw::EnumFormats::new().collect()
+}
+
+/// The number of GDI objects this process is using, for finding bitmaps that were leaked.
+#[cfg(all(test, feature = "image-data-png"))]
+pub(crate) fn gdi_objects() -> u32 {
+	use windows_sys::Win32::System::Threading::{
+		GetCurrentProcess, GetGuiResources, GR_GDIOBJECTS,
+	};
+	unsafe { GetGuiResources(GetCurrentProcess(), GR_GDIOBJECTS) }
+}
+
+/// A global memory object, which is freed when dropped unless it was handed over to the
+/// clipboard.
+struct OwnedGlobal(HGLOBAL);
+
+impl OwnedGlobal {
+	fn alloc(bytes: usize) -> Result<Self, Error> {
+		let hdata = unsafe { GlobalAlloc(GHND, bytes) };
+		if hdata.is_null() {
+			Err(last_error("Could not allocate global memory object"))
+		} else {
+			Ok(Self(hdata))
+		}
+	}
+
+	/// Allocates a global memory object holding a copy of `bytes`.
+	fn with_bytes(bytes: &[u8]) -> Result<Self, Error> {
+		let global = Self::alloc(bytes.len())?;
+		unsafe {
+			let data_ptr = global_lock(global.0)?;
+			std::ptr::copy_nonoverlapping(bytes.as_ptr(), data_ptr, bytes.len());
+			global_unlock_checked(global.0);
+		}
+		Ok(global)
+	}
+
+	/// Places the object onto the open clipboard, which owns it from then on.
+	fn set_clipboard_data(self, format: u32) -> Result<(), Error> {
+		if unsafe { SetClipboardData(format, self.0 as HANDLE) }.failure() {
+			Err(last_error("SetClipboardData failed with error"))
+		} else {
+			std::mem::forget(self);
+			Ok(())
+		}
+	}
+}
+
+impl Drop for OwnedGlobal {
+	fn drop(&mut self) {
+		unsafe { GlobalFree(self.0) };
 	}
 }
 
@@ -513,7 +758,12 @@ impl ResultValue for isize {
 /// open at once, so we have to open it very sparingly or risk causing the rest
 /// of the system to be unresponsive. Instead, the clipboard is opened for
 /// every operation and then closed afterwards.
-pub(crate) struct Clipboard(());
+pub(crate) struct Clipboard {
+	/// The clipboard's sequence number once contents were last placed through this `Clipboard`.
+	placed: Option<NonZeroU32>,
+	/// When the contents with each sequence number were first noticed.
+	arrivals: Arrivals<Option<NonZeroU32>>,
+}
 
 // The other platforms have `Drop` implementation on their
 // clipboard, so Windows should too for consistently.
@@ -529,11 +779,29 @@ struct OpenClipboard<'clipboard> {
 	_for_shim: &'clipboard mut Clipboard,
 }
 
+impl<'clipboard> OpenClipboard<'clipboard> {
+	/// Closes the clipboard, so that it can be opened again later.
+	fn close(self) -> &'clipboard mut Clipboard {
+		self._for_shim
+	}
+}
+
 impl Clipboard {
 	const DEFAULT_OPEN_ATTEMPTS: usize = 5;
 
 	pub(crate) fn new() -> Result<Self, Error> {
-		Ok(Self(()))
+		Ok(Self { placed: None, arrivals: Arrivals::new(clipboard_win::raw::seq_num()) })
+	}
+
+	pub(crate) fn diagnose(&self, diagnostics: &mut Diagnostics) {
+		diagnostics.backend = "Windows clipboard".into();
+		diagnostics.backend_reason = "the only backend on Windows".into();
+	}
+
+	/// The age of the contents, which is only known if they were placed through this `Clipboard`
+	/// or have changed since it was created.
+	pub(crate) fn content_age(&mut self) -> Result<Option<Duration>, Error> {
+		Ok(self.arrivals.age(clipboard_win::raw::seq_num()))
 	}
 
 	fn open(&mut self) -> Result<OpenClipboard<'_>, Error> {
@@ -564,6 +832,160 @@ impl Clipboard {
 	}
 }
 
+/// Reads `CF_UNICODETEXT` from the clipboard, which must already be open.
+fn read_text() -> Result<String, Error> {
+	const FORMAT: u32 = clipboard_win::formats::CF_UNICODETEXT;
+
+	// XXX: ToC/ToU race conditions are not possible because we are the sole owners of the clipboard currently.
+	if !clipboard_win::is_format_avail(FORMAT) {
+		return Err(Error::content_not_available());
+	}
+
+	// NB: Its important that whatever functionality decodes the text buffer from the clipboard
+	// uses `WideCharToMultiByte` with `CP_UTF8` (or an equivalent) in order to handle when both "text"
+	// and a locale identifier were placed on the clipboard. It is probable this occurs when an application
+	// is running with a codepage that isn't the current system's, such as under a locale emulator.
+	//
+	// In these cases, Windows decodes the text buffer with whatever codepage that identifier is for
+	// when creating the `CF_UNICODETEXT` buffer. Therefore, the buffer could then be in any format,
+	// not nessecarily wide UTF-16. We need to then undo that, taking the wide data and mapping it into
+	// the UTF-8 space as best as possible.
+	//
+	// (locale-specific text data, locale id) -> app -> system -> arboard (locale-specific text data) -> UTF-8
+	let mut out = Vec::new();
+	clipboard_win::raw::get_string(&mut out).map_err(|_| Error::content_not_available())?;
+	text_from_utf8(out)
+}
+
+/// Reads the start of the text on the clipboard, which must already be open, as at most
+/// `max_bytes` of UTF-8.
+///
+/// Every UTF-16 unit takes up at least one byte in UTF-8, so only as many units as that are
+/// copied out of the clipboard's memory, instead of the whole text.
+fn read_text_truncated(max_bytes: usize) -> Result<(String, bool), Error> {
+	const FORMAT: u32 = clipboard_win::formats::CF_UNICODETEXT;
+
+	if !clipboard_win::is_format_avail(FORMAT) {
+		return Err(Error::content_not_available());
+	}
+
+	let hdata = clipboard_win::raw::get_clipboard_data(FORMAT)
+		.map_err(|_| Error::content_not_available())?
+		.as_ptr() as HGLOBAL;
+
+	let (text, sliced) = unsafe {
+		let data_ptr = global_lock(hdata)?;
+		let _unlock = ScopeGuard::new(|| global_unlock_checked(hdata));
+
+		let available = GlobalSize(hdata) / 2;
+		let units = std::slice::from_raw_parts(
+			data_ptr.cast::<u16>(),
+			available.min(max_bytes.saturating_add(1)),
+		);
+		let mut units = match units.iter().position(|&unit| unit == 0) {
+			Some(nul) => &units[..nul],
+			None => units,
+		};
+		// Don't leave half of a surrogate pair behind where the units were cut off. The text
+		// goes on then, even if what's left fits into `max_bytes`.
+		let mut sliced = false;
+		if let [rest @ .., 0xD800..=0xDBFF] = units {
+			units = rest;
+			sliced = true;
+		}
+		(String::from_utf16_lossy(units), sliced)
+	};
+
+	let (text, truncated) = truncate_text(text, max_bytes);
+	Ok((text, truncated || sliced))
+}
+
+/// Reads the "HTML Format" from the clipboard, which must already be open.
+fn read_html() -> Result<String, Error> {
+	let format = clipboard_win::register_format("HTML Format")
+		.ok_or_else(|| Error::unknown("unable to register HTML format"))?;
+
+	let mut out: Vec<u8> = Vec::new();
+	clipboard_win::raw::get_vec(format.get(), &mut out)
+		.map_err(|_| Error::unknown("failed to read clipboard string"))?;
+
+	formats::parse_cf_html(&out).map(|cf_html| cf_html.fragment)
+}
+
+fn read_file_list() -> Result<Vec<PathBuf>, Error> {
+	let mut data = Vec::new();
+	if clipboard_win::raw::get_vec(CF_HDROP.into(), &mut data).is_ok() {
+		let code_page = ansi_code_page();
+		return Ok(formats::parse_hdrop_with(&data, |name| {
+			let wide = ansi_to_wide(name, code_page).ok()?;
+			Some(OsString::from_wide(&wide).into())
+		}));
+	}
+
+	// Some applications, like older Office dialogs, only exchange a single path.
+	// https://learn.microsoft.com/en-us/windows/win32/shell/clipboard#cfstr_filename
+	let path = match clipboard_win::register_format("FileNameW") {
+		Some(format) if clipboard_win::raw::get_vec(format.get(), &mut data).is_ok() => {
+			formats::parse_file_name_w(&data)
+		}
+		_ => match clipboard_win::register_format("FileName") {
+			Some(format) if clipboard_win::raw::get_vec(format.get(), &mut data).is_ok() => {
+				let name = data.split(|byte| *byte == 0).next().unwrap_or_default();
+				let wide = ansi_to_wide(name, ansi_code_page())?;
+				(!wide.is_empty()).then(|| OsString::from_wide(&wide).into())
+			}
+			_ => None,
+		},
+	};
+	path.map(|path| vec![path]).ok_or_else(Error::content_not_available)
+}
+
+/// The ANSI code page of the locale that `CF_LOCALE` names, which is what text in the ANSI
+/// formats is encoded with, or the system's one if it's missing.
+fn ansi_code_page() -> u32 {
+	let mut locale = Vec::new();
+	if clipboard_win::raw::get_vec(CF_LOCALE.into(), &mut locale).is_ok() {
+		if let Some(lcid) = locale.get(..4).and_then(|lcid| <[u8; 4]>::try_from(lcid).ok()) {
+			let mut code_page = 0u32;
+			// With `LOCALE_RETURN_NUMBER`, the number is written in place of two UTF-16 units.
+			let written = unsafe {
+				GetLocaleInfoW(
+					u32::from_ne_bytes(lcid),
+					LOCALE_IDEFAULTANSICODEPAGE | LOCALE_RETURN_NUMBER,
+					(&mut code_page as *mut u32).cast(),
+					2,
+				)
+			};
+			if written != 0 {
+				return code_page;
+			}
+		}
+	}
+	CP_ACP
+}
+
+/// Converts `bytes` in `code_page` to UTF-16.
+fn ansi_to_wide(bytes: &[u8], code_page: u32) -> Result<Vec<u16>, Error> {
+	if bytes.is_empty() {
+		return Ok(Vec::new());
+	}
+	let len = i32::try_from(bytes.len()).map_err(|_| Error::content_not_available())?;
+	let convert = |wide: &mut [u16]| unsafe {
+		MultiByteToWideChar(code_page, 0, bytes.as_ptr(), len, wide.as_mut_ptr(), wide.len() as i32)
+	};
+
+	let units = convert(&mut []);
+	let mut wide = vec![0; units as usize];
+	if units == 0 || convert(&mut wide) != units {
+		return Err(Error::conversion(
+			Format::FileList,
+			ConversionStage::Decode,
+			format!("the file name isn't valid in code page {code_page}"),
+		));
+	}
+	Ok(wide)
+}
+
 // Note: In all of the builders, a clipboard opening result is stored.
 // This is done for a few reasons:
 // 1. consistently with the other platforms which can have an occupied clipboard.
@@ -573,57 +995,126 @@ impl Clipboard {
 // 3. Due to how the clipboard works on Windows, we need to open it for every operation
 // and keep it open until its finished. This approach allows RAII to still be applicable.
 
+/// The configuration of a `Get`, which outlives it.
+#[derive(Clone)]
+pub(crate) struct GetConfig {
+	#[cfg(feature = "image-data-png")]
+	max_image_pixels: u64,
+}
+
 pub(crate) struct Get<'clipboard> {
 	clipboard: Result<OpenClipboard<'clipboard>, Error>,
+	/// The closed clipboard while waiting for it to change, see `Get::wait`.
+	waiting: Option<Waiting<'clipboard>>,
+	#[cfg(feature = "image-data-png")]
+	max_image_pixels: u64,
+}
+
+struct Waiting<'clipboard> {
+	clipboard: &'clipboard mut Clipboard,
+	/// The clipboard's sequence number when the wait started.
+	sequence: Option<NonZeroU32>,
+	deadline: Option<Instant>,
 }
 
 impl<'clipboard> Get<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard: clipboard.open() }
+		Self {
+			clipboard: clipboard.open(),
+			waiting: None,
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: DEFAULT_MAX_IMAGE_PIXELS,
+		}
 	}
 
-	pub(crate) fn text(self) -> Result<String, Error> {
-		const FORMAT: u32 = clipboard_win::formats::CF_UNICODETEXT;
+	#[cfg_attr(not(feature = "image-data-png"), allow(unused_variables))]
+	pub(crate) fn with_config(clipboard: &'clipboard mut Clipboard, config: &GetConfig) -> Self {
+		Self {
+			clipboard: clipboard.open(),
+			waiting: None,
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: config.max_image_pixels,
+		}
+	}
 
-		let _clipboard_assertion = self.clipboard?;
+	pub(crate) fn into_config(self) -> GetConfig {
+		GetConfig {
+			#[cfg(feature = "image-data-png")]
+			max_image_pixels: self.max_image_pixels,
+		}
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn max_image_pixels(mut self, max_pixels: u64) -> Self {
+		self.max_image_pixels = max_pixels;
+		self
+	}
+
+	// Reading doesn't wait for the application which placed the contents, so there is nothing a
+	// deadline could cut short.
+	pub(crate) fn deadline(self, _deadline: Instant) -> Self {
+		self
+	}
 
-		// XXX: ToC/ToU race conditions are not possible because we are the sole owners of the clipboard currently.
-		if !clipboard_win::is_format_avail(FORMAT) {
-			return Err(Error::ContentNotAvailable);
+	pub(crate) fn wait(mut self, deadline: Option<Instant>) -> Self {
+		if let Ok(clipboard) = self.clipboard {
+			// Other applications can't change the clipboard while it's open. It's opened again
+			// once it changed.
+			let sequence = clipboard_win::raw::seq_num();
+			self.waiting = Some(Waiting { clipboard: clipboard.close(), sequence, deadline });
+			self.clipboard = Err(Error::ClipboardOccupied);
 		}
+		self
+	}
 
-		// NB: Its important that whatever functionality decodes the text buffer from the clipboard
-		// uses `WideCharToMultiByte` with `CP_UTF8` (or an equivalent) in order to handle when both "text"
-		// and a locale identifier were placed on the clipboard. It is probable this occurs when an application
-		// is running with a codepage that isn't the current system's, such as under a locale emulator.
-		//
-		// In these cases, Windows decodes the text buffer with whatever codepage that identifier is for
-		// when creating the `CF_UNICODETEXT` buffer. Therefore, the buffer could then be in any format,
-		// not nessecarily wide UTF-16. We need to then undo that, taking the wide data and mapping it into
-		// the UTF-8 space as best as possible.
-		//
-		// (locale-specific text data, locale id) -> app -> system -> arboard (locale-specific text data) -> UTF-8
-		let mut out = Vec::new();
-		clipboard_win::raw::get_string(&mut out).map_err(|_| Error::ContentNotAvailable)?;
-		String::from_utf8(out).map_err(|_| Error::ConversionFailure)
+	pub(crate) fn wait_for_change(mut self) -> Result<Self, Error> {
+		if let Some(waiting) = self.waiting.take() {
+			poll_for_change(waiting.deadline, || {
+				Ok(clipboard_win::raw::seq_num() != waiting.sequence)
+			})?;
+			self.clipboard = waiting.clipboard.open();
+		}
+		Ok(self)
+	}
+
+	pub(crate) fn text(self) -> Result<String, Error> {
+		let _clipboard_assertion = self.clipboard?;
+		read_text()
+	}
+
+	pub(crate) fn text_or_html_stripped(self) -> Result<(String, TextSource), Error> {
+		let _clipboard_assertion = self.clipboard?;
+		text_or_html_stripped(read_text(), read_html)
+	}
+
+	pub(crate) fn text_truncated(self, max_bytes: usize) -> Result<(String, bool), Error> {
+		let _clipboard_assertion = self.clipboard?;
+		read_text_truncated(max_bytes)
 	}
 
 	pub(crate) fn html(self) -> Result<String, Error> {
 		let _clipboard_assertion = self.clipboard?;
+		read_html()
+	}
 
-		let format = clipboard_win::register_format("HTML Format")
-			.ok_or_else(|| Error::unknown("unable to register HTML format"))?;
+	pub(crate) fn html_and_text(self) -> Result<(Option<String>, Option<String>), Error> {
+		let _clipboard_assertion = self.clipboard?;
 
-		let mut out: Vec<u8> = Vec::new();
-		clipboard_win::raw::get_html(format.get(), &mut out)
-			.map_err(|_| Error::unknown("failed to read clipboard string"))?;
+		let html_available = clipboard_win::register_format("HTML Format")
+			.is_some_and(|format| clipboard_win::is_format_avail(format.get()));
+		let html = if html_available { Some(read_html()?) } else { None };
 
-		String::from_utf8(out).map_err(|_| Error::ConversionFailure)
+		Ok((html, if_available(read_text())?))
 	}
 
-	#[cfg(feature = "image-data")]
+	#[cfg(feature = "image-data-png")]
 	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
-		let _clipboard_assertion = self.clipboard?;
+		self.image_or_bytes()?.into_image()
+	}
+
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image_or_bytes(self) -> Result<ImageOrBytes, Error> {
+		let open_clipboard = self.clipboard?;
 		let mut data = Vec::new();
 
 		let png_format: Option<u32> = clipboard_win::register_format("PNG").map(From::from);
@@ -631,51 +1122,205 @@ impl<'clipboard> Get<'clipboard> {
 			// Looks like PNG is available! Let's try it
 			clipboard_win::raw::get_vec(id, &mut data)
 				.map_err(|_| Error::unknown("failed to read clipboard PNG data"))?;
-			return image_data::read_png(&data);
+			let decode = |png: &[u8]| decode_png(png, self.max_image_pixels);
+			return Ok(ImageOrBytes::decode(data, decode, || "PNG".to_owned()));
 		}
 
-		if !clipboard_win::is_format_avail(clipboard_win::formats::CF_DIBV5) {
-			return Err(Error::ContentNotAvailable);
+		#[cfg(feature = "image-data")]
+		if clipboard_win::is_format_avail(clipboard_win::formats::CF_DIBV5) {
+			clipboard_win::raw::get_vec(clipboard_win::formats::CF_DIBV5, &mut data)
+				.map_err(|_| Error::unknown("failed to read clipboard image data"))?;
+			return Ok(image_data::decode_cf_dibv5(data, self.max_image_pixels));
 		}
 
-		clipboard_win::raw::get_vec(clipboard_win::formats::CF_DIBV5, &mut data)
-			.map_err(|_| Error::unknown("failed to read clipboard image data"))?;
-		image_data::read_cf_dibv5(&mut data)
+		// Windows normally synthesizes a DIB from a `CF_BITMAP`, but not for every bitmap. Without
+		// the `image` crate to decode DIBs, they are read through the `CF_BITMAP` Windows
+		// synthesizes from them in turn, which loses their transparency.
+		if clipboard_win::is_format_avail(clipboard_win::formats::CF_BITMAP) {
+			return image_data::read_cf_bitmap(&open_clipboard, self.max_image_pixels)
+				.map(ImageOrBytes::Image);
+		}
+		Err(Error::content_not_available())
 	}
 
 	pub(crate) fn file_list(self) -> Result<Vec<PathBuf>, Error> {
 		let _clipboard_assertion = self.clipboard?;
+		read_file_list()
+	}
+
+	/// The formats are read as a whole, as the clipboard hands them out in one piece of memory.
+	pub(crate) fn write_to(self, format: &str, writer: &mut dyn Write) -> Result<u64, Error> {
+		write_contents(writer, &self.raw_format(format.into())?)
+	}
+
+	fn raw_format(self, format: WindowsFormat) -> Result<Vec<u8>, Error> {
+		let _clipboard_assertion = self.clipboard?;
+		let id = format.id()?;
+		if !clipboard_win::is_format_avail(id) {
+			return Err(Error::content_not_available());
+		}
+
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(id, &mut data).map_err(|_| Error::content_not_available())?;
+		Ok(data)
+	}
+
+	pub(crate) fn file_list_with_op(self) -> Result<(Vec<PathBuf>, FileOp), Error> {
+		let _clipboard_assertion = self.clipboard?;
+		let paths = read_file_list()?;
 
-		let mut file_list = Vec::new();
-		clipboard_win::raw::get_file_list_path(&mut file_list)
-			.map_err(|_| Error::ContentNotAvailable)?;
+		let mut drop_effect = Vec::new();
+		let op = match clipboard_win::register_format("Preferred DropEffect") {
+			Some(format) if clipboard_win::raw::get_vec(format.get(), &mut drop_effect).is_ok() => {
+				formats::parse_preferred_drop_effect(&drop_effect)
+			}
+			_ => FileOp::Copy,
+		};
 
-		Ok(file_list)
+		Ok((paths, op))
 	}
 }
 
+/// The configuration of a `Set`, which outlives it.
+#[derive(Clone)]
+pub(crate) struct SetConfig {
+	exclude_from_monitoring: bool,
+	exclude_from_cloud: bool,
+	exclude_from_history: bool,
+	max_format_bytes: usize,
+	without_clearing: bool,
+}
+
 pub(crate) struct Set<'clipboard> {
-	clipboard: Result<OpenClipboard<'clipboard>, Error>,
+	clipboard: &'clipboard mut Clipboard,
 	exclude_from_monitoring: bool,
 	exclude_from_cloud: bool,
 	exclude_from_history: bool,
+	max_format_bytes: usize,
+	without_clearing: bool,
+	/// Formats placed as they are after the data, with `SetExtWindows::raw_format`.
+	raw_formats: Vec<(WindowsFormat, Vec<u8>)>,
+	validate_only: bool,
+	report: Recorder<'clipboard>,
 }
 
+// Every completing method converts the data before opening the clipboard, so that it's only held
+// open while the data is placed and a failed conversion leaves it untouched.
 impl<'clipboard> Set<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
 		Self {
-			clipboard: clipboard.open(),
+			clipboard,
 			exclude_from_monitoring: false,
 			exclude_from_cloud: false,
 			exclude_from_history: false,
+			max_format_bytes: DEFAULT_MAX_FORMAT_BYTES,
+			without_clearing: false,
+			raw_formats: Vec::new(),
+			validate_only: false,
+			report: Recorder::default(),
 		}
 	}
 
-	pub(crate) fn text(self, data: Cow<'_, str>) -> Result<(), Error> {
-		let open_clipboard = self.clipboard?;
+	pub(crate) fn with_config(clipboard: &'clipboard mut Clipboard, config: &SetConfig) -> Self {
+		Self {
+			clipboard,
+			exclude_from_monitoring: config.exclude_from_monitoring,
+			exclude_from_cloud: config.exclude_from_cloud,
+			exclude_from_history: config.exclude_from_history,
+			max_format_bytes: config.max_format_bytes,
+			without_clearing: config.without_clearing,
+			raw_formats: Vec::new(),
+			validate_only: false,
+			report: Recorder::default(),
+		}
+	}
+
+	pub(crate) fn into_config(self) -> SetConfig {
+		SetConfig {
+			exclude_from_monitoring: self.exclude_from_monitoring,
+			exclude_from_cloud: self.exclude_from_cloud,
+			exclude_from_history: self.exclude_from_history,
+			max_format_bytes: self.max_format_bytes,
+			without_clearing: self.without_clearing,
+		}
+	}
+
+	pub(crate) fn validate_only(mut self) -> Self {
+		self.validate_only = true;
+		self
+	}
+
+	pub(crate) fn report_to<'a>(self, report: &'a mut SetReport) -> Set<'a>
+	where
+		'clipboard: 'a,
+	{
+		// Excluding the data from monitoring keeps it out of the history as well.
+		let excluded = self.exclude_from_history || self.exclude_from_monitoring;
+		Set {
+			clipboard: self.clipboard,
+			exclude_from_monitoring: self.exclude_from_monitoring,
+			exclude_from_cloud: self.exclude_from_cloud,
+			exclude_from_history: self.exclude_from_history,
+			max_format_bytes: self.max_format_bytes,
+			without_clearing: self.without_clearing,
+			raw_formats: self.raw_formats,
+			validate_only: self.validate_only,
+			report: Recorder::new(report, excluded),
+		}
+	}
+
+	/// Whether nothing but the data itself would be placed, so that the clipboard holding the
+	/// same data means it holds what would be placed.
+	pub(crate) fn places_only_data(&self) -> bool {
+		self.raw_formats.is_empty()
+			&& !self.exclude_from_history
+			&& !self.exclude_from_monitoring
+			&& !self.exclude_from_cloud
+	}
+
+	/// Whether the contents on the clipboard are still the ones last placed through this
+	/// `Clipboard`.
+	pub(crate) fn is_owner(&mut self) -> Result<bool, Error> {
+		let placed = self.clipboard.placed;
+		Ok(placed.is_some() && clipboard_win::raw::seq_num() == placed)
+	}
 
-		clipboard_win::raw::set_string(&data)
-			.map_err(|_| Error::unknown("Could not place the specified text to the clipboard"))?;
+	/// Reads the clipboard, which isn't held open by a `Set` until it's completed.
+	pub(crate) fn read_back(&mut self) -> Get<'_> {
+		Get::new(self.clipboard)
+	}
+
+	/// Checks the sizes of the formats given to `SetExtWindows::raw_format` and resolves them,
+	/// before the clipboard is opened.
+	fn resolve_raw_formats(&mut self) -> Result<Vec<(u32, String, Vec<u8>)>, Error> {
+		std::mem::take(&mut self.raw_formats)
+			.into_iter()
+			.map(|(format, bytes)| {
+				check_format_size(bytes.len(), self.max_format_bytes)?;
+				let id = format.id()?;
+				if is_gdi_handle_format(id) {
+					return Err(Error::unknown(format!(
+						"Clipboard format {format} holds a GDI handle, not memory, so it can't be placed from bytes."
+					)));
+				}
+				Ok((id, format.to_string(), bytes))
+			})
+			.collect()
+	}
+
+	pub(crate) fn text<'a>(mut self, data: impl ClipboardText<'a>) -> Result<(), Error> {
+		let data = data.into_cow();
+		check_format_size(utf16_size(&data), self.max_format_bytes)?;
+		let raw_formats = self.resolve_raw_formats()?;
+		if self.validate_only {
+			return Ok(());
+		}
+		let open_clipboard = self.clipboard.open()?;
+
+		empty_unless(self.without_clearing)?;
+		set_text(&data)?;
+		self.report.format("CF_UNICODETEXT", Some(utf16_size(&data)));
+		place_raw_formats(raw_formats, &mut self.report)?;
 
 		add_clipboard_exclusions(
 			open_clipboard,
@@ -685,21 +1330,45 @@ impl<'clipboard> Set<'clipboard> {
 		)
 	}
 
-	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
-		let open_clipboard = self.clipboard?;
+	pub(crate) fn text_bytes(self, utf8_bytes: Vec<u8>) -> Result<(), Error> {
+		let text = String::from_utf8(utf8_bytes).map_err(|error| {
+			Error::conversion(Format::Text, ConversionStage::Validate, error.to_string())
+		})?;
+		self.text(text)
+	}
 
+	pub(crate) fn html(
+		mut self,
+		html: Cow<'_, str>,
+		alt: Option<Cow<'_, str>>,
+	) -> Result<(), Error> {
 		let alt = match alt {
 			Some(s) => s.into(),
 			None => String::new(),
 		};
-		clipboard_win::raw::set_string(&alt)
-			.map_err(|_| Error::unknown("Could not place the specified text to the clipboard"))?;
+		// Both sizes are checked before either format is placed, so that a failure doesn't leave
+		// only the alternative text behind.
+		check_format_size(utf16_size(&alt), self.max_format_bytes)?;
+		// Checked before building the payload, so that oversized HTML isn't copied first.
+		let html_size = html.len().saturating_add(formats::CF_HTML_OVERHEAD);
+		check_format_size(html_size, self.max_format_bytes)?;
+		let html = formats::build_cf_html(&html);
+		let raw_formats = self.resolve_raw_formats()?;
+		if self.validate_only {
+			return Ok(());
+		}
+		let open_clipboard = self.clipboard.open()?;
+
+		empty_unless(self.without_clearing)?;
+		set_text(&alt)?;
+		self.report.format("CF_UNICODETEXT", Some(utf16_size(&alt)));
 
 		if let Some(format) = clipboard_win::register_format("HTML Format") {
-			let html = wrap_html(&html);
-			clipboard_win::raw::set_without_clear(format.get(), html.as_bytes())
+			clipboard_win::raw::set_without_clear(format.get(), &html)
 				.map_err(|e| Error::unknown(e.to_string()))?;
+			self.report.format("HTML Format", Some(html.len()));
 		}
+		place_raw_formats(raw_formats, &mut self.report)?;
 
 		add_clipboard_exclusions(
 			open_clipboard,
@@ -709,82 +1378,263 @@ impl<'clipboard> Set<'clipboard> {
 		)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn image(self, image: ImageData) -> Result<(), Error> {
-		let open_clipboard = self.clipboard?;
+	/// Windows has no way to produce clipboard data in pieces, so the chunks are collected
+	/// up front.
+	pub(crate) fn stream(self, mime: &str, chunks: Chunks) -> Result<(), Error> {
+		self.encoded(mime, chunks.flatten().collect())
+	}
 
-		if let Err(e) = clipboard_win::raw::empty() {
-			return Err(Error::unknown(format!(
-				"Failed to empty the clipboard. Got error code: {e}"
-			)));
+	/// Places already encoded data under the registered format matching its MIME type.
+	pub(crate) fn encoded(mut self, mime: &str, bytes: Vec<u8>) -> Result<(), Error> {
+		check_format_size(bytes.len(), self.max_format_bytes)?;
+		let raw_formats = self.resolve_raw_formats()?;
+		if self.validate_only {
+			return Ok(());
+		}
+		// Most applications only paste images which are offered as a bitmap, so images are
+		// placed as one as well, like with `image`, if they can be decoded. The bitmap is held
+		// to the size limit as well, which images larger than it fail on before being decoded.
+		#[cfg(feature = "image-data-png")]
+		let max_pixels = image_data::max_dib_pixels(self.max_format_bytes);
+		#[cfg(feature = "image-data-png")]
+		let bitmaps = match image_data::decode_file(mime, &bytes, max_pixels) {
+			Some(decoded) => match decoded.and_then(|image| {
+				check_format_size(image_data::dib_size(&image), self.max_format_bytes)?;
+				let bitmap = image_data::create_bitmap(&image)?;
+				Ok((image, bitmap))
+			}) {
+				Ok(bitmaps) => Some(bitmaps),
+				Err(Error::TooLarge) => return Err(Error::TooLarge),
+				Err(error) => {
+					log::warn!("failed to place the {mime} image as a bitmap: {error}");
+					None
+				}
+			},
+			None => None,
+		};
+		let open_clipboard = self.clipboard.open()?;
+
+		// These are the names that other applications register for the image formats.
+		let format_name = match mime {
+			"image/png" => "PNG",
+			"image/jpeg" => "JFIF",
+			"image/gif" => "GIF",
+			other => other,
 		};
+		let format = clipboard_win::register_format(format_name).ok_or_else(|| {
+			last_error(&format!("Cannot register {format_name} clipboard format."))
+		})?;
+
+		empty_unless(self.without_clearing)?;
+		// The bitmaps come first for the clipboard history, see `image_inner`.
+		#[cfg(feature = "image-data-png")]
+		if let Some((image, bitmap)) = bitmaps {
+			let dib_size = image_data::add_cf_dibv5(&open_clipboard, image)?;
+			self.report.format("CF_DIBV5", Some(dib_size));
+			image_data::add_cf_bitmap(&open_clipboard, bitmap)?;
+			self.report.format("CF_BITMAP", None);
+		}
+		clipboard_win::raw::set_without_clear(format.get(), &bytes)
+			.map_err(|e| Error::unknown(e.to_string()))?;
+		self.report.format(format_name, Some(bytes.len()));
+		place_raw_formats(raw_formats, &mut self.report)?;
 
-		// XXX: The ordering of these functions is important, as some programs will grab the
-		// first format available. PNGs tend to have better compatibility on Windows, so it is set first.
-		image_data::add_png_file(&image)?;
-		image_data::add_cf_dibv5(open_clipboard, image)?;
-		Ok(())
+		add_clipboard_exclusions(
+			open_clipboard,
+			self.exclude_from_monitoring,
+			self.exclude_from_cloud,
+			self.exclude_from_history,
+		)
 	}
 
-	pub(crate) fn file_list(self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
-		const DROPFILES_HEADER_SIZE: usize = std::mem::size_of::<DROPFILES>();
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image(self, image: ImageData) -> Result<(), Error> {
+		self.image_inner(image, None)
+	}
 
-		let clipboard_assertion = self.clipboard?;
+	/// Delayed rendering isn't used, so the image is encoded right away, like with `image`.
+	#[cfg(feature = "image-data-png")]
+	pub(crate) fn image_lazy(self, image: ImageData<'static>) -> Result<(), Error> {
+		self.image(image)
+	}
 
-		// https://learn.microsoft.com/en-us/windows/win32/shell/clipboard#cf_hdrop
-		// CF_HDROP consists of an STGMEDIUM structure that contains a global memory object.
-		// The structure's hGlobal member points to the resulting data:
-		// | DROPFILES | FILENAME | NULL | ... | nth FILENAME | NULL | NULL |
-		let dropfiles = DROPFILES {
-			pFiles: DROPFILES_HEADER_SIZE as u32,
-			pt: POINT { x: 0, y: 0 },
-			fNC: 0,
-			fWide: 1,
-		};
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_thumbnail(
+		self,
+		image: ImageData,
+		thumbnail: ImageData,
+	) -> Result<(), Error> {
+		self.image_inner(image, Some(thumbnail))
+	}
 
-		let mut data_len = DROPFILES_HEADER_SIZE;
+	#[cfg(feature = "image-data-png")]
+	fn image_inner(mut self, image: ImageData, thumbnail: Option<ImageData>) -> Result<(), Error> {
+		check_format_size(image.bytes.len(), self.max_format_bytes)?;
+		// Placing the DIB consumes the image, so the files are encoded beforehand.
+		let png = encode_png(&image)?;
+		let thumbnail = thumbnail.as_ref().map(encode_png).transpose()?;
+		// Some older applications only accept `CF_BITMAP`, which Windows doesn't always
+		// synthesize for them, so it's placed explicitly after the DIB.
+		let bitmap = image_data::create_bitmap(&image)?;
+		let raw_formats = self.resolve_raw_formats()?;
+		if self.validate_only {
+			return Ok(());
+		}
+		let open_clipboard = self.clipboard.open()?;
+
+		empty_unless(self.without_clearing)?;
+
+		// XXX: The ordering of these functions is important. Clipboard history (Win+V) only keeps
+		// images that are offered as a bitmap, and has been reported to miss them when the
+		// registered PNG format came first, so the bitmaps are placed before it. Applications
+		// which look for PNG by name, as most that support it do, are unaffected by the order.
+		let dib_size = image_data::add_cf_dibv5(&open_clipboard, image)?;
+		self.report.format("CF_DIBV5", Some(dib_size));
+		image_data::add_cf_bitmap(&open_clipboard, bitmap)?;
+		// The bitmap is a GDI object, which has no size of its own on the clipboard.
+		self.report.format("CF_BITMAP", None);
+		image_data::add_file(&png, "PNG")?;
+		self.report.format("PNG", Some(png.len()));
+		if let Some(thumbnail) = thumbnail {
+			image_data::add_file(&thumbnail, THUMBNAIL_MIME)?;
+			self.report.format(THUMBNAIL_MIME, Some(thumbnail.len()));
+		}
+		place_raw_formats(raw_formats, &mut self.report)?;
 
-		let paths: Vec<_> = file_list
-			.iter()
-			.filter_map(|path| {
-				to_final_path_wide(path.as_ref()).map(|wide| {
-					// Windows uses wchar_t which is 16 bit
-					data_len += wide.len() * std::mem::size_of::<u16>();
-					wide
-				})
-			})
-			.collect();
+		add_clipboard_exclusions(
+			open_clipboard,
+			self.exclude_from_monitoring,
+			self.exclude_from_cloud,
+			self.exclude_from_history,
+		)
+	}
 
-		if paths.is_empty() {
-			return Err(Error::ConversionFailure);
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_multi(
+		mut self,
+		image: ImageData,
+		encodings: &[ImageEncoding],
+	) -> Result<(), Error> {
+		check_format_size(image.bytes.len(), self.max_format_bytes)?;
+
+		let mut last_error =
+			Error::conversion(Format::Image, ConversionStage::Validate, "no encodings were given");
+		let mut files = Vec::new();
+		let mut bitmap = None;
+		for (i, &encoding) in encodings.iter().enumerate() {
+			if encodings[..i].contains(&encoding) {
+				continue;
+			}
+			let converted = match encoding {
+				ImageEncoding::Png => encode_png(&image).map(|png| files.push((png, "PNG"))),
+				ImageEncoding::Jpeg => {
+					ImageEncoding::Jpeg.encode(&image).map(|jpeg| files.push((jpeg, "JFIF")))
+				}
+				ImageEncoding::Bmp => {
+					image_data::create_bitmap(&image).map(|created| bitmap = Some(created))
+				}
+			};
+			if let Err(error) = converted {
+				log::warn!("failed to place the image as {encoding:?}: {error}");
+				last_error = error;
+			}
 		}
 
-		// Add space for the final null character
-		data_len += std::mem::size_of::<u16>();
-
-		unsafe {
-			let h_global = global_alloc(data_len)?;
-			let data_ptr = global_lock(h_global)?;
+		if files.is_empty() && bitmap.is_none() {
+			return Err(last_error);
+		}
+		let raw_formats = self.resolve_raw_formats()?;
+		if self.validate_only {
+			return Ok(());
+		}
+		let open_clipboard = self.clipboard.open()?;
+
+		empty_unless(self.without_clearing)?;
+
+		// Bitmaps are placed as `CF_DIBV5`, from which Windows synthesizes `CF_DIB`, along with a
+		// `CF_BITMAP`. They come before the files, as clipboard history (Win+V) only keeps images
+		// that are offered as a bitmap first, see `image_inner`.
+		let mut placed = 0;
+		if let Some(bitmap) = bitmap {
+			let placed_bitmap = image_data::add_cf_dibv5(&open_clipboard, image).and_then(|size| {
+				image_data::add_cf_bitmap(&open_clipboard, bitmap)?;
+				Ok(size)
+			});
+			match placed_bitmap {
+				Ok(dib_size) => {
+					self.report.format("CF_DIBV5", Some(dib_size));
+					self.report.format("CF_BITMAP", None);
+					placed += 1;
+				}
+				Err(error) => {
+					log::warn!("failed to place the image as {:?}: {error}", ImageEncoding::Bmp);
+					last_error = error;
+				}
+			}
+		}
 
-			(data_ptr as *mut DROPFILES).write(dropfiles);
+		for (bytes, format_name) in files {
+			match image_data::add_file(&bytes, format_name) {
+				Ok(()) => {
+					self.report.format(format_name, Some(bytes.len()));
+					placed += 1;
+				}
+				Err(error) => {
+					log::warn!("failed to place the image as {format_name}: {error}");
+					last_error = error;
+				}
+			}
+		}
 
-			let mut ptr = data_ptr.add(DROPFILES_HEADER_SIZE) as *mut u16;
+		if placed == 0 {
+			return Err(last_error);
+		}
+		place_raw_formats(raw_formats, &mut self.report)?;
 
-			for wide_path in paths {
-				std::ptr::copy_nonoverlapping::<u16>(wide_path.as_ptr(), ptr, wide_path.len());
-				ptr = ptr.add(wide_path.len());
-			}
+		add_clipboard_exclusions(
+			open_clipboard,
+			self.exclude_from_monitoring,
+			self.exclude_from_cloud,
+			self.exclude_from_history,
+		)
+	}
 
-			// Write final null character
-			ptr.write(0);
+	pub(crate) fn file_list(mut self, file_list: &[impl AsRef<Path>]) -> Result<(), Error> {
+		let paths: Vec<_> =
+			file_list.iter().filter_map(|path| to_final_path(path.as_ref())).collect();
 
-			global_unlock_checked(h_global);
+		if paths.is_empty() {
+			return Err(Error::conversion(
+				Format::FileList,
+				ConversionStage::Validate,
+				"none of the paths exist",
+			));
+		}
 
-			if SetClipboardData(CF_HDROP.into(), h_global as HANDLE).failure() {
-				GlobalFree(h_global);
-				return Err(last_error("SetClipboardData failed with error"));
-			}
+		// https://learn.microsoft.com/en-us/windows/win32/shell/clipboard#cf_hdrop
+		// CF_HDROP consists of an STGMEDIUM structure that contains a global memory object.
+		// The structure's hGlobal member points to the resulting data.
+		let data = formats::build_hdrop(&paths);
+		check_format_size(data.len(), self.max_format_bytes)?;
+		let raw_formats = self.resolve_raw_formats()?;
+		if self.validate_only {
+			return Ok(());
+		}
+		let clipboard_assertion = self.clipboard.open()?;
+
+		empty_unless(self.without_clearing)?;
+		OwnedGlobal::with_bytes(&data)?.set_clipboard_data(CF_HDROP.into())?;
+		self.report.format("CF_HDROP", Some(data.len()));
+
+		// A single path is offered to the applications which only read one as well.
+		if let [path] = paths.as_slice() {
+			let format = clipboard_win::register_format("FileNameW")
+				.ok_or_else(|| Error::unknown("unable to register the FileNameW format"))?;
+			let file_name = formats::build_file_name_w(path);
+			OwnedGlobal::with_bytes(&file_name)?.set_clipboard_data(format.get())?;
+			self.report.format("FileNameW", Some(file_name.len()));
 		}
+		place_raw_formats(raw_formats, &mut self.report)?;
 
 		add_clipboard_exclusions(
 			clipboard_assertion,
@@ -795,8 +1645,79 @@ impl<'clipboard> Set<'clipboard> {
 	}
 }
 
+/// The largest amount of memory, in bytes, which a `Set` allocates for a single format unless
+/// configured otherwise. This is the same as the default limit on the pixels of an image read.
+const DEFAULT_MAX_FORMAT_BYTES: usize = 512_000_000;
+
+/// Returns [`Error::TooLarge`] if a format of `bytes` exceeds `max_bytes`.
+fn check_format_size(bytes: usize, max_bytes: usize) -> Result<(), Error> {
+	if bytes > max_bytes {
+		Err(Error::TooLarge)
+	} else {
+		Ok(())
+	}
+}
+
+/// Whether the data of the standard clipboard format `id` is a GDI handle rather than an
+/// `HGLOBAL`, which raw bytes can't be placed as.
+fn is_gdi_handle_format(id: u32) -> bool {
+	use clipboard_win::formats::{
+		CF_BITMAP, CF_DSPBITMAP, CF_DSPENHMETAFILE, CF_DSPMETAFILEPICT, CF_ENHMETAFILE,
+		CF_METAFILEPICT, CF_PALETTE,
+	};
+	matches!(
+		id,
+		CF_BITMAP
+			| CF_DSPBITMAP
+			| CF_DSPENHMETAFILE
+			| CF_DSPMETAFILEPICT
+			| CF_ENHMETAFILE
+			| CF_METAFILEPICT
+			| CF_PALETTE
+	)
+}
+
+/// Empties the clipboard, which must already be open, unless `SetExtWindows::without_clearing`
+/// asked to keep its contents.
+fn empty_unless(without_clearing: bool) -> Result<(), Error> {
+	if without_clearing {
+		return Ok(());
+	}
+	clipboard_win::raw::empty()
+		.map_err(|e| Error::unknown(format!("Failed to empty the clipboard. Got error code: {e}")))
+}
+
+/// Places `text` under `CF_UNICODETEXT` without emptying the clipboard, which must already be
+/// open.
+fn set_text(text: &str) -> Result<(), Error> {
+	let wide: Vec<u8> =
+		text.encode_utf16().chain(std::iter::once(0)).flat_map(u16::to_ne_bytes).collect();
+	clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_UNICODETEXT, &wide)
+		.map_err(|_| Error::unknown("Could not place the specified text to the clipboard"))
+}
+
+/// Places the formats given to `SetExtWindows::raw_format`, as resolved by
+/// `Set::resolve_raw_formats`, onto the clipboard, which must already be open.
+fn place_raw_formats(
+	raw_formats: Vec<(u32, String, Vec<u8>)>,
+	report: &mut Recorder<'_>,
+) -> Result<(), Error> {
+	for (id, name, bytes) in raw_formats {
+		OwnedGlobal::with_bytes(&bytes)?.set_clipboard_data(id)?;
+		report.format(&name, Some(bytes.len()));
+	}
+	Ok(())
+}
+
+/// The size of `text` as the NUL terminated UTF-16 placed under `CF_UNICODETEXT`.
+fn utf16_size(text: &str) -> usize {
+	(text.encode_utf16().count() + 1) * 2
+}
+
+/// Applies the exclusions to the contents that were just placed, then closes the clipboard and
+/// remembers the contents as ours for `Clear::clear_if_owner`.
 fn add_clipboard_exclusions(
-	_open_clipboard: OpenClipboard<'_>,
+	open_clipboard: OpenClipboard<'_>,
 	exclude_from_monitoring: bool,
 	exclude_from_cloud: bool,
 	exclude_from_history: bool,
@@ -839,9 +1760,95 @@ fn add_clipboard_exclusions(
 		}
 	}
 
+	// The sequence number is read once the clipboard is closed, in case closing it counts as
+	// a change as well.
+	let clipboard = open_clipboard.close();
+	clipboard.placed = clipboard_win::raw::seq_num();
+	clipboard.arrivals.placed(clipboard.placed);
 	Ok(())
 }
 
+/// A clipboard format on Windows, for [`SetExtWindows::raw_format`] and
+/// [`GetExtWindows::raw_format`].
+///
+/// It converts from a `u32` format ID and from a format name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WindowsFormat {
+	/// A standard format like `CF_TEXT`, or one that was already registered, by its ID.
+	Id(u32),
+	/// A registered format by its name, like `Rich Text Format`. It's registered if no
+	/// application did so yet.
+	Name(String),
+}
+
+impl WindowsFormat {
+	fn id(&self) -> Result<u32, Error> {
+		match self {
+			Self::Id(id) => Ok(*id),
+			Self::Name(name) => clipboard_win::register_format(name)
+				.map(|format| format.get())
+				.ok_or_else(|| last_error(&format!("Cannot register {name} clipboard format."))),
+		}
+	}
+}
+
+impl From<u32> for WindowsFormat {
+	fn from(id: u32) -> Self {
+		Self::Id(id)
+	}
+}
+
+impl From<&str> for WindowsFormat {
+	fn from(name: &str) -> Self {
+		Self::Name(name.to_owned())
+	}
+}
+
+impl From<String> for WindowsFormat {
+	fn from(name: String) -> Self {
+		Self::Name(name)
+	}
+}
+
+impl std::fmt::Display for WindowsFormat {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Id(id) => write!(f, "{id}"),
+			Self::Name(name) => f.write_str(name),
+		}
+	}
+}
+
+/// Windows-specific extensions to the [`Get`](crate::Get) builder.
+pub trait GetExtWindows: private::Sealed {
+	/// Completes the "get" operation by fetching the bytes of `format` exactly as they are on the
+	/// clipboard.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error, GetExtWindows};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let rtf = clipboard.get().raw_format("Rich Text Format")?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ContentNotAvailable`] if the clipboard doesn't hold `format`. Formats
+	/// which aren't kept in memory, like `CF_BITMAP`, can't be read this way either.
+	fn raw_format(self, format: impl Into<WindowsFormat>) -> Result<Vec<u8>, Error>;
+}
+
+impl GetExtWindows for crate::Get<'_> {
+	fn raw_format(self, format: impl Into<WindowsFormat>) -> Result<Vec<u8>, Error> {
+		self.platform.wait_for_change()?.raw_format(format.into())
+	}
+}
+
 /// Windows-specific extensions to the [`Set`](crate::Set) builder.
 pub trait SetExtWindows: private::Sealed {
 	/// Exclude the data which will be set on the clipboard from being processed
@@ -861,6 +1868,47 @@ pub trait SetExtWindows: private::Sealed {
 	///
 	/// [clipboard history]: https://support.microsoft.com/en-us/windows/get-help-with-clipboard-30375039-ce71-9fe4-5b30-21b7aab6b13f
 	fn exclude_from_history(self) -> Self;
+
+	/// Sets the largest amount of memory, in bytes, which is allocated for any one format placed
+	/// onto the clipboard, such as the UTF-16 copy of text or the `CF_HTML` copy of HTML.
+	///
+	/// Data that exceeds it makes the operation return [`Error::TooLarge`] before anything is
+	/// allocated or placed onto the clipboard. Defaults to 512 MB, the same as the default limit
+	/// for reading images.
+	fn max_format_bytes(self, max_bytes: usize) -> Self;
+
+	/// Places `bytes` under `format` as well, exactly as they are, after the data of the
+	/// completing method.
+	///
+	/// This can be called several times to place several formats, which are placed in the
+	/// order they were given. They aren't kept by [`Set::into_config`](crate::Set::into_config).
+	///
+	/// # Errors
+	///
+	/// The completing method returns [`Error::Unknown`] without touching the clipboard if
+	/// `format` is one whose data is a GDI handle instead of memory, such as `CF_BITMAP`,
+	/// `CF_ENHMETAFILE`, `CF_METAFILEPICT` or `CF_PALETTE`.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error, SetExtWindows};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let rtf = br"{\rtf1 {\b bold} text}".to_vec();
+	/// clipboard.set().raw_format("Rich Text Format", rtf).text("bold text")?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn raw_format(self, format: impl Into<WindowsFormat>, bytes: Vec<u8>) -> Self;
+
+	/// Adds the data to what is already on the clipboard instead of replacing it, by not
+	/// emptying the clipboard first.
+	///
+	/// Formats which are on the clipboard already are replaced by the new data, while all others
+	/// are kept. Together with [`raw_format`](SetExtWindows::raw_format), this builds up the
+	/// contents over several "set" operations.
+	fn without_clearing(self) -> Self;
 }
 
 impl SetExtWindows for crate::Set<'_> {
@@ -878,6 +1926,21 @@ impl SetExtWindows for crate::Set<'_> {
 		self.platform.exclude_from_history = true;
 		self
 	}
+
+	fn max_format_bytes(mut self, max_bytes: usize) -> Self {
+		self.platform.max_format_bytes = max_bytes;
+		self
+	}
+
+	fn raw_format(mut self, format: impl Into<WindowsFormat>, bytes: Vec<u8>) -> Self {
+		self.platform.raw_formats.push((format.into(), bytes));
+		self
+	}
+
+	fn without_clearing(mut self) -> Self {
+		self.platform.without_clearing = true;
+		self
+	}
 }
 
 pub(crate) struct Clear<'clipboard> {
@@ -890,37 +1953,28 @@ impl<'clipboard> Clear<'clipboard> {
 	}
 
 	pub(crate) fn clear(self) -> Result<(), Error> {
-		let _clipboard_assertion = self.clipboard?;
-		clipboard_win::empty().map_err(|_| Error::unknown("failed to clear clipboard"))
-	}
-}
-
-fn wrap_html(ctn: &str) -> String {
-	let h_version = "Version:0.9";
-	let h_start_html = "\r\nStartHTML:";
-	let h_end_html = "\r\nEndHTML:";
-	let h_start_frag = "\r\nStartFragment:";
-	let h_end_frag = "\r\nEndFragment:";
-	let c_start_frag = "\r\n<html>\r\n<body>\r\n<!--StartFragment-->\r\n";
-	let c_end_frag = "\r\n<!--EndFragment-->\r\n</body>\r\n</html>";
-	let h_len = h_version.len()
-		+ h_start_html.len()
-		+ 10 + h_end_html.len()
-		+ 10 + h_start_frag.len()
-		+ 10 + h_end_frag.len()
-		+ 10;
-	let n_start_html = h_len + 2;
-	let n_start_frag = h_len + c_start_frag.len();
-	let n_end_frag = n_start_frag + ctn.len();
-	let n_end_html = n_end_frag + c_end_frag.len();
-	format!(
-		"{h_version}{h_start_html}{n_start_html:010}{h_end_html}{n_end_html:010}{h_start_frag}{n_start_frag:010}{h_end_frag}{n_end_frag:010}{c_start_frag}{ctn}{c_end_frag}"
-	)
+		let clipboard_assertion = self.clipboard?;
+		clipboard_win::empty().map_err(|_| Error::unknown("failed to clear clipboard"))?;
+		clipboard_assertion.close().placed = None;
+		Ok(())
+	}
+
+	pub(crate) fn clear_if_owner(self) -> Result<bool, Error> {
+		let clipboard_assertion = self.clipboard?;
+		// No other application can change the contents while the clipboard is open.
+		let placed = clipboard_assertion._for_shim.placed;
+		if placed.is_none() || clipboard_win::raw::seq_num() != placed {
+			return Ok(false);
+		}
+		clipboard_win::empty().map_err(|_| Error::unknown("failed to clear clipboard"))?;
+		clipboard_assertion.close().placed = None;
+		Ok(true)
+	}
 }
 
 /// Given a file path attempt to open it and call GetFinalPathNameByHandleW,
-/// on success return the final path as a NULL terminated u16 Vec
-fn to_final_path_wide(p: &Path) -> Option<Vec<u16>> {
+/// on success return the final path
+fn to_final_path(p: &Path) -> Option<PathBuf> {
 	let file = std::fs::OpenOptions::new()
 		// No read or write permissions are necessary
 		.access_mode(0)
@@ -938,15 +1992,10 @@ fn to_final_path_wide(p: &Path) -> Option<Vec<u16>> {
 			wide.extend_from_slice(buf);
 			wide.push(0);
 
-			let hr = unsafe { PathCchStripPrefix(wide.as_mut_ptr(), wide.len()) };
-			// On success truncate invalid data
-			if hr == S_OK {
-				if let Some(end) = wide.iter().position(|c| *c == 0) {
-					// Retain NULL character
-					wide.truncate(end + 1)
-				}
-			}
-			wide
+			let _ = unsafe { PathCchStripPrefix(wide.as_mut_ptr(), wide.len()) };
+			// Stripping the prefix moves the NULL character forward
+			let end = wide.iter().position(|c| *c == 0).unwrap_or(wide.len());
+			std::ffi::OsString::from_wide(&wide[..end]).into()
 		},
 	)
 }
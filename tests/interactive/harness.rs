@@ -0,0 +1,193 @@
+/*
+SPDX-License-Identifier: Apache-2.0 OR MIT
+
+Copyright 2022 The Arboard contributors
+
+The project to which this file belongs is licensed under either of
+the Apache 2.0 or the MIT license at the licensee's choice. The terms
+and conditions of the chosen license apply to this file.
+*/
+
+//! Spawning helper processes and external tools, with timeouts, and finding out what the
+//! session the tests run in supports.
+
+use std::{
+	env,
+	io::{BufRead, BufReader, Read},
+	path::PathBuf,
+	process::{Child, Command, ExitStatus, Stdio},
+	sync::{
+		mpsc::{self, Receiver},
+		Mutex, MutexGuard, PoisonError,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+/// The environment variable telling a spawned copy of the test binary which role to play.
+pub(crate) const ROLE_VAR: &str = "ARBOARD_INTERACTIVE_ROLE";
+/// The environment variable with the text the role works with.
+pub(crate) const TEXT_VAR: &str = "ARBOARD_INTERACTIVE_TEXT";
+/// What lines the helpers print for the tests start with, to tell them apart from the output of
+/// the test runner.
+pub(crate) const LINE_PREFIX: &str = "arboard-interactive:";
+
+/// How long anything a test waits for may take.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns from the test, passing it, if `$reason` keeps it from running here.
+macro_rules! skip_unless {
+	($condition:expr, $reason:expr) => {
+		if !$condition {
+			eprintln!("skipped: {}", $reason);
+			return;
+		}
+	};
+}
+pub(crate) use skip_unless;
+
+/// The clipboard is shared by all tests, which would otherwise overwrite each other's contents.
+pub(crate) fn lock_clipboard() -> MutexGuard<'static, ()> {
+	static CLIPBOARD: Mutex<()> = Mutex::new(());
+	CLIPBOARD.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What the session the tests run in offers.
+#[derive(Debug)]
+pub(crate) struct Session {
+	/// Whether an X server can be connected to, possibly Xwayland.
+	pub(crate) x11: bool,
+	/// Whether a Wayland compositor can be connected to.
+	pub(crate) wayland: bool,
+}
+
+impl Session {
+	pub(crate) fn detect() -> Self {
+		if cfg!(target_os = "linux") {
+			let set = |var| env::var_os(var).is_some_and(|value| !value.is_empty());
+			Self { x11: set("DISPLAY"), wayland: set("WAYLAND_DISPLAY") }
+		} else {
+			// Windows and macOS always have a clipboard.
+			Self { x11: false, wayland: false }
+		}
+	}
+
+	/// Whether there's a clipboard at all.
+	pub(crate) fn has_clipboard(&self) -> bool {
+		!cfg!(target_os = "linux") || self.x11 || self.wayland
+	}
+}
+
+/// The path of the executable `name` on the `PATH`, if it's installed.
+pub(crate) fn tool(name: &str) -> Option<PathBuf> {
+	let path = env::var_os("PATH")?;
+	env::split_paths(&path).map(|dir| dir.join(name)).find(|candidate| candidate.is_file())
+}
+
+/// Calls `done` until it returns `true` or the `TIMEOUT` passes, returning whether it did.
+pub(crate) fn poll_until(mut done: impl FnMut() -> bool) -> bool {
+	let deadline = Instant::now() + TIMEOUT;
+	while Instant::now() < deadline {
+		if done() {
+			return true;
+		}
+		thread::sleep(Duration::from_millis(50));
+	}
+	false
+}
+
+/// A copy of this test binary playing a role, which is killed if it's still running when this
+/// is dropped.
+pub(crate) struct Helper {
+	child: Child,
+	lines: Receiver<String>,
+}
+
+impl Helper {
+	/// Spawns the test binary to run just the `helper` test, which plays `role` with `text`.
+	pub(crate) fn spawn(role: &str, text: &str) -> Self {
+		let mut child = Command::new(env::current_exe().unwrap())
+			.args(["helper", "--exact", "--ignored", "--nocapture"])
+			.env(ROLE_VAR, role)
+			.env(TEXT_VAR, text)
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.spawn()
+			.unwrap();
+
+		let (sender, lines) = mpsc::channel();
+		let stdout = BufReader::new(child.stdout.take().unwrap());
+		thread::spawn(move || {
+			for line in stdout.lines() {
+				let Ok(line) = line else { break };
+				if let Some(line) = line.strip_prefix(LINE_PREFIX) {
+					if sender.send(line.to_owned()).is_err() {
+						break;
+					}
+				}
+			}
+		});
+		Self { child, lines }
+	}
+
+	/// The next line the helper printed for the test.
+	pub(crate) fn line(&self) -> String {
+		self.lines.recv_timeout(TIMEOUT).expect("the helper didn't print anything in time")
+	}
+
+	/// Waits for the helper to exit by itself.
+	pub(crate) fn wait(&mut self) -> ExitStatus {
+		let exited = poll_until(|| self.child.try_wait().unwrap().is_some());
+		assert!(exited, "the helper didn't exit in time");
+		self.child.wait().unwrap()
+	}
+
+	/// Whether the helper is still running.
+	pub(crate) fn is_running(&mut self) -> bool {
+		self.child.try_wait().unwrap().is_none()
+	}
+}
+
+impl Drop for Helper {
+	fn drop(&mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+
+/// Runs an external tool to completion and returns what it printed, or `None` if it failed or
+/// didn't finish in time.
+pub(crate) fn run_tool(command: &mut Command) -> Option<Vec<u8>> {
+	let mut child =
+		command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn().ok()?;
+	let mut stdout = child.stdout.take()?;
+	let reader = thread::spawn(move || {
+		let mut output = Vec::new();
+		stdout.read_to_end(&mut output).map(|_| output)
+	});
+
+	if !poll_until(|| child.try_wait().ok().flatten().is_some()) {
+		let _ = child.kill();
+		let _ = child.wait();
+		return None;
+	}
+	let status = child.wait().ok()?;
+	let output = reader.join().ok()?.ok()?;
+	status.success().then_some(output)
+}
+
+/// Runs an external tool which places `input` onto the clipboard, and usually stays in the
+/// background to serve it, returning whether it started.
+pub(crate) fn run_copy_tool(command: &mut Command, input: &str) -> bool {
+	use std::io::Write;
+
+	// Tools serving in the background keep their output open, which mustn't be waited for.
+	let Ok(mut child) =
+		command.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()
+	else {
+		return false;
+	};
+	let written =
+		child.stdin.take().is_some_and(|mut stdin| stdin.write_all(input.as_bytes()).is_ok());
+	written && poll_until(|| child.try_wait().ok().flatten().is_some())
+}
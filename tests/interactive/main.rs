@@ -0,0 +1,193 @@
+/*
+SPDX-License-Identifier: Apache-2.0 OR MIT
+
+Copyright 2022 The Arboard contributors
+
+The project to which this file belongs is licensed under either of
+the Apache 2.0 or the MIT license at the licensee's choice. The terms
+and conditions of the chosen license apply to this file.
+*/
+
+//! Tests of what can only be seen from other processes: contents outliving the process which
+//! placed them, handing them over to a clipboard manager, and other applications reading them.
+//!
+//! They need a desktop session, so they are ignored by default. Run them with
+//!
+//! ```text
+//! cargo test --test interactive -- --ignored --test-threads=1
+//! ```
+//!
+//! Tests which need a tool, like `xclip` or `wl-paste`, or a kind of session that isn't there
+//! are skipped, telling why on stderr.
+//!
+//! Other processes are played by copies of the test binary running just the `helper` test, with
+//! their role in the environment.
+
+mod harness;
+
+use arboard::Clipboard;
+use harness::{
+	lock_clipboard, poll_until, run_copy_tool, run_tool, skip_unless, tool, Helper, Session,
+	LINE_PREFIX, ROLE_VAR, TEXT_VAR,
+};
+use std::{env, process::Command};
+
+/// Plays the role the test which spawned this process asked for, and does nothing otherwise.
+#[test]
+#[ignore = "only run by the other tests"]
+fn helper() {
+	let Ok(role) = env::var(ROLE_VAR) else { return };
+	let text = env::var(TEXT_VAR).unwrap_or_default();
+
+	match role.as_str() {
+		// Places the text and keeps it on the clipboard until it's replaced, like the process
+		// spawned in the `daemonize` example.
+		"serve" => {
+			#[cfg(target_os = "linux")]
+			{
+				use arboard::SetExtLinux;
+				Clipboard::new().unwrap().set().wait().text(text).unwrap();
+			}
+			#[cfg(not(target_os = "linux"))]
+			Clipboard::new().unwrap().set_text(text).unwrap();
+			println!("{LINE_PREFIX}replaced");
+		}
+		// Places the text and exits right away, leaving it to a clipboard manager if any.
+		"set" => Clipboard::new().unwrap().set_text(text).unwrap(),
+		"print" => {
+			let text = Clipboard::new().unwrap().get_text();
+			println!("{LINE_PREFIX}{:?}", text.ok());
+		}
+		role => panic!("unknown role {role}"),
+	}
+}
+
+/// The text on the clipboard, read by a new `Clipboard` like another application would.
+fn clipboard_text() -> Option<String> {
+	Clipboard::new().ok()?.get_text().ok()
+}
+
+#[test]
+#[ignore = "needs a desktop session"]
+fn served_text_outlives_the_placing_process() {
+	let session = Session::detect();
+	skip_unless!(session.has_clipboard(), "there's no display to connect to");
+	let _lock = lock_clipboard();
+
+	let text = "served by a daemon";
+	let mut daemon = Helper::spawn("serve", text);
+	assert!(poll_until(|| clipboard_text().as_deref() == Some(text)));
+	if cfg!(target_os = "linux") {
+		assert!(daemon.is_running(), "the daemon stopped serving before it was replaced");
+	}
+
+	// Replacing the text ends the daemon.
+	Clipboard::new().unwrap().set_text("replaced").unwrap();
+	if cfg!(target_os = "linux") {
+		assert_eq!(daemon.line(), "replaced");
+	}
+	assert!(daemon.wait().success());
+}
+
+#[test]
+#[ignore = "needs a desktop session"]
+fn text_is_read_by_another_process() {
+	let session = Session::detect();
+	skip_unless!(session.has_clipboard(), "there's no display to connect to");
+	let _lock = lock_clipboard();
+
+	// The `Clipboard` is kept, so that the text is still served on Linux when it's read.
+	let text = "line one\nline two\u{1f414}";
+	let mut clipboard = Clipboard::new().unwrap();
+	clipboard.set_text(text).unwrap();
+
+	let mut reader = Helper::spawn("print", "");
+	assert_eq!(reader.line(), format!("{:?}", Some(text)));
+	assert!(reader.wait().success());
+}
+
+#[test]
+#[ignore = "needs an X11 session with a clipboard manager"]
+fn contents_are_handed_to_the_clipboard_manager() {
+	let session = Session::detect();
+	skip_unless!(session.x11, "there's no X server to connect to");
+	skip_unless!(clipboard_manager_running(), "no clipboard manager is running");
+	let _lock = lock_clipboard();
+
+	let text = "kept by the manager";
+	let mut placer = Helper::spawn("set", text);
+	assert!(placer.wait().success());
+	assert!(
+		poll_until(|| clipboard_text().as_deref() == Some(text)),
+		"the text didn't outlive the process which placed it"
+	);
+}
+
+/// Whether a clipboard manager owns the `CLIPBOARD_MANAGER` selection, which it needs to take
+/// over the contents of exiting applications.
+fn clipboard_manager_running() -> bool {
+	#[cfg(target_os = "linux")]
+	{
+		use x11rb::{protocol::xproto::ConnectionExt, NONE};
+
+		let Ok((conn, _)) = x11rb::connect(None) else { return false };
+		let owner = conn
+			.intern_atom(true, b"CLIPBOARD_MANAGER")
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.filter(|reply| reply.atom != NONE)
+			.and_then(|reply| conn.get_selection_owner(reply.atom).ok()?.reply().ok());
+		owner.is_some_and(|owner| owner.owner != NONE)
+	}
+	#[cfg(not(target_os = "linux"))]
+	false
+}
+
+#[test]
+#[ignore = "needs an X11 session with xclip"]
+fn xclip_exchanges_text_with_us() {
+	let session = Session::detect();
+	skip_unless!(session.x11, "there's no X server to connect to");
+	let Some(xclip) = tool("xclip") else {
+		eprintln!("skipped: xclip isn't installed");
+		return;
+	};
+	let _lock = lock_clipboard();
+
+	let text = "read by xclip";
+	let _daemon = Helper::spawn("serve", text);
+	assert!(poll_until(|| clipboard_text().as_deref() == Some(text)));
+	let pasted = run_tool(Command::new(&xclip).args(["-o", "-selection", "clipboard"]));
+	assert_eq!(pasted.as_deref(), Some(text.as_bytes()));
+
+	let copied = "copied with xclip";
+	assert!(run_copy_tool(Command::new(&xclip).args(["-i", "-selection", "clipboard"]), copied));
+	assert!(poll_until(|| clipboard_text().as_deref() == Some(copied)));
+}
+
+#[test]
+#[ignore = "needs a Wayland session with wl-clipboard"]
+fn wl_clipboard_exchanges_text_with_us() {
+	let session = Session::detect();
+	skip_unless!(session.wayland, "there's no Wayland compositor to connect to");
+	let (Some(wl_copy), Some(wl_paste)) = (tool("wl-copy"), tool("wl-paste")) else {
+		eprintln!("skipped: wl-clipboard isn't installed");
+		return;
+	};
+	let _lock = lock_clipboard();
+
+	// Whichever backend was chosen, possibly X11 through Xwayland when the compositor lacks the
+	// data control protocol, Wayland applications see the same clipboard.
+	let diagnostics = Clipboard::new().unwrap().diagnostics();
+	eprintln!("using {}: {}", diagnostics.backend, diagnostics.backend_reason);
+
+	let text = "read by wl-paste";
+	let _daemon = Helper::spawn("serve", text);
+	assert!(poll_until(|| clipboard_text().as_deref() == Some(text)));
+	let pasted = run_tool(Command::new(&wl_paste).arg("--no-newline"));
+	assert_eq!(pasted.as_deref(), Some(text.as_bytes()));
+
+	let copied = "copied with wl-copy";
+	assert!(run_copy_tool(&mut Command::new(&wl_copy), copied));
+	assert!(poll_until(|| clipboard_text().as_deref() == Some(copied)));
+}